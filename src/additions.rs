@@ -0,0 +1,105 @@
+use crate::mrpack::{Environment, FileHashes, MRFile, Requirement};
+use crate::pack::{IndexError, Pack};
+use crate::util::is_contained_path;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+use url::Url;
+
+/// Looked for in the server directory when no additions file is given.
+pub const ADDITIONS_FILE_NAME: &str = "additions.toml";
+
+/// Extra files installed alongside the pack, e.g. server-only mods the
+/// pack author doesn't ship:
+///
+/// ```toml
+/// [[file]]
+/// path = "mods/chunky.jar"
+/// url = "https://cdn.modrinth.com/data/fALzjamp/versions/.../Chunky.jar"
+/// sha1 = "..."
+/// sha512 = "..."
+/// size = 284000
+/// ```
+///
+/// They are synced as index files, so prune, the lockfile and `verify`
+/// treat them like the pack's own. Hosts other than Modrinth's still need
+/// `--allow-domain`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Additions {
+    #[serde(rename = "file")]
+    pub files: Vec<Addition>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Addition {
+    #[serde(deserialize_with = "crate::util::pack_path::deserialize")]
+    pub path: PathBuf,
+    pub url: Url,
+    #[serde(deserialize_with = "hex::deserialize")]
+    pub sha1: [u8; 20],
+    #[serde(deserialize_with = "hex::deserialize")]
+    pub sha512: [u8; 64],
+    pub size: u64,
+}
+
+impl Additions {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, IndexError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        Ok(toml::from_str(&content)
+            .map_err(|err| format!("invalid additions {}: {}", path.display(), err))?)
+    }
+
+    /// Loads `additions.toml` from `dir` if it exists.
+    pub fn discover(dir: impl AsRef<Path>) -> Result<Option<Self>, IndexError> {
+        let path = dir.as_ref().join(ADDITIONS_FILE_NAME);
+        if path.is_file() {
+            Self::load(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Adds the files to `pack` as server-only index files, replacing any
+    /// file or override the pack has at the same path.
+    pub fn apply(self, mut pack: Pack) -> Result<Pack, IndexError> {
+        if let Some(addition) = self
+            .files
+            .iter()
+            .find(|addition| !is_contained_path(&addition.path))
+        {
+            return Err(format!(
+                "refusing addition `{}`: paths must be relative and stay inside the server directory",
+                addition.path.display()
+            )
+            .into());
+        }
+
+        let paths: HashSet<&PathBuf> = self.files.iter().map(|addition| &addition.path).collect();
+        pack.index.files.retain(|file| !paths.contains(&file.path));
+        pack.overrides.retain(|path, _| !paths.contains(path));
+
+        pack.index
+            .files
+            .extend(self.files.into_iter().map(|addition| MRFile {
+                path: addition.path,
+                hashes: FileHashes {
+                    sha1: addition.sha1,
+                    sha512: addition.sha512,
+                    other_hashes: HashMap::new(),
+                },
+                env: Some(Environment {
+                    client: Requirement::Unsupported,
+                    server: Requirement::Required,
+                }),
+                downloads: vec![addition.url],
+                file_size: addition.size,
+            }));
+        Ok(pack)
+    }
+}
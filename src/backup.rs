@@ -0,0 +1,21 @@
+use crate::errors::{FileError, IoContext};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Directory under the server root that override files are copied into
+/// before a sync overwrites them, one timestamped folder per sync.
+pub const BACKUP_DIR: &str = ".observe-backup";
+
+/// Copies `path` (relative to `root`) to `BACKUP_DIR/<batch>/<path>` and
+/// returns the copy's path relative to `root`.
+pub(crate) fn back_up(root: &Path, batch: &str, path: &Path) -> Result<PathBuf, FileError> {
+    let backup = Path::new(BACKUP_DIR).join(batch).join(path);
+    let dest = root.join(&backup);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).at(parent)?;
+    }
+    fs::copy(root.join(path), &dest).at(&dest)?;
+    Ok(backup)
+}
@@ -0,0 +1,185 @@
+use crate::errors::{FileError, IoContext};
+use crate::hashing::HashPolicy;
+use crate::mod_manager::file_is_valid;
+use crate::mrpack::MRFile;
+use serde::Deserialize;
+use std::{
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tokio::{
+    fs::{create_dir_all, remove_file, rename},
+    task::spawn_blocking,
+};
+
+/// How files are materialized out of the cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    /// Plain byte copy.
+    #[default]
+    Copy,
+    /// Hardlink to the cache entry, copying when the cache is on another
+    /// filesystem.
+    Hardlink,
+    /// Copy-on-write clone where the filesystem supports it (btrfs, XFS),
+    /// copying otherwise.
+    Reflink,
+}
+
+impl FromStr for LinkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "copy" => Ok(LinkMode::Copy),
+            "hardlink" => Ok(LinkMode::Hardlink),
+            "reflink" => Ok(LinkMode::Reflink),
+            other => Err(format!(
+                "unknown link mode `{}` (expected copy, hardlink or reflink)",
+                other
+            )),
+        }
+    }
+}
+
+impl Display for LinkMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkMode::Copy => write!(f, "copy"),
+            LinkMode::Hardlink => write!(f, "hardlink"),
+            LinkMode::Reflink => write!(f, "reflink"),
+        }
+    }
+}
+
+/// Content-addressed store of verified downloads, laid out as
+/// `<root>/sha1/<hex digest>` so several server directories can share it.
+#[derive(Debug, Clone)]
+pub struct DownloadCache {
+    root: PathBuf,
+    link_mode: LinkMode,
+}
+
+impl DownloadCache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        DownloadCache {
+            root: root.into(),
+            link_mode: LinkMode::default(),
+        }
+    }
+
+    pub fn with_link_mode(mut self, link_mode: LinkMode) -> Self {
+        self.link_mode = link_mode;
+        self
+    }
+
+    /// `$XDG_CACHE_HOME/observe-rs`, falling back to `~/.cache/observe-rs`.
+    pub fn default_dir() -> Option<PathBuf> {
+        std::env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .map(|dir| dir.join("observe-rs"))
+    }
+
+    fn entry_path(&self, sha1: &[u8; 20]) -> PathBuf {
+        self.root.join("sha1").join(hex::encode(sha1))
+    }
+
+    /// Materializes `src` at `dest` according to the configured link mode.
+    async fn link(&self, src: &Path, dest: &Path) -> io::Result<()> {
+        let (src, dest, mode) = (src.to_path_buf(), dest.to_path_buf(), self.link_mode);
+        spawn_blocking(move || link_file(&src, &dest, mode)).await?
+    }
+
+    /// Copies the cached copy of `file` to `dest` if one exists and still
+    /// verifies. Corrupt entries are evicted. Returns whether `dest` was
+    /// written.
+    pub(crate) async fn fetch(
+        &self,
+        file: &MRFile,
+        dest: &Path,
+        policy: HashPolicy,
+    ) -> Result<bool, FileError> {
+        let entry = self.entry_path(&file.hashes.sha1);
+        if self.link(&entry, dest).await.is_err() {
+            return Ok(false);
+        }
+
+        let dest_path = dest.to_path_buf();
+        let expected = file.clone();
+        let is_valid = spawn_blocking(move || match std::fs::File::open(&dest_path) {
+            Ok(mut f) => file_is_valid(&mut f, &expected, policy),
+            Err(_) => false,
+        })
+        .await?;
+
+        if !is_valid {
+            let _ = remove_file(dest).await;
+            let _ = remove_file(&entry).await;
+        }
+
+        Ok(is_valid)
+    }
+
+    /// Adds an already verified file to the cache.
+    pub(crate) async fn store(&self, file: &MRFile, src: &Path) -> Result<(), FileError> {
+        let entry = self.entry_path(&file.hashes.sha1);
+        if entry.exists() {
+            return Ok(());
+        }
+
+        if let Some(parent) = entry.parent() {
+            create_dir_all(parent).await.at(parent)?;
+        }
+
+        let mut tmp = entry.clone().into_os_string();
+        tmp.push(format!(".{:016x}.tmp", fastrand::u64(..)));
+        let tmp = PathBuf::from(tmp);
+
+        if let Err(err) = self.link(src, &tmp).await {
+            let _ = remove_file(&tmp).await;
+            return Err(err).at(&tmp);
+        }
+        rename(&tmp, &entry).await.at(&entry)?;
+
+        Ok(())
+    }
+}
+
+fn link_file(src: &Path, dest: &Path, mode: LinkMode) -> io::Result<()> {
+    // A stale file left by an interrupted run would make link/clone fail.
+    let _ = std::fs::remove_file(dest);
+    match mode {
+        LinkMode::Copy => std::fs::copy(src, dest).map(|_| ()),
+        LinkMode::Hardlink => {
+            std::fs::hard_link(src, dest).or_else(|_| std::fs::copy(src, dest).map(|_| ()))
+        }
+        LinkMode::Reflink => reflink(src, dest).or_else(|_| {
+            let _ = std::fs::remove_file(dest);
+            std::fs::copy(src, dest).map(|_| ())
+        }),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dest: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let src = std::fs::File::open(src)?;
+    let dest = std::fs::File::create_new(dest)?;
+    // SAFETY: both descriptors are open for the duration of the call.
+    let ret = unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_src: &Path, _dest: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
@@ -0,0 +1,238 @@
+use crate::diff::{DependencyChange, PackDiff};
+use crate::modrinth::{ApiError, ModrinthClient, Version};
+use crate::mrpack::MRFile;
+use crate::pack::Pack;
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+/// A readable summary of what changed between two versions of a pack, with
+/// files resolved to the Modrinth projects and versions they belong to.
+#[derive(Debug, Clone, Serialize)]
+pub struct Changelog {
+    pub name: String,
+    pub old_version: String,
+    pub new_version: String,
+    pub dependencies: Vec<DependencyChange>,
+    pub updated: Vec<ModUpdate>,
+    pub added: Vec<ModEntry>,
+    pub removed: Vec<ModEntry>,
+    pub overrides_added: Vec<PathBuf>,
+    pub overrides_removed: Vec<PathBuf>,
+    pub overrides_changed: Vec<PathBuf>,
+}
+
+/// A file in the pack, named after its Modrinth project when Modrinth
+/// knows the file and after the file itself otherwise.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModUpdate {
+    pub name: String,
+    pub old: ModEntry,
+    pub new: ModEntry,
+}
+
+impl Changelog {
+    /// Diffs the packs and looks up every added, removed and changed file
+    /// by hash in one batch request.
+    pub async fn generate(
+        modrinth: &ModrinthClient,
+        old: &Pack,
+        new: &Pack,
+    ) -> Result<Self, ApiError> {
+        let diff = PackDiff::new(old, new);
+
+        let mut files: Vec<&MRFile> = Vec::new();
+        files.extend(diff.removed.iter().filter_map(|path| file(old, path)));
+        files.extend(diff.added.iter().filter_map(|path| file(new, path)));
+        for change in &diff.changed {
+            files.extend(file(old, &change.old));
+            files.extend(file(new, &change.new));
+        }
+        let hashes: Vec<String> = files
+            .iter()
+            .map(|file| hex::encode(file.hashes.sha1))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let versions = modrinth.versions_from_hashes(&hashes, "sha1").await?;
+
+        let project_ids: BTreeSet<&str> =
+            versions.values().map(|v| v.project_id.as_str()).collect();
+        let titles: HashMap<String, String> = modrinth
+            .projects(&project_ids.into_iter().collect::<Vec<_>>())
+            .await?
+            .into_iter()
+            .map(|project| (project.id, project.title))
+            .collect();
+
+        let resolver = Resolver { versions, titles };
+        let mut changelog = Changelog {
+            name: new.index.name.clone(),
+            old_version: diff.old_version,
+            new_version: diff.new_version,
+            dependencies: diff.dependencies,
+            updated: diff
+                .changed
+                .iter()
+                .map(|change| {
+                    let old = resolver.entry(old, &change.old);
+                    let new = resolver.entry(new, &change.new);
+                    ModUpdate {
+                        name: new.name.clone(),
+                        old,
+                        new,
+                    }
+                })
+                .collect(),
+            added: diff
+                .added
+                .iter()
+                .map(|path| resolver.entry(new, path))
+                .collect(),
+            removed: diff
+                .removed
+                .iter()
+                .map(|path| resolver.entry(old, path))
+                .collect(),
+            overrides_added: diff.overrides_added,
+            overrides_removed: diff.overrides_removed,
+            overrides_changed: diff.overrides_changed,
+        };
+        changelog
+            .updated
+            .sort_by_key(|update| update.name.to_lowercase());
+        changelog
+            .added
+            .sort_by_key(|entry| entry.name.to_lowercase());
+        changelog
+            .removed
+            .sort_by_key(|entry| entry.name.to_lowercase());
+        Ok(changelog)
+    }
+}
+
+struct Resolver {
+    /// Keyed by hex SHA-1.
+    versions: HashMap<String, Version>,
+    /// Project titles keyed by project id.
+    titles: HashMap<String, String>,
+}
+
+impl Resolver {
+    fn entry(&self, pack: &Pack, path: &Path) -> ModEntry {
+        let version = file(pack, path).and_then(|f| self.versions.get(&hex::encode(f.hashes.sha1)));
+        let name = version
+            .and_then(|v| self.titles.get(&v.project_id))
+            .cloned()
+            .unwrap_or_else(|| {
+                path.file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy()
+                    .into_owned()
+            });
+        ModEntry {
+            name,
+            path: path.to_path_buf(),
+            version: version.map(|v| v.version_number.clone()),
+        }
+    }
+}
+
+fn file<'a>(pack: &'a Pack, path: &Path) -> Option<&'a MRFile> {
+    pack.index.files.iter().find(|file| file.path == path)
+}
+
+impl Display for ModEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{} {}", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl Display for ModUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.old.version, &self.new.version) {
+            (Some(old), Some(new)) if old != new => write!(f, "{} {} → {}", self.name, old, new),
+            (Some(_), Some(new)) => write!(f, "{} {} (file replaced)", self.name, new),
+            _ => write!(f, "`{}` (file changed)", self.new.path.display()),
+        }
+    }
+}
+
+/// Renders as Markdown, ready for release notes.
+impl Display for Changelog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "# {} {} → {}",
+            self.name, self.old_version, self.new_version
+        )?;
+
+        let mut sections: Vec<(&str, Vec<String>)> = vec![(
+            "Dependencies",
+            self.dependencies
+                .iter()
+                .map(|dep| match (&dep.old, &dep.new) {
+                    (Some(old), Some(new)) => format!("{} {} → {}", dep.id, old, new),
+                    _ => dep.to_string(),
+                })
+                .collect(),
+        )];
+        sections.push((
+            "Updated",
+            self.updated.iter().map(ToString::to_string).collect(),
+        ));
+        sections.push((
+            "Added",
+            self.added.iter().map(ToString::to_string).collect(),
+        ));
+        sections.push((
+            "Removed",
+            self.removed.iter().map(ToString::to_string).collect(),
+        ));
+        let overrides = [
+            ("Added", &self.overrides_added),
+            ("Removed", &self.overrides_removed),
+            ("Changed", &self.overrides_changed),
+        ];
+        sections.push((
+            "Overrides",
+            overrides
+                .into_iter()
+                .flat_map(|(verb, paths)| {
+                    paths
+                        .iter()
+                        .map(move |path| format!("{} `{}`", verb, path.display()))
+                })
+                .collect(),
+        ));
+
+        let mut empty = true;
+        for (heading, items) in sections {
+            if items.is_empty() {
+                continue;
+            }
+            empty = false;
+            write!(f, "\n\n## {}\n", heading)?;
+            for item in items {
+                write!(f, "\n- {}", item)?;
+            }
+        }
+        if empty {
+            write!(f, "\n\nNo changes.")?;
+        }
+        Ok(())
+    }
+}
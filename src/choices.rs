@@ -0,0 +1,96 @@
+use crate::config::ConfigError;
+use crate::mrpack::MRFile;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Per-file decisions kept in the `[choices]` table of `observe.toml`,
+/// e.g.
+///
+/// ```toml
+/// [choices.optional]
+/// "mods/xaeros-minimap.jar" = false
+///
+/// [choices.overrides]
+/// "config/sodium-options.json" = "keep"
+/// ```
+///
+/// They apply on every sync, over the optional-file policy, and are written
+/// by `observe sync --pick`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Choices {
+    /// Server-optional files by path: `true` to install, `false` to leave
+    /// out.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub optional: BTreeMap<PathBuf, bool>,
+    /// What to do with overrides whose local copy differs from the pack's.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub overrides: BTreeMap<PathBuf, OverrideChoice>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverrideChoice {
+    /// Leave the local copy alone.
+    Keep,
+    /// Write the pack's version over it, without merging.
+    Replace,
+}
+
+/// What a sync could ask about: the pack's server-optional files, and
+/// overrides whose local edits the sync would either overwrite or leave in
+/// conflict.
+#[derive(Debug, Clone, Default)]
+pub struct Choosable {
+    pub optional: Vec<MRFile>,
+    pub overrides: Vec<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct ChoicesTable<'a> {
+    choices: &'a Choices,
+}
+
+impl Choices {
+    /// Writes these choices to the `[choices]` table of the config file at
+    /// `path`, creating it if needed. The old table is replaced and the rest
+    /// of the file, comments included, is left as it was.
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(format!("failed to read {}: {}", path.display(), err).into()),
+        };
+
+        let mut kept = String::new();
+        let mut in_choices = false;
+        for line in content.lines() {
+            if let Some(name) = table_name(line) {
+                in_choices = name == "choices";
+            }
+            if !in_choices {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+
+        let mut updated = kept.trim_end().to_string();
+        if !updated.is_empty() {
+            updated.push_str("\n\n");
+        }
+        updated.push_str(&toml::to_string(&ChoicesTable { choices: self })?);
+        std::fs::write(path, updated)
+            .map_err(|err| format!("failed to write {}: {}", path.display(), err).into())
+    }
+}
+
+/// The top-level key of a `[table]` or `[[array]]` header line.
+fn table_name(line: &str) -> Option<&str> {
+    let header = line.trim().strip_prefix('[')?.trim_start_matches('[');
+    let end = header.find(']')?;
+    header[..end].split('.').next().map(str::trim)
+}
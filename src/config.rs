@@ -0,0 +1,233 @@
+use crate::cache::LinkMode;
+use crate::choices::Choices;
+use crate::hashing::{HashPolicy, VerifyPolicy};
+use crate::notify::NotifyOn;
+use crate::pack::PackSource;
+use crate::schedule::CronSchedule;
+use crate::syncer::{JavaPolicy, OptionalPolicy, PrunePolicy};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+pub type ConfigError = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CONFIG_FILE_NAME: &str = "observe.toml";
+
+/// Settings read from `observe.toml`. Every field is optional; command-line
+/// flags take precedence over anything set here.
+///
+/// Relative paths are resolved against the directory containing the file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// The `.mrpack` or packwiz `pack.toml` to sync: a path or an `http(s)` URL.
+    pub pack: Option<String>,
+    /// Server directory the pack is synced into.
+    pub dir: Option<PathBuf>,
+    /// Remote server to deploy to after syncing, e.g.
+    /// `sftp://mc@host/srv/minecraft` or `s3://bucket/prefix`.
+    pub target: Option<String>,
+    /// Extra files to install alongside the pack. Defaults to
+    /// `additions.toml` in the server directory, if there is one.
+    pub additions: Option<PathBuf>,
+    /// Pack files never to install, by path or Modrinth project id, e.g.
+    /// `["mods/xaeros-minimap.jar", "AANobbMI"]`. Prune may delete them.
+    pub skip: Vec<String>,
+    pub prune: Option<bool>,
+    pub prune_mode: Option<PrunePolicy>,
+    /// Directories checked against the index, e.g. `["mods", "shaderpacks"]`.
+    /// Defaults to `mods`, `resourcepacks` and the pack's own directories.
+    pub prune_index_dirs: Option<Vec<PathBuf>>,
+    /// Directories checked against the overrides, e.g. `["config"]`.
+    /// Defaults to `config` and the overrides' own directories.
+    pub prune_override_dirs: Option<Vec<PathBuf>>,
+    /// Glob patterns; when set, only matching files are pruned.
+    pub prune_include: Vec<String>,
+    /// Glob patterns that are never pruned, e.g. `["config/luckperms/**"]`.
+    pub prune_exclude: Vec<String>,
+    pub optional: Option<OptionalPolicy>,
+    /// Optional files to install or leave out, and what to do with edited
+    /// overrides, one by one; see [`Choices`].
+    pub choices: Choices,
+    /// Extra download hosts beyond the Modrinth allowlist.
+    pub allow_domains: Vec<String>,
+    /// Set to `true` to permit plain-HTTP download URLs.
+    pub allow_insecure: Option<bool>,
+    pub concurrency: Option<usize>,
+    pub retries: Option<u32>,
+    /// Base retry backoff in milliseconds.
+    pub retry_delay: Option<u64>,
+    /// Connect timeout in seconds.
+    pub connect_timeout: Option<u64>,
+    /// Seconds without data before a transfer is retried.
+    pub read_timeout: Option<u64>,
+    pub cache_dir: Option<PathBuf>,
+    /// Set to `false` to disable the shared download cache.
+    pub download_cache: Option<bool>,
+    pub link_mode: Option<LinkMode>,
+    /// Combined download rate cap, e.g. `"10M"`.
+    pub limit_rate: Option<String>,
+    /// Per-transfer download rate cap, e.g. `"2M"`.
+    pub limit_rate_per_download: Option<String>,
+    /// `standard` (default), `cheapest` or `all`.
+    pub hash_policy: Option<HashPolicy>,
+    /// How files already on disk are checked: `strict` (default), `fast`
+    /// or `skip`.
+    pub verify: Option<VerifyPolicy>,
+    /// Set to `false` to re-hash every file instead of trusting the
+    /// size/mtime state from the last sync.
+    pub state_cache: Option<bool>,
+    /// Set to `false` to overwrite local edits to overrides instead of
+    /// merging the pack's changes into them.
+    pub merge_overrides: Option<bool>,
+    /// Merge `.properties` overrides key by key instead of as text.
+    pub merge_properties: Option<bool>,
+    /// Set to `true` to substitute placeholders in overrides.
+    pub templates: Option<bool>,
+    /// Template variables, e.g. `[vars]` / `SERVER_PORT = "25566"`. These
+    /// take precedence over environment variables of the same name.
+    pub vars: BTreeMap<String, String>,
+    /// Dotenv or JSON file of secrets to inject into overrides.
+    pub secrets_file: Option<PathBuf>,
+    /// Glob patterns of overrides secrets may be injected into. Defaults to
+    /// every override.
+    pub secret_targets: Vec<String>,
+    /// Unix modes forced onto overrides by glob, in octal, e.g.
+    /// `{ "*.sh" = "755" }`. Otherwise overrides get the modes their zip
+    /// entries record.
+    pub override_modes: BTreeMap<String, String>,
+    /// Set to `true` to give downloaded files the time the pack was built
+    /// as their modification time.
+    pub stamp_downloads: Option<bool>,
+    /// Set to `true` to look for files to prune in symlinked directories
+    /// that stay inside the server directory.
+    pub follow_symlinks: Option<bool>,
+    /// Set to `true` to accept the Minecraft EULA in `eula.txt`.
+    pub accept_eula: Option<bool>,
+    /// Set to `false` to leave installing the mod loader to the operator.
+    pub install_loader: Option<bool>,
+    /// Set to `true` to warn instead of failing when the pack installs the
+    /// same mod from more than one file.
+    pub allow_duplicates: Option<bool>,
+    /// Set to `false` to skip looking up the pack's mods on Modrinth to
+    /// warn about missing required dependencies.
+    pub check_dependencies: Option<bool>,
+    /// Set to `true` to write start scripts after each sync.
+    pub scaffold: Option<bool>,
+    /// Server heap size for the start scripts, e.g. `"6G"`.
+    pub memory: Option<String>,
+    /// `check` (default), `provision` or `ignore`.
+    pub java: Option<JavaPolicy>,
+    /// Prometheus textfile to write metrics to after each sync and verify,
+    /// e.g. in node_exporter's `--collector.textfile.directory`.
+    pub metrics_file: Option<PathBuf>,
+    /// When `observe daemon` runs jobs on its own.
+    pub schedule: Schedules,
+    /// Chat webhooks told how syncs, update checks and verifies went.
+    pub notify: Notifications,
+    /// RCON access to the running server, so syncs that change anything
+    /// warn players and save, or stop, the server first.
+    pub rcon: Option<RconConfig>,
+}
+
+/// Cron schedules for `observe daemon`'s jobs, e.g. `verify = "0 * * * *"`
+/// for hourly audits and `update = "30 4 * * *"` for a nightly check for a
+/// new version of the installed Modrinth pack.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Schedules {
+    pub sync: Option<CronSchedule>,
+    pub verify: Option<CronSchedule>,
+    pub update: Option<CronSchedule>,
+}
+
+/// Discord and Slack incoming webhooks to post outcomes to, e.g.
+/// `discord = "https://discord.com/api/webhooks/…"`. `on` limits them to
+/// some of `sync`, `update` and `drift`; all are posted by default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Notifications {
+    pub discord: Option<String>,
+    pub slack: Option<String>,
+    pub on: Option<Vec<NotifyOn>>,
+}
+
+/// `[rcon]` in `observe.toml`, e.g.
+///
+/// ```toml
+/// [rcon]
+/// warning = "Restarting for a modpack update in 30 seconds"
+/// warning-delay = 30
+/// stop = true
+/// start-command = "systemctl start minecraft"
+/// ```
+///
+/// The address defaults to `127.0.0.1` on the `rcon.port` of the server's
+/// `server.properties`, and the password to `OBSERVE_RCON_PASSWORD`, then
+/// to `rcon.password` there.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RconConfig {
+    /// `host:port`.
+    pub address: Option<String>,
+    pub password: Option<String>,
+    /// Broadcast to players before the sync.
+    pub warning: Option<String>,
+    /// Seconds between the warning and the sync.
+    pub warning_delay: Option<u64>,
+    /// Set to `true` to stop the server for the sync; needs `start-command`.
+    pub stop: Option<bool>,
+    /// Shell command that starts the server again after a sync stopped it.
+    pub start_command: Option<String>,
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        let mut config: Config = toml::from_str(&content)
+            .map_err(|err| format!("invalid config {}: {}", path.display(), err))?;
+
+        if let Some(base) = path.parent() {
+            config.resolve_paths(base);
+        }
+        Ok(config)
+    }
+
+    /// Loads `observe.toml` from `dir` if it exists.
+    pub fn discover(dir: impl AsRef<Path>) -> Result<Option<Self>, ConfigError> {
+        let path = dir.as_ref().join(CONFIG_FILE_NAME);
+        if path.is_file() {
+            Self::load(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) fn resolve_paths(&mut self, base: &Path) {
+        if let Some(pack) = &mut self.pack
+            && let Ok(PackSource::Path(path)) = pack.parse()
+            && path.is_relative()
+        {
+            *pack = base.join(path).to_string_lossy().into_owned();
+        }
+
+        for path in [
+            &mut self.dir,
+            &mut self.additions,
+            &mut self.cache_dir,
+            &mut self.secrets_file,
+            &mut self.metrics_file,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if path.is_relative() {
+                *path = base.join(&*path);
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::hashing::HashingWriter;
+use crate::loader::ServerTarget;
+use crate::lockfile::Lockfile;
+use crate::modrinth::{ModrinthClient, Project};
+use crate::mrpack::{DependencyId, Environment, MRFile, MRIndex, Requirement};
+use crate::pack::{IndexError, Pack};
+use crate::util::STATE_DIR;
+use rayon::prelude::*;
+use semver::Version;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Directories whose files are looked up on Modrinth by hash.
+pub const HOSTED_DIRS: &[&str] = &["mods", "resourcepacks"];
+
+/// Directories always shipped as overrides.
+pub const OVERRIDE_DIRS: &[&str] = &["config"];
+
+/// What to call the pack and what else to bundle.
+#[derive(Debug, Clone)]
+pub struct CreateOptions {
+    pub name: String,
+    pub version_id: String,
+    pub summary: Option<String>,
+    pub dependencies: HashMap<DependencyId, Version>,
+    /// Extra directories to ship as overrides, relative to the server root.
+    pub include: Vec<PathBuf>,
+}
+
+/// A pack built from a server directory, with the files Modrinth didn't
+/// know that had to be bundled as overrides instead.
+#[derive(Debug, Clone)]
+pub struct CreatedPack {
+    pub pack: Pack,
+    pub bundled: Vec<PathBuf>,
+}
+
+/// The game and loader versions `root` was last synced to, from its
+/// lockfile, or else from the loader observe-rs installed there.
+pub fn installed_dependencies(root: &Path) -> HashMap<DependencyId, Version> {
+    if let Ok(Some(lockfile)) = Lockfile::load(root) {
+        let deps: HashMap<DependencyId, Version> = lockfile
+            .dependencies
+            .iter()
+            .filter_map(|(id, version)| {
+                Some((
+                    DependencyId::from(id.as_str()),
+                    Version::parse(version).ok()?,
+                ))
+            })
+            .collect();
+        if !deps.is_empty() {
+            return deps;
+        }
+    }
+    ServerTarget::installed(root)
+        .map(|target| target.dependencies())
+        .unwrap_or_default()
+}
+
+/// Builds a pack from `root`, the reverse of a sync: files under
+/// [`HOSTED_DIRS`] that Modrinth knows by hash become index entries
+/// downloading from Modrinth, and the rest, with [`OVERRIDE_DIRS`] and
+/// `options.include`, are bundled as overrides.
+pub async fn create(
+    modrinth: &ModrinthClient,
+    root: &Path,
+    options: CreateOptions,
+) -> Result<CreatedPack, IndexError> {
+    if !options.dependencies.contains_key(&DependencyId::Minecraft) {
+        return Err("the pack needs a Minecraft version".into());
+    }
+
+    let hosted = list_files(root, HOSTED_DIRS.iter().map(PathBuf::from))?;
+    let root_owned = root.to_path_buf();
+    let hashed = tokio::task::spawn_blocking(move || {
+        hosted
+            .into_par_iter()
+            .map(|path| {
+                let mut writer = HashingWriter::new(io::sink());
+                let size = io::copy(&mut File::open(root_owned.join(&path))?, &mut writer)?;
+                Ok((path, writer.into_hasher().finish(), size))
+            })
+            .collect::<io::Result<Vec<_>>>()
+    })
+    .await??;
+
+    let sha1s: Vec<String> = hashed
+        .iter()
+        .map(|(_, hashes, _)| hex::encode(hashes.sha1))
+        .collect();
+    let versions = modrinth.versions_from_hashes(&sha1s, "sha1").await?;
+    let project_ids: BTreeSet<&str> = versions.values().map(|v| v.project_id.as_str()).collect();
+    let projects: HashMap<String, Project> = modrinth
+        .projects(&project_ids.into_iter().collect::<Vec<_>>())
+        .await?
+        .into_iter()
+        .map(|project| (project.id.clone(), project))
+        .collect();
+
+    let mut files = Vec::new();
+    let mut bundled = Vec::new();
+    for (path, hashes, size) in hashed {
+        let sha1 = hex::encode(hashes.sha1);
+        let download = versions.get(&sha1).and_then(|version| {
+            let file = version
+                .files
+                .iter()
+                .find(|file| file.hashes.get("sha1") == Some(&sha1))?;
+            Some((file.url.clone(), projects.get(&version.project_id)))
+        });
+        match download {
+            Some((url, project)) => files.push(MRFile {
+                env: Some(Environment {
+                    client: project
+                        .and_then(|p| p.client_side.as_deref())
+                        .map_or(Requirement::Required, side_requirement),
+                    // It's on this server, so the server needs it.
+                    server: Requirement::Required,
+                }),
+                file_size: size,
+                path,
+                hashes,
+                downloads: vec![url],
+            }),
+            None => bundled.push(path),
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    bundled.sort();
+
+    let override_dirs = OVERRIDE_DIRS
+        .iter()
+        .map(PathBuf::from)
+        .chain(options.include.iter().cloned());
+    let mut overrides = HashMap::new();
+    for path in bundled
+        .iter()
+        .cloned()
+        .chain(list_files(root, override_dirs)?)
+    {
+        let content =
+            fs::read(root.join(&path)).map_err(|err| format!("{}: {}", path.display(), err))?;
+        overrides.insert(path, content);
+    }
+
+    let index = MRIndex {
+        game: "minecraft".to_string(),
+        format_version: 1,
+        version_id: options.version_id,
+        name: options.name,
+        summary: options.summary,
+        files,
+        dependencies: options.dependencies,
+    };
+    Ok(CreatedPack {
+        pack: Pack::new(index, overrides)?,
+        bundled,
+    })
+}
+
+/// Regular files under `dirs`, relative to `root`, skipping missing
+/// directories, symlinks and observe-rs' own state.
+fn list_files(
+    root: &Path,
+    dirs: impl IntoIterator<Item = PathBuf>,
+) -> Result<Vec<PathBuf>, IndexError> {
+    let mut files = Vec::new();
+    for dir in dirs {
+        let dir = root.join(dir);
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in WalkDir::new(&dir).sort_by_file_name() {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let path = entry.path().strip_prefix(root)?.to_path_buf();
+            if !path.starts_with(STATE_DIR) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Maps a Modrinth project's `client_side`, treating `unknown` as required.
+fn side_requirement(side: &str) -> Requirement {
+    match side {
+        "optional" => Requirement::Optional,
+        "unsupported" => Requirement::Unsupported,
+        _ => Requirement::Required,
+    }
+}
@@ -0,0 +1,377 @@
+use crate::http;
+use crate::mrpack::{
+    DependencyId, Environment, FileHashes, MRFile, MRIndex, Requirement, parse_dependency_version,
+};
+use crate::pack::{IndexError, Pack};
+use futures_util::{StreamExt, TryStreamExt, stream};
+use reqwest::Client;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha1::{Digest, Sha1};
+use sha2::Sha512;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use url::Url;
+use zip::ZipArchive;
+
+pub const API_BASE: &str = "https://api.curseforge.com/v1/";
+
+/// Environment variable holding a CurseForge API key, which the API
+/// requires for every request.
+pub const KEY_ENV: &str = "CURSEFORGE_API_KEY";
+
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Files downloaded at once to hash them while loading.
+const LOAD_CONCURRENCY: usize = 8;
+
+/// CurseForge class ids and the directory their files install into.
+const CLASS_DIRS: &[(u32, &str)] = &[
+    (6, "mods"),
+    (12, "resourcepacks"),
+    (6552, "shaderpacks"),
+    (17, "saves"),
+];
+
+/// `manifest.json` of a CurseForge modpack export.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    minecraft: ManifestMinecraft,
+    name: String,
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    files: Vec<ManifestFile>,
+    /// Directory in the zip holding the overrides.
+    #[serde(default = "default_overrides")]
+    overrides: String,
+}
+
+fn default_overrides() -> String {
+    "overrides".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestMinecraft {
+    version: String,
+    #[serde(default)]
+    mod_loaders: Vec<ManifestLoader>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ManifestLoader {
+    /// Loader and version, e.g. `forge-47.2.0`.
+    id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ManifestFile {
+    #[serde(rename = "projectID")]
+    project_id: u64,
+    #[serde(rename = "fileID")]
+    file_id: u64,
+    #[serde(default = "default_required")]
+    required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DataResponse<T> {
+    data: T,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurseForgeFile {
+    id: u64,
+    mod_id: u64,
+    file_name: String,
+    /// `None` when the author has opted out of third-party downloads.
+    download_url: Option<Url>,
+    #[serde(default)]
+    hashes: Vec<CurseForgeHash>,
+    /// Besides game versions, may hold `Client` and `Server` markers.
+    #[serde(default)]
+    game_versions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CurseForgeHash {
+    value: String,
+    /// 1 is SHA-1, 2 is MD5.
+    algo: u8,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurseForgeMod {
+    id: u64,
+    name: String,
+    class_id: Option<u32>,
+    links: Option<CurseForgeLinks>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurseForgeLinks {
+    website_url: Option<String>,
+}
+
+/// Whether the zip at `path` is a CurseForge export: it has a
+/// `manifest.json` and no `modrinth.index.json`.
+pub fn is_curseforge(path: &Path) -> Result<bool, IndexError> {
+    let zip = ZipArchive::new(File::open(path)?)?;
+    let names: Vec<&str> = zip.file_names().collect();
+    Ok(names.contains(&MANIFEST_FILE_NAME) && !names.contains(&"modrinth.index.json"))
+}
+
+/// Loads a CurseForge export, resolving its project and file ids through
+/// the API with `key`. The API gives SHA-1 but no SHA-512, so each file
+/// is downloaded once here to fill in the hashes an [`MRFile`] needs.
+pub async fn load(client: &Client, path: &Path, key: Option<&str>) -> Result<Pack, IndexError> {
+    let (manifest, overrides) = read_zip(path)?;
+
+    let mut dependencies = HashMap::new();
+    dependencies.insert(
+        DependencyId::Minecraft,
+        version(&manifest.minecraft.version)?,
+    );
+    for loader in &manifest.minecraft.mod_loaders {
+        let (name, version) = loader
+            .id
+            .split_once('-')
+            .ok_or_else(|| format!("unrecognized mod loader `{}`", loader.id))?;
+        let id = match name {
+            "forge" => DependencyId::Forge,
+            "neoforge" => DependencyId::Neoforge,
+            "fabric" => DependencyId::FabricLoader,
+            "quilt" => DependencyId::QuiltLoader,
+            other => DependencyId::Other(other.to_string()),
+        };
+        dependencies.insert(id, self::version(version)?);
+    }
+
+    let files = if manifest.files.is_empty() {
+        Vec::new()
+    } else {
+        let key = key.ok_or_else(|| {
+            format!(
+                "{} is a CurseForge pack: set {} or pass --curseforge-key to resolve its files",
+                path.display(),
+                KEY_ENV
+            )
+        })?;
+        resolve_files(client, key, &manifest.files).await?
+    };
+
+    let index = MRIndex {
+        game: "minecraft".to_string(),
+        format_version: 1,
+        version_id: manifest.version,
+        name: manifest.name,
+        summary: None,
+        files,
+        dependencies,
+    };
+    Pack::new(index, overrides)
+}
+
+fn version(version: &str) -> Result<semver::Version, IndexError> {
+    parse_dependency_version(version)
+        .map_err(|err| format!("invalid version `{}`: {}", version, err).into())
+}
+
+fn read_zip(path: &Path) -> Result<(Manifest, HashMap<PathBuf, Vec<u8>>), IndexError> {
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+    let manifest: Manifest = {
+        let mut file = zip.by_name(MANIFEST_FILE_NAME)?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        serde_json::from_slice(&buf)?
+    };
+
+    let prefix = format!("{}/", manifest.overrides.trim_end_matches('/'));
+    let mut overrides = HashMap::new();
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().replace('\\', "/");
+        let Some(path) = name.strip_prefix(&prefix).map(PathBuf::from) else {
+            continue;
+        };
+        if path.as_os_str().is_empty() {
+            continue;
+        }
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        overrides.insert(path, buf);
+    }
+    Ok((manifest, overrides))
+}
+
+async fn resolve_files(
+    client: &Client,
+    key: &str,
+    entries: &[ManifestFile],
+) -> Result<Vec<MRFile>, IndexError> {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct FilesRequest<'a> {
+        file_ids: &'a [u64],
+    }
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ModsRequest<'a> {
+        mod_ids: &'a [u64],
+    }
+
+    let file_ids: Vec<u64> = entries.iter().map(|entry| entry.file_id).collect();
+    let files: HashMap<u64, CurseForgeFile> = post::<_, Vec<CurseForgeFile>>(
+        client,
+        key,
+        "mods/files",
+        &FilesRequest {
+            file_ids: &file_ids,
+        },
+    )
+    .await?
+    .into_iter()
+    .map(|file| (file.id, file))
+    .collect();
+    let mod_ids: Vec<u64> = entries
+        .iter()
+        .map(|entry| entry.project_id)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let mods: HashMap<u64, CurseForgeMod> =
+        post::<_, Vec<CurseForgeMod>>(client, key, "mods", &ModsRequest { mod_ids: &mod_ids })
+            .await?
+            .into_iter()
+            .map(|project| (project.id, project))
+            .collect();
+
+    let mut resolved = Vec::new();
+    let mut blocked = Vec::new();
+    for entry in entries {
+        let file = files.get(&entry.file_id).ok_or_else(|| {
+            format!(
+                "CurseForge has no file {} (project {})",
+                entry.file_id, entry.project_id
+            )
+        })?;
+        let project = mods.get(&file.mod_id);
+        match &file.download_url {
+            Some(url) => resolved.push((entry, file, project, url.clone())),
+            None => blocked.push(match project {
+                Some(project) => format!(
+                    "{} ({})",
+                    project.name,
+                    project
+                        .links
+                        .as_ref()
+                        .and_then(|links| links.website_url.as_deref())
+                        .unwrap_or("no website")
+                ),
+                None => file.file_name.clone(),
+            }),
+        }
+    }
+    if !blocked.is_empty() {
+        return Err(format!(
+            "these files can only be downloaded through CurseForge: {}",
+            blocked.join(", ")
+        )
+        .into());
+    }
+
+    stream::iter(
+        resolved
+            .into_iter()
+            .map(|(entry, file, project, url)| async move {
+                let dir = project
+                    .and_then(|project| project.class_id)
+                    .and_then(|class| CLASS_DIRS.iter().find(|(id, _)| *id == class))
+                    .map_or("mods", |(_, dir)| dir);
+                let path = Path::new(dir).join(&file.file_name);
+
+                let data = http::get(client, &url, None)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?;
+                let sha1: [u8; 20] = Sha1::digest(&data).into();
+                if let Some(expected) = file.hashes.iter().find(|hash| hash.algo == 1)
+                    && !hex::encode(sha1).eq_ignore_ascii_case(&expected.value)
+                {
+                    return Err(format!(
+                        "{}: content from {} does not match its SHA-1",
+                        path.display(),
+                        url
+                    )
+                    .into());
+                }
+
+                Ok::<_, IndexError>(MRFile {
+                    env: environment(entry, file),
+                    hashes: FileHashes {
+                        sha1,
+                        sha512: Sha512::digest(&data).into(),
+                        other_hashes: HashMap::new(),
+                    },
+                    downloads: vec![url],
+                    file_size: data.len() as u64,
+                    path,
+                })
+            }),
+    )
+    .buffered(LOAD_CONCURRENCY)
+    .try_collect()
+    .await
+}
+
+/// A file is required on both sides unless the manifest marks it optional
+/// or CurseForge tags it for only one side.
+fn environment(entry: &ManifestFile, file: &CurseForgeFile) -> Option<Environment> {
+    let wanted = if entry.required {
+        Requirement::Required
+    } else {
+        Requirement::Optional
+    };
+    let tagged = |side: &str| file.game_versions.iter().any(|v| v == side);
+    let (client, server) = match (tagged("Client"), tagged("Server")) {
+        (true, false) => (wanted, Requirement::Unsupported),
+        (false, true) => (Requirement::Unsupported, wanted),
+        _ if entry.required => return None,
+        _ => (wanted, wanted),
+    };
+    Some(Environment { client, server })
+}
+
+async fn post<B: Serialize, T: DeserializeOwned>(
+    client: &Client,
+    key: &str,
+    path: &str,
+    body: &B,
+) -> Result<T, IndexError> {
+    let url = Url::parse(API_BASE)?.join(path)?;
+    let response = client
+        .post(url)
+        .header("x-api-key", key)
+        .json(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.json::<DataResponse<T>>().await?.data)
+}
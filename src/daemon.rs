@@ -0,0 +1,764 @@
+use crate::{
+    Action, CliError, EXIT_USAGE, SyncArgs, check_unattended, exit_code, load_config, log_event,
+    log_summary, notify, notify_sync, pack_label, prepare_server, prepare_sync, record_sync,
+    report_failure, restore_server, run_deploy, target_dir, write_metrics,
+};
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{Query, State},
+    http::{
+        HeaderMap, StatusCode,
+        header::{AUTHORIZATION, CONTENT_TYPE},
+    },
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use hmac::{Hmac, Mac};
+use observe_rs::{
+    PackSource, SyncSummary, VerifyReport,
+    config::Schedules,
+    errors::error_chain,
+    http,
+    lockfile::Lockfile,
+    metrics::Metrics,
+    modrinth::{ModrinthClient, ModrinthSource},
+    notify::{Notification, Notifier},
+    remote::DeployReport,
+    schedule::CronSchedule,
+    systemd,
+    util::rfc3339_timestamp,
+};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+use tracing::{error, info, warn};
+
+/// Where `observe daemon` listens unless told otherwise.
+pub(crate) const DEFAULT_DAEMON_LISTEN: &str = "127.0.0.1:8420";
+
+/// Environment variable holding the bearer token the daemon's API requires.
+pub(crate) const CONTROL_TOKEN_ENV: &str = "OBSERVE_CONTROL_TOKEN";
+
+/// Environment variable holding the secret webhook calls are signed with.
+pub(crate) const WEBHOOK_SECRET_ENV: &str = "OBSERVE_WEBHOOK_SECRET";
+
+/// Header carrying a webhook body's signature, as GitHub sends it:
+/// `sha256=` and the hex HMAC-SHA256 of the body under the secret.
+const WEBHOOK_SIGNATURE_HEADER: &str = "x-hub-signature-256";
+
+struct DaemonState {
+    /// Flags every run starts from; `observe.toml` is re-read each time, so
+    /// edits apply without a restart.
+    args: SyncArgs,
+    token: Option<String>,
+    webhook_secret: Option<String>,
+    status: std::sync::Mutex<DaemonStatus>,
+    metrics: std::sync::Mutex<Metrics>,
+    /// Where metrics are written after each run; read at start, like the
+    /// schedules.
+    metrics_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct DaemonStatus {
+    activity: DaemonActivity,
+    last_sync: Option<DaemonRun>,
+    last_verify: Option<DaemonRun>,
+    /// When each scheduled job next runs, by job.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    next_runs: BTreeMap<&'static str, String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum DaemonActivity {
+    #[default]
+    Idle,
+    Syncing,
+    Verifying,
+}
+
+impl DaemonActivity {
+    fn as_str(self) -> &'static str {
+        match self {
+            DaemonActivity::Idle => "idle",
+            DaemonActivity::Syncing => "syncing",
+            DaemonActivity::Verifying => "verifying",
+        }
+    }
+}
+
+/// What the daemon can run on a schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DaemonJob {
+    Sync,
+    Verify,
+    /// Syncs to the newest version of the installed Modrinth pack, if it
+    /// isn't installed already.
+    Update,
+}
+
+impl DaemonJob {
+    fn as_str(self) -> &'static str {
+        match self {
+            DaemonJob::Sync => "sync",
+            DaemonJob::Verify => "verify",
+            DaemonJob::Update => "update",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DaemonRun {
+    started: String,
+    finished: String,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<SyncSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deployed: Option<DeployReport>,
+    /// Files that differ from the pack, for verify runs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drifted: Option<usize>,
+}
+
+type ApiResponse = (StatusCode, Json<serde_json::Value>);
+
+fn api_error(status: StatusCode, message: impl Display) -> ApiResponse {
+    (
+        status,
+        Json(serde_json::json!({ "error": message.to_string() })),
+    )
+}
+
+/// The control API's endpoints.
+fn routes(state: Arc<DaemonState>) -> Router {
+    Router::new()
+        .route("/status", get(daemon_status))
+        .route("/lock", get(daemon_lock))
+        .route("/metrics", get(daemon_metrics))
+        .route("/sync", post(daemon_sync))
+        .route("/verify", post(daemon_verify))
+        .route("/webhook", post(daemon_webhook))
+        .with_state(state)
+}
+
+/// Serves the control API on `listen` and runs `schedules` until
+/// interrupted.
+pub(crate) async fn daemon(
+    listen: SocketAddr,
+    control_token: Option<String>,
+    webhook_secret: Option<String>,
+    schedules: Schedules,
+    args: SyncArgs,
+) -> Result<(), CliError> {
+    let config = load_config(args.config.as_deref())?;
+    check_unattended(&args, &config).map_err(|err| CliError::new(EXIT_USAGE, err))?;
+    // Flags win over `observe.toml`, which is only read for these at start.
+    let jobs = [
+        (DaemonJob::Sync, schedules.sync.or(config.schedule.sync)),
+        (
+            DaemonJob::Verify,
+            schedules.verify.or(config.schedule.verify),
+        ),
+        (
+            DaemonJob::Update,
+            schedules.update.or(config.schedule.update),
+        ),
+    ];
+
+    let state = Arc::new(DaemonState {
+        args: args.clone(),
+        token: control_token,
+        webhook_secret,
+        status: Default::default(),
+        metrics: Default::default(),
+        metrics_file: args.metrics_file.clone().or(config.metrics_file.clone()),
+    });
+    let app = routes(state.clone());
+
+    let (listener, activated) = match systemd::activated_listener()? {
+        Some(listener) => (tokio::net::TcpListener::from_std(listener)?, true),
+        None => {
+            let listener = tokio::net::TcpListener::bind(listen)
+                .await
+                .map_err(|err| format!("failed to listen on {}: {}", listen, err))?;
+            (listener, false)
+        }
+    };
+    let local = listener.local_addr()?;
+    if state.token.is_none() && !local.ip().is_loopback() {
+        return Err(CliError::new(
+            EXIT_USAGE,
+            format!(
+                "set --control-token (or {}) to listen on {}: anyone who can reach it could sync the server",
+                CONTROL_TOKEN_ENV, local
+            ),
+        ));
+    }
+    if activated {
+        info!(address = %local, "Listening on http://{} (socket from systemd)", local);
+    } else {
+        info!(address = %local, "Listening on http://{}", local);
+    }
+    for (job, schedule) in jobs {
+        if let Some(schedule) = schedule {
+            info!(job = job.as_str(), %schedule, "Scheduled {}: {}", job.as_str(), schedule);
+            tokio::spawn(schedule_loop(state.clone(), job, schedule));
+        }
+    }
+    if let Some(interval) = systemd::watchdog_interval() {
+        tokio::spawn(async move {
+            loop {
+                systemd::notify_watchdog();
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+    systemd::notify_status(DaemonActivity::Idle.as_str());
+    systemd::notify_ready();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            shutdown_signal().await;
+            systemd::notify_stopping();
+        })
+        .await?;
+    Ok(())
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM, which is how systemd stops services.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            },
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Runs `job` each time `schedule` fires. A run that is still going when
+/// the schedule next fires makes that firing a no-op.
+async fn schedule_loop(state: Arc<DaemonState>, job: DaemonJob, schedule: CronSchedule) {
+    loop {
+        let Some(next) = schedule.next_after(SystemTime::now()) else {
+            warn!(
+                job = job.as_str(),
+                "the {} schedule `{}` never fires again",
+                job.as_str(),
+                schedule
+            );
+            state
+                .status
+                .lock()
+                .expect("daemon status lock poisoned")
+                .next_runs
+                .remove(job.as_str());
+            return;
+        };
+        state
+            .status
+            .lock()
+            .expect("daemon status lock poisoned")
+            .next_runs
+            .insert(job.as_str(), rfc3339_timestamp(next));
+        tokio::time::sleep(next.duration_since(SystemTime::now()).unwrap_or_default()).await;
+
+        let activity = match job {
+            DaemonJob::Verify => DaemonActivity::Verifying,
+            DaemonJob::Sync | DaemonJob::Update => DaemonActivity::Syncing,
+        };
+        if let Err(busy) = start_activity(&state, activity) {
+            info!(
+                job = job.as_str(),
+                "Skipping scheduled {}: already {}",
+                job.as_str(),
+                busy.as_str()
+            );
+            continue;
+        }
+        info!(job = job.as_str(), "Scheduled {} started", job.as_str());
+        match job {
+            DaemonJob::Sync => {
+                let _ = spawn_sync(state.clone(), false).await;
+            }
+            DaemonJob::Update => {
+                let _ = spawn_sync(state.clone(), true).await;
+            }
+            DaemonJob::Verify => match run_verify(&state).await {
+                Ok(report) => info!("{}", report),
+                Err(err) => error!("{}", err.error),
+            },
+        }
+    }
+}
+
+/// Runs the future `task` makes on a blocking thread of its own. `CliError`
+/// is `Send`; what isn't provably so is the download and resolve streams
+/// (`ModManager::stage`, packwiz and CurseForge loading) whose closures
+/// borrow their items. Nested in a larger future, rustc asks for those to be
+/// `Send` for every lifetime and gives up ("implementation of `Send` is not
+/// general enough"), so these futures can't go through `tokio::spawn`.
+pub(crate) fn run_detached<F, T>(
+    task: impl FnOnce() -> F + Send + 'static,
+) -> tokio::task::JoinHandle<T>
+where
+    F: Future<Output = T>,
+    T: Send + 'static,
+{
+    let runtime = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || runtime.block_on(task()))
+}
+
+/// Checks the request's bearer token, when the daemon has one.
+fn authorize(state: &DaemonState, headers: &HeaderMap) -> Result<(), ApiResponse> {
+    let Some(token) = &state.token else {
+        return Ok(());
+    };
+    let given = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // Compared in full whatever differs, so timing doesn't leak the token.
+    let matches = given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    if matches {
+        Ok(())
+    } else {
+        Err(api_error(
+            StatusCode::UNAUTHORIZED,
+            "missing or wrong bearer token",
+        ))
+    }
+}
+
+/// Marks the daemon busy with `activity`, unless it already is, in which
+/// case returns what it is busy with.
+fn start_activity(state: &DaemonState, activity: DaemonActivity) -> Result<(), DaemonActivity> {
+    let mut status = state.status.lock().expect("daemon status lock poisoned");
+    match status.activity {
+        DaemonActivity::Idle => {
+            set_activity(&mut status, activity);
+            Ok(())
+        }
+        busy => Err(busy),
+    }
+}
+
+/// Records what the daemon is doing, in its status and for systemd.
+fn set_activity(status: &mut DaemonStatus, activity: DaemonActivity) {
+    status.activity = activity;
+    systemd::notify_status(activity.as_str());
+}
+
+fn busy_error(busy: DaemonActivity) -> ApiResponse {
+    api_error(StatusCode::CONFLICT, format!("already {}", busy.as_str()))
+}
+
+async fn daemon_status(State(state): State<Arc<DaemonState>>, headers: HeaderMap) -> ApiResponse {
+    if let Err(response) = authorize(&state, &headers) {
+        return response;
+    }
+    let status = state
+        .status
+        .lock()
+        .expect("daemon status lock poisoned")
+        .clone();
+    (StatusCode::OK, Json(serde_json::json!(status)))
+}
+
+async fn daemon_lock(State(state): State<Arc<DaemonState>>, headers: HeaderMap) -> ApiResponse {
+    if let Err(response) = authorize(&state, &headers) {
+        return response;
+    }
+    let config = match load_config(state.args.config.as_deref()) {
+        Ok(config) => config,
+        Err(err) => return api_error(StatusCode::INTERNAL_SERVER_ERROR, err),
+    };
+    match Lockfile::load(&target_dir(&state.args, &config)) {
+        Ok(Some(lock)) => (StatusCode::OK, Json(serde_json::json!(lock))),
+        Ok(None) => api_error(StatusCode::NOT_FOUND, "the server has never been synced"),
+        Err(err) => api_error(StatusCode::INTERNAL_SERVER_ERROR, err),
+    }
+}
+
+/// Serves the sync and verify metrics for Prometheus to scrape.
+async fn daemon_metrics(State(state): State<Arc<DaemonState>>, headers: HeaderMap) -> Response {
+    if let Err(response) = authorize(&state, &headers) {
+        return response.into_response();
+    }
+    let metrics = state
+        .metrics
+        .lock()
+        .expect("daemon metrics lock poisoned")
+        .render();
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        metrics,
+    )
+        .into_response()
+}
+
+/// Starts a sync in the background; poll `/status` for how it went.
+async fn daemon_sync(State(state): State<Arc<DaemonState>>, headers: HeaderMap) -> ApiResponse {
+    if let Err(response) = authorize(&state, &headers) {
+        return response;
+    }
+    if let Err(busy) = start_activity(&state, DaemonActivity::Syncing) {
+        return busy_error(busy);
+    }
+    info!(job = "sync", "Sync started");
+    spawn_sync(state, false);
+    (
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({ "activity": DaemonActivity::Syncing })),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookQuery {
+    /// `sync` (default) or `update`.
+    job: Option<String>,
+}
+
+/// Starts a sync, or with `?job=update` an update of the installed Modrinth
+/// pack, for CI or release automation. Signed with the webhook secret
+/// rather than the bearer token, since senders like GitHub can't send one.
+async fn daemon_webhook(
+    State(state): State<Arc<DaemonState>>,
+    headers: HeaderMap,
+    Query(query): Query<WebhookQuery>,
+    body: Bytes,
+) -> ApiResponse {
+    let Some(secret) = &state.webhook_secret else {
+        return api_error(
+            StatusCode::NOT_FOUND,
+            "webhooks are off: start the daemon with --webhook-secret",
+        );
+    };
+    if let Err(response) = check_signature(secret, &headers, &body) {
+        return response;
+    }
+    let job = match query.job.as_deref() {
+        None | Some("sync") => DaemonJob::Sync,
+        Some("update") => DaemonJob::Update,
+        Some(other) => {
+            return api_error(
+                StatusCode::BAD_REQUEST,
+                format!("unknown job `{}`: expected sync or update", other),
+            );
+        }
+    };
+    // GitHub checks a new webhook with a ping; answering is enough.
+    if headers
+        .get("x-github-event")
+        .is_some_and(|event| event == "ping")
+    {
+        return (StatusCode::OK, Json(serde_json::json!({ "pong": true })));
+    }
+    if let Err(busy) = start_activity(&state, DaemonActivity::Syncing) {
+        return busy_error(busy);
+    }
+    info!(job = job.as_str(), "Webhook {} started", job.as_str());
+    spawn_sync(state, job == DaemonJob::Update);
+    (
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({ "activity": DaemonActivity::Syncing, "job": job.as_str() })),
+    )
+}
+
+/// Checks that `body` carries a valid signature under `secret`.
+fn check_signature(secret: &str, headers: &HeaderMap, body: &[u8]) -> Result<(), ApiResponse> {
+    let signature = headers
+        .get(WEBHOOK_SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("sha256="))
+        .and_then(|hex| hex::decode(hex).ok());
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    // verify_slice compares in constant time.
+    match signature {
+        Some(signature) if mac.verify_slice(&signature).is_ok() => Ok(()),
+        _ => Err(api_error(
+            StatusCode::UNAUTHORIZED,
+            "missing or wrong X-Hub-Signature-256 signature",
+        )),
+    }
+}
+
+/// Syncs in the background once the daemon is marked syncing, recording
+/// how it went in `last_sync`. With `update`, only syncs if the installed
+/// Modrinth pack has a newer version, and then to that version.
+fn spawn_sync(state: Arc<DaemonState>, update: bool) -> tokio::task::JoinHandle<()> {
+    run_detached(move || async move {
+        let started = rfc3339_timestamp(SystemTime::now());
+        let result = if update {
+            daemon_run_update(&state).await
+        } else {
+            daemon_run_sync(&state, None).await.map(Some)
+        };
+        let run = match result {
+            Ok(Some((summary, deployed))) => {
+                log_summary(&summary);
+                if let Some(deployed) = &deployed {
+                    info!("{}", deployed);
+                }
+                Some(DaemonRun {
+                    started,
+                    finished: rfc3339_timestamp(SystemTime::now()),
+                    ok: true,
+                    error: None,
+                    summary: Some(summary),
+                    deployed,
+                    drifted: None,
+                })
+            }
+            // Nothing to update to, so nothing ran.
+            Ok(None) => None,
+            Err(err) => {
+                error!("{}", err.error);
+                Some(DaemonRun {
+                    started,
+                    finished: rfc3339_timestamp(SystemTime::now()),
+                    ok: false,
+                    error: Some(err.error.to_string()),
+                    summary: None,
+                    deployed: None,
+                    drifted: None,
+                })
+            }
+        };
+        let mut status = state.status.lock().expect("daemon status lock poisoned");
+        set_activity(&mut status, DaemonActivity::Idle);
+        if run.is_some() {
+            status.last_sync = run;
+        }
+    })
+}
+
+async fn daemon_run_sync(
+    daemon: &DaemonState,
+    source: Option<PackSource>,
+) -> Result<(SyncSummary, Option<DeployReport>), CliError> {
+    let args = &daemon.args;
+    let config = load_config(args.config.as_deref())?;
+    check_unattended(args, &config).map_err(|err| CliError::new(EXIT_USAGE, err))?;
+    let prepared = prepare_sync(args.clone(), config, source, Action::Sync).await?;
+    let label = pack_label(&prepared.pack);
+    let control = prepared.control.as_ref();
+    let state = prepare_server(control, &prepared.syncer, &prepared.pack).await?;
+    let syncer = prepared.syncer.on_progress(log_event);
+    let started = Instant::now();
+    let result = syncer.sync(prepared.pack).await;
+    restore_server(control, state).await;
+    record_sync(
+        &mut daemon.metrics.lock().expect("daemon metrics lock poisoned"),
+        daemon.metrics_file.as_deref(),
+        started,
+        &result,
+    );
+    notify_sync(prepared.notifier.as_ref(), &label, &result).await;
+    let report = result.map_err(|err| {
+        let message = format!("Sync failed: {}", error_chain(&err));
+        CliError::new(exit_code(err.category()), message)
+    })?;
+    if !report.is_success() {
+        return Err(report_failure(&report));
+    }
+    let deployed = match prepared.deploy {
+        Some(deploy) => Some(run_deploy(deploy).await?),
+        None => None,
+    };
+    Ok((report.summary, deployed))
+}
+
+/// Syncs to the newest version of the installed Modrinth pack, like
+/// `observe update`; `None` when there is nothing newer.
+async fn daemon_run_update(
+    daemon: &DaemonState,
+) -> Result<Option<(SyncSummary, Option<DeployReport>)>, CliError> {
+    let args = &daemon.args;
+    let config = load_config(args.config.as_deref())?;
+    let dir = target_dir(args, &config);
+    let Some(installed) = ModrinthSource::load(&dir)? else {
+        info!(
+            "No installed Modrinth pack recorded in {}; nothing to update",
+            dir.display()
+        );
+        return Ok(None);
+    };
+    let modrinth = ModrinthClient::new(http::client()).with_token(args.api_token.clone());
+    let (project, latest) = modrinth.resolve_modpack(&installed.slug, None).await?;
+    if installed.project_id == project.id && installed.version_id == latest.id {
+        info!(
+            version = latest.version_number,
+            "{} is up to date ({})", project.title, latest.version_number
+        );
+        return Ok(None);
+    }
+    let file = latest.mrpack_file().ok_or_else(|| {
+        format!(
+            "{} {} has no .mrpack file",
+            project.title, latest.version_number
+        )
+    })?;
+
+    let notification = Notification::update_available(
+        &project.title,
+        &installed.version_number,
+        &latest.version_number,
+    );
+    let notifier = Notifier::new(&config.notify)?.filter(|_| !args.dry_run);
+    notify(notifier.as_ref(), notification).await;
+    info!(
+        from = installed.version_number,
+        to = latest.version_number,
+        "Updating {}: {} -> {}",
+        project.title,
+        installed.version_number,
+        latest.version_number
+    );
+    let synced = daemon_run_sync(daemon, Some(PackSource::Url(file.url.clone()))).await?;
+    if !args.dry_run {
+        ModrinthSource::new(&project, &latest).save(&dir)?;
+    }
+    Ok(Some(synced))
+}
+
+/// Checks the server against the pack and answers with how it differs.
+async fn daemon_verify(State(state): State<Arc<DaemonState>>, headers: HeaderMap) -> ApiResponse {
+    if let Err(response) = authorize(&state, &headers) {
+        return response;
+    }
+    if let Err(busy) = start_activity(&state, DaemonActivity::Verifying) {
+        return busy_error(busy);
+    }
+    match run_verify(&state).await {
+        Ok(report) => (StatusCode::OK, Json(serde_json::json!(report))),
+        Err(err) => api_error(StatusCode::INTERNAL_SERVER_ERROR, &err.error),
+    }
+}
+
+/// Verifies once the daemon is marked verifying, recording how it went in
+/// `last_verify`.
+async fn run_verify(state: &DaemonState) -> Result<VerifyReport, CliError> {
+    let started = rfc3339_timestamp(SystemTime::now());
+    let args = state.args.clone();
+    let result = run_detached(move || async move {
+        let config = load_config(args.config.as_deref())?;
+        let prepared = prepare_sync(args, config, None, Action::Verify).await?;
+        let label = pack_label(&prepared.pack);
+        let report = prepared
+            .syncer
+            .verify(prepared.pack)
+            .await
+            .map_err(|err| CliError::new(exit_code(err.category()), err))?;
+        if !report.is_clean() {
+            let notification = Notification::drift(&label, &report);
+            notify(prepared.notifier.as_ref(), notification).await;
+        }
+        Ok::<_, CliError>(report)
+    })
+    .await
+    .unwrap_or_else(|err| Err(err.to_string().into()));
+
+    let finished = rfc3339_timestamp(SystemTime::now());
+    let run = match &result {
+        Ok(report) => DaemonRun {
+            started,
+            finished,
+            ok: true,
+            error: None,
+            summary: None,
+            deployed: None,
+            drifted: Some(report.missing.len() + report.modified.len() + report.extra.len()),
+        },
+        Err(err) => DaemonRun {
+            started,
+            finished,
+            ok: false,
+            error: Some(err.error.to_string()),
+            summary: None,
+            deployed: None,
+            drifted: None,
+        },
+    };
+    let mut metrics = state.metrics.lock().expect("daemon metrics lock poisoned");
+    metrics.record_verify(result.as_ref().ok());
+    write_metrics(&metrics, state.metrics_file.as_deref());
+    drop(metrics);
+
+    let mut status = state.status.lock().expect("daemon status lock poisoned");
+    set_activity(&mut status, DaemonActivity::Idle);
+    status.last_verify = Some(run);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    const SECRET: &str = "It's a Secret to Everybody";
+    const BODY: &[u8] = b"Hello, World!";
+
+    fn signed(signature: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            WEBHOOK_SIGNATURE_HEADER,
+            HeaderValue::from_str(signature).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn valid_signature_is_accepted() {
+        // GitHub's documented example for this secret and body.
+        let headers =
+            signed("sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17");
+        assert!(check_signature(SECRET, &headers, BODY).is_ok());
+    }
+
+    #[test]
+    fn wrong_signature_is_refused() {
+        let headers =
+            signed("sha256=0000000000000000000000000000000000000000000000000000000000000000");
+        let (status, _) = check_signature(SECRET, &headers, BODY).unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let other_body =
+            signed("sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17");
+        assert!(check_signature(SECRET, &other_body, b"Hello, World?").is_err());
+    }
+
+    #[test]
+    fn missing_or_malformed_signature_is_refused() {
+        let (status, _) = check_signature(SECRET, &HeaderMap::new(), BODY).unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert!(check_signature(SECRET, &signed("sha1=abcdef"), BODY).is_err());
+        assert!(check_signature(SECRET, &signed("sha256=not hex"), BODY).is_err());
+    }
+}
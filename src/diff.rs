@@ -0,0 +1,227 @@
+use crate::modrinth::project_id_from_url;
+use crate::mrpack::{DependencyId, MRFile};
+use crate::pack::Pack;
+use semver::Version;
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    path::PathBuf,
+};
+
+/// What changes between two versions of a pack.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PackDiff {
+    pub old_version: String,
+    pub new_version: String,
+    pub added: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    /// Index files whose content changed. A file replaced by one at a new
+    /// path is paired with it when both download from the same Modrinth
+    /// project, as when a mod's jar name carries its version.
+    pub changed: Vec<FileChange>,
+    pub overrides_added: Vec<PathBuf>,
+    pub overrides_removed: Vec<PathBuf>,
+    pub overrides_changed: Vec<PathBuf>,
+    pub dependencies: Vec<DependencyChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct FileChange {
+    pub old: PathBuf,
+    pub new: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyChange {
+    pub id: DependencyId,
+    /// `None` when the dependency was added.
+    pub old: Option<Version>,
+    /// `None` when the dependency was dropped.
+    pub new: Option<Version>,
+}
+
+impl PackDiff {
+    pub fn new(old: &Pack, new: &Pack) -> Self {
+        let mut diff = PackDiff {
+            old_version: old.index.version_id.clone(),
+            new_version: new.index.version_id.clone(),
+            ..Default::default()
+        };
+
+        let old_files: HashMap<&PathBuf, &MRFile> =
+            old.index.files.iter().map(|f| (&f.path, f)).collect();
+        let new_files: HashMap<&PathBuf, &MRFile> =
+            new.index.files.iter().map(|f| (&f.path, f)).collect();
+
+        let mut added: Vec<&MRFile> = Vec::new();
+        for file in &new.index.files {
+            match old_files.get(&file.path) {
+                Some(before) if before.hashes.sha512 != file.hashes.sha512 => {
+                    diff.changed.push(FileChange {
+                        old: file.path.clone(),
+                        new: file.path.clone(),
+                    });
+                }
+                Some(_) => {}
+                None => added.push(file),
+            }
+        }
+        let mut removed: Vec<&MRFile> = old
+            .index
+            .files
+            .iter()
+            .filter(|f| !new_files.contains_key(&f.path))
+            .collect();
+
+        for file in added {
+            let paired = project_id(file).and_then(|id| {
+                removed
+                    .iter()
+                    .position(|before| project_id(before) == Some(id))
+            });
+            match paired {
+                Some(i) => {
+                    let before = removed.remove(i);
+                    diff.changed.push(FileChange {
+                        old: before.path.clone(),
+                        new: file.path.clone(),
+                    });
+                }
+                None => diff.added.push(file.path.clone()),
+            }
+        }
+        diff.removed = removed.into_iter().map(|f| f.path.clone()).collect();
+
+        for (path, content) in &new.overrides {
+            match old.overrides.get(path) {
+                Some(before) if before != content => diff.overrides_changed.push(path.clone()),
+                Some(_) => {}
+                None => diff.overrides_added.push(path.clone()),
+            }
+        }
+        diff.overrides_removed = old
+            .overrides
+            .keys()
+            .filter(|path| !new.overrides.contains_key(*path))
+            .cloned()
+            .collect();
+
+        let ids: BTreeSet<&str> = old
+            .index
+            .dependencies
+            .keys()
+            .chain(new.index.dependencies.keys())
+            .map(DependencyId::as_str)
+            .collect();
+        for id in ids {
+            let find = |pack: &Pack| {
+                pack.index
+                    .dependencies
+                    .iter()
+                    .find(|(dep, _)| dep.as_str() == id)
+                    .map(|(dep, version)| (dep.clone(), version.clone()))
+            };
+            let (before, after) = (find(old), find(new));
+            if before.as_ref().map(|(_, v)| v) == after.as_ref().map(|(_, v)| v) {
+                continue;
+            }
+            let dep = before
+                .as_ref()
+                .or(after.as_ref())
+                .map(|(dep, _)| dep.clone())
+                .expect("id comes from one of the packs");
+            diff.dependencies.push(DependencyChange {
+                id: dep,
+                old: before.map(|(_, v)| v),
+                new: after.map(|(_, v)| v),
+            });
+        }
+
+        diff.sort();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.overrides_added.is_empty()
+            && self.overrides_removed.is_empty()
+            && self.overrides_changed.is_empty()
+            && self.dependencies.is_empty()
+    }
+
+    fn sort(&mut self) {
+        self.added.sort();
+        self.removed.sort();
+        self.changed.sort();
+        self.overrides_added.sort();
+        self.overrides_removed.sort();
+        self.overrides_changed.sort();
+    }
+}
+
+fn project_id(file: &MRFile) -> Option<&str> {
+    file.downloads.iter().find_map(project_id_from_url)
+}
+
+impl Display for DependencyChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) => write!(f, "{} {} -> {}", self.id, old, new),
+            (None, Some(new)) => write!(f, "{} {} (added)", self.id, new),
+            (Some(old), None) => write!(f, "{} {} (removed)", self.id, old),
+            (None, None) => write!(f, "{}", self.id),
+        }
+    }
+}
+
+impl Display for FileChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.old == self.new {
+            write!(f, "{}", self.new.display())
+        } else {
+            write!(f, "{} -> {}", self.old.display(), self.new.display())
+        }
+    }
+}
+
+impl Display for PackDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -> {}", self.old_version, self.new_version)?;
+        if self.is_empty() {
+            return write!(f, "\nNo differences");
+        }
+
+        if !self.dependencies.is_empty() {
+            write!(f, "\nDependencies ({}):", self.dependencies.len())?;
+            for dep in &self.dependencies {
+                write!(f, "\n  {}", dep)?;
+            }
+        }
+
+        let paths = |paths: &[PathBuf]| -> Vec<String> {
+            paths.iter().map(|p| p.display().to_string()).collect()
+        };
+        let changed: Vec<String> = self.changed.iter().map(ToString::to_string).collect();
+        let sections = [
+            ("Added", "+", paths(&self.added)),
+            ("Removed", "-", paths(&self.removed)),
+            ("Changed", "~", changed),
+            ("Overrides added", "+", paths(&self.overrides_added)),
+            ("Overrides removed", "-", paths(&self.overrides_removed)),
+            ("Overrides changed", "~", paths(&self.overrides_changed)),
+        ];
+        for (label, marker, entries) in sections {
+            if entries.is_empty() {
+                continue;
+            }
+            write!(f, "\n{} ({}):", label, entries.len())?;
+            for entry in entries {
+                write!(f, "\n  {} {}", marker, entry)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,409 @@
+use crate::duplicates::find_duplicates;
+use crate::java::{find_java, required_java};
+use crate::loader::ServerTarget;
+use crate::mod_dependencies::missing_dependencies;
+use crate::modrinth::ModrinthClient;
+use crate::mrpack::Requirement;
+use crate::pack::{Pack, PackSource};
+use crate::util::STATE_DIR;
+use indicatif::HumanBytes;
+use reqwest::Client;
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs, io,
+    net::ToSocketAddrs,
+    path::{Component, Path, PathBuf},
+};
+use url::Url;
+
+/// Always probed, since nearly every pack downloads from it.
+const MODRINTH_CDN: &str = "cdn.modrinth.com";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// The outcome of one preflight check, with what to do about it when it
+/// didn't pass.
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn problem(
+        status: CheckStatus,
+        name: impl Into<String>,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self.status {
+            CheckStatus::Pass => "ok",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Fail => "FAIL",
+        };
+        write!(f, "[{:>4}] {}: {}", label, self.name, self.detail)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n       {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that syncing `sources`, layered, into `root` can succeed, without
+/// changing anything: the packs open, the disk has room, the directories
+/// are writable, no mod is installed twice, required mod dependencies are
+/// present, download hosts answer and a suitable `java` is installed.
+pub async fn diagnose(
+    client: &Client,
+    sources: &[PackSource],
+    download_dir: &Path,
+    token: Option<&str>,
+    curseforge_key: Option<&str>,
+    root: &Path,
+) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let mut merged: Option<Pack> = None;
+    for source in sources {
+        match source
+            .load(client, download_dir, token, curseforge_key)
+            .await
+        {
+            Ok(pack) => {
+                checks.push(Check::pass(
+                    "Pack",
+                    format!(
+                        "{} {} ({} files, {} overrides)",
+                        pack.index.name,
+                        pack.index.version_id,
+                        pack.index.files.len(),
+                        pack.overrides.len()
+                    ),
+                ));
+                merged = Some(match merged {
+                    Some(base) => base.overlay(pack),
+                    None => pack,
+                });
+            }
+            Err(err) => {
+                checks.push(Check::problem(
+                    CheckStatus::Fail,
+                    "Pack",
+                    format!("{} could not be read: {}", source, err),
+                    "Check the path or URL, and re-download the pack if the zip is truncated",
+                ));
+                return checks;
+            }
+        }
+    }
+    let Some(pack) = merged else {
+        return checks;
+    };
+
+    let root_owned = root.to_path_buf();
+    let dirs = managed_dirs(&pack);
+    let needed = needed_bytes(&pack);
+    let local = tokio::task::spawn_blocking(move || {
+        let mut checks = vec![check_disk_space(&root_owned, needed)];
+        checks.extend(check_writable(&root_owned, &dirs));
+        checks
+    })
+    .await
+    .unwrap_or_default();
+    checks.extend(local);
+
+    checks.push(check_duplicates(&pack));
+    let modrinth = ModrinthClient::new(client.clone()).with_token(token.map(String::from));
+    checks.push(check_dependencies(&modrinth, &pack).await);
+
+    for host in download_hosts(&pack) {
+        checks.push(check_host(client, &host).await);
+    }
+
+    if let Some(target) = ServerTarget::from_index(&pack.index) {
+        let root = root.to_path_buf();
+        checks.push(
+            tokio::task::spawn_blocking(move || check_java(&root, &target))
+                .await
+                .unwrap_or_else(|err| {
+                    Check::problem(
+                        CheckStatus::Warn,
+                        "Java",
+                        err.to_string(),
+                        "Run `java -version` to check it by hand",
+                    )
+                }),
+        );
+    }
+
+    checks
+}
+
+/// Bytes the pack's server files and overrides take up once installed.
+fn needed_bytes(pack: &Pack) -> u64 {
+    let files: u64 = pack
+        .index
+        .files
+        .iter()
+        .filter(|file| file.server_requirement() != Requirement::Unsupported)
+        .map(|file| file.file_size)
+        .sum();
+    let overrides: u64 = pack.overrides.values().map(|c| c.len() as u64).sum();
+    files + overrides
+}
+
+/// Top-level directories the pack writes into, plus the state directory.
+fn managed_dirs(pack: &Pack) -> BTreeSet<PathBuf> {
+    let mut dirs: BTreeSet<PathBuf> = pack
+        .index
+        .files
+        .iter()
+        .map(|file| &file.path)
+        .chain(pack.overrides.keys())
+        .filter_map(|path| match path.components().next() {
+            Some(Component::Normal(first)) if path.components().count() > 1 => {
+                Some(PathBuf::from(first))
+            }
+            _ => None,
+        })
+        .collect();
+    dirs.insert(PathBuf::from(STATE_DIR));
+    dirs
+}
+
+fn download_hosts(pack: &Pack) -> BTreeSet<String> {
+    let mut hosts: BTreeSet<String> = pack
+        .index
+        .files
+        .iter()
+        .flat_map(|file| &file.downloads)
+        .filter_map(|url| url.host_str().map(str::to_string))
+        .collect();
+    hosts.insert(MODRINTH_CDN.to_string());
+    hosts
+}
+
+fn check_disk_space(root: &Path, needed: u64) -> Check {
+    let existing = nearest_existing(root);
+    match free_space(&existing) {
+        Ok(free) if free >= needed => Check::pass(
+            "Disk space",
+            format!(
+                "{} needed, {} free on {}",
+                HumanBytes(needed),
+                HumanBytes(free),
+                existing.display()
+            ),
+        ),
+        Ok(free) => Check::problem(
+            CheckStatus::Fail,
+            "Disk space",
+            format!(
+                "{} needed, only {} free on {}",
+                HumanBytes(needed),
+                HumanBytes(free),
+                existing.display()
+            ),
+            format!("Free at least {} before syncing", HumanBytes(needed - free)),
+        ),
+        Err(err) => Check::problem(
+            CheckStatus::Warn,
+            "Disk space",
+            format!("{} needed; free space unknown: {}", HumanBytes(needed), err),
+            "Make sure the disk has room for the pack",
+        ),
+    }
+}
+
+/// Creates and removes a probe file in the server directory (or its
+/// nearest existing parent) and in each managed directory already there.
+fn check_writable(root: &Path, dirs: &BTreeSet<PathBuf>) -> Vec<Check> {
+    let mut probe_dirs = vec![nearest_existing(root)];
+    probe_dirs.extend(
+        dirs.iter()
+            .map(|dir| root.join(dir))
+            .filter(|dir| dir.is_dir()),
+    );
+
+    probe_dirs
+        .into_iter()
+        .map(|dir| {
+            let name = format!("Write access to {}", dir.display());
+            let probe = dir.join(".observe-doctor");
+            match fs::write(&probe, b"").and_then(|_| fs::remove_file(&probe)) {
+                Ok(()) => Check::pass(name, "writable"),
+                Err(err) => Check::problem(
+                    CheckStatus::Fail,
+                    name,
+                    err.to_string(),
+                    "Run as the user that owns the server directory, or fix its permissions",
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Resolves `host` and makes an HTTPS request to it. Any HTTP response
+/// counts: only DNS, connection and TLS failures are problems.
+async fn check_host(client: &Client, host: &str) -> Check {
+    let name = format!("Reachability of {}", host);
+    let lookup = format!("{}:443", host);
+    let resolved =
+        tokio::task::spawn_blocking(move || lookup.to_socket_addrs().map(|addrs| addrs.count()))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err)));
+    match resolved {
+        Ok(0) | Err(_) => {
+            return Check::problem(
+                CheckStatus::Fail,
+                name,
+                "DNS lookup failed",
+                "Check the machine's DNS settings and network connection",
+            );
+        }
+        Ok(_) => {}
+    }
+
+    let url = Url::parse(&format!("https://{}/", host)).expect("valid host URL");
+    match client.head(url).send().await {
+        Ok(response) => Check::pass(name, format!("HTTPS {}", response.status())),
+        Err(err) => Check::problem(
+            CheckStatus::Fail,
+            name,
+            format!("HTTPS request failed: {}", err),
+            "Check firewalls and proxies between this machine and the host",
+        ),
+    }
+}
+
+fn check_duplicates(pack: &Pack) -> Check {
+    let duplicates = find_duplicates(pack);
+    if duplicates.is_empty() {
+        return Check::pass("Duplicates", "no mod is installed twice");
+    }
+    Check::problem(
+        CheckStatus::Fail,
+        "Duplicates",
+        duplicates
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; "),
+        "Leave all but one out with --skip, or fix the pack",
+    )
+}
+
+async fn check_dependencies(modrinth: &ModrinthClient, pack: &Pack) -> Check {
+    match missing_dependencies(modrinth, pack).await {
+        Ok(missing) if missing.is_empty() => Check::pass(
+            "Dependencies",
+            "every required dependency Modrinth knows of is in the pack",
+        ),
+        Ok(missing) => Check::problem(
+            CheckStatus::Warn,
+            "Dependencies",
+            missing
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; "),
+            "Add the missing mods to the pack, or to additions.toml",
+        ),
+        Err(err) => Check::problem(
+            CheckStatus::Warn,
+            "Dependencies",
+            format!("could not look up the pack's mods on Modrinth: {}", err),
+            "Check that api.modrinth.com is reachable",
+        ),
+    }
+}
+
+fn check_java(root: &Path, target: &ServerTarget) -> Check {
+    let required = required_java(&target.minecraft);
+    let installs = find_java(root);
+    match installs.first() {
+        Some(java) if java.major == required => Check::pass(
+            "Java",
+            format!("Java {} at {}", java.version, java.path.display()),
+        ),
+        Some(java) => Check::problem(
+            CheckStatus::Warn,
+            "Java",
+            format!(
+                "{} needs Java {}, but {} is Java {}",
+                target,
+                required,
+                java.path.display(),
+                java.major
+            ),
+            match installs.iter().find(|java| java.major == required) {
+                Some(other) => format!("Set JAVA={} to use it", other.path.display()),
+                None => "Sync with --java provision to download one".to_string(),
+            },
+        ),
+        None => Check::problem(
+            CheckStatus::Fail,
+            "Java",
+            format!("{} needs Java {}, but no java was found", target, required),
+            "Install it, or sync with --java provision to download one",
+        ),
+    }
+}
+
+/// `path`, or its closest ancestor that exists, since the server directory
+/// may not have been created yet.
+fn nearest_existing(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find(|dir| !dir.as_os_str().is_empty() && dir.exists())
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+}
+
+#[cfg(unix)]
+fn free_space(path: &Path) -> io::Result<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(io::Error::other)?;
+    // SAFETY: `statvfs` is plain old data, and `path` is a valid C string
+    // that outlives the call.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "not supported on this platform",
+    ))
+}
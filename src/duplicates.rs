@@ -0,0 +1,142 @@
+use crate::modrinth;
+use crate::mrpack::Requirement;
+use crate::pack::Pack;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+};
+use zip::ZipArchive;
+
+/// Mod loaders only load jars from here, so only duplicates here crash.
+const MODS_DIR: &str = "mods";
+
+/// A mod the server would load from more than one file.
+#[derive(Debug, Clone, Serialize)]
+pub struct Duplicate {
+    /// The Modrinth project id or the mod id the files share.
+    pub id: String,
+    pub kind: DuplicateKind,
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateKind {
+    /// Files downloaded from the same Modrinth project.
+    ModrinthProject,
+    /// Jars declaring the same id in `fabric.mod.json`, `quilt.mod.json`
+    /// or `mods.toml`.
+    ModId,
+}
+
+impl Display for Duplicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let paths: Vec<_> = self.paths.iter().map(|p| p.display().to_string()).collect();
+        match self.kind {
+            DuplicateKind::ModrinthProject => write!(f, "Modrinth project {}", self.id)?,
+            DuplicateKind::ModId => write!(f, "mod `{}`", self.id)?,
+        }
+        write!(f, " in {}", paths.join(", "))
+    }
+}
+
+/// Finds mods the server would load twice. Index files are matched by the
+/// Modrinth project id in their download URL, and jars among the overrides
+/// by the mod ids their metadata declares. Client-only files are ignored.
+pub fn find_duplicates(pack: &Pack) -> Vec<Duplicate> {
+    let mut seen: BTreeMap<(DuplicateKind, String), BTreeSet<PathBuf>> = BTreeMap::new();
+    for file in &pack.index.files {
+        if file.server_requirement() == Requirement::Unsupported || !file.path.starts_with(MODS_DIR)
+        {
+            continue;
+        }
+        if let Some(project) = file
+            .downloads
+            .iter()
+            .find_map(modrinth::project_id_from_url)
+        {
+            seen.entry((DuplicateKind::ModrinthProject, project.to_string()))
+                .or_default()
+                .insert(file.path.clone());
+        }
+    }
+    for (path, content) in &pack.overrides {
+        if !path.starts_with(MODS_DIR) || !is_jar(path) {
+            continue;
+        }
+        for id in mod_ids(content) {
+            seen.entry((DuplicateKind::ModId, id))
+                .or_default()
+                .insert(path.clone());
+        }
+    }
+
+    seen.into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|((kind, id), paths)| Duplicate {
+            id,
+            kind,
+            paths: paths.into_iter().collect(),
+        })
+        .collect()
+}
+
+fn is_jar(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "jar")
+}
+
+/// The mod ids a jar declares for Fabric, Quilt, Forge or NeoForge. Jars
+/// that can't be read declare none.
+pub fn mod_ids(jar: &[u8]) -> BTreeSet<String> {
+    #[derive(Deserialize)]
+    struct FabricMod {
+        id: String,
+    }
+    #[derive(Deserialize)]
+    struct QuiltMod {
+        quilt_loader: FabricMod,
+    }
+    #[derive(Deserialize)]
+    struct ForgeMods {
+        #[serde(default)]
+        mods: Vec<ForgeMod>,
+    }
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ForgeMod {
+        mod_id: String,
+    }
+
+    let mut ids = BTreeSet::new();
+    let Ok(mut zip) = ZipArchive::new(Cursor::new(jar)) else {
+        return ids;
+    };
+    let mut read = |name: &str| {
+        let mut file = zip.by_name(name).ok()?;
+        let mut content = String::new();
+        file.read_to_string(&mut content).ok()?;
+        Some(content)
+    };
+
+    if let Some(json) = read("fabric.mod.json")
+        && let Ok(fabric) = serde_json::from_str::<FabricMod>(&json)
+    {
+        ids.insert(fabric.id);
+    }
+    if let Some(json) = read("quilt.mod.json")
+        && let Ok(quilt) = serde_json::from_str::<QuiltMod>(&json)
+    {
+        ids.insert(quilt.quilt_loader.id);
+    }
+    for name in ["META-INF/mods.toml", "META-INF/neoforge.mods.toml"] {
+        if let Some(toml) = read(name)
+            && let Ok(forge) = toml::from_str::<ForgeMods>(&toml)
+        {
+            ids.extend(forge.mods.into_iter().map(|m| m.mod_id));
+        }
+    }
+    ids
+}
@@ -1,32 +1,156 @@
-use std::fmt::Display;
+use serde::Serialize;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use url::Url;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Error)]
 pub enum FileError {
-    IOError,
-    AllDownloadsFailed,
-    DownloadFailed,
-    DeleteFailed,
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("request to {url} failed: {source}")]
+    Request {
+        url: Url,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("{url} returned HTTP {status}")]
+    HttpStatus { url: Url, status: u16 },
+    #[error("{}: content from {url} does not match the pack's hashes", path.display())]
+    HashMismatch { path: PathBuf, url: Url },
+    #[error(
+        "{}: downloaded {actual} bytes, but the pack declares {expected}",
+        path.display()
+    )]
+    SizeMismatch {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("could not download {} from any of its URLs", path.display())]
+    AllDownloadsFailed {
+        path: PathBuf,
+        /// The last error seen, if any URL was tried.
+        #[source]
+        last: Option<Box<FileError>>,
+    },
+    #[error("could not delete {}: {source}", path.display())]
+    DeleteFailed {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(
+        "{} is held by another observe-rs process (use --wait-lock to wait for it)",
+        path.display()
+    )]
+    Locked { path: PathBuf },
+    #[error("background task failed: {0}")]
+    TaskFailed(#[from] tokio::task::JoinError),
+    #[error(
+        "unsafe path `{}`: pack paths must be relative and stay inside the server directory",
+        .0.display()
+    )]
+    UnsafePath(PathBuf),
+    #[error(
+        "refusing to write {}: {} is a symlink leading outside the server directory",
+        path.display(),
+        link.display()
+    )]
+    SymlinkEscape { path: PathBuf, link: PathBuf },
+    #[error("download URL {0} is not on an allowed domain (see --allow-domain)")]
+    DisallowedUrl(Url),
+    #[error("download URL {0} is not HTTPS (see --allow-insecure)")]
+    InsecureUrl(Url),
+    /// The sync's [`CancellationToken`](tokio_util::sync::CancellationToken)
+    /// was cancelled. Nothing it staged was installed.
+    #[error("the sync was cancelled")]
+    Cancelled,
 }
 
-impl From<reqwest::Error> for FileError {
-    fn from(_: reqwest::Error) -> Self {
-        FileError::DownloadFailed
-    }
+/// Broad class of a [`FileError`], for callers that react differently to a
+/// bad pack, a network problem or corrupt content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// The pack itself is unusable or violates the download policy.
+    InvalidPack,
+    /// Files could not be fetched.
+    Download,
+    /// Fetched content did not match the pack's hashes.
+    Verification,
+    /// Removing files that left the pack failed.
+    Prune,
+    /// The sync was cancelled.
+    Cancelled,
+    /// Local I/O and anything else.
+    Other,
 }
 
-impl From<std::io::Error> for FileError {
-    fn from(_: std::io::Error) -> Self {
-        FileError::IOError
+impl FileError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            FileError::UnsafePath(_) | FileError::DisallowedUrl(_) | FileError::InsecureUrl(_) => {
+                ErrorCategory::InvalidPack
+            }
+            FileError::Request { .. } | FileError::HttpStatus { .. } => ErrorCategory::Download,
+            FileError::AllDownloadsFailed { last, .. } => last
+                .as_ref()
+                .map_or(ErrorCategory::Download, |err| err.category()),
+            FileError::HashMismatch { .. } | FileError::SizeMismatch { .. } => {
+                ErrorCategory::Verification
+            }
+            FileError::DeleteFailed { .. } => ErrorCategory::Prune,
+            FileError::Cancelled => ErrorCategory::Cancelled,
+            FileError::Io { .. }
+            | FileError::Locked { .. }
+            | FileError::SymlinkEscape { .. }
+            | FileError::TaskFailed(_) => ErrorCategory::Other,
+        }
     }
-}
 
-impl Display for FileError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    /// Whether retrying the same request might succeed: connection problems,
+    /// timeouts, throttling, and server-side errors.
+    pub fn is_transient(&self) -> bool {
         match self {
-            FileError::IOError => write!(f, "IO Error"),
-            FileError::AllDownloadsFailed => write!(f, "All Downloads Failed"),
-            FileError::DownloadFailed => write!(f, "Download Failed"),
-            FileError::DeleteFailed => write!(f, "Delete Failed"),
+            FileError::Request { .. } => true,
+            FileError::HttpStatus { status, .. } => {
+                *status >= 500 || *status == 408 || *status == 429
+            }
+            _ => false,
         }
     }
 }
+
+/// Attaches the path an I/O operation was working on.
+pub(crate) trait IoContext<T> {
+    fn at(self, path: impl AsRef<Path>) -> Result<T, FileError>;
+}
+
+impl<T> IoContext<T> for io::Result<T> {
+    fn at(self, path: impl AsRef<Path>) -> Result<T, FileError> {
+        self.map_err(|source| FileError::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// Formats `err` followed by each of its sources, e.g.
+/// `could not download mods/a.jar from any of its URLs: https://… returned HTTP 404`.
+pub fn error_chain(err: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+    message
+}
@@ -0,0 +1,26 @@
+use crate::util::file_timestamp;
+use std::time::SystemTime;
+
+/// The file a Minecraft server refuses to start without.
+pub const EULA_FILE_NAME: &str = "eula.txt";
+
+pub const EULA_URL: &str = "https://aka.ms/MinecraftEULA";
+
+/// Whether `content` of an `eula.txt` records acceptance.
+pub fn is_accepted(content: &[u8]) -> bool {
+    String::from_utf8_lossy(content).lines().any(|line| {
+        line.split_once('=').is_some_and(|(key, value)| {
+            key.trim() == "eula" && value.trim().eq_ignore_ascii_case("true")
+        })
+    })
+}
+
+/// An `eula.txt` accepting the EULA, in the format the server writes.
+pub(crate) fn accepted(time: SystemTime) -> Vec<u8> {
+    format!(
+        "#By changing the setting below to TRUE you are indicating your agreement to our EULA ({}).\n#Accepted by observe-rs at {}\neula=true\n",
+        EULA_URL,
+        file_timestamp(time)
+    )
+    .into_bytes()
+}
@@ -0,0 +1,144 @@
+use crate::plan::SyncPlan;
+use crate::verify::DriftKind;
+use indicatif::HumanBytes;
+use serde::Serialize;
+use std::{fmt::Display, path::PathBuf, sync::Arc, time::Duration};
+
+/// Progress notifications emitted while a sync runs. Paths are relative to
+/// the target directory.
+///
+/// Serializes as an object tagged with an `event` field, e.g.
+/// `{"event":"file_verified","path":"mods/sodium.jar"}`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SyncEvent {
+    DownloadStarted {
+        path: PathBuf,
+        url: String,
+    },
+    DownloadFinished {
+        path: PathBuf,
+        /// The mirror it came from.
+        url: String,
+        bytes: u64,
+        /// From the first byte requested to the last received.
+        elapsed_ms: u64,
+    },
+    FileVerified {
+        path: PathBuf,
+    },
+    CacheHit {
+        path: PathBuf,
+    },
+    /// A file could not be installed and was skipped (keep-going mode only).
+    FileFailed {
+        path: PathBuf,
+        error: String,
+    },
+    OverrideWritten {
+        path: PathBuf,
+    },
+    OverrideSkipped {
+        path: PathBuf,
+    },
+    /// Local edits to an override were kept and the pack's changes merged in.
+    OverrideMerged {
+        path: PathBuf,
+    },
+    /// Local edits to an override clash with the pack's changes; the local
+    /// file was left alone.
+    OverrideConflict {
+        path: PathBuf,
+    },
+    /// The local copy of an override was kept, as chosen in
+    /// [`Choices`](crate::Choices).
+    OverrideKept {
+        path: PathBuf,
+    },
+    /// The previous contents of an overwritten override were copied to
+    /// `backup`.
+    OverrideBackedUp {
+        path: PathBuf,
+        backup: PathBuf,
+    },
+    FilePruned {
+        path: PathBuf,
+    },
+    /// `eula.txt` was written with `eula=true` on the operator's behalf.
+    EulaAccepted,
+    StartScriptsWritten {
+        paths: Vec<PathBuf>,
+    },
+    /// The pack's mod loader was installed, with its launcher at `path`.
+    LoaderInstalled {
+        loader: String,
+        path: PathBuf,
+    },
+    /// A watched file stopped matching the pack.
+    DriftDetected {
+        path: PathBuf,
+        kind: DriftKind,
+    },
+    /// A file reported by [`SyncEvent::DriftDetected`] matches the pack
+    /// again.
+    DriftResolved {
+        path: PathBuf,
+    },
+    /// The `java` start scripts would use doesn't match the pack's
+    /// Minecraft version. `found` is its major version, if any was found.
+    JavaMismatch {
+        required: u32,
+        found: Option<u32>,
+    },
+    /// A Java runtime was downloaded into `.runtime/`.
+    JavaProvisioned {
+        version: String,
+    },
+    Planned(SyncPlan),
+    SyncSummary(SyncSummary),
+}
+
+/// Totals for a completed sync.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncSummary {
+    pub verified: u64,
+    pub cache_hits: u64,
+    pub downloaded: u64,
+    pub bytes_downloaded: u64,
+    pub overrides_written: u64,
+    /// Overrides written with local edits merged in; also counted in
+    /// `overrides_written`.
+    pub overrides_merged: u64,
+    pub merge_conflicts: u64,
+    pub pruned: u64,
+    pub failed: u64,
+    /// How long the sync took, waiting for the lock included.
+    pub elapsed_ms: u64,
+}
+
+impl Display for SyncSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Verified:     {}", self.verified)?;
+        writeln!(
+            f,
+            "Downloaded:   {} ({})",
+            self.downloaded,
+            HumanBytes(self.bytes_downloaded)
+        )?;
+        writeln!(f, "From cache:   {}", self.cache_hits)?;
+        writeln!(f, "Failed:       {}", self.failed)?;
+        writeln!(
+            f,
+            "Overrides:    {} written ({} merged), {} in conflict",
+            self.overrides_written, self.overrides_merged, self.merge_conflicts
+        )?;
+        writeln!(f, "Pruned:       {}", self.pruned)?;
+        write!(
+            f,
+            "Time:         {:.1?}",
+            Duration::from_millis(self.elapsed_ms)
+        )
+    }
+}
+
+pub type ProgressCallback = Arc<dyn Fn(&SyncEvent) + Send + Sync>;
@@ -0,0 +1,96 @@
+use crate::config::{Config, ConfigError};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+pub const FLEET_FILE_NAME: &str = "fleet.toml";
+
+/// Several servers synced together, read from a `fleet.toml` such as:
+///
+/// ```toml
+/// parallel = 4
+///
+/// [defaults]
+/// prune = true
+/// cache-dir = "cache"
+///
+/// [servers.survival]
+/// pack = "packs/survival.mrpack"
+///
+/// [servers.creative]
+/// pack = "packs/creative.mrpack"
+/// dir = "/srv/creative"
+/// prune = false
+/// ```
+///
+/// Each server takes the keys of `observe.toml`, falling back to those in
+/// `[defaults]`, and lives in the directory named after it beside the
+/// fleet file unless it sets `dir`.
+#[derive(Debug, Clone)]
+pub struct Fleet {
+    /// How many servers sync at once.
+    pub parallel: Option<usize>,
+    pub servers: Vec<FleetServer>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FleetServer {
+    pub name: String,
+    pub config: Config,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct FleetFile {
+    parallel: Option<usize>,
+    defaults: toml::Table,
+    servers: BTreeMap<String, toml::Table>,
+}
+
+impl Fleet {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        let file: FleetFile = toml::from_str(&content)
+            .map_err(|err| format!("invalid fleet {}: {}", path.display(), err))?;
+        if file.servers.is_empty() {
+            return Err(format!("{} lists no [servers]", path.display()).into());
+        }
+        if file.parallel == Some(0) {
+            return Err(format!("{}: parallel must be at least 1", path.display()).into());
+        }
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut dirs: HashMap<PathBuf, String> = HashMap::new();
+        let mut servers = Vec::new();
+        for (name, table) in file.servers {
+            let mut merged = file.defaults.clone();
+            merged.extend(table);
+            let mut config: Config = toml::Value::Table(merged).try_into().map_err(|err| {
+                format!("invalid server `{}` in {}: {}", name, path.display(), err)
+            })?;
+            config.dir.get_or_insert_with(|| PathBuf::from(&name));
+            config.resolve_paths(base);
+
+            let dir = config.dir.clone().expect("dir was just set");
+            if let Some(other) = dirs.insert(dir.clone(), name.clone()) {
+                return Err(format!(
+                    "servers `{}` and `{}` in {} both sync into {}",
+                    other,
+                    name,
+                    path.display(),
+                    dir.display()
+                )
+                .into());
+            }
+            servers.push(FleetServer { name, config });
+        }
+        Ok(Fleet {
+            parallel: file.parallel,
+            servers,
+        })
+    }
+}
@@ -0,0 +1,245 @@
+use crate::mrpack::FileHashes;
+use md5::Md5;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use sha2::{Sha256, Sha512};
+use std::{
+    io::{self, Write},
+    pin::Pin,
+    str::FromStr,
+    task::{Context, Poll},
+};
+use tokio::io::AsyncWrite;
+
+/// Which of a file's hashes are checked when verifying it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashPolicy {
+    /// SHA-1 and SHA-512, which every Modrinth pack lists.
+    #[default]
+    Standard,
+    /// Only the cheapest collision-resistant hash the file lists: BLAKE3
+    /// when a third-party tool added one, else SHA-1.
+    Cheapest,
+    /// Every hash the file lists that observe-rs knows: SHA-1 and SHA-512,
+    /// plus SHA-256, BLAKE3 and MD5 when present.
+    All,
+}
+
+impl FromStr for HashPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(HashPolicy::Standard),
+            "cheapest" => Ok(HashPolicy::Cheapest),
+            "all" => Ok(HashPolicy::All),
+            other => Err(format!(
+                "unknown hash policy `{}` (expected standard, cheapest or all)",
+                other
+            )),
+        }
+    }
+}
+
+/// How thoroughly files already on disk are checked before a sync decides
+/// to keep them. Downloads are always checked under the [`HashPolicy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyPolicy {
+    /// Both SHA-1 and SHA-512, plus whatever else the hash policy adds.
+    #[default]
+    Strict,
+    /// A single hash, as with [`HashPolicy::Cheapest`].
+    Fast,
+    /// Only that the file exists and has the size the pack declares.
+    Skip,
+}
+
+impl VerifyPolicy {
+    /// The hashes to check existing files with, or `None` to check only
+    /// their size.
+    pub(crate) fn existing_hashes(self, policy: HashPolicy) -> Option<HashPolicy> {
+        match self {
+            VerifyPolicy::Strict if policy == HashPolicy::Cheapest => Some(HashPolicy::Standard),
+            VerifyPolicy::Strict => Some(policy),
+            VerifyPolicy::Fast => Some(HashPolicy::Cheapest),
+            VerifyPolicy::Skip => None,
+        }
+    }
+}
+
+impl FromStr for VerifyPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(VerifyPolicy::Strict),
+            "fast" => Ok(VerifyPolicy::Fast),
+            "skip" => Ok(VerifyPolicy::Skip),
+            other => Err(format!(
+                "unknown verify policy `{}` (expected strict, fast or skip)",
+                other
+            )),
+        }
+    }
+}
+
+/// Running hashes over the same byte stream. By default SHA-1 and SHA-512;
+/// [`FileHasher::for_file`] picks the algorithms by [`HashPolicy`].
+#[derive(Clone)]
+pub(crate) struct FileHasher {
+    sha1: Option<Sha1>,
+    sha512: Option<Sha512>,
+    sha256: Option<Sha256>,
+    md5: Option<Md5>,
+    blake3: Option<Box<blake3::Hasher>>,
+}
+
+impl Default for FileHasher {
+    fn default() -> Self {
+        FileHasher {
+            sha1: Some(Sha1::new()),
+            sha512: Some(Sha512::new()),
+            sha256: None,
+            md5: None,
+            blake3: None,
+        }
+    }
+}
+
+impl FileHasher {
+    /// A hasher for checking a file with `expected`'s hashes under `policy`.
+    pub(crate) fn for_file(expected: &FileHashes, policy: HashPolicy) -> Self {
+        let has = |algorithm: &str| expected.other_hashes.contains_key(algorithm);
+        match policy {
+            HashPolicy::Standard => FileHasher::default(),
+            HashPolicy::Cheapest => FileHasher {
+                sha1: (!has("blake3")).then(Sha1::new),
+                sha512: None,
+                sha256: None,
+                md5: None,
+                blake3: has("blake3").then(Box::default),
+            },
+            HashPolicy::All => FileHasher {
+                sha256: has("sha256").then(Sha256::new),
+                md5: has("md5").then(Md5::new),
+                blake3: has("blake3").then(Box::default),
+                ..FileHasher::default()
+            },
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        if let Some(hasher) = &mut self.sha1 {
+            hasher.update(data);
+        }
+        if let Some(hasher) = &mut self.sha512 {
+            hasher.update(data);
+        }
+        if let Some(hasher) = &mut self.sha256 {
+            hasher.update(data);
+        }
+        if let Some(hasher) = &mut self.md5 {
+            hasher.update(data);
+        }
+        if let Some(hasher) = &mut self.blake3 {
+            hasher.update(data);
+        }
+    }
+
+    /// The SHA-1 and SHA-512 of the bytes seen so far. Only for hashers
+    /// that compute both, as the default one does.
+    pub(crate) fn finish(self) -> FileHashes {
+        FileHashes {
+            sha1: self.sha1.expect("hasher computes SHA-1").finalize().into(),
+            sha512: self
+                .sha512
+                .expect("hasher computes SHA-512")
+                .finalize()
+                .into(),
+            other_hashes: Default::default(),
+        }
+    }
+
+    /// Whether the bytes seen so far hash to `expected`, in every algorithm
+    /// this hasher computes.
+    pub(crate) fn matches(self, expected: &FileHashes) -> bool {
+        let other = |algorithm: &str, digest: &[u8]| {
+            expected
+                .other_hashes
+                .get(algorithm)
+                .is_some_and(|hex| hex.eq_ignore_ascii_case(&hex::encode(digest)))
+        };
+        self.sha1
+            .is_none_or(|hasher| hasher.finalize().as_slice() == expected.sha1)
+            && self
+                .sha512
+                .is_none_or(|hasher| hasher.finalize().as_slice() == expected.sha512)
+            && self
+                .sha256
+                .is_none_or(|hasher| other("sha256", &hasher.finalize()))
+            && self
+                .md5
+                .is_none_or(|hasher| other("md5", &hasher.finalize()))
+            && self
+                .blake3
+                .is_none_or(|hasher| other("blake3", hasher.finalize().as_bytes()))
+    }
+}
+
+/// Writer that hashes everything passing through it on the way to `inner`,
+/// so content is verified without being read back.
+pub(crate) struct HashingWriter<W> {
+    inner: W,
+    hasher: FileHasher,
+}
+
+impl<W> HashingWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self::with_hasher(inner, FileHasher::default())
+    }
+
+    pub(crate) fn with_hasher(inner: W, hasher: FileHasher) -> Self {
+        HashingWriter { inner, hasher }
+    }
+
+    pub(crate) fn into_hasher(self) -> FileHasher {
+        self.hasher
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for HashingWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            this.hasher.update(&buf[..n]);
+        }
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,195 @@
+use crate::errors::FileError;
+use crate::events::SyncSummary;
+use crate::util::STATE_DIR;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// SQLite database under `.observe/` with one row per sync.
+pub const HISTORY_FILE_NAME: &str = "history.db";
+
+/// Bumped whenever the schema changes, so older databases can be migrated.
+const SCHEMA_VERSION: i64 = 1;
+
+/// One sync run as recorded in the history.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncRun {
+    /// Seconds since the Unix epoch.
+    pub started_at: u64,
+    pub pack_name: String,
+    pub pack_version: String,
+    pub duration_ms: u64,
+    pub bytes_downloaded: u64,
+    /// Files downloaded or installed from cache, plus overrides written.
+    pub files_changed: u64,
+    pub pruned: u64,
+    pub failed: u64,
+    /// Why the sync stopped, if it aborted.
+    pub error: Option<String>,
+}
+
+impl SyncRun {
+    pub(crate) fn new(
+        pack_name: &str,
+        pack_version: &str,
+        started: SystemTime,
+        duration: Duration,
+        summary: &SyncSummary,
+        error: Option<String>,
+    ) -> Self {
+        SyncRun {
+            started_at: started
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            pack_name: pack_name.to_string(),
+            pack_version: pack_version.to_string(),
+            duration_ms: duration.as_millis() as u64,
+            bytes_downloaded: summary.bytes_downloaded,
+            files_changed: summary.downloaded + summary.cache_hits + summary.overrides_written,
+            pruned: summary.pruned,
+            failed: summary.failed,
+            error,
+        }
+    }
+
+    pub fn started(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.started_at)
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failed == 0 && self.error.is_none()
+    }
+}
+
+/// The sync history of one server directory.
+pub struct History {
+    conn: Connection,
+    path: PathBuf,
+}
+
+impl History {
+    /// Opens the history database under `root`, creating it if needed.
+    pub fn open(root: &Path) -> Result<Self, FileError> {
+        let dir = root.join(STATE_DIR);
+        let path = dir.join(HISTORY_FILE_NAME);
+        fs::create_dir_all(&dir).map_err(|source| FileError::Io {
+            path: dir.clone(),
+            source,
+        })?;
+        let conn = Connection::open(&path).map_err(|err| db_error(&path, err))?;
+        let history = History { conn, path };
+        history.migrate()?;
+        Ok(history)
+    }
+
+    /// Opens the history under `root` only if a sync has recorded one.
+    pub fn open_existing(root: &Path) -> Result<Option<Self>, FileError> {
+        if root.join(STATE_DIR).join(HISTORY_FILE_NAME).is_file() {
+            Self::open(root).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn migrate(&self) -> Result<(), FileError> {
+        let version: i64 = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(|err| self.error(err))?;
+        if version >= SCHEMA_VERSION {
+            return Ok(());
+        }
+        self.conn
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS runs (
+                    id INTEGER PRIMARY KEY,
+                    started_at INTEGER NOT NULL,
+                    pack_name TEXT NOT NULL,
+                    pack_version TEXT NOT NULL,
+                    duration_ms INTEGER NOT NULL,
+                    bytes_downloaded INTEGER NOT NULL,
+                    files_changed INTEGER NOT NULL,
+                    pruned INTEGER NOT NULL,
+                    failed INTEGER NOT NULL,
+                    error TEXT
+                );
+                PRAGMA user_version = 1;",
+            )
+            .map_err(|err| self.error(err))
+    }
+
+    pub(crate) fn record(&self, run: &SyncRun) -> Result<(), FileError> {
+        self.conn
+            .execute(
+                "INSERT INTO runs (started_at, pack_name, pack_version, duration_ms,
+                    bytes_downloaded, files_changed, pruned, failed, error)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    run.started_at as i64,
+                    run.pack_name,
+                    run.pack_version,
+                    run.duration_ms as i64,
+                    run.bytes_downloaded as i64,
+                    run.files_changed as i64,
+                    run.pruned as i64,
+                    run.failed as i64,
+                    run.error,
+                ],
+            )
+            .map(|_| ())
+            .map_err(|err| self.error(err))
+    }
+
+    /// The most recent `limit` runs, newest first.
+    pub fn recent(&self, limit: usize) -> Result<Vec<SyncRun>, FileError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT started_at, pack_name, pack_version, duration_ms, bytes_downloaded,
+                    files_changed, pruned, failed, error
+                 FROM runs ORDER BY id DESC LIMIT ?1",
+            )
+            .map_err(|err| self.error(err))?;
+        let rows = statement
+            .query_map([limit as i64], |row| {
+                Ok(SyncRun {
+                    started_at: row.get::<_, i64>(0)? as u64,
+                    pack_name: row.get(1)?,
+                    pack_version: row.get(2)?,
+                    duration_ms: row.get::<_, i64>(3)? as u64,
+                    bytes_downloaded: row.get::<_, i64>(4)? as u64,
+                    files_changed: row.get::<_, i64>(5)? as u64,
+                    pruned: row.get::<_, i64>(6)? as u64,
+                    failed: row.get::<_, i64>(7)? as u64,
+                    error: row.get(8)?,
+                })
+            })
+            .map_err(|err| self.error(err))?;
+        rows.collect::<Result<_, _>>()
+            .map_err(|err| self.error(err))
+    }
+
+    /// The most recent run, if any.
+    pub fn last(&self) -> Result<Option<SyncRun>, FileError> {
+        Ok(self.recent(1)?.into_iter().next())
+    }
+
+    fn error(&self, err: rusqlite::Error) -> FileError {
+        db_error(&self.path, err)
+    }
+}
+
+fn db_error(path: &Path, err: rusqlite::Error) -> FileError {
+    FileError::Io {
+        path: path.to_path_buf(),
+        source: io::Error::other(err),
+    }
+}
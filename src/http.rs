@@ -0,0 +1,123 @@
+use crate::errors::{FileError, IoContext};
+use futures_util::StreamExt;
+use reqwest::{Client, ClientBuilder, RequestBuilder, header::AUTHORIZATION};
+use serde::de::DeserializeOwned;
+use std::{path::Path, time::Duration};
+use tokio::io::AsyncWriteExt;
+use url::Url;
+
+/// Sent with every request, as Modrinth asks API consumers to identify
+/// themselves.
+pub const USER_AGENT: &str = concat!(
+    "owayys/observe-rs/",
+    env!("CARGO_PKG_VERSION"),
+    " (https://github.com/owayys/observe-rs)"
+);
+
+/// Environment variable holding a Modrinth personal access token.
+pub const TOKEN_ENV: &str = "MODRINTH_TOKEN";
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest gap allowed between bytes of a response before the transfer is
+/// abandoned, so a stalled connection fails (and is retried) instead of
+/// hanging the sync.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Client builder with the crate's User-Agent and default timeouts.
+pub fn builder() -> ClientBuilder {
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(DEFAULT_CONNECT_TIMEOUT)
+        .read_timeout(DEFAULT_READ_TIMEOUT)
+}
+
+pub fn client() -> Client {
+    builder().build().expect("failed to build HTTP client")
+}
+
+/// GET `url`, attaching `token` only when the host is Modrinth's so it never
+/// leaks to GitHub or third-party mirrors.
+pub(crate) fn get(client: &Client, url: &Url, token: Option<&str>) -> RequestBuilder {
+    let request = client.get(url.clone());
+    match token {
+        Some(token) if is_modrinth_host(url) => request.header(AUTHORIZATION, token),
+        _ => request,
+    }
+}
+
+/// POST to `url`, with the same token handling as [`get`].
+pub(crate) fn post(client: &Client, url: &Url, token: Option<&str>) -> RequestBuilder {
+    let request = client.post(url.clone());
+    match token {
+        Some(token) if is_modrinth_host(url) => request.header(AUTHORIZATION, token),
+        _ => request,
+    }
+}
+
+fn is_modrinth_host(url: &Url) -> bool {
+    url.host_str()
+        .is_some_and(|host| host == "modrinth.com" || host.ends_with(".modrinth.com"))
+}
+
+/// Downloads `url` to `dest` through a `.part` file, so `dest` only ever
+/// holds a complete download. Returns the URL the response came from after
+/// redirects.
+pub(crate) async fn download(client: &Client, url: &Url, dest: &Path) -> Result<Url, FileError> {
+    let response = send(client, url).await?;
+    let final_url = response.url().clone();
+
+    let mut part = dest.as_os_str().to_owned();
+    part.push(".part");
+    let part = Path::new(&part);
+    if let Some(parent) = dest.parent() {
+        tokio::fs::create_dir_all(parent).await.at(parent)?;
+    }
+    let mut file = tokio::fs::File::create(part).await.at(part)?;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|source| FileError::Request {
+            url: url.clone(),
+            source,
+        })?;
+        file.write_all(&chunk).await.at(part)?;
+    }
+    file.flush().await.at(part)?;
+    drop(file);
+
+    tokio::fs::rename(part, dest).await.at(dest)?;
+    Ok(final_url)
+}
+
+/// GETs `url` and parses the JSON response.
+pub(crate) async fn get_json<T: DeserializeOwned>(
+    client: &Client,
+    url: &Url,
+) -> Result<T, FileError> {
+    send(client, url)
+        .await?
+        .json()
+        .await
+        .map_err(|source| FileError::Request {
+            url: url.clone(),
+            source,
+        })
+}
+
+async fn send(client: &Client, url: &Url) -> Result<reqwest::Response, FileError> {
+    let response = client
+        .get(url.clone())
+        .send()
+        .await
+        .map_err(|source| FileError::Request {
+            url: url.clone(),
+            source,
+        })?;
+    if !response.status().is_success() {
+        return Err(FileError::HttpStatus {
+            url: url.clone(),
+            status: response.status().as_u16(),
+        });
+    }
+    Ok(response)
+}
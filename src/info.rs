@@ -0,0 +1,165 @@
+use crate::mrpack::{DependencyId, Requirement};
+use crate::pack::Pack;
+use indicatif::HumanBytes;
+use semver::Version;
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt::Display, path::Component};
+
+/// A summary of a pack's metadata and contents.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackInfo {
+    pub name: String,
+    pub version_id: String,
+    pub summary: Option<String>,
+    pub game: String,
+    pub format_version: u32,
+    pub dependencies: Vec<Dependency>,
+    pub files: usize,
+    pub server: RequirementCounts,
+    pub client: RequirementCounts,
+    /// Total `fileSize` of every index file.
+    pub download_size: u64,
+    /// Total `fileSize` of the files a server installs.
+    pub server_download_size: u64,
+    pub overrides: usize,
+    pub override_size: u64,
+    /// Override counts by top-level directory; files at the root are
+    /// counted under `.`.
+    pub override_dirs: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Dependency {
+    pub id: DependencyId,
+    pub version: Version,
+}
+
+/// How many index files one side requires, may skip, or must not install.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RequirementCounts {
+    pub required: usize,
+    pub optional: usize,
+    pub unsupported: usize,
+}
+
+impl RequirementCounts {
+    fn add(&mut self, requirement: Requirement) {
+        match requirement {
+            Requirement::Required => self.required += 1,
+            Requirement::Optional => self.optional += 1,
+            Requirement::Unsupported => self.unsupported += 1,
+        }
+    }
+}
+
+impl PackInfo {
+    pub fn new(pack: &Pack) -> Self {
+        let index = &pack.index;
+        let mut dependencies: Vec<Dependency> = index
+            .dependencies
+            .iter()
+            .map(|(id, version)| Dependency {
+                id: id.clone(),
+                version: version.clone(),
+            })
+            .collect();
+        // Minecraft first, then loaders and anything else by name.
+        dependencies.sort_by_key(|dep| {
+            (
+                dep.id != DependencyId::Minecraft,
+                dep.id.as_str().to_string(),
+            )
+        });
+
+        let mut server = RequirementCounts::default();
+        let mut client = RequirementCounts::default();
+        let mut download_size = 0;
+        let mut server_download_size = 0;
+        for file in &index.files {
+            server.add(file.server_requirement());
+            client.add(file.client_requirement());
+            download_size += file.file_size;
+            if file.server_requirement() != Requirement::Unsupported {
+                server_download_size += file.file_size;
+            }
+        }
+
+        let mut override_dirs = BTreeMap::new();
+        for path in pack.overrides.keys() {
+            let dir = match path.components().next() {
+                Some(Component::Normal(first)) if path.components().count() > 1 => {
+                    first.to_string_lossy().into_owned()
+                }
+                _ => ".".to_string(),
+            };
+            *override_dirs.entry(dir).or_insert(0) += 1;
+        }
+
+        PackInfo {
+            name: index.name.clone(),
+            version_id: index.version_id.clone(),
+            summary: index.summary.clone(),
+            game: index.game.clone(),
+            format_version: index.format_version,
+            dependencies,
+            files: index.files.len(),
+            server,
+            client,
+            download_size,
+            server_download_size,
+            overrides: pack.overrides.len(),
+            override_size: pack.overrides.values().map(|c| c.len() as u64).sum(),
+            override_dirs,
+        }
+    }
+}
+
+impl Display for RequirementCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} required, {} optional, {} unsupported",
+            self.required, self.optional, self.unsupported
+        )
+    }
+}
+
+impl Display for PackInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Name:         {}", self.name)?;
+        writeln!(f, "Version:      {}", self.version_id)?;
+        if let Some(summary) = &self.summary {
+            writeln!(f, "Summary:      {}", summary)?;
+        }
+        writeln!(
+            f,
+            "Game:         {} (format {})",
+            self.game, self.format_version
+        )?;
+        if !self.dependencies.is_empty() {
+            writeln!(f, "Dependencies:")?;
+            for dep in &self.dependencies {
+                writeln!(f, "  {:<12}{}", dep.id.to_string(), dep.version)?;
+            }
+        }
+        writeln!(
+            f,
+            "Files:        {} ({} to download, {} for a server)",
+            self.files,
+            HumanBytes(self.download_size),
+            HumanBytes(self.server_download_size)
+        )?;
+        writeln!(f, "  Server:     {}", self.server)?;
+        writeln!(f, "  Client:     {}", self.client)?;
+        write!(
+            f,
+            "Overrides:    {} ({})",
+            self.overrides,
+            HumanBytes(self.override_size)
+        )?;
+        for (dir, count) in &self.override_dirs {
+            write!(f, "\n  {:<12}{}", dir, count)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use crate::errors::{FileError, IoContext};
+use crate::http;
+use crate::util::STATE_DIR;
+use reqwest::Client;
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use url::Url;
+
+/// Directory under the server root that a provisioned Java runtime is
+/// unpacked into. Start scripts prefer it over the system `java`.
+pub const RUNTIME_DIR: &str = ".runtime";
+
+const ADOPTIUM_API: &str = "https://api.adoptium.net/v3";
+
+/// A `java` executable and the version it reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JavaInstall {
+    pub path: PathBuf,
+    /// Feature release, e.g. `8`, `17` or `21`.
+    pub major: u32,
+    pub version: String,
+}
+
+/// The Java feature release a Minecraft version's server is built for.
+pub fn required_java(minecraft: &Version) -> u32 {
+    match (minecraft.major, minecraft.minor, minecraft.patch) {
+        (1, 0..=16, _) => 8,
+        (1, 17, _) => 16,
+        (1, 18..=19, _) | (1, 20, 0..=4) => 17,
+        _ => 21,
+    }
+}
+
+/// Java executables a start script could use, in the order it looks for
+/// them: `$JAVA`, the provisioned runtime, `$JAVA_HOME` and then `PATH`.
+/// Candidates that don't run are skipped.
+pub fn find_java(root: &Path) -> Vec<JavaInstall> {
+    let exe = if cfg!(windows) { "java.exe" } else { "java" };
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    if let Some(java) = env::var_os("JAVA") {
+        candidates.push(java.into());
+    }
+    candidates.push(runtime_java(root));
+    if let Some(home) = env::var_os("JAVA_HOME") {
+        candidates.push(Path::new(&home).join("bin").join(exe));
+    }
+    candidates.push(PathBuf::from(exe));
+
+    let mut installs: Vec<JavaInstall> = Vec::new();
+    for path in candidates {
+        if let Some(install) = probe(&path)
+            && !installs.iter().any(|i| i.path == install.path)
+        {
+            installs.push(install);
+        }
+    }
+    installs
+}
+
+/// Path of the `java` executable inside [`RUNTIME_DIR`].
+pub fn runtime_java(root: &Path) -> PathBuf {
+    let exe = if cfg!(windows) { "java.exe" } else { "java" };
+    root.join(RUNTIME_DIR).join("bin").join(exe)
+}
+
+fn probe(path: &Path) -> Option<JavaInstall> {
+    let output = Command::new(path).arg("-version").output().ok()?;
+    // `java -version` prints to stderr.
+    let text = String::from_utf8_lossy(&output.stderr);
+    let (version, major) = parse_version(&text)?;
+    Some(JavaInstall {
+        path: path.to_path_buf(),
+        major,
+        version,
+    })
+}
+
+/// Reads the version out of `java -version` output such as
+/// `openjdk version "17.0.9" 2023-10-17` or `java version "1.8.0_392"`.
+fn parse_version(output: &str) -> Option<(String, u32)> {
+    let line = output.lines().find(|line| line.contains(" version \""))?;
+    let version = line.split('"').nth(1)?;
+    let mut parts = version.split(['.', '_', '-', '+']);
+    let major = match parts.next()?.parse().ok()? {
+        1 => parts.next()?.parse().ok()?,
+        major => major,
+    };
+    Some((version.to_string(), major))
+}
+
+#[derive(Deserialize)]
+struct AdoptiumAsset {
+    binary: AdoptiumBinary,
+}
+
+#[derive(Deserialize)]
+struct AdoptiumBinary {
+    package: AdoptiumPackage,
+}
+
+#[derive(Deserialize)]
+struct AdoptiumPackage {
+    /// SHA-256 of the archive.
+    #[serde(with = "hex")]
+    checksum: Vec<u8>,
+    link: Url,
+    name: String,
+}
+
+/// Downloads the latest Eclipse Temurin JRE for Java `major`, checks it
+/// against the SHA-256 Adoptium publishes, and unpacks it into
+/// `<root>/.runtime`, replacing any runtime already there.
+pub async fn provision(client: &Client, root: &Path, major: u32) -> Result<JavaInstall, FileError> {
+    let os = match env::consts::OS {
+        "macos" => "mac",
+        os => os,
+    };
+    let arch = match env::consts::ARCH {
+        "x86_64" => "x64",
+        arch => arch,
+    };
+    let mut url = Url::parse(&format!("{}/assets/latest/{}/hotspot", ADOPTIUM_API, major))
+        .expect("valid Adoptium URL");
+    url.query_pairs_mut()
+        .append_pair("architecture", arch)
+        .append_pair("image_type", "jre")
+        .append_pair("os", os)
+        .append_pair("vendor", "eclipse");
+    let assets: Vec<AdoptiumAsset> = http::get_json(client, &url).await?;
+    let package = assets
+        .into_iter()
+        .next()
+        .map(|asset| asset.binary.package)
+        .ok_or_else(|| FileError::Io {
+            path: root.join(RUNTIME_DIR),
+            source: io::Error::other(format!(
+                "Adoptium has no Java {} runtime for {} {}",
+                major, os, arch
+            )),
+        })?;
+
+    let archive = root.join(STATE_DIR).join("runtime.download");
+    http::download(client, &package.link, &archive).await?;
+    let path = archive.clone();
+    let digest = tokio::task::spawn_blocking(move || fs::read(&path).map(Sha256::digest))
+        .await?
+        .at(&archive)?;
+    if digest.as_slice() != package.checksum {
+        let _ = fs::remove_file(&archive);
+        return Err(FileError::Io {
+            path: archive,
+            source: io::Error::other(format!(
+                "content from {} does not match Adoptium's SHA-256",
+                package.link
+            )),
+        });
+    }
+    let is_zip = package.name.ends_with(".zip");
+
+    let root_dir = root.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let unpacked = unpack(&archive, &root_dir, is_zip);
+        let _ = fs::remove_file(&archive);
+        unpacked
+    })
+    .await??;
+
+    probe(&runtime_java(root)).ok_or_else(|| FileError::Io {
+        path: runtime_java(root),
+        source: io::Error::other("the downloaded runtime does not run"),
+    })
+}
+
+/// Extracts `archive` next to [`RUNTIME_DIR`], then moves the Java home
+/// inside it into place.
+fn unpack(archive: &Path, root: &Path, is_zip: bool) -> Result<(), FileError> {
+    let tmp = root.join(STATE_DIR).join("runtime.tmp");
+    if tmp.exists() {
+        fs::remove_dir_all(&tmp).at(&tmp)?;
+    }
+    fs::create_dir_all(&tmp).at(&tmp)?;
+
+    let file = fs::File::open(archive).at(archive)?;
+    if is_zip {
+        zip::ZipArchive::new(file)
+            .and_then(|mut zip| zip.extract(&tmp))
+            .map_err(io::Error::other)
+            .at(archive)?;
+    } else {
+        tar::Archive::new(flate2::read::GzDecoder::new(file))
+            .unpack(&tmp)
+            .at(archive)?;
+    }
+
+    // Archives hold a single `jdk-<version>-jre` directory; macOS builds
+    // nest the Java home under `Contents/Home`.
+    let top = fs::read_dir(&tmp)
+        .at(&tmp)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| path.is_dir())
+        .ok_or_else(|| FileError::Io {
+            path: archive.to_path_buf(),
+            source: io::Error::other("archive contains no Java runtime"),
+        })?;
+    let mac_home = top.join("Contents").join("Home");
+    let home = if mac_home.is_dir() { mac_home } else { top };
+
+    let dest = root.join(RUNTIME_DIR);
+    if dest.exists() {
+        fs::remove_dir_all(&dest).at(&dest)?;
+    }
+    fs::rename(&home, &dest).at(&dest)?;
+    fs::remove_dir_all(&tmp).at(&tmp)
+}
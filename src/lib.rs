@@ -0,0 +1,90 @@
+//! Sync a Minecraft server directory against a Modrinth `.mrpack`.
+//!
+//! Open a pack with [`Pack::open`], then configure and run a sync with
+//! [`PackSyncer`]. Server-unsupported files are skipped, existing files are
+//! hash-checked and only re-downloaded when they differ, and overrides are
+//! written over whatever is on disk.
+
+pub mod additions;
+pub mod backup;
+pub mod cache;
+pub mod changelog;
+pub mod choices;
+pub mod config;
+pub mod create;
+pub mod curseforge;
+pub mod diff;
+pub mod doctor;
+pub mod duplicates;
+pub mod errors;
+pub mod eula;
+pub mod events;
+pub mod fleet;
+mod hashing;
+pub mod history;
+pub mod http;
+pub mod info;
+pub mod java;
+pub mod loader;
+pub mod lock;
+pub mod lockfile;
+pub mod logging;
+pub mod merge;
+pub mod metrics;
+mod mirrors;
+pub mod mod_dependencies;
+pub mod mod_manager;
+pub mod modlist;
+pub mod modrinth;
+pub mod mrpack;
+pub mod notify;
+pub mod observeignore;
+pub mod observer;
+pub mod pack;
+pub mod packwiz;
+pub mod permissions;
+pub mod plan;
+pub mod policy;
+mod properties;
+pub mod prune;
+mod ratelimit;
+pub mod rcon;
+pub mod remote;
+pub mod report;
+pub mod s3;
+pub mod sbom;
+pub mod scaffold;
+pub mod schedule;
+pub mod secrets;
+pub mod sftp;
+pub mod state;
+pub mod syncer;
+pub mod systemd;
+mod template;
+pub mod transaction;
+pub mod trash;
+pub mod tui;
+pub mod upgrade;
+pub mod util;
+pub mod verify;
+
+pub use cache::{DownloadCache, LinkMode};
+pub use choices::{Choices, OverrideChoice};
+pub use config::Config;
+pub use errors::FileError;
+pub use events::{SyncEvent, SyncSummary};
+pub use hashing::{HashPolicy, VerifyPolicy};
+pub use lockfile::Lockfile;
+pub use mod_manager::ModManager;
+pub use mrpack::{MRFile, MRIndex};
+pub use observer::{SyncObserver, SyncPhase};
+pub use pack::{Pack, PackSource};
+pub use permissions::OverrideModes;
+pub use plan::{PruneCandidate, SyncPlan};
+pub use policy::DownloadPolicy;
+pub use prune::PruneRules;
+pub use report::{FileReport, FileStatus, SyncReport};
+pub use secrets::Secrets;
+pub use syncer::{JavaPolicy, OptionalPolicy, PackSyncer, PrunePolicy};
+pub use tokio_util::sync::CancellationToken;
+pub use verify::VerifyReport;
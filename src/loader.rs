@@ -0,0 +1,447 @@
+use crate::errors::{FileError, IoContext};
+use crate::http;
+use crate::java::{find_java, required_java};
+use crate::mrpack::{DependencyId, MRIndex};
+use crate::util::STATE_DIR;
+use reqwest::Client;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use url::Url;
+
+/// Launcher jar the Fabric server installer produces, renamed to a stable
+/// name so start scripts don't change between loader versions.
+pub const FABRIC_LAUNCHER_JAR: &str = "fabric-server-launch.jar";
+pub const QUILT_LAUNCHER_JAR: &str = "quilt-server-launch.jar";
+/// The vanilla server jar, which the Fabric and Quilt launchers also load.
+pub const VANILLA_SERVER_JAR: &str = "server.jar";
+
+/// Records, under `.observe/`, which loader was last installed so an
+/// unchanged one isn't downloaded again.
+pub const LOADER_FILE_NAME: &str = "loader.json";
+
+const MOJANG_MANIFEST: &str = "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+const FABRIC_META: &str = "https://meta.fabricmc.net/v2";
+const QUILT_META: &str = "https://meta.quiltmc.org/v3";
+const FORGE_MAVEN: &str = "https://maven.minecraftforge.net";
+const NEOFORGE_MAVEN: &str = "https://maven.neoforged.net/releases";
+/// Where the Forge, NeoForge or Quilt installer is downloaded to, under `.observe/`.
+const INSTALLER_FILE_NAME: &str = "installer.jar";
+
+/// The mod loader a pack runs on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "loader", content = "version", rename_all = "lowercase")]
+pub enum Loader {
+    Vanilla,
+    Fabric(Version),
+    Quilt(Version),
+    Forge(Version),
+    NeoForge(Version),
+}
+
+/// The game and loader versions a pack declares in its `dependencies`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerTarget {
+    pub minecraft: Version,
+    #[serde(flatten)]
+    pub loader: Loader,
+}
+
+impl ServerTarget {
+    /// `None` if the pack doesn't declare a Minecraft version.
+    pub fn from_index(index: &MRIndex) -> Option<Self> {
+        let deps = &index.dependencies;
+        let minecraft = deps.get(&DependencyId::Minecraft)?.clone();
+        let loader = if let Some(v) = deps.get(&DependencyId::FabricLoader) {
+            Loader::Fabric(v.clone())
+        } else if let Some(v) = deps.get(&DependencyId::QuiltLoader) {
+            Loader::Quilt(v.clone())
+        } else if let Some(v) = deps.get(&DependencyId::Neoforge) {
+            Loader::NeoForge(v.clone())
+        } else if let Some(v) = deps.get(&DependencyId::Forge) {
+            Loader::Forge(v.clone())
+        } else {
+            Loader::Vanilla
+        };
+        Some(ServerTarget { minecraft, loader })
+    }
+
+    /// The target last installed into `root`, from the marker
+    /// [`install`] leaves under `.observe/`.
+    pub fn installed(root: &Path) -> Option<Self> {
+        let bytes = fs::read(root.join(STATE_DIR).join(LOADER_FILE_NAME)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// The `dependencies` of a pack declaring this target; the reverse of
+    /// [`ServerTarget::from_index`].
+    pub fn dependencies(&self) -> HashMap<DependencyId, Version> {
+        let mut deps = HashMap::from([(DependencyId::Minecraft, self.minecraft.clone())]);
+        match &self.loader {
+            Loader::Vanilla => {}
+            Loader::Fabric(v) => {
+                deps.insert(DependencyId::FabricLoader, v.clone());
+            }
+            Loader::Quilt(v) => {
+                deps.insert(DependencyId::QuiltLoader, v.clone());
+            }
+            Loader::Forge(v) => {
+                deps.insert(DependencyId::Forge, v.clone());
+            }
+            Loader::NeoForge(v) => {
+                deps.insert(DependencyId::Neoforge, v.clone());
+            }
+        }
+        deps
+    }
+
+    /// Whether the server loads Mojang's [`VANILLA_SERVER_JAR`] from the
+    /// root. Forge and NeoForge installers fetch it into `libraries/`.
+    pub fn needs_server_jar(&self) -> bool {
+        matches!(
+            self.loader,
+            Loader::Vanilla | Loader::Fabric(_) | Loader::Quilt(_)
+        )
+    }
+
+    /// The file an installed loader starts from, relative to the server
+    /// root.
+    pub fn launcher(&self) -> PathBuf {
+        match &self.loader {
+            Loader::Vanilla => VANILLA_SERVER_JAR.into(),
+            Loader::Fabric(_) => FABRIC_LAUNCHER_JAR.into(),
+            Loader::Quilt(_) => QUILT_LAUNCHER_JAR.into(),
+            Loader::Forge(v) if self.minecraft >= Version::new(1, 17, 0) => format!(
+                "libraries/net/minecraftforge/forge/{}-{}/unix_args.txt",
+                self.minecraft, v
+            )
+            .into(),
+            Loader::Forge(v) => format!("forge-{}-{}.jar", self.minecraft, v).into(),
+            Loader::NeoForge(v) if self.minecraft == Version::new(1, 20, 1) => format!(
+                "libraries/net/neoforged/forge/{}-{}/unix_args.txt",
+                self.minecraft, v
+            )
+            .into(),
+            Loader::NeoForge(v) => {
+                format!("libraries/net/neoforged/neoforge/{}/unix_args.txt", v).into()
+            }
+        }
+    }
+
+    /// The `java` arguments after the JVM flags that start this server.
+    /// Forge and NeoForge installs since 1.17 start from an argument file
+    /// that differs between Unix and Windows.
+    pub fn launch_args(&self, windows: bool) -> String {
+        let args_file = if windows {
+            "win_args.txt"
+        } else {
+            "unix_args.txt"
+        };
+        match &self.loader {
+            Loader::Vanilla => format!("-jar {} nogui", VANILLA_SERVER_JAR),
+            Loader::Fabric(_) => format!("-jar {} nogui", FABRIC_LAUNCHER_JAR),
+            Loader::Quilt(_) => format!("-jar {} nogui", QUILT_LAUNCHER_JAR),
+            Loader::Forge(v) if self.minecraft >= Version::new(1, 17, 0) => format!(
+                "@libraries/net/minecraftforge/forge/{}-{}/{} nogui",
+                self.minecraft, v, args_file
+            ),
+            Loader::Forge(v) => format!("-jar forge-{}-{}.jar nogui", self.minecraft, v),
+            // NeoForge for 1.20.1 still used Forge's versioning and layout.
+            Loader::NeoForge(v) if self.minecraft == Version::new(1, 20, 1) => format!(
+                "@libraries/net/neoforged/forge/{}-{}/{} nogui",
+                self.minecraft, v, args_file
+            ),
+            Loader::NeoForge(v) => {
+                format!(
+                    "@libraries/net/neoforged/neoforge/{}/{} nogui",
+                    v, args_file
+                )
+            }
+        }
+    }
+}
+
+impl Display for ServerTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.loader {
+            Loader::Vanilla => write!(f, "Minecraft {}", self.minecraft),
+            Loader::Fabric(v) => write!(f, "Fabric {} for Minecraft {}", v, self.minecraft),
+            Loader::Quilt(v) => write!(f, "Quilt {} for Minecraft {}", v, self.minecraft),
+            Loader::Forge(v) => write!(f, "Forge {} for Minecraft {}", v, self.minecraft),
+            Loader::NeoForge(v) => write!(f, "NeoForge {} for Minecraft {}", v, self.minecraft),
+        }
+    }
+}
+
+/// What [`install`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum LoaderInstall {
+    /// The launcher was downloaded to this path, relative to the root.
+    Installed(PathBuf),
+    /// The same loader was installed by an earlier sync.
+    UpToDate,
+}
+
+/// Installs the vanilla server jar if `target` needs it and the server side
+/// of its loader into `root`, unless the marker in `.observe/` shows they
+/// are already there.
+pub(crate) async fn install(
+    client: &Client,
+    root: &Path,
+    target: &ServerTarget,
+) -> Result<LoaderInstall, FileError> {
+    let launcher = target.launcher();
+    let marker = root.join(STATE_DIR).join(LOADER_FILE_NAME);
+    let installed = ServerTarget::installed(root);
+    let server_jar = root.join(VANILLA_SERVER_JAR);
+    if installed.as_ref() == Some(target)
+        && root.join(&launcher).is_file()
+        && (!target.needs_server_jar() || server_jar.is_file())
+    {
+        return Ok(LoaderInstall::UpToDate);
+    }
+
+    if target.needs_server_jar() {
+        install_server_jar(client, root, &target.minecraft).await?;
+    }
+    match &target.loader {
+        Loader::Fabric(loader) => install_fabric(client, root, &target.minecraft, loader).await?,
+        Loader::Quilt(loader) => install_quilt(client, root, &target.minecraft, loader).await?,
+        Loader::Forge(_) | Loader::NeoForge(_) => install_forge(client, root, target).await?,
+        Loader::Vanilla => {}
+    }
+
+    let json = serde_json::to_vec_pretty(target).expect("ServerTarget serializes");
+    fs::create_dir_all(root.join(STATE_DIR)).at(root.join(STATE_DIR))?;
+    fs::write(&marker, json).at(&marker)?;
+    Ok(LoaderInstall::Installed(launcher))
+}
+
+#[derive(Deserialize)]
+struct VersionManifest {
+    versions: Vec<ManifestVersion>,
+}
+
+#[derive(Deserialize)]
+struct ManifestVersion {
+    id: String,
+    url: Url,
+}
+
+#[derive(Deserialize)]
+struct VersionDetails {
+    downloads: HashMap<String, VersionDownload>,
+}
+
+#[derive(Deserialize)]
+struct VersionDownload {
+    #[serde(with = "hex")]
+    sha1: Vec<u8>,
+    url: Url,
+}
+
+/// Downloads Mojang's server jar for `minecraft` and checks it against the
+/// SHA-1 in the version manifest.
+async fn install_server_jar(
+    client: &Client,
+    root: &Path,
+    minecraft: &Version,
+) -> Result<(), FileError> {
+    let manifest_url = Url::parse(MOJANG_MANIFEST).expect("valid manifest URL");
+    let manifest: VersionManifest = http::get_json(client, &manifest_url).await?;
+    // Mojang drops a zero patch: `1.20.0` is listed as `1.20`.
+    let short = format!("{}.{}", minecraft.major, minecraft.minor);
+    let full = minecraft.to_string();
+    let version = manifest
+        .versions
+        .iter()
+        .find(|v| v.id == full || (minecraft.patch == 0 && v.id == short))
+        .ok_or_else(|| FileError::Io {
+            path: PathBuf::from(VANILLA_SERVER_JAR),
+            source: std::io::Error::other(format!(
+                "Minecraft {} is not in Mojang's version manifest",
+                minecraft
+            )),
+        })?;
+    let details: VersionDetails = http::get_json(client, &version.url).await?;
+    let server = details
+        .downloads
+        .get("server")
+        .ok_or_else(|| FileError::Io {
+            path: PathBuf::from(VANILLA_SERVER_JAR),
+            source: std::io::Error::other(format!(
+                "Minecraft {} has no server download",
+                minecraft
+            )),
+        })?;
+
+    let dest = root.join(VANILLA_SERVER_JAR);
+    http::download(client, &server.url, &dest).await?;
+    let path = dest.clone();
+    let digest = tokio::task::spawn_blocking(move || fs::read(&path).map(Sha1::digest))
+        .await?
+        .at(&dest)?;
+    if digest.as_slice() != server.sha1 {
+        let _ = fs::remove_file(&dest);
+        return Err(FileError::Io {
+            path: dest,
+            source: std::io::Error::other(format!(
+                "content from {} does not match Mojang's SHA-1",
+                server.url
+            )),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct FabricInstaller {
+    version: String,
+    stable: bool,
+}
+
+/// Fabric's meta server builds the server launcher for any loader and
+/// installer pair, so there is no installer to run.
+async fn install_fabric(
+    client: &Client,
+    root: &Path,
+    minecraft: &Version,
+    loader: &Version,
+) -> Result<(), FileError> {
+    let url = Url::parse(&format!("{}/versions/installer", FABRIC_META)).expect("valid meta URL");
+    let installers: Vec<FabricInstaller> = http::get_json(client, &url).await?;
+    let Some(installer) = installers.iter().find(|i| i.stable) else {
+        return Err(FileError::Io {
+            path: PathBuf::from(FABRIC_LAUNCHER_JAR),
+            source: std::io::Error::other("Fabric lists no stable installer"),
+        });
+    };
+
+    let url = Url::parse(&format!(
+        "{}/versions/loader/{}/{}/{}/server/jar",
+        FABRIC_META, minecraft, loader, installer.version
+    ))
+    .expect("valid meta URL");
+    http::download(client, &url, &root.join(FABRIC_LAUNCHER_JAR)).await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct QuiltInstaller {
+    url: Url,
+}
+
+/// Quilt, unlike Fabric, only ships the launcher through its installer,
+/// which is run in server mode.
+async fn install_quilt(
+    client: &Client,
+    root: &Path,
+    minecraft: &Version,
+    loader: &Version,
+) -> Result<(), FileError> {
+    let url = Url::parse(&format!("{}/versions/installer", QUILT_META)).expect("valid meta URL");
+    let installers: Vec<QuiltInstaller> = http::get_json(client, &url).await?;
+    // Listed newest first.
+    let Some(installer) = installers.first() else {
+        return Err(FileError::Io {
+            path: PathBuf::from(QUILT_LAUNCHER_JAR),
+            source: std::io::Error::other("Quilt lists no installer"),
+        });
+    };
+    let path = root.join(STATE_DIR).join(INSTALLER_FILE_NAME);
+    http::download(client, &installer.url, &path).await?;
+
+    let root = root.to_path_buf();
+    let required = required_java(minecraft);
+    let args = vec![
+        "install".into(),
+        "server".into(),
+        minecraft.to_string(),
+        loader.to_string(),
+        "--install-dir=.".into(),
+    ];
+    tokio::task::spawn_blocking(move || run_installer(&root, required, &args)).await?
+}
+
+/// Downloads the Forge or NeoForge installer and runs it in headless
+/// server mode inside `root`.
+async fn install_forge(
+    client: &Client,
+    root: &Path,
+    target: &ServerTarget,
+) -> Result<(), FileError> {
+    let mc = &target.minecraft;
+    let url = match &target.loader {
+        Loader::Forge(v) => format!(
+            "{0}/net/minecraftforge/forge/{1}-{2}/forge-{1}-{2}-installer.jar",
+            FORGE_MAVEN, mc, v
+        ),
+        Loader::NeoForge(v) if *mc == Version::new(1, 20, 1) => format!(
+            "{0}/net/neoforged/forge/{1}-{2}/forge-{1}-{2}-installer.jar",
+            NEOFORGE_MAVEN, mc, v
+        ),
+        Loader::NeoForge(v) => format!(
+            "{0}/net/neoforged/neoforge/{1}/neoforge-{1}-installer.jar",
+            NEOFORGE_MAVEN, v
+        ),
+        _ => unreachable!("only called for Forge and NeoForge"),
+    };
+    let url = Url::parse(&url).expect("valid maven URL");
+    let installer = root.join(STATE_DIR).join(INSTALLER_FILE_NAME);
+    http::download(client, &url, &installer).await?;
+
+    let root = root.to_path_buf();
+    let required = required_java(mc);
+    let args = vec!["--installServer".to_string()];
+    tokio::task::spawn_blocking(move || run_installer(&root, required, &args)).await?
+}
+
+/// Runs the downloaded installer with `args` inside `root`, using the
+/// `java` that suits the Minecraft version or else whichever one start
+/// scripts would use. Its output is kept in `installer.log` next to it when
+/// it fails.
+fn run_installer(root: &Path, required: u32, args: &[String]) -> Result<(), FileError> {
+    // The installer runs inside `root`, so relative paths would no longer
+    // point at it or at a provisioned java.
+    let root = fs::canonicalize(root).at(root)?;
+    let installer = root.join(STATE_DIR).join(INSTALLER_FILE_NAME);
+    let installs = find_java(&root);
+    let java = installs
+        .iter()
+        .find(|java| java.major == required)
+        .or(installs.first())
+        .ok_or_else(|| FileError::Io {
+            path: installer.clone(),
+            source: std::io::Error::other(
+                "no java found to run the installer (sync with --java provision)",
+            ),
+        })?;
+
+    let output = Command::new(&java.path)
+        .arg("-jar")
+        .arg(&installer)
+        .args(args)
+        .current_dir(&root)
+        .output()
+        .at(&java.path)?;
+    if !output.status.success() {
+        let log = root.join(STATE_DIR).join("installer.log");
+        fs::write(&log, [output.stdout, output.stderr].concat()).at(&log)?;
+        return Err(FileError::Io {
+            path: installer,
+            source: std::io::Error::other(format!(
+                "installer exited with {} (see {})",
+                output.status,
+                log.display()
+            )),
+        });
+    }
+    fs::remove_file(&installer).at(&installer)
+}
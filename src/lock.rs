@@ -0,0 +1,49 @@
+use crate::errors::{FileError, IoContext};
+use crate::util::STATE_DIR;
+use std::{
+    fs::{self, File, TryLockError},
+    io::Write,
+    path::Path,
+};
+
+/// Name of the lock file inside [`STATE_DIR`].
+pub const LOCK_FILE_NAME: &str = "sync.lock";
+
+/// Exclusive advisory lock on a target directory, held for the duration of
+/// a sync and released when dropped.
+#[derive(Debug)]
+pub(crate) struct SyncLock {
+    _file: File,
+}
+
+impl SyncLock {
+    /// Locks `root`, failing with [`FileError::Locked`] if another process
+    /// holds the lock, or blocking until it is released when `wait` is set.
+    pub(crate) fn acquire(root: &Path, wait: bool) -> Result<Self, FileError> {
+        let dir = root.join(STATE_DIR);
+        fs::create_dir_all(&dir).at(&dir)?;
+        let path = dir.join(LOCK_FILE_NAME);
+        let mut file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .at(&path)?;
+
+        if wait {
+            file.lock().at(&path)?;
+        } else {
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => return Err(FileError::Locked { path }),
+                Err(TryLockError::Error(err)) => return Err(err).at(&path),
+            }
+        }
+
+        // Only informational, so a failure here doesn't matter.
+        let _ = file
+            .set_len(0)
+            .and_then(|_| writeln!(file, "{}", std::process::id()));
+        Ok(SyncLock { _file: file })
+    }
+}
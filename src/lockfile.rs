@@ -0,0 +1,92 @@
+use crate::mrpack::{MRFile, MRIndex};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Written to the root of the target directory after each successful sync.
+pub const LOCKFILE_NAME: &str = "observed.lock";
+
+/// The pack state a directory was last synced to: which pack version, the
+/// expected hashes of every installed index file, and digests of the
+/// overrides that were shipped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lockfile {
+    pub name: String,
+    pub version_id: String,
+    pub dependencies: BTreeMap<String, String>,
+    pub files: BTreeMap<PathBuf, LockedFile>,
+    /// Hex SHA-256 of each override's content as shipped by the pack.
+    pub overrides: BTreeMap<PathBuf, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockedFile {
+    #[serde(with = "hex::serde")]
+    pub sha1: [u8; 20],
+    #[serde(with = "hex::serde")]
+    pub sha512: [u8; 64],
+    pub file_size: u64,
+}
+
+impl Lockfile {
+    pub(crate) fn new(
+        index: &MRIndex,
+        files: &[MRFile],
+        overrides: &HashMap<PathBuf, Vec<u8>>,
+    ) -> Self {
+        Lockfile {
+            name: index.name.clone(),
+            version_id: index.version_id.clone(),
+            dependencies: index
+                .dependencies
+                .iter()
+                .map(|(id, version)| (id.as_str().to_string(), version.to_string()))
+                .collect(),
+            files: files
+                .iter()
+                .map(|f| {
+                    let locked = LockedFile {
+                        sha1: f.hashes.sha1,
+                        sha512: f.hashes.sha512,
+                        file_size: f.file_size,
+                    };
+                    (f.path.clone(), locked)
+                })
+                .collect(),
+            overrides: overrides
+                .iter()
+                .map(|(path, content)| (path.clone(), content_digest(content)))
+                .collect(),
+        }
+    }
+
+    /// Reads `<root>/observed.lock`, or `None` if the directory has never
+    /// been synced.
+    pub fn load(root: &Path) -> io::Result<Option<Self>> {
+        match fs::read(root.join(LOCKFILE_NAME)) {
+            Ok(data) => serde_json::from_slice(&data).map(Some).map_err(Into::into),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Replaces `<root>/observed.lock` atomically.
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        let mut data = serde_json::to_vec_pretty(self)?;
+        data.push(b'\n');
+        let tmp = root.join(format!("{}.tmp", LOCKFILE_NAME));
+        fs::write(&tmp, data)?;
+        fs::rename(tmp, root.join(LOCKFILE_NAME))
+    }
+}
+
+/// Hex SHA-256 of override content, as recorded in the lockfile.
+pub fn content_digest(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
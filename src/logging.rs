@@ -0,0 +1,151 @@
+use crate::systemd;
+use std::{
+    fmt,
+    fs::OpenOptions,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tracing::{Event, Level, Subscriber, field::Field};
+use tracing_subscriber::{
+    EnvFilter, Layer,
+    field::Visit,
+    fmt::{FmtContext, FormatEvent, FormatFields, format::Writer},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+};
+
+pub type LogError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Log file used when `--log-file` isn't given.
+pub const LOG_FILE_ENV: &str = "OBSERVE_LOG_FILE";
+
+/// Where logs go and how much of them, from the command line.
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    /// `-v` count: 1 for per-file events, 2 for every download and check.
+    pub verbose: u8,
+    /// Only warnings and errors on stderr.
+    pub quiet: bool,
+    /// Also append every event, as JSON lines, to this file.
+    pub file: Option<PathBuf>,
+    /// Nothing on stderr, for a full-screen UI that owns the terminal.
+    pub silent: bool,
+}
+
+/// Sends `tracing` events to stderr as plain lines, and to the log file as
+/// JSON lines. `RUST_LOG` overrides the levels of both, e.g.
+/// `RUST_LOG=observe_rs::syncer=trace`.
+pub fn init(options: &LogOptions) -> Result<(), LogError> {
+    let stderr_level = match (options.quiet, options.verbose) {
+        (true, _) => "warn",
+        (false, 0) => "info",
+        (false, 1) => "debug",
+        (false, _) => "trace",
+    };
+    let stderr = (!options.silent).then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_writer(std::io::stderr)
+            .event_format(HumanFormat {
+                journal: systemd::stderr_is_journal(),
+            })
+            .with_filter(filter(stderr_level))
+    });
+
+    let file = match &options.file {
+        Some(path) => {
+            let file = open(path)?;
+            let level = if options.verbose > 1 {
+                "trace"
+            } else {
+                "debug"
+            };
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .flatten_event(true)
+                    .with_writer(Mutex::new(file))
+                    .with_filter(filter(level)),
+            )
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(stderr)
+        .with(file)
+        .try_init()?;
+    Ok(())
+}
+
+fn open(path: &Path) -> Result<std::fs::File, LogError> {
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty());
+    parent
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| OpenOptions::new().create(true).append(true).open(path))
+        .map_err(|err| format!("could not open log file {}: {}", path.display(), err).into())
+}
+
+/// `RUST_LOG` if set, otherwise `level` for this crate and warnings for
+/// its dependencies.
+fn filter(level: &str) -> EnvFilter {
+    EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,observe_rs={}", level)))
+}
+
+/// Just the message, prefixed like the CLI always has for warnings and
+/// errors; fields are for the log file. Under systemd each line starts
+/// with its syslog priority for the journal.
+struct HumanFormat {
+    journal: bool,
+}
+
+impl<S, N> FormatEvent<S, N> for HumanFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let level = *event.metadata().level();
+        if self.journal {
+            let priority = match level {
+                Level::ERROR => 3,
+                Level::WARN => 4,
+                Level::INFO => 6,
+                Level::DEBUG | Level::TRACE => 7,
+            };
+            write!(writer, "<{}>", priority)?;
+        }
+        match level {
+            Level::ERROR => write!(writer, "Error: ")?,
+            Level::WARN => write!(writer, "Warning: ")?,
+            _ => {}
+        }
+        let mut message = MessageVisitor(None);
+        event.record(&mut message);
+        writeln!(writer, "{}", message.0.unwrap_or_default())
+    }
+}
+
+struct MessageVisitor(Option<String>);
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
@@ -1,92 +1,2610 @@
-use clap::Parser;
-use mrpack::MRIndex;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
-use std::path::PathBuf;
-use zip::ZipArchive;
+mod daemon;
 
-use crate::mod_manager::ModManager;
-
-mod errors;
-mod mod_manager;
-mod mrpack;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum, error::ErrorKind};
+use daemon::{CONTROL_TOKEN_ENV, DEFAULT_DAEMON_LISTEN, WEBHOOK_SECRET_ENV, daemon, run_detached};
+use dialoguer::MultiSelect;
+use futures_util::StreamExt;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use observe_rs::{
+    CancellationToken, Choices, Config, DownloadCache, DownloadPolicy, FileStatus, HashPolicy,
+    JavaPolicy, LinkMode, MRFile, OptionalPolicy, OverrideChoice, OverrideModes, Pack, PackSource,
+    PackSyncer, PruneCandidate, PrunePolicy, PruneRules, Secrets, SyncEvent, SyncObserver,
+    SyncPhase, SyncReport, SyncSummary, VerifyPolicy,
+    additions::Additions,
+    changelog::Changelog,
+    config::{CONFIG_FILE_NAME, Schedules},
+    create::{self, CreateOptions},
+    curseforge,
+    diff::PackDiff,
+    doctor::{self, CheckStatus},
+    duplicates::find_duplicates,
+    errors::{ErrorCategory, FileError, error_chain},
+    fleet::{self, Fleet, FleetServer},
+    history::{History, SyncRun},
+    http,
+    info::PackInfo,
+    lockfile::Lockfile,
+    logging::{self, LogOptions},
+    metrics::Metrics,
+    mod_dependencies::missing_dependencies,
+    modlist::Modlist,
+    modrinth::{ModrinthClient, ModrinthSource, Project, Version},
+    mrpack::{DependencyId, Requirement},
+    notify::{Notification, Notifier},
+    observer::ProgressLines,
+    pack::IndexError,
+    packwiz,
+    rcon::{ServerControl, ServerState},
+    remote::{self, DeployOptions, DeployReport},
+    sbom::Sbom,
+    scaffold,
+    schedule::CronSchedule,
+    trash::{TRASH_DIR, purge_trash},
+    tui::Dashboard,
+    upgrade,
+    util::{display_timestamp, parse_byte_size},
+};
+use ratatui::{
+    DefaultTerminal,
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+};
+use sha1::{Digest, Sha1};
+use std::{
+    cmp::Reverse,
+    collections::BTreeSet,
+    io::{IsTerminal, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+use tracing::{debug, error, info, trace, warn};
+use url::Url;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct Args {
-    #[arg(short, long, value_name = "FILE")]
-    path: PathBuf,
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    after_help = EXIT_CODES_HELP
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    sync: SyncArgs,
+    /// Also append logs to this file as JSON lines, at debug level or above; RUST_LOG overrides the levels
+    #[arg(long, global = true, env = logging::LOG_FILE_ENV, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+}
+
+impl Cli {
+    /// How to log, from `-v`, `-q` and `--log-file`.
+    fn log_options(&self) -> LogOptions {
+        let args = match &self.command {
+            None => Some(&self.sync),
+            Some(command) => command.sync_args(),
+        };
+        LogOptions {
+            verbose: args.map_or(0, |args| args.verbose),
+            quiet: args.is_some_and(|args| args.quiet),
+            file: self.log_file.clone(),
+            // The dashboard owns the terminal.
+            silent: matches!(self.command, Some(Command::Tui(_))),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Sync a server directory against a pack (the default)
+    Sync(Box<SyncArgs>),
+    /// Download a modpack from Modrinth by project slug and sync it
+    Install {
+        /// Project slug, optionally with a version: `slug` or `slug@1.2.0`
+        #[arg(value_name = "SLUG[@VERSION]")]
+        spec: String,
+        #[command(flatten)]
+        sync: Box<SyncArgs>,
+    },
+    /// Re-sync when the installed Modrinth pack has a newer version
+    Update {
+        /// Project slug [default: the pack recorded by install]
+        slug: Option<String>,
+        /// Only report whether an update is available
+        #[arg(long)]
+        check_only: bool,
+        #[command(flatten)]
+        sync: Box<SyncArgs>,
+    },
+    /// Bump the pack's mods to their newest Modrinth versions for its loader and Minecraft version, then sync
+    Upgrade {
+        /// Only list the available upgrades
+        #[arg(long)]
+        check_only: bool,
+        /// Also write the upgraded pack to this .mrpack
+        #[arg(long, value_name = "FILE", conflicts_with = "check_only")]
+        out: Option<PathBuf>,
+        #[command(flatten)]
+        sync: Box<SyncArgs>,
+    },
+    /// Sync into a local staging directory, then upload only what changed to a server over SSH
+    Deploy {
+        /// Server directory to deploy to: user@host:/srv/mc, or ssh://user@host:port/srv/mc
+        #[arg(
+            long,
+            value_name = "USER@HOST:/DIR",
+            value_parser = remote::parse_ssh_destination,
+            conflicts_with = "target"
+        )]
+        ssh: Url,
+        #[command(flatten)]
+        sync: Box<SyncArgs>,
+    },
+    /// Sync every server listed in a fleet file, several at once
+    Fleet {
+        /// Fleet file with a [servers.NAME] table per server and shared [defaults]
+        #[arg(value_name = "FILE", default_value = fleet::FLEET_FILE_NAME)]
+        file: PathBuf,
+        /// Servers to sync at once [default: 4, or `parallel` in the fleet file]
+        #[arg(long, value_name = "N")]
+        parallel: Option<usize>,
+        #[command(flatten)]
+        sync: Box<SyncArgs>,
+    },
+    /// Serve an HTTP API to trigger and monitor syncs: GET /status, /lock and /metrics, POST /sync, /verify and /webhook
+    Daemon {
+        /// Address to serve the API on, unless systemd passes a socket to use
+        #[arg(long, value_name = "ADDR", default_value = DEFAULT_DAEMON_LISTEN)]
+        listen: SocketAddr,
+        /// Bearer token every request must carry; required to listen beyond localhost
+        #[arg(
+            long,
+            env = CONTROL_TOKEN_ENV,
+            hide_env_values = true,
+            value_name = "TOKEN"
+        )]
+        control_token: Option<String>,
+        /// Secret for POST /webhook, which checks GitHub-style X-Hub-Signature-256 HMACs
+        #[arg(
+            long,
+            env = WEBHOOK_SECRET_ENV,
+            hide_env_values = true,
+            value_name = "SECRET"
+        )]
+        webhook_secret: Option<String>,
+        /// Sync on this cron schedule, e.g. "0 4 * * *" for 04:00 daily
+        #[arg(long, value_name = "CRON")]
+        schedule_sync: Option<CronSchedule>,
+        /// Verify on this cron schedule, e.g. "0 * * * *" for hourly
+        #[arg(long, value_name = "CRON")]
+        schedule_verify: Option<CronSchedule>,
+        /// Update the installed Modrinth pack to its newest version on this cron schedule
+        #[arg(long, value_name = "CRON")]
+        schedule_update: Option<CronSchedule>,
+        #[command(flatten)]
+        sync: Box<SyncArgs>,
+    },
+    /// Check an installed server against a pack without changing anything; exits 6 on drift
+    Verify(Box<SyncArgs>),
+    /// Keep watching an installed server and report files that stop matching the pack
+    Watch(Box<SyncArgs>),
+    /// Sync from a full-screen dashboard showing each file's progress, drift and prune candidates
+    Tui(Box<SyncArgs>),
+    /// Check disk space, permissions, network and Java before a sync
+    Doctor(Box<SyncArgs>),
+    /// Show a pack's metadata, dependencies and contents
+    Info {
+        /// A .mrpack path or an http(s) URL
+        #[arg(value_name = "FILE|URL")]
+        pack: PackSource,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        output: OutputFormat,
+    },
+    /// Compare two packs: files, overrides and dependency versions
+    Diff {
+        /// The older pack, as a .mrpack path or an http(s) URL
+        #[arg(value_name = "OLD")]
+        old: PackSource,
+        /// The newer pack, as a .mrpack path or an http(s) URL
+        #[arg(value_name = "NEW")]
+        new: PackSource,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        output: OutputFormat,
+    },
+    /// Write Markdown release notes between two packs, naming mods via Modrinth
+    Changelog {
+        /// The older pack, as a .mrpack path or an http(s) URL
+        #[arg(value_name = "OLD")]
+        old: PackSource,
+        /// The newer pack, as a .mrpack path or an http(s) URL
+        #[arg(value_name = "NEW")]
+        new: PackSource,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        output: OutputFormat,
+    },
+    /// Publish a pack's mods with names, versions, authors and links
+    Modlist {
+        /// A .mrpack path or an http(s) URL
+        #[arg(value_name = "FILE|URL")]
+        pack: PackSource,
+        #[arg(long, value_enum, default_value_t = ModlistFormat::Markdown)]
+        format: ModlistFormat,
+        /// Write to this file instead of stdout
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// Report the licenses of what a pack ships, as a summary or a CycloneDX or SPDX SBOM
+    Sbom {
+        /// A .mrpack path or an http(s) URL
+        #[arg(value_name = "FILE|URL")]
+        pack: PackSource,
+        #[arg(long, value_enum, default_value_t = SbomFormat::Summary)]
+        format: SbomFormat,
+        /// Write to this file instead of stdout
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// List the files in a pack's index
+    List {
+        /// A .mrpack path or an http(s) URL
+        #[arg(value_name = "FILE|URL")]
+        pack: PackSource,
+        /// Only files this side installs
+        #[arg(long, value_enum)]
+        side: Option<Side>,
+        /// Order by path, size (largest first) or download host
+        #[arg(long, value_enum, default_value_t = SortKey::Path)]
+        sort: SortKey,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        output: OutputFormat,
+    },
+    /// List past syncs of a server directory
+    History {
+        /// Server directory [default: from observe.toml, else current directory]
+        #[arg(short, long, value_name = "DIR")]
+        dir: Option<PathBuf>,
+        /// How many runs to show, newest first
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        output: OutputFormat,
+    },
+    /// Build a .mrpack from a server directory, the reverse of sync
+    Pack {
+        /// Server directory
+        #[arg(value_name = "DIR", default_value = ".")]
+        dir: PathBuf,
+        /// Where to write the pack [default: NAME-VERSION.mrpack]
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+        /// Pack name [default: from observed.lock, else the directory name]
+        #[arg(long)]
+        name: Option<String>,
+        /// Pack version [default: from observed.lock, else 1.0.0]
+        #[arg(long, value_name = "VERSION")]
+        version_id: Option<String>,
+        #[arg(long)]
+        summary: Option<String>,
+        /// Game or loader version, e.g. minecraft=1.20.1 or fabric-loader=0.15.11 (repeatable) [default: from the last sync]
+        #[arg(long, value_name = "ID=VERSION", value_parser = parse_var)]
+        dependency: Vec<(String, String)>,
+        /// Also bundle this directory as overrides, e.g. kubejs (repeatable)
+        #[arg(long, value_name = "DIR")]
+        include: Vec<PathBuf>,
+    },
+    /// Convert a pack into a packwiz pack.toml/index.toml tree
+    ExportPackwiz {
+        /// A .mrpack path or an http(s) URL
+        #[arg(value_name = "FILE|URL")]
+        pack: PackSource,
+        /// Directory to write the packwiz pack into
+        #[arg(value_name = "DIR")]
+        out: PathBuf,
+    },
+    /// Permanently delete files quarantined by prune
+    PurgeTrash {
+        /// Server directory [default: from observe.toml, else current directory]
+        #[arg(short, long, value_name = "DIR")]
+        dir: Option<PathBuf>,
+        /// Only purge batches older than this many days
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+    },
+    /// Print a shell completion script, e.g. `observe-rs completions bash > /etc/bash_completion.d/observe-rs`
+    Completions {
+        /// The shell to complete in
+        #[arg(value_name = "SHELL")]
+        shell: clap_complete::Shell,
+    },
+    /// Write troff man pages for the CLI, for packagers
+    #[command(hide = true)]
+    Mangen {
+        /// Write one page per command into this directory instead of printing the top-level page
+        #[arg(long, value_name = "DIR")]
+        out: Option<PathBuf>,
+    },
+}
+
+impl Command {
+    fn sync_args(&self) -> Option<&SyncArgs> {
+        match self {
+            Command::Sync(sync)
+            | Command::Install { sync, .. }
+            | Command::Update { sync, .. }
+            | Command::Upgrade { sync, .. }
+            | Command::Deploy { sync, .. }
+            | Command::Fleet { sync, .. }
+            | Command::Daemon { sync, .. }
+            | Command::Verify(sync)
+            | Command::Watch(sync)
+            | Command::Tui(sync)
+            | Command::Doctor(sync) => Some(sync),
+            _ => None,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+struct SyncArgs {
+    /// Pack to sync: a .mrpack or packwiz pack.toml, as a path or an http(s) URL.
+    /// Repeat to layer packs, later ones winning where they overlap; with
+    /// install and update, these layer on top of the Modrinth pack
+    #[arg(short, long, value_name = "FILE|URL")]
+    path: Vec<PackSource>,
+    /// Server directory to sync into [default: current directory]
+    #[arg(short, long, value_name = "DIR")]
+    dir: Option<PathBuf>,
+    /// Deploy the synced server to a remote target, e.g. sftp://mc@host/srv/minecraft or
+    /// s3://bucket/prefix; the pack is synced into --dir [default: a staging directory in the
+    /// cache] and then uploaded
+    #[arg(long, value_name = "URL", value_parser = remote::parse_target)]
+    target: Option<Url>,
+    /// Config file [default: ./observe.toml if present]
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
     #[arg(long)]
     prune: bool,
+    /// What prune does with unknown files: quarantine or delete [default: quarantine]
+    #[arg(long, value_name = "MODE")]
+    prune_mode: Option<PrunePolicy>,
+    /// Only prune files matching this glob (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    prune_include: Vec<String>,
+    /// Never prune files matching this glob, e.g. 'config/luckperms/**' (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    prune_exclude: Vec<String>,
+    /// Prune without asking for confirmation
+    #[arg(short, long)]
+    yes: bool,
+    /// Server-optional files: include, exclude, or ask [default: include]
+    #[arg(long, value_name = "POLICY")]
+    optional: Option<OptionalPolicy>,
+    /// Pick optional files to install and edited overrides to keep before syncing, saving the choices to observe.toml [default: only new ones, under --optional ask]
+    #[arg(long)]
+    pick: bool,
+    /// Modrinth personal access token for API and CDN requests
+    #[arg(
+        long,
+        env = http::TOKEN_ENV,
+        hide_env_values = true,
+        value_name = "TOKEN"
+    )]
+    api_token: Option<String>,
+    /// Extra files to install alongside the pack [default: additions.toml in the server directory]
+    #[arg(long, value_name = "FILE")]
+    additions: Option<PathBuf>,
+    /// Leave out a pack file, by path or Modrinth project id; prune may then delete it (repeatable)
+    #[arg(long, value_name = "PATH|ID")]
+    skip: Vec<String>,
+    /// CurseForge API key, needed to resolve the files of CurseForge pack exports
+    #[arg(
+        long,
+        env = curseforge::KEY_ENV,
+        hide_env_values = true,
+        value_name = "KEY"
+    )]
+    curseforge_key: Option<String>,
+    /// Also download from DOMAIN, e.g. a self-hosted mirror (repeatable)
+    #[arg(long, value_name = "DOMAIN")]
+    allow_domain: Vec<String>,
+    /// Reject plain-HTTP download URLs [default]
+    #[arg(long, overrides_with = "allow_insecure")]
+    require_https: bool,
+    /// Allow plain-HTTP download URLs
+    #[arg(long, overrides_with = "require_https")]
+    allow_insecure: bool,
+    #[arg(short = 'j', long)]
+    concurrency: Option<usize>,
+    /// Retries per download URL for transient failures [default: 3]
+    #[arg(long)]
+    retries: Option<u32>,
+    /// Base backoff between retries, in milliseconds [default: 500]
+    #[arg(long, value_name = "MS")]
+    retry_delay: Option<u64>,
+    /// Seconds to wait for a connection [default: 10]
+    #[arg(long, value_name = "SECS")]
+    connect_timeout: Option<u64>,
+    /// Seconds without data before a download is aborted and retried [default: 30]
+    #[arg(long, value_name = "SECS")]
+    read_timeout: Option<u64>,
+    /// Shared download cache [default: ~/.cache/observe-rs]
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+    /// Always download from the network, bypassing the cache
+    #[arg(long, conflicts_with = "cache_dir")]
+    no_download_cache: bool,
+    /// Hashes to check: standard (SHA-1 and SHA-512), cheapest (BLAKE3 if the pack lists it, else SHA-1) or all listed [default: standard]
+    #[arg(long, value_name = "POLICY")]
+    hash_policy: Option<HashPolicy>,
+    /// How to check files already on disk: strict (SHA-1 and SHA-512), fast (a single hash) or skip (existence and size only) [default: strict]
+    #[arg(long, value_name = "POLICY")]
+    verify: Option<VerifyPolicy>,
+    /// Re-hash every existing file instead of trusting size and mtime
+    #[arg(long)]
+    no_cache: bool,
+    /// Overwrite local edits to overrides instead of merging the pack's changes into them
+    #[arg(long)]
+    no_merge: bool,
+    /// Merge .properties overrides key by key, keeping local keys the pack doesn't change
+    #[arg(long)]
+    merge_properties: bool,
+    /// Substitute {{NAME}} and ${NAME} placeholders in overrides from --var, [vars] and the environment
+    #[arg(long)]
+    templates: bool,
+    /// Template variable for --templates, e.g. SERVER_PORT=25566 (repeatable)
+    #[arg(long, value_name = "NAME=VALUE", value_parser = parse_var)]
+    var: Vec<(String, String)>,
+    /// Dotenv or JSON file whose values fill placeholders in overrides; values are never logged
+    #[arg(long, value_name = "FILE")]
+    secrets_file: Option<PathBuf>,
+    /// Only inject secrets into overrides matching this glob (repeatable) [default: all overrides]
+    #[arg(long, value_name = "GLOB")]
+    secret_target: Vec<String>,
+    /// Force this octal Unix mode onto overrides matching GLOB, e.g. '*.sh=755' (repeatable) [default: the modes in the pack]
+    #[arg(long, value_name = "GLOB=MODE", value_parser = parse_var)]
+    override_mode: Vec<(String, String)>,
+    /// Give downloaded files the pack's build time as their modification time, so every machine agrees
+    #[arg(long)]
+    stamp_downloads: bool,
+    /// Prune inside symlinked directories too, as long as they stay inside the server directory
+    #[arg(long)]
+    follow_symlinks: bool,
+    /// Accept the Minecraft EULA (https://aka.ms/MinecraftEULA) by writing eula=true to eula.txt
+    #[arg(long)]
+    accept_eula: bool,
+    /// Don't install the pack's server jar and mod loader (Fabric launcher, or the Quilt, Forge or NeoForge installer)
+    #[arg(long)]
+    no_loader: bool,
+    /// Warn instead of failing when the pack installs the same mod from more than one file
+    #[arg(long)]
+    allow_duplicates: bool,
+    /// Don't look up the pack's mods on Modrinth to warn about missing required dependencies
+    #[arg(long)]
+    no_dependency_check: bool,
+    /// Write start.sh and start.bat that launch the pack's loader with tuned JVM flags
+    #[arg(long)]
+    scaffold: bool,
+    /// Server heap size for the start scripts, e.g. 6G [default: 4G]
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+    memory: Option<u64>,
+    /// Java runtime for the pack's Minecraft version: check, provision (download Temurin into .runtime/) or ignore [default: check]
+    #[arg(long, value_name = "POLICY")]
+    java: Option<JavaPolicy>,
+    /// Cap combined download speed, e.g. 500K, 10M [bytes per second]
+    #[arg(long, value_name = "RATE", value_parser = parse_byte_size)]
+    limit_rate: Option<u64>,
+    /// Cap the speed of each individual download
+    #[arg(long, value_name = "RATE", value_parser = parse_byte_size)]
+    limit_rate_per_download: Option<u64>,
+    /// How cached files are installed: copy, hardlink or reflink [default: copy]
+    #[arg(long, value_name = "MODE")]
+    link_mode: Option<LinkMode>,
+    /// Wait for another sync of the same directory to finish instead of failing
+    #[arg(long)]
+    wait_lock: bool,
+    /// Keep syncing past files that fail, then report them all and exit non-zero
+    #[arg(long)]
+    keep_going: bool,
+    /// Report what would be downloaded, overwritten and pruned without changing anything
+    #[arg(long)]
+    dry_run: bool,
+    /// Write Prometheus metrics here after each sync and verify, e.g. for node_exporter's textfile collector
+    #[arg(long, value_name = "FILE")]
+    metrics_file: Option<PathBuf>,
+    /// Output format: progress bars, or one JSON event per line
+    #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+    output: OutputFormat,
+    /// Only print the final summary and errors
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Log each file as it is handled; repeat for verified files and URLs
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Plain status lines instead of progress bars
+    #[arg(long)]
+    no_progress: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    Server,
+    Client,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SbomFormat {
+    /// Components grouped by license
+    Summary,
+    /// CycloneDX 1.5 JSON
+    Cyclonedx,
+    /// SPDX 2.3 JSON
+    Spdx,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ModlistFormat {
+    Markdown,
+    Html,
+    Csv,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SortKey {
+    Path,
+    Size,
+    Host,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(err) => {
+            let _ = err.print();
+            return match err.kind() {
+                ErrorKind::DisplayHelp | ErrorKind::DisplayVersion => ExitCode::SUCCESS,
+                _ => ExitCode::from(EXIT_USAGE),
+            };
+        }
+    };
+
+    if let Err(err) = logging::init(&cli.log_options()) {
+        eprintln!("Error: {}", err);
+        return ExitCode::from(EXIT_FAILURE);
+    }
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{}", err.error);
+            ExitCode::from(err.code)
+        }
+    }
 }
 
-type IndexError = Box<dyn std::error::Error>;
+const EXIT_FAILURE: u8 = 1;
+const EXIT_BAD_PACK: u8 = 2;
+const EXIT_DOWNLOAD: u8 = 3;
+const EXIT_VERIFICATION: u8 = 4;
+const EXIT_PRUNE: u8 = 5;
+const EXIT_DRIFT: u8 = 6;
+const EXIT_USAGE: u8 = 64;
+const EXIT_CANCELLED: u8 = 130;
+
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0   success
+  1   other errors (I/O, configuration)
+  2   the pack is invalid or breaks the download policy
+  3   files could not be downloaded
+  4   downloaded files failed hash verification
+  5   pruning failed
+  6   verify found files that differ from the pack
+  64  invalid command-line usage
+  130 the sync was cancelled with Ctrl-C";
+
+/// An error and the process exit code it maps to.
+struct CliError {
+    code: u8,
+    error: IndexError,
+}
 
-fn read_index_data(zip: &mut ZipArchive<File>) -> Result<Vec<u8>, IndexError> {
-    for i in 0..zip.len() {
-        let mut file = zip.by_index(i)?;
-        if file.name() == "modrinth.index.json" {
-            let mut buf = Vec::new();
-            file.read_to_end(&mut buf)?;
-            return Ok(buf);
+impl CliError {
+    fn new(code: u8, error: impl Into<IndexError>) -> Self {
+        CliError {
+            code,
+            error: error.into(),
         }
     }
-    Err("modrinth.index.json not found in zip file".into())
 }
 
-fn read_overrides(zip: &mut ZipArchive<File>) -> Result<HashMap<PathBuf, Vec<u8>>, IndexError> {
-    let mut overrides: HashMap<PathBuf, Vec<u8>> = HashMap::new();
+impl<E: Into<IndexError>> From<E> for CliError {
+    fn from(error: E) -> Self {
+        CliError::new(EXIT_FAILURE, error)
+    }
+}
 
-    for i in 0..zip.len() {
-        let mut file = zip.by_index(i)?;
-        let name = file.name().to_string();
+fn exit_code(category: ErrorCategory) -> u8 {
+    match category {
+        ErrorCategory::InvalidPack => EXIT_BAD_PACK,
+        ErrorCategory::Download => EXIT_DOWNLOAD,
+        ErrorCategory::Verification => EXIT_VERIFICATION,
+        ErrorCategory::Prune => EXIT_PRUNE,
+        ErrorCategory::Cancelled => EXIT_CANCELLED,
+        ErrorCategory::Other => EXIT_FAILURE,
+    }
+}
 
-        if let Some(path) = name.strip_prefix("overrides/") {
-            if !path.is_empty() && !file.is_dir() {
-                let mut buf = Vec::new();
-                file.read_to_end(&mut buf)?;
-                overrides.insert(PathBuf::from(path), buf);
+async fn run(cli: Cli) -> Result<(), CliError> {
+    match cli.command {
+        None => sync(cli.sync, None, Action::Sync).await,
+        Some(Command::Sync(args)) => sync(*args, None, Action::Sync).await,
+        Some(Command::Deploy {
+            ssh,
+            sync: mut args,
+        }) => {
+            args.target = Some(ssh);
+            sync(*args, None, Action::Sync).await
+        }
+        Some(Command::Fleet {
+            file,
+            parallel,
+            sync: args,
+        }) => fleet(&file, parallel, *args).await,
+        Some(Command::Daemon {
+            listen,
+            control_token,
+            webhook_secret,
+            schedule_sync,
+            schedule_verify,
+            schedule_update,
+            sync: args,
+        }) => {
+            let schedules = Schedules {
+                sync: schedule_sync,
+                verify: schedule_verify,
+                update: schedule_update,
+            };
+            daemon(listen, control_token, webhook_secret, schedules, *args).await
+        }
+        Some(Command::Verify(args)) => sync(*args, None, Action::Verify).await,
+        Some(Command::Watch(args)) => sync(*args, None, Action::Watch).await,
+        Some(Command::Tui(args)) => tui(*args).await,
+        Some(Command::Install { spec, sync: args }) => {
+            let (slug, version) = match spec.split_once('@') {
+                Some((slug, version)) => (slug, Some(version)),
+                None => (spec.as_str(), None),
+            };
+            let modrinth = ModrinthClient::new(http::client()).with_token(args.api_token.clone());
+            let (project, version) = modrinth.resolve_modpack(slug, version).await?;
+            println!("Installing {} {}", project.title, version.version_number);
+            install_version(*args, &project, &version).await
+        }
+        Some(Command::Update {
+            slug,
+            check_only,
+            sync: args,
+        }) => {
+            let config = load_config(args.config.as_deref())?;
+            let dir = target_dir(&args, &config);
+            let notifier = Notifier::new(&config.notify)?.filter(|_| !args.dry_run);
+            let installed = ModrinthSource::load(&dir)?;
+            let slug = slug
+                .or_else(|| installed.as_ref().map(|source| source.slug.clone()))
+                .ok_or("no installed Modrinth pack recorded: pass a project slug")?;
+
+            let modrinth = ModrinthClient::new(http::client()).with_token(args.api_token.clone());
+            let (project, latest) = modrinth.resolve_modpack(&slug, None).await?;
+
+            let current = installed.filter(|source| source.project_id == project.id);
+            if current
+                .as_ref()
+                .is_some_and(|source| source.version_id == latest.id)
+            {
+                println!(
+                    "{} is up to date ({})",
+                    project.title, latest.version_number
+                );
+                return Ok(());
             }
+
+            let from = current.map_or_else(
+                || "(not installed)".to_string(),
+                |source| source.version_number,
+            );
+            let notification =
+                Notification::update_available(&project.title, &from, &latest.version_number);
+            notify(notifier.as_ref(), notification).await;
+            if check_only {
+                println!(
+                    "Update available for {}: {} -> {}",
+                    project.title, from, latest.version_number
+                );
+                return Ok(());
+            }
+
+            println!(
+                "Updating {}: {} -> {}",
+                project.title, from, latest.version_number
+            );
+            install_version(*args, &project, &latest).await
+        }
+        Some(Command::Upgrade {
+            check_only,
+            out,
+            sync: args,
+        }) => upgrade(*args, check_only, out).await,
+        Some(Command::Doctor(args)) => doctor(*args).await,
+        Some(Command::Info { pack, output }) => {
+            let info = PackInfo::new(&open_pack(&pack).await?);
+            match output {
+                OutputFormat::Human => println!("{}", info),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string(&info).expect("PackInfo serializes")
+                ),
+            }
+            Ok(())
+        }
+        Some(Command::Diff { old, new, output }) => {
+            let diff = PackDiff::new(&open_pack(&old).await?, &open_pack(&new).await?);
+            match output {
+                OutputFormat::Human => println!("{}", diff),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string(&diff).expect("PackDiff serializes")
+                ),
+            }
+            Ok(())
+        }
+        Some(Command::Changelog { old, new, output }) => {
+            let (old, new) = (open_pack(&old).await?, open_pack(&new).await?);
+            let modrinth =
+                ModrinthClient::new(http::client()).with_token(std::env::var(http::TOKEN_ENV).ok());
+            let changelog = Changelog::generate(&modrinth, &old, &new)
+                .await
+                .map_err(|err| {
+                    CliError::new(EXIT_DOWNLOAD, format!("Modrinth lookup failed: {}", err))
+                })?;
+            match output {
+                OutputFormat::Human => println!("{}", changelog),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string(&changelog).expect("Changelog serializes")
+                ),
+            }
+            Ok(())
+        }
+        Some(Command::Modlist { pack, format, out }) => {
+            let pack = open_pack(&pack).await?;
+            let modrinth =
+                ModrinthClient::new(http::client()).with_token(std::env::var(http::TOKEN_ENV).ok());
+            let modlist = Modlist::resolve(&modrinth, &pack).await.map_err(|err| {
+                CliError::new(EXIT_DOWNLOAD, format!("Modrinth lookup failed: {}", err))
+            })?;
+            let rendered = match format {
+                ModlistFormat::Markdown => modlist.to_markdown(),
+                ModlistFormat::Html => modlist.to_html(),
+                ModlistFormat::Csv => modlist.to_csv(),
+                ModlistFormat::Json => {
+                    serde_json::to_string(&modlist).expect("Modlist serializes") + "\n"
+                }
+            };
+            match out {
+                Some(path) => std::fs::write(&path, rendered)
+                    .map_err(|err| format!("{}: {}", path.display(), err))?,
+                None => print!("{}", rendered),
+            }
+            Ok(())
+        }
+        Some(Command::Sbom { pack, format, out }) => {
+            let pack = open_pack(&pack).await?;
+            let modrinth =
+                ModrinthClient::new(http::client()).with_token(std::env::var(http::TOKEN_ENV).ok());
+            let sbom = Sbom::resolve(&modrinth, &pack).await.map_err(|err| {
+                CliError::new(EXIT_DOWNLOAD, format!("Modrinth lookup failed: {}", err))
+            })?;
+            let rendered = match format {
+                SbomFormat::Summary => sbom.license_summary().to_string(),
+                SbomFormat::Cyclonedx => sbom.to_cyclonedx(),
+                SbomFormat::Spdx => sbom.to_spdx(),
+            } + "\n";
+            match out {
+                Some(path) => std::fs::write(&path, rendered)
+                    .map_err(|err| format!("{}: {}", path.display(), err))?,
+                None => print!("{}", rendered),
+            }
+            Ok(())
+        }
+        Some(Command::List {
+            pack,
+            side,
+            sort,
+            output,
+        }) => {
+            let pack = open_pack(&pack).await?;
+            let mut files: Vec<&MRFile> = pack
+                .index
+                .files
+                .iter()
+                .filter(|file| match side {
+                    Some(Side::Server) => file.server_requirement() != Requirement::Unsupported,
+                    Some(Side::Client) => file.client_requirement() != Requirement::Unsupported,
+                    None => true,
+                })
+                .collect();
+            match sort {
+                SortKey::Path => files.sort_by(|a, b| a.path.cmp(&b.path)),
+                SortKey::Size => files.sort_by_key(|file| Reverse(file.file_size)),
+                SortKey::Host => files
+                    .sort_by(|a, b| (primary_host(a), &a.path).cmp(&(primary_host(b), &b.path))),
+            }
+            match output {
+                OutputFormat::Human => print_files(&files),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string(&files).expect("MRFile serializes")
+                ),
+            }
+            Ok(())
+        }
+        Some(Command::History { dir, limit, output }) => {
+            let dir = match dir {
+                Some(dir) => dir,
+                None => load_config(None)?.dir.unwrap_or_else(|| PathBuf::from(".")),
+            };
+            let runs = match History::open_existing(&dir)? {
+                Some(history) => history.recent(limit)?,
+                None => Vec::new(),
+            };
+            match output {
+                OutputFormat::Human => print_history(&runs),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string(&runs).expect("SyncRun serializes")
+                ),
+            }
+            Ok(())
+        }
+        Some(Command::Pack {
+            dir,
+            out,
+            name,
+            version_id,
+            summary,
+            dependency,
+            include,
+        }) => {
+            let lockfile = Lockfile::load(&dir)?;
+            let mut dependencies = create::installed_dependencies(&dir);
+            for (id, version) in dependency {
+                let version = semver::Version::parse(&version)
+                    .map_err(|err| format!("invalid version `{}` for {}: {}", version, id, err))?;
+                dependencies.insert(DependencyId::from(id.as_str()), version);
+            }
+            let options = CreateOptions {
+                name: name
+                    .or_else(|| lockfile.as_ref().map(|l| l.name.clone()))
+                    .or_else(|| {
+                        std::path::absolute(&dir)
+                            .ok()?
+                            .file_name()
+                            .map(|n| n.to_string_lossy().into_owned())
+                    })
+                    .unwrap_or_else(|| "server".to_string()),
+                version_id: version_id
+                    .or_else(|| lockfile.map(|l| l.version_id))
+                    .unwrap_or_else(|| "1.0.0".to_string()),
+                summary,
+                dependencies,
+                include,
+            };
+            if !options.dependencies.contains_key(&DependencyId::Minecraft) {
+                return Err(CliError::new(
+                    EXIT_USAGE,
+                    "no Minecraft version recorded for this directory: pass --dependency minecraft=VERSION",
+                ));
+            }
+
+            let modrinth =
+                ModrinthClient::new(http::client()).with_token(std::env::var(http::TOKEN_ENV).ok());
+            let created = create::create(&modrinth, &dir, options).await?;
+            let pack = &created.pack;
+            let out = out.unwrap_or_else(|| {
+                PathBuf::from(format!(
+                    "{}-{}.mrpack",
+                    pack.index.name, pack.index.version_id
+                ))
+            });
+            pack.save(&out)?;
+
+            println!(
+                "Wrote {} with {} files from Modrinth and {} overrides",
+                out.display(),
+                pack.index.files.len(),
+                pack.overrides.len()
+            );
+            if !created.bundled.is_empty() {
+                println!(
+                    "Not found on Modrinth, bundled as overrides (check their licenses allow redistribution):"
+                );
+                for path in &created.bundled {
+                    println!("  {}", path.display());
+                }
+            }
+            Ok(())
+        }
+        Some(Command::ExportPackwiz { pack, out }) => {
+            let pack = open_pack(&pack).await?;
+            packwiz::export(&pack, &out)?;
+            println!(
+                "Exported {} {} to {}",
+                pack.index.name,
+                pack.index.version_id,
+                out.join(packwiz::PACK_FILE_NAME).display()
+            );
+            Ok(())
+        }
+        Some(Command::PurgeTrash { dir, older_than }) => {
+            let dir = match dir {
+                Some(dir) => dir,
+                None => load_config(None)?.dir.unwrap_or_else(|| PathBuf::from(".")),
+            };
+            let purged = purge_trash(
+                &dir,
+                older_than.map(|days| Duration::from_secs(days * 86_400)),
+            )?;
+            println!("Purged {} quarantined batches", purged);
+            Ok(())
+        }
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                env!("CARGO_BIN_NAME"),
+                &mut std::io::stdout(),
+            );
+            Ok(())
+        }
+        Some(Command::Mangen { out }) => {
+            match out {
+                Some(dir) => std::fs::create_dir_all(&dir)
+                    .and_then(|()| clap_mangen::generate_to(Cli::command(), &dir))
+                    .map_err(|err| format!("{}: {}", dir.display(), err))?,
+                None => clap_mangen::Man::new(Cli::command())
+                    .render(&mut std::io::stdout())
+                    .map_err(|err| format!("stdout: {}", err))?,
+            }
+            Ok(())
         }
     }
+}
 
-    for i in 0..zip.len() {
-        let mut file = zip.by_index(i)?;
-        let name = file.name().to_string();
+fn parse_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("invalid variable `{}` (expected NAME=VALUE)", s)),
+    }
+}
+
+fn load_config(path: Option<&Path>) -> Result<Config, IndexError> {
+    match path {
+        Some(path) => Config::load(path),
+        None => Ok(Config::discover(".")?.unwrap_or_default()),
+    }
+}
+
+fn target_dir(args: &SyncArgs, config: &Config) -> PathBuf {
+    args.dir
+        .clone()
+        .or_else(|| config.dir.clone())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Where a server deployed to `target` is staged when no `--dir` is given:
+/// one directory per target, so later syncs only upload what changed.
+fn staging_dir(target: &Url, cache_dir: Option<&Path>) -> PathBuf {
+    let base = cache_dir.map_or_else(
+        || std::env::temp_dir().join("observe-rs"),
+        Path::to_path_buf,
+    );
+    let hash = hex::encode(Sha1::digest(target.as_str().as_bytes()));
+    base.join("targets").join(format!(
+        "{}-{}",
+        target.host_str().unwrap_or_default(),
+        &hash[..12]
+    ))
+}
+
+/// The packs to layer, base first: those given on the command line, else
+/// the one in the config file.
+fn pack_sources(
+    sources: Vec<PackSource>,
+    config_pack: Option<String>,
+) -> Result<Vec<PackSource>, CliError> {
+    match (sources.is_empty(), config_pack) {
+        (false, _) => Ok(sources),
+        (true, Some(pack)) => Ok(vec![pack.parse()?]),
+        (true, None) => Err("no pack given: pass --path or set `pack` in observe.toml".into()),
+    }
+}
 
-        if let Some(path) = name.strip_prefix("server-overrides/") {
-            if !path.is_empty() && !file.is_dir() {
-                let mut buf = Vec::new();
-                file.read_to_end(&mut buf)?;
-                overrides.insert(PathBuf::from(path), buf);
+/// Where remote packs are downloaded before they are opened.
+fn pack_download_dir(cache_dir: Option<&Path>) -> PathBuf {
+    cache_dir.map_or_else(
+        || std::env::temp_dir().join("observe-rs"),
+        |dir| dir.join("packs"),
+    )
+}
+
+/// Loads `sources` and layers them, base first.
+async fn load_packs(
+    client: &reqwest::Client,
+    sources: &[PackSource],
+    download_dir: &Path,
+    token: Option<&str>,
+    curseforge_key: Option<&str>,
+) -> Result<Pack, CliError> {
+    let mut pack: Option<Pack> = None;
+    for source in sources {
+        let layer = source
+            .load(client, download_dir, token, curseforge_key)
+            .await
+            .map_err(|err| {
+                // A pack that couldn't be fetched is a network problem, not a bad pack.
+                let code = if err.downcast_ref::<reqwest::Error>().is_some() {
+                    EXIT_DOWNLOAD
+                } else {
+                    EXIT_BAD_PACK
+                };
+                if sources.len() > 1 {
+                    CliError::new(code, format!("{}: {}", source, err))
+                } else {
+                    CliError::new(code, err)
+                }
+            })?;
+        pack = Some(match pack {
+            Some(base) => base.overlay(layer),
+            None => layer,
+        });
+    }
+    Ok(pack.expect("pack_sources returns at least one source"))
+}
+
+/// Opens a pack for the commands that only read it, downloading it first
+/// if `source` is a URL.
+async fn open_pack(source: &PackSource) -> Result<Pack, CliError> {
+    let download_dir = pack_download_dir(DownloadCache::default_dir().as_deref());
+    let token = std::env::var(http::TOKEN_ENV).ok();
+    let curseforge_key = std::env::var(curseforge::KEY_ENV).ok();
+    source
+        .load(
+            &http::client(),
+            &download_dir,
+            token.as_deref(),
+            curseforge_key.as_deref(),
+        )
+        .await
+        .map_err(|err| {
+            let code = if err.downcast_ref::<reqwest::Error>().is_some() {
+                EXIT_DOWNLOAD
+            } else {
+                EXIT_BAD_PACK
+            };
+            CliError::new(code, format!("{}: {}", source, err))
+        })
+}
+
+/// Runs the preflight checks and fails if any of them did.
+async fn doctor(args: SyncArgs) -> Result<(), CliError> {
+    let config = load_config(args.config.as_deref())?;
+    let target_dir = target_dir(&args, &config);
+    let cache_dir = args
+        .cache_dir
+        .clone()
+        .or(config.cache_dir.clone())
+        .or_else(DownloadCache::default_dir);
+    let sources = pack_sources(args.path, config.pack)?;
+
+    let checks = doctor::diagnose(
+        &http::client(),
+        &sources,
+        &pack_download_dir(cache_dir.as_deref()),
+        args.api_token.as_deref(),
+        args.curseforge_key.as_deref(),
+        &target_dir,
+    )
+    .await;
+    for check in &checks {
+        println!("{}", check);
+    }
+
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Fail)
+        .count();
+    let warned = checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Warn)
+        .count();
+    if failed > 0 {
+        return Err(format!("{} checks failed, {} warnings", failed, warned).into());
+    }
+    println!("All checks passed ({} warnings)", warned);
+    Ok(())
+}
+
+/// Finds newer versions of the installed mods, then syncs the pack with
+/// them swapped in and removes the files they replace.
+async fn upgrade(
+    mut args: SyncArgs,
+    check_only: bool,
+    out: Option<PathBuf>,
+) -> Result<(), CliError> {
+    let config = load_config(args.config.as_deref())?;
+    let target_dir = target_dir(&args, &config);
+    let cache_dir = args
+        .cache_dir
+        .clone()
+        .or(config.cache_dir.clone())
+        .or_else(DownloadCache::default_dir);
+    let download_dir = pack_download_dir(cache_dir.as_deref());
+    let sources = pack_sources(std::mem::take(&mut args.path), config.pack)?;
+
+    let client = http::client();
+    let mut pack = load_packs(
+        &client,
+        &sources,
+        &download_dir,
+        args.api_token.as_deref(),
+        args.curseforge_key.as_deref(),
+    )
+    .await?;
+    let installed = Lockfile::load(&target_dir)?
+        .map(|lockfile| lockfile.files.into_keys().collect::<BTreeSet<_>>());
+
+    let modrinth = ModrinthClient::new(client).with_token(args.api_token.clone());
+    let upgrades = upgrade::find_upgrades(&modrinth, &pack, installed.as_ref())
+        .await
+        .map_err(|err| CliError::new(EXIT_DOWNLOAD, format!("Modrinth lookup failed: {}", err)))?;
+    if upgrades.is_empty() {
+        println!("All mods are up to date");
+        return Ok(());
+    }
+    println!("Upgrades available:");
+    for upgrade in &upgrades {
+        println!("  {}", upgrade);
+    }
+    if check_only {
+        return Ok(());
+    }
+
+    upgrade::apply(&mut pack, &upgrades);
+    let out = match out {
+        Some(out) => out,
+        None => {
+            std::fs::create_dir_all(&download_dir)
+                .map_err(|err| format!("{}: {}", download_dir.display(), err))?;
+            download_dir.join("upgraded.mrpack")
+        }
+    };
+    pack.save(&out)
+        .map_err(|err| format!("{}: {}", out.display(), err))?;
+
+    let dry_run = args.dry_run;
+    args.path = vec![PackSource::Path(out)];
+    sync(args, None, Action::Sync).await?;
+
+    if !dry_run {
+        for upgrade in &upgrades {
+            if upgrade.old_path == upgrade.file.path {
+                continue;
+            }
+            let old = target_dir.join(&upgrade.old_path);
+            match std::fs::remove_file(&old) {
+                Ok(()) => println!("Removed {}", upgrade.old_path.display()),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(format!("{}: {}", old.display(), err).into()),
             }
         }
     }
+    Ok(())
+}
+
+/// Syncs a Modrinth modpack version and records it for `update`.
+async fn install_version(
+    args: SyncArgs,
+    project: &Project,
+    version: &Version,
+) -> Result<(), CliError> {
+    let file = version.mrpack_file().ok_or_else(|| {
+        format!(
+            "{} {} has no .mrpack file",
+            project.title, version.version_number
+        )
+    })?;
+
+    let dry_run = args.dry_run;
+    let dir = target_dir(&args, &load_config(args.config.as_deref())?);
+    sync(args, Some(PackSource::Url(file.url.clone())), Action::Sync).await?;
 
-    Ok(overrides)
+    if !dry_run {
+        ModrinthSource::new(project, version).save(&dir)?;
+    }
+    Ok(())
 }
 
-fn get_index_data(zip_file: &mut ZipArchive<File>) -> Result<MRIndex, IndexError> {
-    let index_data = read_index_data(zip_file)?;
-    serde_json::from_slice(&index_data).map_err(Into::into)
+/// What [`sync`] does with the configured syncer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Sync,
+    /// Audit the server against the pack instead of changing it.
+    Verify,
+    /// Report drift from the pack as it happens.
+    Watch,
 }
 
-fn main() -> Result<(), IndexError> {
-    let args = Args::parse();
+/// A configured sync, ready to run.
+struct PreparedSync {
+    syncer: PackSyncer,
+    pack: Pack,
+    /// Where the synced directory is uploaded afterwards, if anywhere.
+    deploy: Option<Deploy>,
+    /// Where outcomes are posted, if anywhere.
+    notifier: Option<Notifier>,
+    /// How to coordinate with the running server, if at all.
+    control: Option<ServerControl>,
+    /// Where metrics are written, if anywhere.
+    metrics_file: Option<PathBuf>,
+}
 
-    let file = File::open(args.path)?;
-    let mut zip_file = ZipArchive::new(file)?;
+struct Deploy {
+    url: Url,
+    dir: PathBuf,
+    options: DeployOptions,
+}
 
-    let modrinth_index = get_index_data(&mut zip_file)?;
-    let overrides = read_overrides(&mut zip_file)?;
+async fn sync(args: SyncArgs, source: Option<PackSource>, action: Action) -> Result<(), CliError> {
+    let config = load_config(args.config.as_deref())?;
+    let (output, quiet, verbose) = (args.output, args.quiet, args.verbose);
+    let (no_progress, yes, dry_run) = (args.no_progress, args.yes, args.dry_run);
+    let config_path = args
+        .config
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(CONFIG_FILE_NAME));
+    let ask = args.optional.or(config.optional) == Some(OptionalPolicy::Ask);
+    let prune_mode = args
+        .prune_mode
+        .or(config.prune_mode)
+        .unwrap_or(PrunePolicy::Quarantine);
+    let (pick_all, choices) = (args.pick, config.choices.clone());
+    let PreparedSync {
+        mut syncer,
+        pack,
+        deploy,
+        notifier,
+        control,
+        metrics_file,
+    } = prepare_sync(args, config, source, action).await?;
 
-    let manager = ModManager::new(modrinth_index, overrides, args.prune);
+    match action {
+        Action::Sync => {}
+        Action::Verify => return verify(syncer, pack, output, notifier, metrics_file).await,
+        Action::Watch => return watch(syncer, pack, output, notifier).await,
+    }
+    if pick_all || ask {
+        syncer = pick(syncer, &pack, choices, &config_path, pick_all).await?;
+    }
+    let server = prepare_server(control.as_ref(), &syncer, &pack).await?;
+
+    match output {
+        OutputFormat::Json => {
+            syncer = syncer.on_progress(|event| {
+                if let Ok(line) = serde_json::to_string(event) {
+                    println!("{}", line);
+                }
+            });
+        }
+        OutputFormat::Human if quiet => {
+            syncer = syncer.on_progress(|event| match event {
+                SyncEvent::SyncSummary(summary) => print_summary(summary),
+                SyncEvent::Planned(plan) => println!("{}", plan),
+                _ => {}
+            });
+        }
+        OutputFormat::Human => {
+            syncer = syncer.progress_bars(verbose == 0 && !no_progress);
+        }
+    }
+    syncer = syncer.on_progress(log_event);
+    let cancel = CancellationToken::new();
+    syncer = syncer.cancel_token(cancel.clone());
+    tokio::spawn(cancel_on_ctrl_c(cancel));
+
+    if !yes && !dry_run {
+        syncer = syncer.confirm_prune(move |candidates| confirm_prune(candidates, prune_mode));
+    }
+
+    let label = pack_label(&pack);
+    let started = Instant::now();
+    let result = syncer.sync(pack).await;
+    restore_server(control.as_ref(), server).await;
+    let mut metrics = Metrics::default();
+    record_sync(&mut metrics, metrics_file.as_deref(), started, &result);
+    notify_sync(notifier.as_ref(), &label, &result).await;
+    let report = match (result, output) {
+        (Ok(report), _) => report,
+        (Err(err), OutputFormat::Human) => {
+            let message = format!("Sync failed: {}", error_chain(&err));
+            return Err(CliError::new(exit_code(err.category()), message));
+        }
+        (Err(err), OutputFormat::Json) => {
+            println!(
+                "{}",
+                serde_json::json!({ "event": "sync_failed", "error": error_chain(&err) })
+            );
+            return Err(CliError::new(exit_code(err.category()), err));
+        }
+    };
 
-    match manager.sync() {
-        Ok(_) => println!("Sync completed successfully"),
-        Err(err) => println!("Sync failed: {}", err),
+    if output == OutputFormat::Human && !quiet {
+        println!("{}", report.summary);
+    }
+    if !report.is_success() {
+        if output == OutputFormat::Human {
+            print_failures(&report);
+        }
+        return Err(report_failure(&report));
+    }
+    if output == OutputFormat::Human && report.conflicts().next().is_some() {
+        print_conflicts(&report);
+    }
+    if output == OutputFormat::Human && !quiet {
+        println!("Sync completed successfully");
     }
 
+    if let Some(deploy) = deploy {
+        let report = run_deploy(deploy).await?;
+        match output {
+            OutputFormat::Human if !quiet => println!("{}", report),
+            OutputFormat::Human => {}
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::json!({ "event": "deployed", "report": report })
+            ),
+        }
+    }
     Ok(())
 }
+
+/// The error for a sync that finished with files it could not install.
+fn report_failure(report: &SyncReport) -> CliError {
+    let failed = report.failures().count();
+    let code = report
+        .failures()
+        .find_map(|f| match f.status {
+            FileStatus::Failed { category, .. } => Some(exit_code(category)),
+            _ => None,
+        })
+        .unwrap_or(EXIT_FAILURE);
+    let message = format!("Sync failed: {} file(s) could not be installed", failed);
+    CliError::new(code, message)
+}
+
+/// Warns players and saves, or stops, the server over RCON before a sync
+/// that will change something. `None` when nothing was done.
+async fn prepare_server(
+    control: Option<&ServerControl>,
+    syncer: &PackSyncer,
+    pack: &Pack,
+) -> Result<Option<ServerState>, CliError> {
+    let Some(control) = control else {
+        return Ok(None);
+    };
+    let plan = syncer
+        .clone()
+        .plan(pack.clone())
+        .await
+        .map_err(|err| CliError::new(exit_code(err.category()), err))?;
+    if plan.is_empty() {
+        return Ok(None);
+    }
+    let state = control
+        .before_sync()
+        .await
+        .map_err(|err| format!("RCON: {}", err))?;
+    match state {
+        ServerState::NotRunning => info!(
+            address = control.address(),
+            "Nothing answers RCON on {}; syncing without warning players",
+            control.address()
+        ),
+        ServerState::Saved => info!("Warned players and saved the world over RCON"),
+        ServerState::Stopped => info!("Stopped the server over RCON"),
+    }
+    Ok(Some(state))
+}
+
+/// Starts the server again if [`prepare_server`] stopped it.
+async fn restore_server(control: Option<&ServerControl>, state: Option<ServerState>) {
+    let (Some(control), Some(state)) = (control, state) else {
+        return;
+    };
+    match control.after_sync(state).await {
+        Ok(()) if state == ServerState::Stopped => info!("Started the server again"),
+        Ok(()) => {}
+        Err(err) => warn!("failed to start the server again: {}", err),
+    }
+}
+
+/// Cancels `token` on Ctrl-C, so the sync stops and rolls back what it
+/// staged. A second Ctrl-C exits at once.
+async fn cancel_on_ctrl_c(token: CancellationToken) {
+    if tokio::signal::ctrl_c().await.is_err() {
+        return;
+    }
+    warn!("cancelling the sync; press Ctrl-C again to exit at once");
+    token.cancel();
+    if tokio::signal::ctrl_c().await.is_ok() {
+        std::process::exit(EXIT_CANCELLED.into());
+    }
+}
+
+/// Adds a sync's outcome to `metrics`, then writes them to `file` if given.
+fn record_sync(
+    metrics: &mut Metrics,
+    file: Option<&Path>,
+    started: Instant,
+    result: &Result<SyncReport, FileError>,
+) {
+    match result {
+        Ok(report) => metrics.record_sync(
+            started.elapsed(),
+            Some(&report.summary),
+            report.is_success(),
+        ),
+        Err(_) => metrics.record_sync(started.elapsed(), None, false),
+    }
+    write_metrics(metrics, file);
+}
+
+fn write_metrics(metrics: &Metrics, file: Option<&Path>) {
+    if let Some(file) = file
+        && let Err(err) = metrics.write(file)
+    {
+        warn!("could not write metrics to {}: {}", file.display(), err);
+    }
+}
+
+/// A pack as notifications name it.
+fn pack_label(pack: &Pack) -> String {
+    format!("{} {}", pack.index.name, pack.index.version_id)
+}
+
+/// Posts `notification` if there is somewhere to, warning rather than
+/// failing when that doesn't work.
+async fn notify(notifier: Option<&Notifier>, notification: Notification) {
+    if let Some(notifier) = notifier
+        && let Err(err) = notifier.send(&notification).await
+    {
+        warn!("failed to send a notification: {}", err);
+    }
+}
+
+/// Tells the chat webhooks how syncing `pack` went.
+async fn notify_sync(
+    notifier: Option<&Notifier>,
+    pack: &str,
+    result: &Result<SyncReport, FileError>,
+) {
+    let notification = match result {
+        Ok(report) if report.is_success() => Notification::synced(pack, &report.summary),
+        Ok(report) => {
+            let failed: Vec<PathBuf> = report.failures().map(|f| f.path.clone()).collect();
+            let error = format!("{} file(s) could not be installed", failed.len());
+            Notification::sync_failed(pack, &error, &failed)
+        }
+        Err(err) => Notification::sync_failed(pack, &error_chain(err), &[]),
+    };
+    notify(notifier, notification).await;
+}
+
+/// Uploads a synced directory to its target.
+async fn run_deploy(deploy: Deploy) -> Result<DeployReport, CliError> {
+    let Deploy { url, dir, options } = deploy;
+    tokio::task::spawn_blocking(move || -> Result<_, CliError> {
+        let shown = remote::redacted(&url);
+        let mut target = remote::connect(&url)
+            .map_err(|err| CliError::new(EXIT_DOWNLOAD, format!("{}: {}", shown, err)))?;
+        remote::deploy(target.as_mut(), &dir, &options)
+            .map_err(|err| format!("Deploy to {} failed: {}", shown, err).into())
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+/// Loads the packs and builds the syncer `args` and `config` describe,
+/// leaving output and prompts to the caller.
+async fn prepare_sync(
+    args: SyncArgs,
+    config: Config,
+    source: Option<PackSource>,
+    action: Action,
+) -> Result<PreparedSync, CliError> {
+    let use_cache = !args.no_download_cache
+        && (args.cache_dir.is_some() || config.download_cache.unwrap_or(true));
+    let cache_dir = if use_cache {
+        args.cache_dir
+            .clone()
+            .or(config.cache_dir.clone())
+            .or_else(DownloadCache::default_dir)
+    } else {
+        None
+    };
+
+    let notifier = Notifier::new(&config.notify)?;
+    let deploy_target = match args.target.clone() {
+        Some(url) => Some(url),
+        None => config
+            .target
+            .as_deref()
+            .map(remote::parse_target)
+            .transpose()?,
+    };
+    let target_dir = match &deploy_target {
+        Some(url) if args.dir.is_none() && config.dir.is_none() => {
+            staging_dir(url, cache_dir.as_deref())
+        }
+        _ => target_dir(&args, &config),
+    };
+
+    let sources = pack_sources(source.into_iter().chain(args.path).collect(), config.pack)?;
+    let download_dir = pack_download_dir(cache_dir.as_deref());
+    let connect_timeout = args
+        .connect_timeout
+        .or(config.connect_timeout)
+        .map_or(http::DEFAULT_CONNECT_TIMEOUT, Duration::from_secs);
+    let read_timeout = args
+        .read_timeout
+        .or(config.read_timeout)
+        .map_or(http::DEFAULT_READ_TIMEOUT, Duration::from_secs);
+
+    let client = http::builder()
+        .connect_timeout(connect_timeout)
+        .read_timeout(read_timeout)
+        .build()?;
+    let mut pack = load_packs(
+        &client,
+        &sources,
+        &download_dir,
+        args.api_token.as_deref(),
+        args.curseforge_key.as_deref(),
+    )
+    .await?;
+
+    let skip: Vec<String> = config.skip.into_iter().chain(args.skip).collect();
+    for entry in pack.skip(&skip) {
+        warn!("skip entry `{}` matches nothing in the pack", entry);
+    }
+
+    let additions = match args.additions.or(config.additions) {
+        Some(path) => Some(Additions::load(path)?),
+        None => Additions::discover(&target_dir)?,
+    };
+    if let Some(additions) = additions {
+        pack = additions
+            .apply(pack)
+            .map_err(|err| CliError::new(EXIT_BAD_PACK, err))?;
+    }
+
+    let duplicates = find_duplicates(&pack);
+    if !duplicates.is_empty() {
+        if action == Action::Sync
+            && !(args.allow_duplicates || config.allow_duplicates.unwrap_or(false))
+        {
+            let list: Vec<String> = duplicates.iter().map(ToString::to_string).collect();
+            return Err(CliError::new(
+                EXIT_BAD_PACK,
+                format!(
+                    "the pack installs the same mod more than once: {}; leave one out with --skip, or pass --allow-duplicates",
+                    list.join("; ")
+                ),
+            ));
+        }
+        for duplicate in &duplicates {
+            warn!("duplicate {}", duplicate);
+        }
+    }
+
+    if action == Action::Sync
+        && !args.no_dependency_check
+        && config.check_dependencies.unwrap_or(true)
+    {
+        let modrinth = ModrinthClient::new(client.clone()).with_token(args.api_token.clone());
+        match missing_dependencies(&modrinth, &pack).await {
+            Ok(missing) => {
+                for dependency in missing {
+                    warn!("missing dependency {}", dependency);
+                }
+            }
+            Err(err) => warn!("could not check mod dependencies: {}", err),
+        }
+    }
+
+    if target_dir.exists() && !target_dir.is_dir() {
+        return Err(format!("{} is not a directory", target_dir.display()).into());
+    }
+
+    let mut prune_rules = PruneRules::new();
+    if let Some(dirs) = config.prune_index_dirs {
+        prune_rules = prune_rules.index_dirs(dirs);
+    }
+    if let Some(dirs) = config.prune_override_dirs {
+        prune_rules = prune_rules.override_dirs(dirs);
+    }
+    let prune_rules = prune_rules
+        .include(config.prune_include.iter().chain(&args.prune_include))?
+        .exclude(config.prune_exclude.iter().chain(&args.prune_exclude))?;
+
+    let download_policy = config
+        .allow_domains
+        .iter()
+        .chain(&args.allow_domain)
+        .fold(DownloadPolicy::new(), |policy, domain| {
+            policy.allow_domain(domain)
+        })
+        .require_https(
+            args.require_https || !(args.allow_insecure || config.allow_insecure.unwrap_or(false)),
+        );
+
+    let prune = args.prune || config.prune.unwrap_or(false);
+    let verify_policy = args.verify.or(config.verify).unwrap_or_default();
+    let mut syncer = PackSyncer::new()
+        .prune_rules(prune_rules)
+        .download_policy(download_policy)
+        .api_token(args.api_token)
+        .rate_limit(
+            args.limit_rate.or(config
+                .limit_rate
+                .as_deref()
+                .map(parse_byte_size)
+                .transpose()?),
+        )
+        .per_download_rate_limit(
+            args.limit_rate_per_download.or(config
+                .limit_rate_per_download
+                .as_deref()
+                .map(parse_byte_size)
+                .transpose()?),
+        )
+        .optional(args.optional.or(config.optional).unwrap_or_default())
+        .select_optional(select_optional)
+        .choices(config.choices)
+        .target_dir(target_dir.clone())
+        .prune(if prune {
+            args.prune_mode
+                .or(config.prune_mode)
+                .unwrap_or(PrunePolicy::Quarantine)
+        } else {
+            PrunePolicy::Disabled
+        })
+        .concurrency(args.concurrency.or(config.concurrency).unwrap_or(8))
+        .retries(args.retries.or(config.retries).unwrap_or(3))
+        .retry_delay(Duration::from_millis(
+            args.retry_delay.or(config.retry_delay).unwrap_or(500),
+        ))
+        .connect_timeout(connect_timeout)
+        .read_timeout(read_timeout)
+        .link_mode(args.link_mode.or(config.link_mode).unwrap_or_default())
+        .hash_policy(args.hash_policy.or(config.hash_policy).unwrap_or_default())
+        .verify_policy(verify_policy)
+        .state_cache(!args.no_cache && config.state_cache.unwrap_or(true))
+        .merge_overrides(!args.no_merge && config.merge_overrides.unwrap_or(true))
+        .merge_properties(args.merge_properties || config.merge_properties.unwrap_or(false))
+        .templates(args.templates || config.templates.unwrap_or(false))
+        .accept_eula(args.accept_eula || config.accept_eula.unwrap_or(false))
+        .install_loader(!args.no_loader && config.install_loader.unwrap_or(true))
+        .java(args.java.or(config.java).unwrap_or_default())
+        .keep_going(args.keep_going)
+        .wait_for_lock(args.wait_lock)
+        .dry_run(args.dry_run);
+
+    for (name, value) in config.vars.into_iter().chain(args.var) {
+        syncer = syncer.template_var(name, value);
+    }
+
+    if args.scaffold || config.scaffold.unwrap_or(false) {
+        let memory = match args.memory {
+            Some(memory) => memory,
+            None => config
+                .memory
+                .as_deref()
+                .map(parse_byte_size)
+                .transpose()?
+                .unwrap_or(scaffold::DEFAULT_MEMORY),
+        };
+        syncer = syncer.scaffold(memory);
+    }
+
+    if let Some(path) = args.secrets_file.or(config.secrets_file) {
+        let targets = if args.secret_target.is_empty() {
+            config.secret_targets
+        } else {
+            args.secret_target
+        };
+        syncer = syncer.secrets(Secrets::load(path)?.targets(targets)?);
+    }
+    let override_modes = if args.override_mode.is_empty() {
+        OverrideModes::new(config.override_modes)?
+    } else {
+        OverrideModes::new(args.override_mode)?
+    };
+    syncer = syncer
+        .override_modes(override_modes)
+        .stamp_downloads(args.stamp_downloads || config.stamp_downloads.unwrap_or(false))
+        .follow_symlinks(args.follow_symlinks || config.follow_symlinks.unwrap_or(false));
+    if let Some(dir) = cache_dir {
+        syncer = syncer.cache_dir(dir);
+    }
+
+    let notifier = notifier.filter(|_| !args.dry_run);
+    let metrics_file = args
+        .metrics_file
+        .clone()
+        .or(config.metrics_file.clone())
+        .filter(|_| !args.dry_run);
+    let control = match &config.rcon {
+        Some(rcon) if action == Action::Sync && !args.dry_run => {
+            Some(ServerControl::new(rcon, &target_dir).map_err(|err| format!("[rcon]: {}", err))?)
+        }
+        _ => None,
+    };
+    let deploy = deploy_target.filter(|_| !args.dry_run).map(|url| Deploy {
+        url,
+        dir: target_dir,
+        options: DeployOptions {
+            prune,
+            verify_hashes: verify_policy == VerifyPolicy::Strict,
+        },
+    });
+    Ok(PreparedSync {
+        syncer,
+        pack,
+        deploy,
+        notifier,
+        control,
+        metrics_file,
+    })
+}
+
+/// Servers a fleet syncs at once unless told otherwise.
+const DEFAULT_FLEET_PARALLEL: usize = 4;
+
+/// Refuses settings under which a sync would stop to ask something, for
+/// syncs nobody is watching.
+fn check_unattended(args: &SyncArgs, config: &Config) -> Result<(), String> {
+    if (args.prune || config.prune.unwrap_or(false)) && !args.yes && !args.dry_run {
+        return Err(
+            "pruning needs --yes, as nobody is there to confirm it (or --dry-run to preview)"
+                .into(),
+        );
+    }
+    if args.optional.or(config.optional) == Some(OptionalPolicy::Ask) {
+        return Err("nobody is there to pick optional files: use include or exclude".into());
+    }
+    if args.pick {
+        return Err("nobody is there to --pick: run it in a terminal first".into());
+    }
+    Ok(())
+}
+
+/// A server's line in `observe fleet`'s progress, counting its files and
+/// overrides and naming the last one done.
+struct FleetBar(ProgressBar);
+
+impl SyncObserver for FleetBar {
+    /// Grows the bar by each phase's total as the sync reports it, so
+    /// skipped and ignored files don't keep it short of the end.
+    fn on_phase_start(&self, _phase: SyncPhase, total: u64) {
+        self.0.inc_length(total);
+    }
+
+    fn on_file_done(&self, path: &Path, _status: &FileStatus) {
+        self.0.inc(1);
+        self.0.set_message(path.display().to_string());
+    }
+}
+
+/// How one server of a fleet sync went.
+struct FleetOutcome {
+    summary: SyncSummary,
+    deployed: Option<DeployReport>,
+}
+
+/// Syncs every server in the fleet file `file`, `parallel` at a time, with
+/// a progress bar each and a combined report at the end. Flags apply to
+/// every server, over what the fleet file sets.
+async fn fleet(file: &Path, parallel: Option<usize>, args: SyncArgs) -> Result<(), CliError> {
+    if args.config.is_some() || args.dir.is_some() || !args.path.is_empty() || args.target.is_some()
+    {
+        return Err(CliError::new(
+            EXIT_USAGE,
+            "--config, --dir, --path and --target are set per server in the fleet file",
+        ));
+    }
+    let fleet = Fleet::load(file)?;
+    let parallel = parallel
+        .or(fleet.parallel)
+        .unwrap_or(DEFAULT_FLEET_PARALLEL);
+    if parallel == 0 {
+        return Err(CliError::new(EXIT_USAGE, "--parallel must be at least 1"));
+    }
+    // Servers sync side by side, so none of them can stop to ask.
+    for server in &fleet.servers {
+        check_unattended(&args, &server.config).map_err(|err| {
+            CliError::new(EXIT_USAGE, format!("server `{}`: {}", server.name, err))
+        })?;
+    }
+
+    let output = args.output;
+    let show_progress = output == OutputFormat::Human && !args.quiet && !args.no_progress;
+    // Bars can't redraw in logs and CI, so each server prints plain lines.
+    let lines = show_progress && !std::io::stderr().is_terminal();
+    let progress = if show_progress && !lines {
+        MultiProgress::new()
+    } else {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    };
+    let width = fleet
+        .servers
+        .iter()
+        .map(|server| server.name.len())
+        .max()
+        .unwrap_or(0);
+    let style = ProgressStyle::default_bar()
+        .template(&format!(
+            "{{prefix:>{}}}: [{{bar:40.cyan/blue}}] {{pos}}/{{len}} {{wide_msg}}",
+            width
+        ))
+        .unwrap()
+        .progress_chars("=> ");
+    let runs = fleet.servers.into_iter().map(|server| {
+        let bar = progress.add(
+            ProgressBar::new(0)
+                .with_style(style.clone())
+                .with_prefix(server.name.clone()),
+        );
+        let name = server.name.clone();
+        let run = sync_fleet_server(server, args.clone(), bar.clone(), lines);
+        async move {
+            let result = run.await;
+            match &result {
+                Ok(_) => bar.finish_with_message("done"),
+                Err(_) => bar.abandon_with_message("failed"),
+            }
+            (name, result)
+        }
+    });
+    let results: Vec<(String, Result<FleetOutcome, CliError>)> = futures_util::stream::iter(runs)
+        .buffered(parallel)
+        .collect()
+        .await;
+
+    let failed: Vec<&CliError> = results
+        .iter()
+        .filter_map(|(_, r)| r.as_ref().err())
+        .collect();
+    match output {
+        OutputFormat::Human => {
+            for (name, result) in &results {
+                match result {
+                    Ok(outcome) => {
+                        let summary = &outcome.summary;
+                        println!(
+                            "{:<width$}  ok      {} downloaded ({}), {} overrides, {} pruned, {} conflicts",
+                            name,
+                            summary.downloaded,
+                            HumanBytes(summary.bytes_downloaded),
+                            summary.overrides_written,
+                            summary.pruned,
+                            summary.merge_conflicts,
+                        );
+                        if let Some(deployed) = &outcome.deployed {
+                            println!("{:<width$}          {}", "", deployed);
+                        }
+                    }
+                    Err(err) => println!("{:<width$}  failed  {}", name, err.error),
+                }
+            }
+        }
+        OutputFormat::Json => {
+            let servers: Vec<serde_json::Value> = results
+                .iter()
+                .map(|(name, result)| match result {
+                    Ok(outcome) => serde_json::json!({
+                        "server": name,
+                        "ok": true,
+                        "summary": outcome.summary,
+                        "deployed": outcome.deployed,
+                    }),
+                    Err(err) => serde_json::json!({
+                        "server": name,
+                        "ok": false,
+                        "error": err.error.to_string(),
+                    }),
+                })
+                .collect();
+            println!(
+                "{}",
+                serde_json::json!({ "event": "fleet_summary", "servers": servers })
+            );
+        }
+    }
+
+    match failed.first() {
+        None => Ok(()),
+        Some(first) => Err(CliError::new(
+            first.code,
+            format!("{} of {} servers failed", failed.len(), results.len()),
+        )),
+    }
+}
+
+/// Syncs, and deploys if configured, one server of a fleet, reporting
+/// progress on `bar`, as plain `lines` or, as JSON, tagged with the
+/// server's name.
+async fn sync_fleet_server(
+    server: FleetServer,
+    args: SyncArgs,
+    bar: ProgressBar,
+    lines: bool,
+) -> Result<FleetOutcome, CliError> {
+    let output = args.output;
+    let PreparedSync {
+        mut syncer,
+        pack,
+        deploy,
+        notifier,
+        control,
+        metrics_file,
+    } = prepare_sync(args, server.config, None, Action::Sync).await?;
+    let state = prepare_server(control.as_ref(), &syncer, &pack).await?;
+
+    let name = server.name;
+    let label = format!("{} on {}", pack_label(&pack), name);
+    syncer = match output {
+        OutputFormat::Human if lines => syncer.observer(ProgressLines::with_prefix(name)),
+        OutputFormat::Human => syncer.observer(FleetBar(bar)),
+        OutputFormat::Json => syncer.on_progress(move |event| {
+            if let Ok(serde_json::Value::Object(mut fields)) = serde_json::to_value(event) {
+                fields.insert("server".into(), name.clone().into());
+                println!("{}", serde_json::Value::Object(fields));
+            }
+        }),
+    };
+
+    let started = Instant::now();
+    let result = syncer.sync(pack).await;
+    restore_server(control.as_ref(), state).await;
+    let mut metrics = Metrics::default();
+    record_sync(&mut metrics, metrics_file.as_deref(), started, &result);
+    notify_sync(notifier.as_ref(), &label, &result).await;
+    let report = result.map_err(|err| {
+        let message = format!("Sync failed: {}", error_chain(&err));
+        CliError::new(exit_code(err.category()), message)
+    })?;
+    if !report.is_success() {
+        return Err(report_failure(&report));
+    }
+    let deployed = match deploy {
+        Some(deploy) => Some(run_deploy(deploy).await?),
+        None => None,
+    };
+    Ok(FleetOutcome {
+        summary: report.summary,
+        deployed,
+    })
+}
+
+/// Prints how the server differs from the pack and fails if it does.
+async fn verify(
+    syncer: PackSyncer,
+    pack: Pack,
+    output: OutputFormat,
+    notifier: Option<Notifier>,
+    metrics_file: Option<PathBuf>,
+) -> Result<(), CliError> {
+    let label = pack_label(&pack);
+    let result = syncer.verify(pack).await;
+    let mut metrics = Metrics::default();
+    metrics.record_verify(result.as_ref().ok());
+    write_metrics(&metrics, metrics_file.as_deref());
+    let report = result.map_err(|err| CliError::new(exit_code(err.category()), err))?;
+    match output {
+        OutputFormat::Human => println!("{}", report),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string(&report).expect("VerifyReport serializes")
+        ),
+    }
+    if report.is_clean() {
+        return Ok(());
+    }
+    notify(notifier.as_ref(), Notification::drift(&label, &report)).await;
+    let drifted = report.missing.len() + report.modified.len() + report.extra.len();
+    Err(CliError::new(
+        EXIT_DRIFT,
+        format!("{} file(s) differ from the pack", drifted),
+    ))
+}
+
+/// Prints drift as the server changes, until interrupted.
+async fn watch(
+    syncer: PackSyncer,
+    pack: Pack,
+    output: OutputFormat,
+    notifier: Option<Notifier>,
+) -> Result<(), CliError> {
+    let mut syncer = match output {
+        OutputFormat::Human => {
+            println!("Watching for changes to managed files (Ctrl-C to stop)");
+            syncer.on_progress(|event| match event {
+                SyncEvent::DriftDetected { path, kind } => {
+                    println!("Drift: {} is {}", path.display(), kind)
+                }
+                SyncEvent::DriftResolved { path } => {
+                    println!(
+                        "Resolved: {} no longer differs from the pack",
+                        path.display()
+                    )
+                }
+                _ => {}
+            })
+        }
+        OutputFormat::Json => syncer.on_progress(|event| {
+            if let Ok(line) = serde_json::to_string(event) {
+                println!("{}", line);
+            }
+        }),
+    };
+    if let Some(notifier) = notifier {
+        let label = pack_label(&pack);
+        let runtime = tokio::runtime::Handle::current();
+        syncer = syncer.on_progress(move |event| {
+            if let SyncEvent::DriftDetected { path, kind } = event {
+                let notifier = notifier.clone();
+                let notification = Notification::file_drifted(&label, path, *kind);
+                runtime.spawn(async move { notify(Some(&notifier), notification).await });
+            }
+        });
+    }
+    syncer
+        .watch(pack)
+        .await
+        .map_err(|err| CliError::new(exit_code(err.category()), err))
+}
+
+/// How often the dashboard redraws while no key is pressed.
+const TUI_TICK: Duration = Duration::from_millis(100);
+
+/// What each dashboard sync needs, cloned into its task.
+#[derive(Clone)]
+struct TuiSync {
+    syncer: PackSyncer,
+    pack: Pack,
+    notifier: Option<Notifier>,
+    control: Option<ServerControl>,
+    metrics_file: Option<PathBuf>,
+    /// What approved pruning does.
+    prune_mode: PrunePolicy,
+}
+
+/// Runs the dashboard until the user quits. It verifies on start, then
+/// syncs, retries and prunes as keys are pressed.
+async fn tui(args: SyncArgs) -> Result<(), CliError> {
+    if !std::io::stdout().is_terminal() {
+        return Err(CliError::new(EXIT_USAGE, "the dashboard needs a terminal"));
+    }
+    if args.target.is_some() {
+        return Err(CliError::new(
+            EXIT_USAGE,
+            "the dashboard only syncs local directories; use sync or deploy with --target",
+        ));
+    }
+    let config = load_config(args.config.as_deref())?;
+    if args.pick || args.optional.or(config.optional) == Some(OptionalPolicy::Ask) {
+        return Err(CliError::new(
+            EXIT_USAGE,
+            "the dashboard can't ask about optional files: pick them with sync --pick first",
+        ));
+    }
+    let prune_mode = args
+        .prune_mode
+        .or(config.prune_mode)
+        .unwrap_or(PrunePolicy::Quarantine);
+    let dir = target_dir(&args, &config);
+    let prepared = prepare_sync(args, config, None, Action::Sync).await?;
+    let dashboard = Dashboard::new(&prepared.pack, &dir);
+    let job = TuiSync {
+        syncer: prepared.syncer,
+        pack: prepared.pack,
+        notifier: prepared.notifier,
+        control: prepared.control,
+        metrics_file: prepared.metrics_file,
+        prune_mode,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = run_tui(&mut terminal, job, dashboard).await;
+    ratatui::restore();
+    result
+}
+
+async fn run_tui(
+    terminal: &mut DefaultTerminal,
+    job: TuiSync,
+    dashboard: Dashboard,
+) -> Result<(), CliError> {
+    let mut cancel = CancellationToken::new();
+    let mut running = Some(tui_verify(job.clone(), dashboard.clone()));
+    let mut quitting = false;
+    loop {
+        if running.as_ref().is_some_and(|task| task.is_finished()) {
+            running = None;
+        }
+        if quitting && running.is_none() {
+            return Ok(());
+        }
+        terminal.draw(|frame| dashboard.draw(frame))?;
+
+        let key = tokio::task::block_in_place(|| -> std::io::Result<Option<KeyEvent>> {
+            if !event::poll(TUI_TICK)? {
+                return Ok(None);
+            }
+            Ok(match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => Some(key),
+                _ => None,
+            })
+        })?;
+        let Some(key) = key else {
+            continue;
+        };
+        let idle = running.is_none();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => quitting = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => quitting = true,
+            KeyCode::Char('s') if idle => {
+                cancel = CancellationToken::new();
+                running = Some(tui_sync(job.clone(), dashboard.clone(), cancel.clone()));
+            }
+            // Files that made it verify quickly, so a sync only fetches the
+            // rest again.
+            KeyCode::Char('r') if idle && dashboard.failed() == 0 => {
+                dashboard.set_message("No failed files to retry");
+            }
+            KeyCode::Char('r') if idle => {
+                cancel = CancellationToken::new();
+                running = Some(tui_sync(job.clone(), dashboard.clone(), cancel.clone()));
+            }
+            KeyCode::Char('v') if idle => {
+                running = Some(tui_verify(job.clone(), dashboard.clone()));
+            }
+            // Syncs reset the approval when they finish, so it can only be
+            // given between tasks.
+            KeyCode::Char('p')
+                if idle && dashboard.prune_candidates() == 0 && !dashboard.prune_approved() =>
+            {
+                dashboard.set_message("Nothing to prune");
+            }
+            KeyCode::Char('p') if idle => {
+                if dashboard.toggle_prune() {
+                    dashboard.set_message("The next sync prunes the candidates; press s");
+                } else {
+                    dashboard.set_message("Pruning withdrawn");
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => dashboard.scroll(-1),
+            KeyCode::Down | KeyCode::Char('j') => dashboard.scroll(1),
+            KeyCode::PageUp => dashboard.scroll(-10),
+            KeyCode::PageDown => dashboard.scroll(10),
+            _ => {}
+        }
+        if quitting && !idle {
+            cancel.cancel();
+            dashboard.set_message("Cancelling…");
+        }
+    }
+}
+
+fn tui_verify(job: TuiSync, dashboard: Dashboard) -> tokio::task::JoinHandle<()> {
+    dashboard.start_verify();
+    run_detached(move || async move {
+        let result = job.syncer.verify(job.pack).await;
+        dashboard.finish_verify(result.map_err(|err| error_chain(&err)));
+    })
+}
+
+/// Syncs, pruning if the dashboard approved it, then verifies to refresh
+/// the drift and prune candidates.
+fn tui_sync(
+    job: TuiSync,
+    dashboard: Dashboard,
+    cancel: CancellationToken,
+) -> tokio::task::JoinHandle<()> {
+    dashboard.start_sync();
+    run_detached(move || async move {
+        let prune = if dashboard.prune_approved() {
+            job.prune_mode
+        } else {
+            PrunePolicy::Disabled
+        };
+        let syncer = job
+            .syncer
+            .clone()
+            .observer(dashboard.clone())
+            .on_progress(log_event)
+            .keep_going(true)
+            .prune(prune)
+            .cancel_token(cancel);
+        let label = pack_label(&job.pack);
+        let result = async {
+            let server = prepare_server(job.control.as_ref(), &syncer, &job.pack).await?;
+            let started = Instant::now();
+            let result = syncer.sync(job.pack.clone()).await;
+            restore_server(job.control.as_ref(), server).await;
+            let mut metrics = Metrics::default();
+            record_sync(&mut metrics, job.metrics_file.as_deref(), started, &result);
+            notify_sync(job.notifier.as_ref(), &label, &result).await;
+            result
+                .map(drop)
+                .map_err(|err| CliError::from(error_chain(&err)))
+        }
+        .await;
+        dashboard.finish_sync(result.map_err(|err| err.error.to_string()));
+
+        dashboard.start_verify();
+        let result = job.syncer.verify(job.pack).await;
+        dashboard.finish_verify(result.map_err(|err| error_chain(&err)));
+    })
+}
+
+/// Host of the first download URL, which sync tries first.
+fn primary_host(file: &MRFile) -> &str {
+    file.downloads
+        .first()
+        .and_then(|url| url.host_str())
+        .unwrap_or("-")
+}
+
+fn requirement_label(requirement: Requirement) -> &'static str {
+    match requirement {
+        Requirement::Required => "required",
+        Requirement::Optional => "optional",
+        Requirement::Unsupported => "unsupported",
+    }
+}
+
+fn print_files(files: &[&MRFile]) {
+    if files.is_empty() {
+        println!("No files");
+        return;
+    }
+    let width = files
+        .iter()
+        .map(|file| file.path.display().to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    println!(
+        "{:<width$}  {:>10}  {:<11}  {:<11}  Host",
+        "Path", "Size", "Server", "Client"
+    );
+    for file in files {
+        println!(
+            "{:<width$}  {:>10}  {:<11}  {:<11}  {}",
+            file.path.display().to_string(),
+            HumanBytes(file.file_size).to_string(),
+            requirement_label(file.server_requirement()),
+            requirement_label(file.client_requirement()),
+            primary_host(file)
+        );
+    }
+    let total: u64 = files.iter().map(|file| file.file_size).sum();
+    println!("{} files, {}", files.len(), HumanBytes(total));
+}
+
+fn print_history(runs: &[SyncRun]) {
+    if runs.is_empty() {
+        println!("No syncs recorded");
+        return;
+    }
+    println!(
+        "{:<19}  {:<32}  {:>8}  {:>10}  {:>7}  {:>6}  {:>6}",
+        "Started (UTC)", "Pack", "Duration", "Downloaded", "Changed", "Pruned", "Failed"
+    );
+    for run in runs {
+        let pack = format!("{} {}", run.pack_name, run.pack_version);
+        println!(
+            "{:<19}  {:<32}  {:>8}  {:>10}  {:>7}  {:>6}  {:>6}",
+            display_timestamp(run.started()),
+            pack,
+            format!("{:.1}s", run.duration().as_secs_f64()),
+            HumanBytes(run.bytes_downloaded).to_string(),
+            run.files_changed,
+            run.pruned,
+            run.failed
+        );
+        if let Some(error) = &run.error {
+            println!("  aborted: {}", error);
+        }
+    }
+}
+
+/// Logs per-file progress: at debug level, shown with `-v`, or trace for
+/// every download and check, shown with `-vv`.
+fn log_event(event: &SyncEvent) {
+    match event {
+        SyncEvent::DownloadStarted { path, url } => {
+            trace!(path = %path.display(), %url, "Downloading {} from {}", path.display(), url)
+        }
+        SyncEvent::DownloadFinished {
+            path,
+            url,
+            bytes,
+            elapsed_ms,
+        } => {
+            let mirror = Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_else(|| url.clone());
+            debug!(
+                path = %path.display(),
+                %url,
+                bytes,
+                elapsed_ms,
+                "Downloaded {} ({}) from {} in {:?}, {}/s",
+                path.display(),
+                HumanBytes(*bytes),
+                mirror,
+                Duration::from_millis(*elapsed_ms),
+                HumanBytes(bytes * 1000 / elapsed_ms.max(&1))
+            )
+        }
+        SyncEvent::FileVerified { path } => {
+            trace!(path = %path.display(), "Verified {}", path.display())
+        }
+        SyncEvent::CacheHit { path } => {
+            debug!(path = %path.display(), "Installed {} from cache", path.display())
+        }
+        SyncEvent::OverrideWritten { path } => {
+            debug!(path = %path.display(), "Wrote {}", path.display())
+        }
+        SyncEvent::OverrideSkipped { path } => {
+            debug!(path = %path.display(), "Kept {} (.observeignore)", path.display())
+        }
+        SyncEvent::OverrideKept { path } => {
+            debug!(path = %path.display(), "Kept {} (your choice)", path.display())
+        }
+        SyncEvent::OverrideMerged { path } => {
+            debug!(path = %path.display(), "Merged {}", path.display())
+        }
+        SyncEvent::OverrideConflict { path } => debug!(
+            path = %path.display(),
+            "Kept {} (conflicts with the pack's changes)",
+            path.display()
+        ),
+        SyncEvent::OverrideBackedUp { path, backup } => debug!(
+            path = %path.display(),
+            backup = %backup.display(),
+            "Backed up {} to {}",
+            path.display(),
+            backup.display()
+        ),
+        SyncEvent::FilePruned { path } => {
+            debug!(path = %path.display(), "Pruned {}", path.display())
+        }
+        _ => {}
+    }
+}
+
+fn print_failures(report: &SyncReport) {
+    let width = report
+        .failures()
+        .map(|f| f.path.display().to_string().len())
+        .max()
+        .unwrap_or(0);
+    eprintln!("Failed files:");
+    for file in report.failures() {
+        if let FileStatus::Failed { error, .. } = &file.status {
+            eprintln!("  {:width$}  {}", file.path.display().to_string(), error);
+        }
+    }
+}
+
+fn print_conflicts(report: &SyncReport) {
+    eprintln!("Kept local edits that conflict with the pack's changes (merge them by hand):");
+    for file in report.conflicts() {
+        eprintln!("  {}", file.path.display());
+    }
+}
+
+/// Logs what a sync did, with each count as a field.
+fn log_summary(summary: &SyncSummary) {
+    info!(
+        verified = summary.verified,
+        downloaded = summary.downloaded,
+        bytes_downloaded = summary.bytes_downloaded,
+        cache_hits = summary.cache_hits,
+        overrides_written = summary.overrides_written,
+        overrides_merged = summary.overrides_merged,
+        merge_conflicts = summary.merge_conflicts,
+        pruned = summary.pruned,
+        failed = summary.failed,
+        elapsed_ms = summary.elapsed_ms,
+        "Sync completed"
+    );
+}
+
+fn print_summary(summary: &SyncSummary) {
+    println!("{}", summary);
+}
+
+/// Asks which optional files to install and which edited overrides to keep
+/// before a sync, saving the answers to the `[choices]` of `config_path`.
+/// Only files without a remembered choice are asked about, unless `all`.
+async fn pick(
+    syncer: PackSyncer,
+    pack: &Pack,
+    mut choices: Choices,
+    config_path: &Path,
+    all: bool,
+) -> Result<PackSyncer, CliError> {
+    if !std::io::stdin().is_terminal() {
+        if all {
+            return Err(CliError::new(EXIT_USAGE, "--pick needs a terminal"));
+        }
+        // Said once here rather than by the hook on every build of the sync.
+        let unchosen = pack.index.files.iter().any(|f| {
+            f.server_requirement() == Requirement::Optional
+                && !choices.optional.contains_key(&f.path)
+        });
+        if unchosen {
+            eprintln!("Not running interactively: installing all optional files");
+        }
+        return Ok(syncer.optional(OptionalPolicy::Include));
+    }
+
+    // Without the selection hook, which would ask about the same files.
+    let choosable = syncer
+        .clone()
+        .optional(OptionalPolicy::Include)
+        .choosable(pack.clone())
+        .await?;
+    let optional: Vec<&MRFile> = choosable
+        .optional
+        .iter()
+        .filter(|f| all || !choices.optional.contains_key(&f.path))
+        .collect();
+    let overrides: Vec<&PathBuf> = choosable
+        .overrides
+        .iter()
+        .filter(|path| all || !choices.overrides.contains_key(*path))
+        .collect();
+    if optional.is_empty() && overrides.is_empty() {
+        return Ok(syncer);
+    }
+
+    if !optional.is_empty() {
+        let items: Vec<String> = optional
+            .iter()
+            .map(|f| f.path.display().to_string())
+            .collect();
+        let defaults: Vec<bool> = optional
+            .iter()
+            .map(|f| choices.optional.get(&f.path).copied().unwrap_or(true))
+            .collect();
+        let selection = MultiSelect::new()
+            .with_prompt("Optional server files to install (space toggles, enter confirms)")
+            .items(&items)
+            .defaults(&defaults)
+            .interact()
+            .map_err(|err| format!("picking optional files: {}", err))?;
+        for (i, file) in optional.iter().enumerate() {
+            choices
+                .optional
+                .insert(file.path.clone(), selection.contains(&i));
+        }
+    }
+
+    if !overrides.is_empty() {
+        let items: Vec<String> = overrides
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        let defaults: Vec<bool> = overrides
+            .iter()
+            .map(|path| choices.overrides.get(*path) != Some(&OverrideChoice::Replace))
+            .collect();
+        let selection = MultiSelect::new()
+            .with_prompt(
+                "Edited overrides to keep; unticked ones get the pack's version (space toggles, enter confirms)",
+            )
+            .items(&items)
+            .defaults(&defaults)
+            .interact()
+            .map_err(|err| format!("picking overrides: {}", err))?;
+        for (i, path) in overrides.iter().enumerate() {
+            let choice = if selection.contains(&i) {
+                OverrideChoice::Keep
+            } else {
+                OverrideChoice::Replace
+            };
+            choices.overrides.insert((*path).clone(), choice);
+        }
+    }
+
+    choices.save(config_path)?;
+    info!("Saved your choices to {}", config_path.display());
+    Ok(syncer.choices(choices))
+}
+
+fn select_optional(files: &[MRFile]) -> Vec<PathBuf> {
+    if !std::io::stdin().is_terminal() {
+        eprintln!("Not running interactively: installing all optional files");
+        return files.iter().map(|f| f.path.clone()).collect();
+    }
+
+    let items: Vec<String> = files.iter().map(|f| f.path.display().to_string()).collect();
+    let selection = MultiSelect::new()
+        .with_prompt("Optional server files to install (space toggles, enter confirms)")
+        .items(&items)
+        .defaults(&vec![true; items.len()])
+        .interact()
+        .unwrap_or_else(|_| (0..items.len()).collect());
+
+    selection
+        .into_iter()
+        .map(|i| files[i].path.clone())
+        .collect()
+}
+
+/// Asks before pruning `candidates`, worded for what `mode` does to them.
+fn confirm_prune(candidates: &[PruneCandidate], mode: PrunePolicy) -> bool {
+    let total: u64 = candidates.iter().map(|c| c.size).sum();
+
+    eprintln!("The following files are not part of the pack and would be pruned:");
+    for candidate in candidates {
+        eprintln!(
+            "  {:>10}  {}",
+            HumanBytes(candidate.size).to_string(),
+            candidate.path.display()
+        );
+    }
+
+    if !std::io::stdin().is_terminal() {
+        eprintln!("Skipping prune: not running interactively, pass --yes to confirm");
+        return false;
+    }
+
+    let count = format!("{} files ({})", candidates.len(), HumanBytes(total));
+    match mode {
+        PrunePolicy::Quarantine => eprint!("Move {} to {}? [y/N] ", count, TRASH_DIR),
+        _ => eprint!("Delete {}? [y/N] ", count),
+    }
+    let _ = std::io::stderr().flush();
+
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
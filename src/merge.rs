@@ -0,0 +1,100 @@
+use crate::lockfile::{Lockfile, content_digest};
+use crate::util::STATE_DIR;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Directory inside [`STATE_DIR`] holding the last-synced content of each
+/// override, named by the SHA-256 recorded in the lockfile.
+pub const BASE_DIR: &str = "base";
+
+/// Outcome of merging upstream override changes into a locally edited file.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Merge {
+    /// Both sides' changes applied cleanly.
+    Clean(Vec<u8>),
+    /// Local and upstream edits overlap; the local file should be kept.
+    Conflict,
+}
+
+/// The override content each path had at the last sync, used as the common
+/// ancestor when merging.
+#[derive(Debug, Default)]
+pub(crate) struct MergeBases {
+    root: PathBuf,
+    digests: BTreeMap<PathBuf, String>,
+}
+
+impl MergeBases {
+    /// Loads the bases recorded by `<root>/observed.lock`. Without a
+    /// lockfile there is nothing to merge against.
+    pub(crate) fn load(root: &Path) -> Self {
+        let digests = Lockfile::load(root)
+            .ok()
+            .flatten()
+            .map(|lockfile| lockfile.overrides)
+            .unwrap_or_default();
+        MergeBases {
+            root: root.to_path_buf(),
+            digests,
+        }
+    }
+
+    /// Digest of the last-synced content of `path`, if any.
+    pub(crate) fn digest(&self, path: &Path) -> Option<&String> {
+        self.digests.get(path)
+    }
+
+    /// Last-synced content of `path`, if it was stored and is intact.
+    pub(crate) fn get(&self, path: &Path) -> Option<Vec<u8>> {
+        let digest = self.digests.get(path)?;
+        let content = fs::read(base_dir(&self.root).join(digest)).ok()?;
+        (content_digest(&content) == *digest).then_some(content)
+    }
+}
+
+/// Three-way merges `local` and `upstream` against their common `base`,
+/// line by line.
+pub(crate) fn merge(base: &[u8], local: &[u8], upstream: &[u8]) -> Merge {
+    match diffy::merge_bytes(base, local, upstream) {
+        Ok(merged) => Merge::Clean(merged),
+        Err(_) => Merge::Conflict,
+    }
+}
+
+/// Stores `overrides` as merge bases for the next sync and drops any base
+/// `lockfile` no longer refers to.
+pub(crate) fn save_bases(
+    root: &Path,
+    lockfile: &Lockfile,
+    overrides: &HashMap<PathBuf, Vec<u8>>,
+) -> io::Result<()> {
+    let dir = base_dir(root);
+    fs::create_dir_all(&dir)?;
+
+    for content in overrides.values() {
+        let path = dir.join(content_digest(content));
+        if !path.exists() {
+            fs::write(path, content)?;
+        }
+    }
+
+    let keep: HashSet<&str> = lockfile.overrides.values().map(String::as_str).collect();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        if !entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| keep.contains(name))
+        {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+fn base_dir(root: &Path) -> PathBuf {
+    root.join(STATE_DIR).join(BASE_DIR)
+}
@@ -0,0 +1,208 @@
+use crate::events::SyncSummary;
+use crate::verify::VerifyReport;
+use std::{
+    fmt::Write as _,
+    io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Counters and gauges about syncs and verifies, in the Prometheus text
+/// format for a `/metrics` endpoint or node_exporter's textfile collector.
+/// Counters cover the life of this value, so one run for the CLI.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    syncs_succeeded: u64,
+    syncs_failed: u64,
+    last_sync: Option<SyncSample>,
+    last_success: Option<SystemTime>,
+    /// Summed over every sync.
+    totals: SyncSummary,
+    verifies_succeeded: u64,
+    verifies_failed: u64,
+    last_verify: Option<SystemTime>,
+    /// Missing, modified and extra files the last successful verify found.
+    drift: Option<[usize; 3]>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SyncSample {
+    finished: SystemTime,
+    duration: Duration,
+    ok: bool,
+}
+
+impl Metrics {
+    /// Records a sync that took `duration` and just finished, with what it
+    /// did if it got as far as installing files.
+    pub fn record_sync(&mut self, duration: Duration, summary: Option<&SyncSummary>, ok: bool) {
+        let finished = SystemTime::now();
+        if ok {
+            self.syncs_succeeded += 1;
+            self.last_success = Some(finished);
+        } else {
+            self.syncs_failed += 1;
+        }
+        self.last_sync = Some(SyncSample {
+            finished,
+            duration,
+            ok,
+        });
+        if let Some(summary) = summary {
+            let totals = &mut self.totals;
+            totals.verified += summary.verified;
+            totals.cache_hits += summary.cache_hits;
+            totals.downloaded += summary.downloaded;
+            totals.bytes_downloaded += summary.bytes_downloaded;
+            totals.overrides_written += summary.overrides_written;
+            totals.overrides_merged += summary.overrides_merged;
+            totals.merge_conflicts += summary.merge_conflicts;
+            totals.pruned += summary.pruned;
+            totals.failed += summary.failed;
+        }
+    }
+
+    /// Records a verify that just finished, with its report if it ran.
+    pub fn record_verify(&mut self, report: Option<&VerifyReport>) {
+        self.last_verify = Some(SystemTime::now());
+        match report {
+            Some(report) => {
+                self.verifies_succeeded += 1;
+                self.drift = Some([
+                    report.missing.len(),
+                    report.modified.len(),
+                    report.extra.len(),
+                ]);
+            }
+            None => self.verifies_failed += 1,
+        }
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, f64)]| {
+            let _ = writeln!(out, "# HELP observe_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE observe_{} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "observe_{}{} {}", name, labels, value);
+            }
+        };
+        let totals = &self.totals;
+
+        metric(
+            "syncs_total",
+            "counter",
+            "Syncs run, by result.",
+            &[
+                (r#"{result="success"}"#, self.syncs_succeeded as f64),
+                (r#"{result="failure"}"#, self.syncs_failed as f64),
+            ],
+        );
+        if let Some(sync) = self.last_sync {
+            metric(
+                "last_sync_timestamp_seconds",
+                "gauge",
+                "When the last sync finished.",
+                &[("", unix_seconds(sync.finished))],
+            );
+            metric(
+                "last_sync_duration_seconds",
+                "gauge",
+                "How long the last sync took.",
+                &[("", sync.duration.as_secs_f64())],
+            );
+            metric(
+                "last_sync_success",
+                "gauge",
+                "Whether the last sync succeeded.",
+                &[("", f64::from(u8::from(sync.ok)))],
+            );
+        }
+        if let Some(finished) = self.last_success {
+            metric(
+                "last_successful_sync_timestamp_seconds",
+                "gauge",
+                "When the last successful sync finished.",
+                &[("", unix_seconds(finished))],
+            );
+        }
+        metric(
+            "files_total",
+            "counter",
+            "Pack files handled by syncs, by outcome.",
+            &[
+                (r#"{outcome="verified"}"#, totals.verified as f64),
+                (r#"{outcome="cache_hit"}"#, totals.cache_hits as f64),
+                (r#"{outcome="downloaded"}"#, totals.downloaded as f64),
+                (r#"{outcome="failed"}"#, totals.failed as f64),
+                (r#"{outcome="pruned"}"#, totals.pruned as f64),
+            ],
+        );
+        metric(
+            "downloaded_bytes_total",
+            "counter",
+            "Bytes downloaded by syncs.",
+            &[("", totals.bytes_downloaded as f64)],
+        );
+        metric(
+            "overrides_written_total",
+            "counter",
+            "Overrides written by syncs, merged or not.",
+            &[("", totals.overrides_written as f64)],
+        );
+
+        metric(
+            "verifies_total",
+            "counter",
+            "Verifies run, by result.",
+            &[
+                (r#"{result="success"}"#, self.verifies_succeeded as f64),
+                (r#"{result="failure"}"#, self.verifies_failed as f64),
+            ],
+        );
+        if let Some(finished) = self.last_verify {
+            metric(
+                "last_verify_timestamp_seconds",
+                "gauge",
+                "When the last verify finished.",
+                &[("", unix_seconds(finished))],
+            );
+        }
+        if let Some([missing, modified, extra]) = self.drift {
+            metric(
+                "drifted_files",
+                "gauge",
+                "Files that differed from the pack at the last verify, by how.",
+                &[
+                    (r#"{kind="missing"}"#, missing as f64),
+                    (r#"{kind="modified"}"#, modified as f64),
+                    (r#"{kind="extra"}"#, extra as f64),
+                ],
+            );
+            metric(
+                "drift",
+                "gauge",
+                "Whether the server differed from the pack at the last verify.",
+                &[("", f64::from(u8::from(missing + modified + extra > 0)))],
+            );
+        }
+        out
+    }
+
+    /// Writes the metrics to `path` for node_exporter's textfile collector,
+    /// via a temporary file so it never reads half of them.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut partial = path.as_os_str().to_owned();
+        // The collector only reads `*.prom`, so skips this.
+        partial.push(".tmp");
+        std::fs::write(&partial, self.render())?;
+        std::fs::rename(&partial, path)
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
@@ -0,0 +1,55 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+use url::Url;
+
+/// Head start given to healthier mirrors for each net failure of a host.
+const PENALTY_PER_FAILURE: Duration = Duration::from_millis(500);
+const MAX_PENALTY: Duration = Duration::from_secs(5);
+
+/// Per-host (and port) download outcomes for the current sync, used to hold back
+/// mirrors that keep failing.
+#[derive(Debug, Default)]
+pub(crate) struct HostHealth {
+    hosts: Mutex<HashMap<String, HostStats>>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct HostStats {
+    successes: u32,
+    failures: u32,
+}
+
+impl HostHealth {
+    pub(crate) fn record_success(&self, url: &Url) {
+        self.update(url, |stats| stats.successes += 1);
+    }
+
+    pub(crate) fn record_failure(&self, url: &Url) {
+        self.update(url, |stats| stats.failures += 1);
+    }
+
+    /// How long a request to `url` should wait before joining a race, based
+    /// on how often its host has failed more than it has succeeded.
+    pub(crate) fn penalty(&self, url: &Url) -> Duration {
+        let hosts = self.hosts.lock().unwrap();
+        let net_failures = host_key(url)
+            .and_then(|host| hosts.get(&host))
+            .map_or(0, |stats| stats.failures.saturating_sub(stats.successes));
+        PENALTY_PER_FAILURE
+            .saturating_mul(net_failures)
+            .min(MAX_PENALTY)
+    }
+
+    fn update(&self, url: &Url, f: impl FnOnce(&mut HostStats)) {
+        if let Some(host) = host_key(url) {
+            f(self.hosts.lock().unwrap().entry(host).or_default());
+        }
+    }
+}
+
+fn host_key(url: &Url) -> Option<String> {
+    let host = url.host_str()?;
+    Some(match url.port_or_known_default() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
@@ -0,0 +1,129 @@
+use crate::modrinth::{self, ApiError, ModrinthClient};
+use crate::pack::Pack;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Display,
+};
+
+/// A Modrinth project that mods in the pack require but the pack doesn't
+/// ship, which usually means the server crashes on start.
+#[derive(Debug, Clone, Serialize)]
+pub struct MissingDependency {
+    pub project_id: String,
+    /// The project title, or its id when Modrinth doesn't return it.
+    pub name: String,
+    /// Titles of the projects that require it.
+    pub required_by: Vec<String>,
+}
+
+impl Display for MissingDependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.name == self.project_id {
+            write!(f, "{}", self.name)?;
+        } else {
+            write!(f, "{} ({})", self.name, self.project_id)?;
+        }
+        write!(f, ", required by {}", self.required_by.join(", "))
+    }
+}
+
+/// Resolves the pack's files, and any jars among its overrides, to Modrinth
+/// versions by hash and returns the required dependencies no file in the
+/// pack belongs to. Files Modrinth doesn't know are taken on trust.
+pub async fn missing_dependencies(
+    modrinth: &ModrinthClient,
+    pack: &Pack,
+) -> Result<Vec<MissingDependency>, ApiError> {
+    let mut hashes: BTreeSet<String> = pack
+        .index
+        .files
+        .iter()
+        .map(|file| hex::encode(file.hashes.sha1))
+        .collect();
+    hashes.extend(
+        pack.overrides
+            .iter()
+            .filter(|(path, _)| path.extension().is_some_and(|ext| ext == "jar"))
+            .map(|(_, content)| hex::encode(Sha1::digest(content))),
+    );
+    let versions = modrinth
+        .versions_from_hashes(&hashes.into_iter().collect::<Vec<_>>(), "sha1")
+        .await?;
+    // Two files can resolve to the same version.
+    let versions: BTreeMap<&str, &modrinth::Version> =
+        versions.values().map(|v| (v.id.as_str(), v)).collect();
+
+    let mut present: HashSet<&str> = versions.values().map(|v| v.project_id.as_str()).collect();
+    present.extend(pack.index.files.iter().filter_map(|file| {
+        file.downloads
+            .iter()
+            .find_map(modrinth::project_id_from_url)
+    }));
+
+    let required = || {
+        versions.values().flat_map(|version| {
+            version
+                .dependencies
+                .iter()
+                .filter(|dep| dep.dependency_type == "required")
+                .map(move |dep| (*version, dep))
+        })
+    };
+    // Some dependencies name only a version; look up which project it is.
+    let version_ids: BTreeSet<&str> = required()
+        .filter(|(_, dep)| dep.project_id.is_none())
+        .filter_map(|(_, dep)| dep.version_id.as_deref())
+        .collect();
+    let version_projects: HashMap<String, String> = modrinth
+        .versions_by_id(&version_ids.into_iter().collect::<Vec<_>>())
+        .await?
+        .into_iter()
+        .map(|version| (version.id, version.project_id))
+        .collect();
+
+    let mut missing: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (version, dep) in required() {
+        let Some(project) = dep.project_id.as_ref().or_else(|| {
+            dep.version_id
+                .as_ref()
+                .and_then(|id| version_projects.get(id))
+        }) else {
+            continue;
+        };
+        if !present.contains(project.as_str()) && *project != version.project_id {
+            missing
+                .entry(project.clone())
+                .or_default()
+                .insert(version.project_id.clone());
+        }
+    }
+    if missing.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let ids: BTreeSet<&str> = missing
+        .iter()
+        .flat_map(|(id, requirers)| std::iter::once(id).chain(requirers))
+        .map(String::as_str)
+        .collect();
+    let titles: HashMap<String, String> = modrinth
+        .projects(&ids.into_iter().collect::<Vec<_>>())
+        .await?
+        .into_iter()
+        .map(|project| (project.id, project.title))
+        .collect();
+    let title = |id: &String| titles.get(id).unwrap_or(id).clone();
+
+    let mut missing: Vec<MissingDependency> = missing
+        .iter()
+        .map(|(id, requirers)| MissingDependency {
+            project_id: id.clone(),
+            name: title(id),
+            required_by: requirers.iter().map(title).collect(),
+        })
+        .collect();
+    missing.sort_by_key(|dep| dep.name.to_lowercase());
+    Ok(missing)
+}
@@ -1,226 +1,1601 @@
-use crate::errors::FileError;
+use crate::backup::back_up;
+use crate::choices::{Choosable, OverrideChoice};
+use crate::errors::{FileError, IoContext, error_chain};
+use crate::eula::{self, EULA_FILE_NAME};
+use crate::events::{SyncEvent, SyncSummary};
+use crate::hashing::{FileHasher, HashPolicy, HashingWriter};
+use crate::history::{History, SyncRun};
+use crate::http;
+use crate::java::{RUNTIME_DIR, find_java, provision, required_java};
+use crate::loader::{self, LoaderInstall, ServerTarget};
+use crate::lock::SyncLock;
+use crate::lockfile::{LOCKFILE_NAME, Lockfile};
+use crate::merge::{BASE_DIR, Merge, MergeBases, merge, save_bases};
+use crate::mirrors::HostHealth;
 use crate::mrpack::{MRFile, MRIndex, Requirement};
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use reqwest::blocking::Client;
-use sha1::{Digest, Sha1};
-use sha2::Sha512;
+use crate::observeignore::{IGNORE_FILE_NAME, ObserveIgnore};
+use crate::observer::{ProgressBars, ProgressLines, SharedObserver, SyncObserver, SyncPhase};
+use crate::pack::Pack;
+use crate::plan::{PruneCandidate, SyncPlan};
+use crate::properties::{is_properties, merge_properties};
+use crate::ratelimit::RateLimiter;
+use crate::report::{FileStatus, SkipReason, SyncReport};
+use crate::scaffold::write_start_scripts;
+use crate::state::{STATE_FILE_NAME, StateCache};
+use crate::syncer::{JavaPolicy, OptionalPolicy, PrunePolicy, SyncOptions};
+use crate::template::render;
+use crate::transaction::Transaction;
+use crate::util::{STATE_DIR, file_timestamp, is_contained_path};
+use crate::verify::{DriftKind, VerifyReport};
+use futures_util::{StreamExt, stream, stream::FuturesUnordered};
+use memmap2::Mmap;
+use notify::{RecursiveMode, Watcher};
+use rayon::prelude::*;
+use reqwest::{Client, Response};
 use std::{
-    collections::HashMap,
-    fs::{File, create_dir_all, remove_dir_all, remove_file},
-    io::{Read, Write},
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    io::{ErrorKind, IsTerminal},
     path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    fs::{File, create_dir_all, remove_file, rename},
+    io::AsyncWriteExt,
+    task::spawn_blocking,
+    time::sleep,
 };
 use url::Url;
 use walkdir::WalkDir;
 
+/// How long watch mode waits for a burst of file changes to settle.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
 pub struct ModManager {
+    index: MRIndex,
     files: Vec<MRFile>,
     overrides: HashMap<PathBuf, Vec<u8>>,
+    override_modes: HashMap<PathBuf, u32>,
+    override_mtimes: HashMap<PathBuf, SystemTime>,
+    index_mtime: Option<SystemTime>,
     client: Client,
-    prune: bool,
+    options: SyncOptions,
+    ignore: ObserveIgnore,
+    state: Arc<StateCache>,
+    bases: MergeBases,
+    /// Overrides with secrets substituted in. Their content is kept out of
+    /// observe-rs' own state.
+    secret_overrides: HashSet<PathBuf>,
+    rate_limiter: Option<RateLimiter>,
+    hosts: HostHealth,
 }
 
-const PRUNE_DIRECTORIES_INDEX: &[&str] = &["mods", "resourcepacks"];
-const PRUNE_DIRECTORIES_OVERRIDES: &[&str] = &["config"];
+enum OverrideOutcome {
+    Written {
+        replaced: bool,
+        merged: bool,
+    },
+    /// Kept as is because of `.observeignore`.
+    Protected,
+    /// Local edits clash with the pack's changes; the local file is kept.
+    Conflict,
+    /// The local copy was chosen over the pack's.
+    Kept,
+}
+
+/// Paths written to the staging area, ready to be moved into place.
+#[derive(Default)]
+struct Staged {
+    paths: Vec<PathBuf>,
+    /// Overrides whose existing file has different content; these are
+    /// backed up before being replaced.
+    replaced_overrides: Vec<PathBuf>,
+}
 
 impl ModManager {
-    pub fn new(index: MRIndex, overrides: HashMap<PathBuf, Vec<u8>>, prune: bool) -> Self {
+    pub(crate) fn new(pack: Pack, mut options: SyncOptions) -> Self {
+        let ignore = ObserveIgnore::load(&options.target_dir).unwrap_or_else(|err| {
+            tracing::warn!("ignoring invalid {}: {}", IGNORE_FILE_NAME, err);
+            ObserveIgnore::empty()
+        });
+
+        let (mut files, optional): (Vec<MRFile>, Vec<MRFile>) = pack
+            .index
+            .files
+            .iter()
+            .filter(|f| f.server_requirement() != Requirement::Unsupported)
+            .cloned()
+            .partition(|f| f.server_requirement() == Requirement::Required);
+
+        let (chosen, optional): (Vec<MRFile>, Vec<MRFile>) = optional
+            .into_iter()
+            .partition(|f| options.choices.optional.contains_key(&f.path));
+        files.extend(
+            chosen
+                .into_iter()
+                .filter(|f| options.choices.optional[&f.path]),
+        );
+        match (options.optional, &options.select_optional) {
+            (OptionalPolicy::Include, _) | (OptionalPolicy::Ask, None) => files.extend(optional),
+            (OptionalPolicy::Exclude, _) => {}
+            (OptionalPolicy::Ask, Some(select)) => {
+                let selected = if optional.is_empty() {
+                    Vec::new()
+                } else {
+                    select(&optional)
+                };
+                files.extend(optional.into_iter().filter(|f| selected.contains(&f.path)));
+            }
+        }
+
+        let mut overrides = pack.overrides;
+        let mut secret_overrides = HashSet::new();
+        for (path, content) in overrides.iter_mut() {
+            let secrets = options
+                .secrets
+                .as_ref()
+                .filter(|secrets| secrets.applies_to(path));
+            if secrets.is_none() && !options.templates {
+                continue;
+            }
+
+            let used_secret = Cell::new(false);
+            let lookup = |name: &str| {
+                if let Some(value) = secrets.and_then(|secrets| secrets.get(name)) {
+                    used_secret.set(true);
+                    return Some(value.to_string());
+                }
+                if !options.templates {
+                    return None;
+                }
+                options
+                    .template_vars
+                    .get(name)
+                    .cloned()
+                    .or_else(|| std::env::var(name).ok())
+            };
+            if let Some(rendered) = render(content, lookup) {
+                *content = rendered;
+            }
+            if used_secret.get() {
+                secret_overrides.insert(path.clone());
+            }
+        }
+
+        let state = Arc::new(StateCache::load(&options.target_dir));
+        let bases = if options.merge_overrides || options.merge_properties {
+            MergeBases::load(&options.target_dir)
+        } else {
+            MergeBases::default()
+        };
+
+        if options.progress_bars {
+            let observer: SharedObserver = if std::io::stderr().is_terminal() {
+                Arc::new(ProgressBars::new())
+            } else {
+                Arc::new(ProgressLines::new())
+            };
+            options.observers.insert(0, observer);
+        }
+
+        options.prune_rules = options.prune_rules.with_pack_dirs(
+            &options.target_dir,
+            files.iter().map(|f| &f.path),
+            overrides.keys(),
+        );
+
         ModManager {
-            files: index
-                .files
-                .iter()
-                .filter(|f| {
-                    f.env
-                        .as_ref()
-                        .map_or(true, |env| env.server != Requirement::Unsupported)
-                })
-                .cloned()
-                .collect(),
+            index: pack.index,
+            files,
             overrides,
-            client: Client::new(),
-            prune,
+            override_modes: pack.override_modes,
+            override_mtimes: pack.override_mtimes,
+            index_mtime: pack.index_mtime,
+            client: http::builder()
+                .connect_timeout(options.connect_timeout)
+                .read_timeout(options.read_timeout)
+                .build()
+                .expect("failed to build HTTP client"),
+            rate_limiter: options.rate_limit.map(RateLimiter::new),
+            hosts: HostHealth::default(),
+            options,
+            ignore,
+            state,
+            bases,
+            secret_overrides,
         }
     }
 
-    pub fn sync(&self) -> Result<(), FileError> {
-        let m = MultiProgress::new();
-
-        let pb_files = m.add(ProgressBar::new(self.files.len() as u64));
-        pb_files.set_style(
-            ProgressStyle::default_bar()
-                .template("Server files: [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-                .unwrap()
-                .progress_chars("=> "),
-        );
+    /// Installs the pack. In keep-going mode, files that fail are recorded
+    /// in the returned report instead of aborting the sync. Dry runs return
+    /// an empty report and emit [`SyncEvent::Planned`].
+    /// Every run except a dry run, or one that never got the lock, is
+    /// recorded in the [`History`].
+    pub async fn sync(&self) -> Result<SyncReport, FileError> {
+        if self.options.dry_run {
+            self.check_paths()?;
+            self.check_urls()?;
+            let plan = self.plan().await?;
+            tracing::info!(
+                downloads = plan.downloads.len(),
+                overrides = plan.new_overrides.len() + plan.overwrites.len(),
+                merges = plan.merges.len(),
+                conflicts = plan.conflicts.len(),
+                prunes = plan.prunes.len(),
+                "{}",
+                plan
+            );
+            self.emit(SyncEvent::Planned(plan));
+            return Ok(SyncReport::default());
+        }
 
-        for file in &self.files {
-            let need_download = match File::open(&file.path) {
-                Ok(mut f) => !self.file_is_valid(&mut f, file),
-                Err(_) => true,
+        let started = SystemTime::now();
+        let clock = Instant::now();
+        let result = self.run_sync(clock).await;
+        if !matches!(result, Err(FileError::Locked { .. })) {
+            let (summary, error) = match &result {
+                Ok(report) => (report.summary.clone(), None),
+                Err(err) => (SyncSummary::default(), Some(error_chain(err))),
             };
+            let run = SyncRun::new(
+                &self.index.name,
+                &self.index.version_id,
+                started,
+                clock.elapsed(),
+                &summary,
+                error,
+            );
+            let root = self.options.target_dir.clone();
+            let recorded = spawn_blocking(move || History::open(&root)?.record(&run)).await;
+            if let Ok(Err(err)) = recorded {
+                tracing::warn!("could not record sync history: {}", err);
+            }
+        }
+        result
+    }
 
-            if need_download {
-                self.download_file(file, &m)?;
+    async fn run_sync(&self, clock: Instant) -> Result<SyncReport, FileError> {
+        self.check_paths()?;
+        self.check_urls()?;
+
+        let root = self.options.target_dir.clone();
+        let wait = self.options.wait_for_lock;
+        let _lock = spawn_blocking(move || SyncLock::acquire(&root, wait)).await??;
+
+        let root = self.options.target_dir.clone();
+        let (txn, recovered) = spawn_blocking(move || Transaction::begin(&root)).await??;
+        if recovered {
+            tracing::warn!("Rolled back changes from an interrupted sync");
+        }
+
+        let mut summary = SyncSummary::default();
+        let mut report = SyncReport::default();
+        self.report_skipped(&mut report);
+
+        let staged = match self
+            .stage(&mut summary, &mut report)
+            .await
+            .and_then(|staged| self.check_cancelled().map(|()| staged))
+        {
+            Ok(staged) => staged,
+            Err(err) => {
+                let _ = spawn_blocking(move || txn.rollback()).await;
+                return Err(err);
+            }
+        };
+
+        let prunes = if self.options.prune != PrunePolicy::Disabled {
+            self.confirmed_prunes()
+        } else {
+            Vec::new()
+        };
+
+        // Nothing in the server directory has changed yet. Apply everything
+        // together, putting the old files back if any step fails.
+        // Backups and quarantined files from one sync share a batch name.
+        let batch = file_timestamp(SystemTime::now());
+        let trash_batch = (self.options.prune == PrunePolicy::Quarantine).then(|| batch.clone());
+        let root = self.options.target_dir.clone();
+        let removals = prunes.clone();
+        let backups = spawn_blocking(move || {
+            let mut txn = txn;
+            let mut backups = Vec::new();
+            let applied = staged
+                .replaced_overrides
+                .iter()
+                .try_for_each(|path| {
+                    backups.push((path.clone(), back_up(&root, &batch, path)?));
+                    Ok(())
+                })
+                .and_then(|_| staged.paths.iter().try_for_each(|path| txn.install(path)))
+                .and_then(|_| {
+                    removals
+                        .iter()
+                        .try_for_each(|path| txn.remove(path, trash_batch.as_deref()))
+                });
+            match applied {
+                Ok(()) => txn.commit().map(|_| backups),
+                Err(err) => {
+                    // If this fails too, the journal is kept and the next
+                    // sync finishes the rollback.
+                    let _ = txn.rollback();
+                    Err(err)
+                }
+            }
+        })
+        .await??;
+
+        for (path, backup) in backups {
+            self.emit(SyncEvent::OverrideBackedUp { path, backup });
+        }
+
+        if self.options.prune != PrunePolicy::Disabled {
+            for path in prunes {
+                self.emit(SyncEvent::FilePruned { path: path.clone() });
+                self.observe(|observer| observer.on_prune(&path));
+                report.push(path, FileStatus::Pruned);
+                summary.pruned += 1;
             }
+            tracing::info!(
+                pruned = summary.pruned,
+                "Pruning files: [✔] {}/{}",
+                summary.pruned,
+                summary.pruned
+            );
+        }
+
+        // Before the loader, whose installer may need the provisioned java.
+        if self.options.java != JavaPolicy::Ignore
+            && let Some(target) = ServerTarget::from_index(&self.index)
+        {
+            self.check_java(&target).await?;
+        }
 
-            pb_files.inc(1);
+        if self.options.install_loader
+            && let Some(target) = ServerTarget::from_index(&self.index)
+        {
+            self.install_loader(&target).await?;
+        }
+
+        if let Some(memory) = self.options.scaffold_memory {
+            self.scaffold(memory).await?;
+        }
+
+        let state = self.state.clone();
+        let root = self.options.target_dir.clone();
+        let paths: Vec<PathBuf> = self.files.iter().map(|f| f.path.clone()).collect();
+        spawn_blocking(move || state.save(&root, &paths))
+            .await?
+            .at(self
+                .options
+                .target_dir
+                .join(STATE_DIR)
+                .join(STATE_FILE_NAME))?;
+
+        // The lockfile describes a complete install, so leave the previous
+        // one in place when some files are missing.
+        if self.options.write_lockfile && summary.failed == 0 {
+            let mut lockfile = Lockfile::new(&self.index, &self.files, &self.overrides);
+            // A conflicted override still has the old upstream version as
+            // its base, so its changes are retried on the next sync.
+            for file in &report.files {
+                if file.status == FileStatus::MergeConflict
+                    && let Some(digest) = self.bases.digest(&file.path)
+                {
+                    lockfile.overrides.insert(file.path.clone(), digest.clone());
+                }
+            }
+            let root = self.options.target_dir.clone();
+            let lockfile = spawn_blocking(move || lockfile.save(&root).map(|_| lockfile))
+                .await?
+                .at(self.options.target_dir.join(LOCKFILE_NAME))?;
+
+            if self.options.merge_overrides || self.options.merge_properties {
+                let root = self.options.target_dir.clone();
+                let overrides = self
+                    .overrides
+                    .iter()
+                    .filter(|(path, _)| !self.secret_overrides.contains(*path))
+                    .map(|(path, content)| (path.clone(), content.clone()))
+                    .collect();
+                spawn_blocking(move || save_bases(&root, &lockfile, &overrides))
+                    .await?
+                    .at(self.options.target_dir.join(STATE_DIR).join(BASE_DIR))?;
+            }
+        }
+
+        summary.elapsed_ms = clock.elapsed().as_millis() as u64;
+        report.summary = summary.clone();
+        self.observe(|observer| observer.on_complete(&summary));
+        self.emit(SyncEvent::SyncSummary(summary));
+        Ok(report)
+    }
+
+    /// Downloads changed files and writes overrides into the transaction's
+    /// staging area.
+    async fn stage(
+        &self,
+        summary: &mut SyncSummary,
+        report: &mut SyncReport,
+    ) -> Result<Staged, FileError> {
+        let files = self.files.len() as u64;
+        self.observe(|observer| observer.on_phase_start(SyncPhase::Files, files));
+
+        let needs_download = self.check_existing().await?;
+        let mut results = stream::iter(self.files.iter().zip(needs_download))
+            .map(|(file, needs_download)| async move {
+                (file, self.sync_file(file, needs_download).await)
+            })
+            .buffer_unordered(self.options.concurrency);
+
+        let mut staged = Staged::default();
+
+        while let Some((file, result)) = results.next().await {
+            let status = match result {
+                Ok(status) => status,
+                Err(err) if self.options.keep_going && !matches!(err, FileError::Cancelled) => {
+                    let error = error_chain(&err);
+                    self.emit(SyncEvent::FileFailed {
+                        path: file.path.clone(),
+                        error: error.clone(),
+                    });
+                    FileStatus::Failed {
+                        error,
+                        category: err.category(),
+                    }
+                }
+                Err(err) => {
+                    let status = FileStatus::Failed {
+                        error: error_chain(&err),
+                        category: err.category(),
+                    };
+                    self.observe(|observer| observer.on_file_done(&file.path, &status));
+                    return Err(err);
+                }
+            };
+            self.observe(|observer| observer.on_file_done(&file.path, &status));
+            match &status {
+                FileStatus::Verified => summary.verified += 1,
+                FileStatus::CacheHit => {
+                    summary.cache_hits += 1;
+                    staged.paths.push(file.path.clone());
+                }
+                FileStatus::Downloaded { bytes } => {
+                    summary.downloaded += 1;
+                    summary.bytes_downloaded += bytes;
+                    staged.paths.push(file.path.clone());
+                }
+                _ => summary.failed += 1,
+            }
+            report.push(file.path.clone(), status);
         }
-        pb_files.finish_and_clear();
-        println!(
+        self.observe(|observer| observer.on_phase_done(SyncPhase::Files));
+        tracing::info!(
+            files = self.files.len(),
             "Server files: [✔] {}/{}",
             self.files.len(),
             self.files.len()
         );
 
-        let pb_overrides = ProgressBar::new(self.overrides.len() as u64);
-        pb_overrides.set_style(
-            ProgressStyle::default_bar()
-                .template("Overrides: [{bar:40.green/blue}] {pos}/{len} ({eta})")
-                .unwrap()
-                .progress_chars("=> "),
-        );
+        let overrides = self.overrides.len() as u64;
+        self.observe(|observer| observer.on_phase_start(SyncPhase::Overrides, overrides));
 
-        for (path, content) in &self.overrides {
-            if let Some(parent) = path.parent() {
-                if !parent.exists() {
-                    create_dir_all(parent)?;
+        let mut writes = stream::iter(&self.overrides)
+            .map(|(path, content)| async move {
+                self.check_cancelled()?;
+                if self.is_protected(path) {
+                    self.emit(SyncEvent::OverrideSkipped { path: path.clone() });
+                    return Ok::<_, FileError>((path, OverrideOutcome::Protected));
                 }
-            }
-            let mut file = File::create(path)?;
-            file.write_all(content)?;
-            pb_overrides.inc(1);
+
+                let existing = self.target_path(path);
+                let local = match tokio::fs::read(&existing).await {
+                    Ok(local) => Some(local),
+                    Err(err) if err.kind() == ErrorKind::NotFound => None,
+                    Err(err) => return Err(err).at(&existing),
+                };
+                let choice = self.override_choice(path, content, local.as_deref());
+                if choice == Some(OverrideChoice::Keep) {
+                    self.emit(SyncEvent::OverrideKept { path: path.clone() });
+                    return Ok((path, OverrideOutcome::Kept));
+                }
+                let merged = match local
+                    .as_deref()
+                    .filter(|_| choice.is_none())
+                    .and_then(|local| self.merge_override(path, content, local))
+                {
+                    Some(Merge::Clean(merged)) => Some(merged),
+                    Some(Merge::Conflict) => {
+                        self.emit(SyncEvent::OverrideConflict { path: path.clone() });
+                        return Ok((path, OverrideOutcome::Conflict));
+                    }
+                    None => None,
+                };
+                let new_content = merged.as_deref().unwrap_or(content);
+
+                let target = self.staged_path(path);
+                if let Some(parent) = target.parent() {
+                    create_dir_all(parent).await.at(parent)?;
+                }
+                let mut file = File::create(&target).await.at(&target)?;
+                file.write_all(new_content).await.at(&target)?;
+                self.set_override_mode(path, &target).await?;
+                // A merged file is no longer the pack's, so it keeps the
+                // time it was written.
+                if merged.is_none()
+                    && let Some(mtime) = self.override_mtimes.get(path)
+                {
+                    set_mtime(&target, *mtime).await?;
+                }
+                if merged.is_some() {
+                    self.emit(SyncEvent::OverrideMerged { path: path.clone() });
+                } else {
+                    self.emit(SyncEvent::OverrideWritten { path: path.clone() });
+                }
+
+                let outcome = OverrideOutcome::Written {
+                    replaced: local.is_some_and(|local| local != new_content),
+                    merged: merged.is_some(),
+                };
+                Ok((path, outcome))
+            })
+            .buffer_unordered(self.options.concurrency);
+
+        while let Some(result) = writes.next().await {
+            let (path, outcome) = result?;
+            let status = match outcome {
+                OverrideOutcome::Written { replaced, merged } => {
+                    summary.overrides_written += 1;
+                    staged.paths.push(path.clone());
+                    if replaced {
+                        staged.replaced_overrides.push(path.clone());
+                    }
+                    if merged {
+                        summary.overrides_merged += 1;
+                        FileStatus::OverrideMerged
+                    } else {
+                        FileStatus::OverrideWritten
+                    }
+                }
+                OverrideOutcome::Conflict => {
+                    summary.merge_conflicts += 1;
+                    FileStatus::MergeConflict
+                }
+                OverrideOutcome::Protected => FileStatus::Skipped {
+                    reason: SkipReason::Ignored,
+                },
+                OverrideOutcome::Kept => FileStatus::Skipped {
+                    reason: SkipReason::LocalKept,
+                },
+            };
+            self.observe(|observer| observer.on_file_done(path, &status));
+            report.push(path.clone(), status);
         }
-        pb_overrides.finish_and_clear();
-        println!(
+        self.observe(|observer| observer.on_phase_done(SyncPhase::Overrides));
+        tracing::info!(
+            overrides = self.overrides.len(),
             "Overrides: [✔] {}/{}",
             self.overrides.len(),
             self.overrides.len()
         );
 
-        if self.prune {
-            let pb_prune = ProgressBar::new_spinner().with_style(
-                ProgressStyle::default_spinner()
-                    .template("Pruning files: [{spinner}]")
-                    .unwrap(),
+        if self.options.accept_eula {
+            self.stage_eula(&mut staged, report).await?;
+        }
+
+        Ok(staged)
+    }
+
+    /// Stages an `eula.txt` with `eula=true`, unless the file on disk or an
+    /// override already accepts it.
+    async fn stage_eula(
+        &self,
+        staged: &mut Staged,
+        report: &mut SyncReport,
+    ) -> Result<(), FileError> {
+        let path = Path::new(EULA_FILE_NAME);
+        let current = if staged.paths.iter().any(|p| p == path) {
+            self.staged_path(path)
+        } else {
+            self.target_path(path)
+        };
+        match tokio::fs::read(&current).await {
+            Ok(content) if eula::is_accepted(&content) => return Ok(()),
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err).at(&current),
+        }
+
+        let target = self.staged_path(path);
+        tokio::fs::write(&target, eula::accepted(SystemTime::now()))
+            .await
+            .at(&target)?;
+        if !staged.paths.iter().any(|p| p == path) {
+            staged.paths.push(path.to_path_buf());
+        }
+
+        tracing::info!(
+            path = EULA_FILE_NAME,
+            "Accepted the Minecraft EULA ({}) in {}",
+            eula::EULA_URL,
+            EULA_FILE_NAME
+        );
+        self.emit(SyncEvent::EulaAccepted);
+        report.push(path.to_path_buf(), FileStatus::EulaAccepted);
+        Ok(())
+    }
+
+    /// Downloads the server jar and launcher for `target`'s loader.
+    async fn install_loader(&self, target: &ServerTarget) -> Result<(), FileError> {
+        match loader::install(&self.client, &self.options.target_dir, target).await? {
+            LoaderInstall::Installed(path) => {
+                tracing::info!(
+                    loader = %target,
+                    path = %path.display(),
+                    "Installed {} as {}",
+                    target,
+                    path.display()
+                );
+                self.emit(SyncEvent::LoaderInstalled {
+                    loader: target.to_string(),
+                    path,
+                });
+            }
+            LoaderInstall::UpToDate => {}
+        }
+        Ok(())
+    }
+
+    /// Writes start scripts for the pack's loader.
+    async fn scaffold(&self, memory: u64) -> Result<(), FileError> {
+        let Some(target) = ServerTarget::from_index(&self.index) else {
+            tracing::info!("Skipping start scripts: the pack does not declare a Minecraft version");
+            return Ok(());
+        };
+
+        let root = self.options.target_dir.clone();
+        let description = target.to_string();
+        let paths = spawn_blocking(move || write_start_scripts(&root, &target, memory))
+            .await?
+            .at(&self.options.target_dir)?;
+
+        tracing::info!(
+            loader = %description,
+            "Wrote {} for {}",
+            paths
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" and "),
+            description
+        );
+        self.emit(SyncEvent::StartScriptsWritten { paths });
+        Ok(())
+    }
+
+    /// Makes sure the `java` the start scripts pick up suits `target`,
+    /// provisioning one if the policy allows and warning otherwise.
+    async fn check_java(&self, target: &ServerTarget) -> Result<(), FileError> {
+        let required = required_java(&target.minecraft);
+        let root = self.options.target_dir.clone();
+        let installs = spawn_blocking(move || find_java(&root)).await?;
+        if installs.first().is_some_and(|java| java.major == required) {
+            return Ok(());
+        }
+
+        if self.options.java == JavaPolicy::Provision {
+            tracing::info!(
+                required,
+                "Downloading a Java {} runtime into {}",
+                required,
+                RUNTIME_DIR
+            );
+            let java = provision(&self.client, &self.options.target_dir, required).await?;
+            tracing::info!(
+                version = %java.version,
+                "Installed Java {} in {}",
+                java.version,
+                RUNTIME_DIR
             );
+            self.emit(SyncEvent::JavaProvisioned {
+                version: java.version,
+            });
+            return Ok(());
+        }
 
-            let mut pruned_files = 0;
+        let found = installs.first().map(|java| java.major);
+        match installs.first() {
+            Some(java) => tracing::warn!(
+                required,
+                found = java.major,
+                "{} needs Java {}, but {} is Java {}",
+                target,
+                required,
+                java.path.display(),
+                java.major
+            ),
+            None => tracing::warn!(
+                required,
+                "{} needs Java {}, but no java was found",
+                target,
+                required
+            ),
+        }
+        if let Some(java) = installs.iter().find(|java| java.major == required) {
+            tracing::info!(
+                "Set JAVA={} to use it, or sync with --java provision",
+                java.path.display()
+            );
+        } else {
+            tracing::info!(
+                "Sync with --java provision to download one into {}",
+                RUNTIME_DIR
+            );
+        }
+        self.emit(SyncEvent::JavaMismatch { required, found });
+        Ok(())
+    }
 
-            for dir in PRUNE_DIRECTORIES_INDEX {
-                let files = WalkDir::new(dir)
-                    .into_iter()
-                    .filter_map(|e| e.ok())
-                    .filter(|entry| entry.file_type().is_file());
+    /// Merges upstream changes to an override into a locally edited copy.
+    /// `None` when the file has no local edits to keep (or merging is off)
+    /// and the pack's version should simply be written.
+    fn merge_override(&self, path: &Path, upstream: &[u8], local: &[u8]) -> Option<Merge> {
+        if local == upstream {
+            return None;
+        }
+        if self.options.merge_properties && is_properties(path) {
+            let base = self.bases.get(path);
+            if let Some(merged) = merge_properties(base.as_deref(), local, upstream) {
+                return Some(Merge::Clean(merged));
+            }
+        }
+        if !self.options.merge_overrides {
+            return None;
+        }
+        let base = self.bases.get(path)?;
+        if base == local {
+            return None;
+        }
+        Some(merge(&base, local, upstream))
+    }
 
-                for file in files {
-                    let is_in_index = self.files.iter().any(|f| f.path == file.path());
-                    if !is_in_index {
-                        self.delete_file(&file.path())?;
-                        pruned_files += 1;
-                    }
+    /// The [choice](crate::Choices) made for an override whose local copy
+    /// differs from the pack's.
+    fn override_choice(
+        &self,
+        path: &Path,
+        upstream: &[u8],
+        local: Option<&[u8]>,
+    ) -> Option<OverrideChoice> {
+        local.filter(|local| *local != upstream)?;
+        self.options.choices.overrides.get(path).copied()
+    }
+
+    /// Whether syncing would lose or leave in conflict local edits to an
+    /// override: its merge clashes, or it is overwritten while differing
+    /// from the version recorded at the last sync (or with none recorded).
+    fn is_conflicting(&self, path: &Path, upstream: &[u8], local: &[u8]) -> bool {
+        match self.merge_override(path, upstream, local) {
+            Some(Merge::Clean(_)) => false,
+            Some(Merge::Conflict) => true,
+            None => local != upstream && self.bases.get(path).is_none_or(|base| base != local),
+        }
+    }
+
+    /// Lists what [`Choices`](crate::Choices) can decide for this pack:
+    /// its server-optional files, and the overrides whose local edits a
+    /// sync would overwrite or leave in conflict. Existing choices don't
+    /// hide anything.
+    pub async fn choosable(&self) -> Result<Choosable, FileError> {
+        self.check_paths()?;
+        let mut choosable = Choosable {
+            optional: self
+                .index
+                .files
+                .iter()
+                .filter(|f| f.server_requirement() == Requirement::Optional)
+                .cloned()
+                .collect(),
+            overrides: Vec::new(),
+        };
+
+        for (path, content) in &self.overrides {
+            if self.is_protected(path) {
+                continue;
+            }
+            match tokio::fs::read(self.target_path(path)).await {
+                Ok(local) if self.is_conflicting(path, content, &local) => {
+                    choosable.overrides.push(path.clone());
                 }
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err).at(self.target_path(path)),
+            }
+        }
+        choosable.overrides.sort();
+        Ok(choosable)
+    }
+
+    /// Prune candidates, after the confirmation hook has had its say.
+    fn confirmed_prunes(&self) -> Vec<PathBuf> {
+        let candidates: Vec<PruneCandidate> = self
+            .prune_candidates()
+            .into_iter()
+            .map(|path| PruneCandidate {
+                size: std::fs::metadata(self.target_path(&path)).map_or(0, |md| md.len()),
+                path,
+            })
+            .collect();
+
+        let confirmed = match &self.options.confirm_prune {
+            Some(confirm) if !candidates.is_empty() => confirm(&candidates),
+            _ => true,
+        };
+        if confirmed {
+            candidates.into_iter().map(|c| c.path).collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Works out what [`sync`](Self::sync) would do without touching the
+    /// target directory. Existing files are still hash-checked.
+    pub async fn plan(&self) -> Result<SyncPlan, FileError> {
+        self.check_paths()?;
+        self.check_urls()?;
+        let mut plan = SyncPlan::default();
+
+        let needs_download = self.check_existing().await?;
+        for (file, needs_download) in self.files.iter().zip(needs_download) {
+            if needs_download {
+                plan.downloads.push(file.path.clone());
+            }
+        }
+
+        for (path, content) in &self.overrides {
+            if self.is_protected(path) {
+                continue;
+            }
+            match tokio::fs::read(self.target_path(path)).await {
+                Ok(existing) if existing == *content => {}
+                Ok(existing) => match self.override_choice(path, content, Some(&existing)) {
+                    Some(OverrideChoice::Keep) => {}
+                    Some(OverrideChoice::Replace) => plan.overwrites.push(path.clone()),
+                    None => match self.merge_override(path, content, &existing) {
+                        Some(Merge::Clean(_)) => plan.merges.push(path.clone()),
+                        Some(Merge::Conflict) => plan.conflicts.push(path.clone()),
+                        None => plan.overwrites.push(path.clone()),
+                    },
+                },
+                Err(_) => plan.new_overrides.push(path.clone()),
+            }
+        }
+
+        if self.options.prune != PrunePolicy::Disabled {
+            plan.prunes = self.prune_candidates();
+        }
+
+        plan.sort();
+        Ok(plan)
+    }
+
+    /// Compares the target directory against the pack without changing
+    /// anything. Every index file is hashed, ignoring the state cache.
+    /// Overrides kept by `.observeignore` are not reported.
+    pub async fn verify(&self) -> Result<VerifyReport, FileError> {
+        self.check_paths()?;
+        let mut report = VerifyReport::default();
+
+        let root = self.options.target_dir.clone();
+        let files = self.files.clone();
+        let policy = self.options.hash_policy;
+        let results: Vec<Option<bool>> = spawn_blocking(move || {
+            files
+                .par_iter()
+                .map(|file| {
+                    let mut f = std::fs::File::open(root.join(&file.path)).ok()?;
+                    Some(file_is_valid(&mut f, file, policy))
+                })
+                .collect()
+        })
+        .await?;
+        for (file, result) in self.files.iter().zip(results) {
+            match result {
+                None => report.missing.push(file.path.clone()),
+                Some(false) => report.modified.push(file.path.clone()),
+                Some(true) => {}
+            }
+        }
+
+        for (path, content) in &self.overrides {
+            if self.is_protected(path) {
+                continue;
+            }
+            match tokio::fs::read(self.target_path(path)).await {
+                Ok(existing) if existing == *content => {}
+                Ok(_) => report.modified.push(path.clone()),
+                Err(_) => report.missing.push(path.clone()),
             }
+        }
+
+        report.extra = self.prune_candidates();
+        report.sort();
+        Ok(report)
+    }
+
+    /// Watches the managed directories (those prune looks at) and emits
+    /// [`SyncEvent::DriftDetected`] when a file stops matching the pack and
+    /// [`SyncEvent::DriftResolved`] when it matches again. Drift already
+    /// present is reported first. Directories created later aren't watched.
+    /// Only returns on error.
+    pub async fn watch(&self) -> Result<(), FileError> {
+        let mut drift: HashMap<PathBuf, DriftKind> = HashMap::new();
+        for (path, kind) in self.verify().await?.drift() {
+            self.emit(SyncEvent::DriftDetected {
+                path: path.clone(),
+                kind,
+            });
+            drift.insert(path.clone(), kind);
+        }
 
-            for dir in PRUNE_DIRECTORIES_OVERRIDES {
-                let files = WalkDir::new(dir)
-                    .into_iter()
-                    .filter_map(|e| e.ok())
-                    .filter(|entry| entry.file_type().is_file());
+        // Events carry absolute paths.
+        let root = &tokio::fs::canonicalize(&self.options.target_dir)
+            .await
+            .at(&self.options.target_dir)?;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })
+        .map_err(|err| watch_error(root, err))?;
+        let rules = &self.options.prune_rules;
+        for dir in rules.index_dirs.iter().chain(&rules.override_dirs) {
+            let dir = root.join(dir);
+            if dir.is_dir() {
+                watcher
+                    .watch(&dir, RecursiveMode::Recursive)
+                    .map_err(|err| watch_error(&dir, err))?;
+            }
+        }
+
+        while let Some(event) = rx.recv().await {
+            // Files are often written in bursts; settle before checking.
+            let mut events = vec![event];
+            while let Ok(Some(event)) = tokio::time::timeout(WATCH_DEBOUNCE, rx.recv()).await {
+                events.push(event);
+            }
+            let mut changed = Vec::new();
+            for event in events {
+                let event: notify::Event = event.map_err(|err| watch_error(root, err))?;
+                changed.extend(
+                    event
+                        .paths
+                        .iter()
+                        .filter_map(|path| path.strip_prefix(root).ok())
+                        .map(Path::to_path_buf),
+                );
+            }
+            changed.sort();
+            changed.dedup();
 
-                for file in files {
-                    let is_in_overrides =
-                        self.overrides.iter().any(|(path, _)| path == file.path());
-                    if !is_in_overrides {
-                        self.delete_file(&file.path())?;
-                        pruned_files += 1;
+            for path in changed {
+                let now = self.drift_of(&path).await;
+                if now == drift.get(&path).copied() {
+                    continue;
+                }
+                match now {
+                    Some(kind) => {
+                        drift.insert(path.clone(), kind);
+                        self.emit(SyncEvent::DriftDetected { path, kind });
+                    }
+                    None => {
+                        drift.remove(&path);
+                        self.emit(SyncEvent::DriftResolved { path });
                     }
                 }
             }
+        }
+        Ok(())
+    }
 
-            pb_prune.finish_and_clear();
-            println!("Pruning files: [✔] {}/{}", pruned_files, pruned_files);
+    /// Applies the mode forced by the override mode rules, else the one the
+    /// pack's zip entry records. Other files keep the default mode.
+    async fn set_override_mode(&self, path: &Path, target: &Path) -> Result<(), FileError> {
+        let Some(mode) = self
+            .options
+            .override_modes
+            .mode_for(path)
+            .or_else(|| self.override_modes.get(path).copied())
+        else {
+            return Ok(());
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            tokio::fs::set_permissions(target, std::fs::Permissions::from_mode(mode))
+                .await
+                .at(target)?;
+        }
+        #[cfg(not(unix))]
+        let _ = (mode, target);
+        Ok(())
+    }
+
+    /// How `path` currently differs from the pack, if it does.
+    async fn drift_of(&self, path: &Path) -> Option<DriftKind> {
+        let full = self.target_path(path);
+        if let Some(file) = self.files.iter().find(|f| f.path == path) {
+            let file = file.clone();
+            let policy = self.options.hash_policy;
+            let valid = spawn_blocking(move || {
+                let mut f = std::fs::File::open(full).ok()?;
+                Some(file_is_valid(&mut f, &file, policy))
+            })
+            .await
+            .ok()?;
+            return match valid {
+                None => Some(DriftKind::Missing),
+                Some(false) => Some(DriftKind::Modified),
+                Some(true) => None,
+            };
         }
+        if let Some(content) = self.overrides.get(path) {
+            if self.is_protected(path) {
+                return None;
+            }
+            return match tokio::fs::read(full).await {
+                Ok(existing) if existing == *content => None,
+                Ok(_) => Some(DriftKind::Modified),
+                Err(_) => Some(DriftKind::Missing),
+            };
+        }
+        (full.is_file() && self.is_unaccounted(path)).then_some(DriftKind::Extra)
+    }
+
+    /// Files under the managed directories that neither the index nor the
+    /// overrides account for.
+    fn prune_candidates(&self) -> Vec<PathBuf> {
+        let rules = &self.options.prune_rules;
+        let mut candidates: Vec<PathBuf> = rules
+            .index_dirs
+            .iter()
+            .chain(&rules.override_dirs)
+            .flat_map(|dir| self.walk_files(dir))
+            .filter(|path| self.is_unaccounted(path))
+            .collect();
+
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+
+    /// Whether `path` lies in a managed directory, is neither a pack file nor
+    /// an override, and prune rules don't protect it.
+    fn is_unaccounted(&self, path: &Path) -> bool {
+        let rules = &self.options.prune_rules;
+        let managed = rules
+            .index_dirs
+            .iter()
+            .chain(&rules.override_dirs)
+            .any(|dir| path.starts_with(dir));
+        // Packs can put index files and overrides in the same directory.
+        let unaccounted = managed
+            && !self.files.iter().any(|f| f.path == path)
+            && !self.overrides.contains_key(path);
+        unaccounted && rules.allows(path) && !self.ignore.is_ignored(path)
+    }
+
+    /// Existing files matched by `.observeignore` are never overwritten.
+    fn is_protected(&self, path: &Path) -> bool {
+        self.ignore.is_ignored(path) && self.target_path(path).exists()
+    }
+
+    fn target_path(&self, path: &Path) -> PathBuf {
+        self.options.target_dir.join(path)
+    }
+
+    fn staged_path(&self, path: &Path) -> PathBuf {
+        Transaction::staged_path(&self.options.target_dir, path)
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .options
+            .retry_delay
+            .saturating_mul(1 << attempt.min(16));
+        delay / 2 + delay.mul_f64(fastrand::f64() / 2.0)
+    }
+
+    fn check_cancelled(&self) -> Result<(), FileError> {
+        match &self.options.cancel {
+            Some(token) if token.is_cancelled() => Err(FileError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Awaits `future`, giving up as soon as the sync is cancelled.
+    async fn unless_cancelled<T>(&self, future: impl Future<Output = T>) -> Result<T, FileError> {
+        match &self.options.cancel {
+            Some(token) => token
+                .run_until_cancelled(future)
+                .await
+                .ok_or(FileError::Cancelled),
+            None => Ok(future.await),
+        }
+    }
+
+    fn observe(&self, notify: impl Fn(&dyn SyncObserver)) {
+        for observer in &self.options.observers {
+            notify(observer.as_ref());
+        }
+    }
+
+    fn emit(&self, event: SyncEvent) {
+        for callback in &self.options.callbacks {
+            callback(&event);
+        }
+    }
 
+    /// Files under `dir`, relative to the target directory. Symlinks are
+    /// only followed with `follow_symlinks`, and never out of the target
+    /// directory, so prune can't reach files that live elsewhere.
+    fn walk_files(&self, dir: &Path) -> Vec<PathBuf> {
+        let root = &self.options.target_dir;
+        let follow = self.options.follow_symlinks;
+        let Ok(canonical_root) = std::fs::canonicalize(root) else {
+            return Vec::new();
+        };
+        WalkDir::new(root.join(dir))
+            .follow_links(follow)
+            .follow_root_links(follow)
+            .into_iter()
+            .filter_entry(|entry| {
+                !entry.path_is_symlink()
+                    || (follow && resolves_within(entry.path(), &canonical_root))
+            })
+            .filter_map(|e| e.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| entry.path().strip_prefix(root).ok().map(Path::to_path_buf))
+            // Never pruned, whether or not --accept-eula wrote it.
+            .filter(|path| path != Path::new(EULA_FILE_NAME))
+            .collect()
+    }
+
+    /// Checks every pack file already on disk across the rayon pool, as
+    /// thoroughly as the verify policy asks. Returns, in `self.files` order,
+    /// whether each file must be fetched.
+    async fn check_existing(&self) -> Result<Vec<bool>, FileError> {
+        let root = self.options.target_dir.clone();
+        let files = self.files.clone();
+        let state = self.state.clone();
+        let trust_state = self.options.state_cache;
+        let policy = self
+            .options
+            .verify_policy
+            .existing_hashes(self.options.hash_policy);
+        let needs_download = spawn_blocking(move || {
+            files
+                .par_iter()
+                .map(|file| {
+                    !verify_existing(&root.join(&file.path), file, &state, trust_state, policy)
+                })
+                .collect()
+        })
+        .await?;
+
+        Ok(needs_download)
+    }
+
+    /// Records pack files that this sync leaves out on purpose.
+    fn report_skipped(&self, report: &mut SyncReport) {
+        let installed: HashSet<&PathBuf> = self.files.iter().map(|f| &f.path).collect();
+        for file in &self.index.files {
+            if installed.contains(&file.path) {
+                continue;
+            }
+            let reason = match file.server_requirement() {
+                Requirement::Unsupported => SkipReason::ServerUnsupported,
+                _ => SkipReason::OptionalExcluded,
+            };
+            report.push(file.path.clone(), FileStatus::Skipped { reason });
+        }
+    }
+
+    /// Rejects packs whose files or overrides would land outside the target
+    /// directory, by their paths or through symlinked directories already
+    /// in it.
+    fn check_paths(&self) -> Result<(), FileError> {
+        let paths = || {
+            self.files
+                .iter()
+                .map(|f| &f.path)
+                .chain(self.overrides.keys())
+        };
+        if let Some(path) = paths().find(|path| !is_contained_path(path)) {
+            return Err(FileError::UnsafePath(path.clone()));
+        }
+
+        let root = &self.options.target_dir;
+        // Nothing to follow before the first sync creates it.
+        let Ok(canonical_root) = std::fs::canonicalize(root) else {
+            return Ok(());
+        };
+        let mut checked = HashSet::new();
+        for path in paths() {
+            for dir in path.ancestors().skip(1) {
+                // Checked along with its own parents already.
+                if dir.as_os_str().is_empty() || !checked.insert(dir) {
+                    break;
+                }
+                let full = root.join(dir);
+                if full.is_symlink() && !resolves_within(&full, &canonical_root) {
+                    return Err(FileError::SymlinkEscape {
+                        path: path.clone(),
+                        link: dir.to_path_buf(),
+                    });
+                }
+            }
+        }
         Ok(())
     }
 
-    fn delete_file(&self, path: &Path) -> Result<(), FileError> {
-        if path.is_dir() {
-            match remove_dir_all(path) {
-                Ok(_) => Ok(()),
-                Err(_) => Err(FileError::DeleteFailed),
+    /// Rejects download URLs the download policy forbids before anything is
+    /// fetched.
+    fn check_urls(&self) -> Result<(), FileError> {
+        let policy = &self.options.download_policy;
+        for url in self.files.iter().flat_map(|f| &f.downloads) {
+            if !policy.allows_scheme(url) {
+                return Err(FileError::InsecureUrl(url.clone()));
             }
-        } else {
-            match remove_file(path) {
-                Ok(_) => Ok(()),
-                Err(_) => Err(FileError::DeleteFailed),
+            if !policy.allows_host(url) {
+                return Err(FileError::DisallowedUrl(url.clone()));
             }
         }
+        Ok(())
     }
 
-    fn download_file(&self, file: &MRFile, m: &MultiProgress) -> Result<(), FileError> {
-        if let Some(parent) = Path::new(&file.path).parent() {
-            if !parent.exists() {
-                create_dir_all(parent)?;
+    /// Gives a fetched file the time the pack was built, when asked to, so
+    /// every machine syncing the pack ends up with the same mtimes.
+    async fn stamp_download(&self, file: &MRFile) -> Result<(), FileError> {
+        match self.index_mtime {
+            Some(mtime) if self.options.stamp_downloads => {
+                set_mtime(&self.staged_path(&file.path), mtime).await
             }
+            _ => Ok(()),
         }
+    }
+
+    /// Remembers a file that was just installed and verified.
+    async fn record_state(&self, file: &MRFile) -> Result<(), FileError> {
+        // The rename into place at commit keeps size and mtime.
+        let target = self.staged_path(&file.path);
+        let metadata = tokio::fs::metadata(&target).await.at(&target)?;
+        self.state.record(&file.path, &metadata, file.hashes.sha1);
+        Ok(())
+    }
+
+    async fn sync_file(
+        &self,
+        file: &MRFile,
+        needs_download: bool,
+    ) -> Result<FileStatus, FileError> {
+        self.check_cancelled()?;
+        if !needs_download {
+            self.emit(SyncEvent::FileVerified {
+                path: file.path.clone(),
+            });
+            Ok(FileStatus::Verified)
+        } else if self.install_from_cache(file).await? {
+            self.stamp_download(file).await?;
+            self.record_state(file).await?;
+            Ok(FileStatus::CacheHit)
+        } else {
+            let bytes = self.download_file(file).await?;
+            self.stamp_download(file).await?;
+            self.record_state(file).await?;
+            Ok(FileStatus::Downloaded { bytes })
+        }
+    }
+
+    async fn install_from_cache(&self, file: &MRFile) -> Result<bool, FileError> {
+        let Some(cache) = &self.options.cache else {
+            return Ok(false);
+        };
+
+        let target = self.staged_path(&file.path);
+        if let Some(parent) = target.parent() {
+            create_dir_all(parent).await.at(parent)?;
+        }
+
+        let part = part_path(&target);
+        if !cache.fetch(file, &part, self.options.hash_policy).await? {
+            return Ok(false);
+        }
+        rename(&part, &target).await.at(&target)?;
+
+        self.emit(SyncEvent::CacheHit {
+            path: file.path.clone(),
+        });
+        Ok(true)
+    }
+
+    async fn download_file(&self, file: &MRFile) -> Result<u64, FileError> {
+        let target = self.staged_path(&file.path);
+        if let Some(parent) = target.parent() {
+            create_dir_all(parent).await.at(parent)?;
+        }
+
+        let part = part_path(&target);
+        let mut candidates: Vec<&Url> = file.downloads.iter().collect();
+        let mut attempt = 0;
+        let mut last_error = None;
 
-        for url in &file.downloads {
-            match self.try_download_file(url, &file.path, m) {
-                Ok(()) => return Ok(()),
-                Err(_) => continue,
+        while !candidates.is_empty() {
+            let failed = match self
+                .unless_cancelled(self.race_mirrors(&candidates))
+                .await?
+            {
+                Ok((url, response)) => {
+                    match self.try_download_file(url, response, file, &part).await {
+                        Ok(bytes) => {
+                            self.hosts.record_success(url);
+                            rename(&part, &target).await.at(&target)?;
+                            if let Some(cache) = &self.options.cache {
+                                let _ = cache.store(file, &target).await;
+                            }
+                            return Ok(bytes);
+                        }
+                        Err(FileError::Cancelled) => {
+                            let _ = remove_file(&part).await;
+                            return Err(FileError::Cancelled);
+                        }
+                        Err(err) => {
+                            self.hosts.record_failure(url);
+                            let _ = remove_file(&part).await;
+                            vec![(url, err)]
+                        }
+                    }
+                }
+                Err(failed) => failed,
+            };
+
+            // Mirrors that failed for good are dropped; transient failures
+            // are retried with backoff.
+            candidates.retain(|url| {
+                !failed
+                    .iter()
+                    .any(|(failed_url, err)| failed_url == url && !err.is_transient())
+            });
+            let transient = failed.iter().any(|(_, err)| err.is_transient());
+            last_error = failed.into_iter().last().map(|(_, err)| Box::new(err));
+            if transient {
+                if attempt >= self.options.retries {
+                    break;
+                }
+                self.unless_cancelled(sleep(self.backoff(attempt))).await?;
+                attempt += 1;
             }
         }
 
-        Err(FileError::AllDownloadsFailed)
+        Err(FileError::AllDownloadsFailed {
+            path: file.path.clone(),
+            last: last_error,
+        })
+    }
+
+    /// Requests every candidate URL at once and keeps the first successful
+    /// response, dropping the others. Hosts that have been failing during
+    /// this sync join the race late. On failure, returns each URL's error.
+    async fn race_mirrors<'a>(
+        &self,
+        urls: &[&'a Url],
+    ) -> Result<(&'a Url, Response), Vec<(&'a Url, FileError)>> {
+        let mut requests: FuturesUnordered<_> = urls
+            .iter()
+            .map(|&url| async move {
+                let penalty = self.hosts.penalty(url);
+                if !penalty.is_zero() {
+                    sleep(penalty).await;
+                }
+                let result = async {
+                    let response = http::get(&self.client, url, self.options.api_token.as_deref())
+                        .send()
+                        .await
+                        .map_err(|source| FileError::Request {
+                            url: url.clone(),
+                            source,
+                        })?;
+                    if !response.status().is_success() {
+                        return Err(FileError::HttpStatus {
+                            url: url.clone(),
+                            status: response.status().as_u16(),
+                        });
+                    }
+                    Ok(response)
+                }
+                .await;
+                (url, result)
+            })
+            .collect();
+
+        let mut failed = Vec::new();
+        while let Some((url, result)) = requests.next().await {
+            match result {
+                Ok(response) => return Ok((url, response)),
+                Err(err) => {
+                    self.hosts.record_failure(url);
+                    failed.push((url, err));
+                }
+            }
+        }
+        Err(failed)
     }
 
-    fn try_download_file(
+    async fn try_download_file(
         &self,
         url: &Url,
-        path: &PathBuf,
-        m: &MultiProgress,
-    ) -> Result<(), FileError> {
-        let mut response = self.client.get(url.clone()).send()?;
-        let total_size = response.content_length().unwrap_or(0);
-
-        let pb_file = m.add(ProgressBar::new(total_size));
-        pb_file.set_style(
-            ProgressStyle::default_bar()
-                .template("Downloading: [{bar:40.green/blue}] {bytes}/{total_bytes} ({eta})")
-                .unwrap()
-                .progress_chars("=> "),
-        );
+        response: Response,
+        file: &MRFile,
+        target: &Path,
+    ) -> Result<u64, FileError> {
+        let started = Instant::now();
+        self.emit(SyncEvent::DownloadStarted {
+            path: file.path.clone(),
+            url: url.to_string(),
+        });
 
-        let mut file_handle = File::create(path)?;
-        let mut buffer = [0u8; 8192];
+        let size_mismatch = |actual| FileError::SizeMismatch {
+            path: file.path.clone(),
+            expected: file.file_size,
+            actual,
+        };
+        // A wrong length is caught before anything is downloaded or hashed.
+        if let Some(length) = response.content_length()
+            && length != file.file_size
+        {
+            return Err(size_mismatch(length));
+        }
+        let total_size = response.content_length().unwrap_or(file.file_size);
 
-        loop {
-            let n = response.read(&mut buffer)?;
-            if n == 0 {
-                break;
+        self.observe(|observer| observer.on_file_start(&file.path, url, total_size));
+
+        let mut writer = HashingWriter::with_hasher(
+            File::create(target).await.at(target)?,
+            FileHasher::for_file(&file.hashes, self.options.hash_policy),
+        );
+        let mut stream = response.bytes_stream();
+        let mut bytes = 0;
+        let per_download = self.options.per_download_rate_limit.map(RateLimiter::new);
+
+        while let Some(chunk) = self.unless_cancelled(stream.next()).await? {
+            let chunk = chunk.map_err(|source| FileError::Request {
+                url: url.clone(),
+                source,
+            })?;
+            for limiter in [&self.rate_limiter, &per_download].into_iter().flatten() {
+                limiter.consume(chunk.len()).await;
+            }
+            bytes += chunk.len() as u64;
+            if bytes > file.file_size {
+                return Err(size_mismatch(bytes));
             }
-            file_handle.write_all(&buffer[..n])?;
-            pb_file.inc(n as u64);
+            writer.write_all(&chunk).await.at(target)?;
+            let chunk = chunk.len() as u64;
+            self.observe(|observer| observer.on_bytes(&file.path, chunk));
         }
+        writer.flush().await.at(target)?;
 
-        pb_file.finish_and_clear();
-        Ok(())
+        if bytes != file.file_size {
+            return Err(size_mismatch(bytes));
+        }
+
+        if !writer.into_hasher().matches(&file.hashes) {
+            return Err(FileError::HashMismatch {
+                path: file.path.clone(),
+                url: url.clone(),
+            });
+        }
+
+        self.emit(SyncEvent::DownloadFinished {
+            path: file.path.clone(),
+            url: url.to_string(),
+            bytes,
+            elapsed_ms: started.elapsed().as_millis() as u64,
+        });
+        Ok(bytes)
     }
+}
 
-    fn file_is_valid(&self, file: &mut File, mr_file: &MRFile) -> bool {
-        let mut data = Vec::with_capacity(file.metadata().map(|md| md.len() as usize).unwrap_or(0));
-        file.read_to_end(&mut data).unwrap();
+/// Downloads are staged next to their destination and only renamed into place
+/// once verified, so an interrupted sync never leaves a truncated file behind.
+fn part_path(target: &Path) -> PathBuf {
+    let mut name = target.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
 
-        self.check_sha1(&data, &mr_file.hashes.sha1)
-            && self.check_sha512(&data, &mr_file.hashes.sha512)
+/// Whether the file at `path` matches `file`, trusting the state cache when
+/// allowed and recording successful checks in it. Without a `policy` only
+/// the size is checked.
+fn verify_existing(
+    path: &Path,
+    file: &MRFile,
+    state: &StateCache,
+    trust_state: bool,
+    policy: Option<HashPolicy>,
+) -> bool {
+    let Ok(mut f) = std::fs::File::open(path) else {
+        return false;
+    };
+    let Ok(metadata) = f.metadata() else {
+        return false;
+    };
+    if metadata.len() != file.file_size {
+        return false;
+    }
+    let Some(policy) = policy else {
+        return true;
+    };
+    if trust_state && state.is_fresh(&file.path, &metadata, &file.hashes.sha1) {
+        return true;
     }
+    let is_valid = file_is_valid(&mut f, file, policy);
+    if is_valid {
+        state.record(&file.path, &metadata, file.hashes.sha1);
+    }
+    is_valid
+}
 
-    fn check_sha1(&self, data: &[u8], expected_hash: &[u8; 20]) -> bool {
-        Sha1::digest(data).as_slice() == expected_hash
+/// Whether `file` has `expected`'s size and hashes. The size is checked
+/// first, so truncated or padded files are caught without hashing them.
+pub(crate) fn file_is_valid(
+    file: &mut std::fs::File,
+    expected: &MRFile,
+    policy: HashPolicy,
+) -> bool {
+    if file
+        .metadata()
+        .is_ok_and(|metadata| metadata.len() != expected.file_size)
+    {
+        return false;
+    }
+    let hashes = &expected.hashes;
+    // SAFETY: the mapping is only read, and only while `file` is open. A
+    // concurrent writer can at worst make the hashes mismatch.
+    if let Ok(map) = unsafe { Mmap::map(&*file) } {
+        let mut hasher = FileHasher::for_file(hashes, policy);
+        hasher.update(&map);
+        return hasher.matches(hashes);
     }
 
-    fn check_sha512(&self, data: &[u8], expected_hash: &[u8; 64]) -> bool {
-        Sha512::digest(data).as_slice() == expected_hash
+    // Empty files and some special filesystems can't be mapped.
+    let mut writer =
+        HashingWriter::with_hasher(std::io::sink(), FileHasher::for_file(hashes, policy));
+    std::io::copy(file, &mut writer).is_ok() && writer.into_hasher().matches(hashes)
+}
+
+/// Whether `path` resolves to somewhere inside `root`, which must itself be
+/// canonical. Dangling symlinks resolve nowhere.
+fn resolves_within(path: &Path, root: &Path) -> bool {
+    std::fs::canonicalize(path).is_ok_and(|path| path.starts_with(root))
+}
+
+async fn set_mtime(path: &Path, mtime: SystemTime) -> Result<(), FileError> {
+    let file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .await
+        .at(path)?
+        .into_std()
+        .await;
+    spawn_blocking(move || file.set_modified(mtime))
+        .await?
+        .at(path)
+}
+
+fn watch_error(path: &Path, err: notify::Error) -> FileError {
+    FileError::Io {
+        path: path.to_path_buf(),
+        source: std::io::Error::other(err),
     }
 }
@@ -0,0 +1,194 @@
+use crate::modrinth::{ApiError, ModrinthClient};
+use crate::pack::Pack;
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write,
+    path::PathBuf,
+};
+use url::Url;
+
+/// The files in a pack, resolved to Modrinth projects where Modrinth hosts
+/// them, for publishing what a server runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct Modlist {
+    pub name: String,
+    pub version: String,
+    pub entries: Vec<ModlistEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModlistEntry {
+    /// The project title, or the file name when Modrinth doesn't know the
+    /// file.
+    pub name: String,
+    pub path: PathBuf,
+    pub version: Option<String>,
+    pub authors: Vec<String>,
+    /// The project page, or the file's first download URL.
+    pub url: Option<Url>,
+}
+
+impl Modlist {
+    /// Looks up every file in the pack by hash, then the projects and
+    /// teams they belong to, in three batch requests.
+    pub async fn resolve(modrinth: &ModrinthClient, pack: &Pack) -> Result<Self, ApiError> {
+        let hashes: Vec<String> = pack
+            .index
+            .files
+            .iter()
+            .map(|file| hex::encode(file.hashes.sha1))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let versions = modrinth.versions_from_hashes(&hashes, "sha1").await?;
+
+        let project_ids: BTreeSet<&str> =
+            versions.values().map(|v| v.project_id.as_str()).collect();
+        let projects: HashMap<String, _> = modrinth
+            .projects(&project_ids.into_iter().collect::<Vec<_>>())
+            .await?
+            .into_iter()
+            .map(|project| (project.id.clone(), project))
+            .collect();
+
+        let team_ids: BTreeSet<&str> = projects.values().map(|p| p.team.as_str()).collect();
+        let mut authors: HashMap<String, Vec<String>> = HashMap::new();
+        for member in modrinth
+            .teams(&team_ids.into_iter().collect::<Vec<_>>())
+            .await?
+            .into_iter()
+            .flatten()
+        {
+            authors
+                .entry(member.team_id)
+                .or_default()
+                .push(member.user.username);
+        }
+
+        let mut entries: Vec<ModlistEntry> = pack
+            .index
+            .files
+            .iter()
+            .map(|file| {
+                let version = versions.get(&hex::encode(file.hashes.sha1));
+                let project = version.and_then(|v| projects.get(&v.project_id));
+                ModlistEntry {
+                    name: project.map_or_else(
+                        || {
+                            file.path
+                                .file_name()
+                                .unwrap_or(file.path.as_os_str())
+                                .to_string_lossy()
+                                .into_owned()
+                        },
+                        |p| p.title.clone(),
+                    ),
+                    path: file.path.clone(),
+                    version: version.map(|v| v.version_number.clone()),
+                    authors: project
+                        .and_then(|p| authors.get(&p.team))
+                        .cloned()
+                        .unwrap_or_default(),
+                    url: project
+                        .map(|p| p.page_url())
+                        .or_else(|| file.downloads.first().cloned()),
+                }
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.name.to_lowercase());
+
+        Ok(Modlist {
+            name: pack.index.name.clone(),
+            version: pack.index.version_id.clone(),
+            entries,
+        })
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!(
+            "# {} {}\n\n| Mod | Version | Authors |\n| --- | --- | --- |\n",
+            self.name, self.version
+        );
+        for entry in &self.entries {
+            let name = markdown_escape(&entry.name);
+            let name = match &entry.url {
+                Some(url) => format!("[{}]({})", name, url),
+                None => name,
+            };
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} |",
+                name,
+                markdown_escape(entry.version.as_deref().unwrap_or("")),
+                markdown_escape(&entry.authors.join(", "))
+            );
+        }
+        out
+    }
+
+    /// A standalone HTML page with the list as a table.
+    pub fn to_html(&self) -> String {
+        let title = html_escape(&format!("{} {}", self.name, self.version));
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n<table>\n<thead><tr><th>Mod</th><th>Version</th><th>Authors</th></tr></thead>\n<tbody>\n",
+            title
+        );
+        for entry in &self.entries {
+            let name = html_escape(&entry.name);
+            let name = match &entry.url {
+                Some(url) => format!("<a href=\"{}\">{}</a>", html_escape(url.as_str()), name),
+                None => name,
+            };
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                name,
+                html_escape(entry.version.as_deref().unwrap_or("")),
+                html_escape(&entry.authors.join(", "))
+            );
+        }
+        out.push_str("</tbody>\n</table>\n</body>\n</html>\n");
+        out
+    }
+
+    /// RFC 4180 CSV with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("name,version,authors,url,path\r\n");
+        for entry in &self.entries {
+            let fields = [
+                entry.name.clone(),
+                entry.version.clone().unwrap_or_default(),
+                entry.authors.join(", "),
+                entry.url.as_ref().map(Url::to_string).unwrap_or_default(),
+                entry.path.display().to_string(),
+            ];
+            let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+            out.push_str(&row.join(","));
+            out.push_str("\r\n");
+        }
+        out
+    }
+}
+
+fn markdown_escape(s: &str) -> String {
+    s.replace('<', "&lt;")
+        .replace('|', "\\|")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
@@ -0,0 +1,347 @@
+use crate::http;
+use crate::util::STATE_DIR;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io, path::Path};
+use url::Url;
+
+pub type ApiError = Box<dyn std::error::Error + Send + Sync>;
+
+pub const API_BASE: &str = "https://api.modrinth.com/v2/";
+
+/// Thin client for the parts of the Modrinth v2 API this crate uses.
+#[derive(Debug, Clone)]
+pub struct ModrinthClient {
+    client: Client,
+    base: Url,
+    token: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Project {
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    pub project_type: String,
+    /// Id of the team whose members are the project's authors.
+    pub team: String,
+    /// `required`, `optional`, `unsupported` or `unknown`.
+    #[serde(default)]
+    pub client_side: Option<String>,
+    #[serde(default)]
+    pub server_side: Option<String>,
+    #[serde(default)]
+    pub license: Option<License>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct License {
+    /// An SPDX identifier or expression, or a `LicenseRef-` for custom and
+    /// all-rights-reserved licenses.
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    pub url: Option<String>,
+}
+
+impl Project {
+    /// The project's page on modrinth.com.
+    pub fn page_url(&self) -> Url {
+        Url::parse(&format!(
+            "https://modrinth.com/{}/{}",
+            self.project_type, self.slug
+        ))
+        .expect("valid project URL")
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TeamMember {
+    pub team_id: String,
+    pub user: User,
+    pub role: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub username: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Version {
+    pub id: String,
+    pub project_id: String,
+    pub name: String,
+    pub version_number: String,
+    pub version_type: String,
+    pub date_published: String,
+    pub files: Vec<VersionFile>,
+    #[serde(default)]
+    pub dependencies: Vec<VersionDependency>,
+}
+
+/// Another project or version a version needs or works with. At least one
+/// of the ids is set.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VersionDependency {
+    pub version_id: Option<String>,
+    pub project_id: Option<String>,
+    /// `required`, `optional`, `incompatible` or `embedded`.
+    pub dependency_type: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VersionFile {
+    pub url: Url,
+    pub filename: String,
+    pub primary: bool,
+    pub size: u64,
+    /// Hex digests keyed by algorithm: `sha1` and `sha512`.
+    pub hashes: HashMap<String, String>,
+}
+
+/// The Modrinth project and version a server directory was installed from,
+/// stored in `.observe/modrinth.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModrinthSource {
+    pub project_id: String,
+    pub slug: String,
+    pub version_id: String,
+    pub version_number: String,
+}
+
+const SOURCE_FILE_NAME: &str = "modrinth.json";
+
+impl ModrinthSource {
+    pub fn new(project: &Project, version: &Version) -> Self {
+        ModrinthSource {
+            project_id: project.id.clone(),
+            slug: project.slug.clone(),
+            version_id: version.id.clone(),
+            version_number: version.version_number.clone(),
+        }
+    }
+
+    pub fn load(root: &Path) -> io::Result<Option<Self>> {
+        match fs::read(root.join(STATE_DIR).join(SOURCE_FILE_NAME)) {
+            Ok(data) => serde_json::from_slice(&data).map(Some).map_err(Into::into),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        let dir = root.join(STATE_DIR);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(SOURCE_FILE_NAME), serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// The project id in a Modrinth CDN URL such as
+/// `https://cdn.modrinth.com/data/AANobbMI/versions/OihdIimA/sodium.jar`.
+pub fn project_id_from_url(url: &Url) -> Option<&str> {
+    cdn_ids(url).map(|(project, _)| project)
+}
+
+/// The version id in a Modrinth CDN URL, as for [`project_id_from_url`].
+pub fn version_id_from_url(url: &Url) -> Option<&str> {
+    cdn_ids(url).and_then(|(_, version)| version)
+}
+
+fn cdn_ids(url: &Url) -> Option<(&str, Option<&str>)> {
+    if url.host_str() != Some("cdn.modrinth.com") {
+        return None;
+    }
+    let mut segments = url.path_segments()?;
+    match (segments.next(), segments.next()) {
+        (Some("data"), Some(project)) if !project.is_empty() => {
+            let version = match (segments.next(), segments.next()) {
+                (Some("versions"), Some(version)) if !version.is_empty() => Some(version),
+                _ => None,
+            };
+            Some((project, version))
+        }
+        _ => None,
+    }
+}
+
+impl Version {
+    /// The version's `.mrpack`, preferring the file marked primary.
+    pub fn mrpack_file(&self) -> Option<&VersionFile> {
+        let mut packs = self
+            .files
+            .iter()
+            .filter(|f| f.filename.ends_with(".mrpack"));
+        let first = packs.clone().next();
+        packs.find(|f| f.primary).or(first)
+    }
+}
+
+impl ModrinthClient {
+    pub fn new(client: Client) -> Self {
+        ModrinthClient {
+            client,
+            base: Url::parse(API_BASE).expect("valid API base URL"),
+            token: None,
+        }
+    }
+
+    /// Authenticate API requests with a Modrinth personal access token.
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, ApiError> {
+        let url = self.base.join(path)?;
+        let response = http::get(&self.client, &url, self.token.as_deref())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json().await?)
+    }
+
+    async fn post<B: Serialize, T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, ApiError> {
+        let url = self.base.join(path)?;
+        let response = http::post(&self.client, &url, self.token.as_deref())
+            .json(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json().await?)
+    }
+
+    pub async fn project(&self, id_or_slug: &str) -> Result<Project, ApiError> {
+        self.get(&format!("project/{}", id_or_slug)).await
+    }
+
+    /// Several projects in one request. Unknown ids are left out.
+    pub async fn projects(&self, ids: &[&str]) -> Result<Vec<Project>, ApiError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.get(&format!("projects?ids={}", ids_query(ids)?)).await
+    }
+
+    /// Members of several teams in one request, one list per team found.
+    pub async fn teams(&self, ids: &[&str]) -> Result<Vec<Vec<TeamMember>>, ApiError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.get(&format!("teams?ids={}", ids_query(ids)?)).await
+    }
+
+    /// The versions files with these hex hashes belong to, keyed by hash.
+    /// `algorithm` is `sha1` or `sha512`. Files Modrinth doesn't host are
+    /// left out.
+    pub async fn versions_from_hashes(
+        &self,
+        hashes: &[String],
+        algorithm: &'static str,
+    ) -> Result<HashMap<String, Version>, ApiError> {
+        if hashes.is_empty() {
+            return Ok(HashMap::new());
+        }
+        #[derive(Serialize)]
+        struct Request<'a> {
+            hashes: &'a [String],
+            algorithm: &'static str,
+        }
+        self.post("version_files", &Request { hashes, algorithm })
+            .await
+    }
+
+    /// The newest version for `loaders` and `game_versions` of each project
+    /// the files with these hex hashes belong to, keyed by hash.
+    pub async fn latest_versions_from_hashes(
+        &self,
+        hashes: &[String],
+        algorithm: &'static str,
+        loaders: &[&str],
+        game_versions: &[String],
+    ) -> Result<HashMap<String, Version>, ApiError> {
+        if hashes.is_empty() {
+            return Ok(HashMap::new());
+        }
+        #[derive(Serialize)]
+        struct Request<'a> {
+            hashes: &'a [String],
+            algorithm: &'static str,
+            loaders: &'a [&'a str],
+            game_versions: &'a [String],
+        }
+        self.post(
+            "version_files/update",
+            &Request {
+                hashes,
+                algorithm,
+                loaders,
+                game_versions,
+            },
+        )
+        .await
+    }
+
+    /// Several versions by id in one request. Unknown ids are left out.
+    pub async fn versions_by_id(&self, ids: &[&str]) -> Result<Vec<Version>, ApiError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.get(&format!("versions?ids={}", ids_query(ids)?)).await
+    }
+
+    /// All versions of a project, newest first.
+    pub async fn versions(&self, id_or_slug: &str) -> Result<Vec<Version>, ApiError> {
+        self.get(&format!("project/{}/version", id_or_slug)).await
+    }
+
+    /// Resolves a modpack version by id or version number, or the newest
+    /// release (falling back to the newest version of any type) when
+    /// `version` is `None`.
+    pub async fn resolve_modpack(
+        &self,
+        slug: &str,
+        version: Option<&str>,
+    ) -> Result<(Project, Version), ApiError> {
+        let project = self.project(slug).await?;
+        if project.project_type != "modpack" {
+            return Err(format!(
+                "{} is a {}, not a modpack",
+                project.slug, project.project_type
+            )
+            .into());
+        }
+
+        let versions = self.versions(&project.id).await?;
+        let selected = match version {
+            Some(wanted) => versions
+                .iter()
+                .find(|v| v.id == wanted || v.version_number == wanted),
+            None => versions
+                .iter()
+                .find(|v| v.version_type == "release")
+                .or(versions.first()),
+        };
+
+        match selected {
+            Some(v) => Ok((project.clone(), v.clone())),
+            None => Err(format!(
+                "no version {} found for {}",
+                version.unwrap_or("(latest)"),
+                project.slug
+            )
+            .into()),
+        }
+    }
+}
+
+/// The URL-encoded JSON array the API's bulk endpoints take.
+fn ids_query(ids: &[&str]) -> Result<String, ApiError> {
+    let ids = serde_json::to_string(ids)?;
+    Ok(url::form_urlencoded::byte_serialize(ids.as_bytes()).collect())
+}
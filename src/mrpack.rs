@@ -15,6 +15,33 @@ pub enum DependencyId {
     Other(String),
 }
 
+impl DependencyId {
+    /// The identifier as written in `modrinth.index.json`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Minecraft => "minecraft",
+            Self::Forge => "forge",
+            Self::Neoforge => "neoforge",
+            Self::FabricLoader => "fabric-loader",
+            Self::QuiltLoader => "quilt-loader",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+impl From<&str> for DependencyId {
+    fn from(id: &str) -> Self {
+        match id {
+            "minecraft" => Self::Minecraft,
+            "forge" => Self::Forge,
+            "neoforge" => Self::Neoforge,
+            "fabric-loader" => Self::FabricLoader,
+            "quilt-loader" => Self::QuiltLoader,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
 impl Display for DependencyId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -28,6 +55,13 @@ impl Display for DependencyId {
     }
 }
 
+/// Parses a game or loader version, padding versions like `1.20` that
+/// other pack formats write but semver rejects.
+pub(crate) fn parse_dependency_version(version: &str) -> Result<Version, semver::Error> {
+    Version::parse(version)
+        .or_else(|err| Version::parse(&format!("{}.0", version)).map_err(|_| err))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileHashes {
     #[serde(serialize_with = "hex::serialize")]
@@ -36,8 +70,9 @@ pub struct FileHashes {
     #[serde(serialize_with = "hex::serialize")]
     #[serde(deserialize_with = "hex::deserialize")]
     pub sha512: [u8; 64],
+    /// Further hex digests keyed by algorithm, e.g. `sha256`, `blake3` or
+    /// `md5`, as some third-party tools write them.
     #[serde(flatten)]
-    #[allow(unused)]
     pub other_hashes: HashMap<String, String>,
 }
 
@@ -58,11 +93,30 @@ pub enum Requirement {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MRFile {
+    /// Relative to the server root. Read with `\\` or `/` separators and
+    /// written with `/`.
+    #[serde(with = "crate::util::pack_path")]
     pub path: PathBuf,
     pub hashes: FileHashes,
     pub env: Option<Environment>,
     pub downloads: Vec<Url>,
-    pub file_size: u32,
+    pub file_size: u64,
+}
+
+impl MRFile {
+    /// Server-side requirement; files without `env` are required everywhere.
+    pub fn server_requirement(&self) -> Requirement {
+        self.env
+            .as_ref()
+            .map_or(Requirement::Required, |env| env.server)
+    }
+
+    /// Client-side requirement; files without `env` are required everywhere.
+    pub fn client_requirement(&self) -> Requirement {
+        self.env
+            .as_ref()
+            .map_or(Requirement::Required, |env| env.client)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -72,6 +126,8 @@ pub struct MRIndex {
     pub format_version: u32,
     pub version_id: String,
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
     pub files: Vec<MRFile>,
     pub dependencies: HashMap<DependencyId, Version>,
 }
@@ -0,0 +1,255 @@
+use crate::config::Notifications;
+use crate::events::SyncSummary;
+use crate::http;
+use crate::verify::{DriftKind, VerifyReport};
+use indicatif::HumanBytes;
+use reqwest::Client;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+pub type NotifyError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Most paths listed in a message before the rest are only counted.
+const MAX_LISTED: usize = 10;
+
+/// Discord embed colours.
+const GREEN: u32 = 0x2e_cc_71;
+const YELLOW: u32 = 0xf1_c4_0f;
+const RED: u32 = 0xe7_4c_3c;
+
+/// The outcomes a chat webhook can be told about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOn {
+    /// A sync finished or failed.
+    Sync,
+    /// An update check found a new version of the installed Modrinth pack.
+    Update,
+    /// A verify found files that differ from the pack.
+    Drift,
+}
+
+/// A message about how something went, for [`Notifier::send`].
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub on: NotifyOn,
+    pub level: Level,
+    pub title: String,
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Ok,
+    Warning,
+    Failure,
+}
+
+impl Notification {
+    /// `pack` synced, with what changed.
+    pub fn synced(pack: &str, summary: &SyncSummary) -> Self {
+        let mut lines = vec![format!(
+            "{} downloaded ({}), {} from cache, {} verified",
+            summary.downloaded,
+            HumanBytes(summary.bytes_downloaded),
+            summary.cache_hits,
+            summary.verified
+        )];
+        if summary.overrides_written > 0 {
+            lines.push(format!("{} overrides written", summary.overrides_written));
+        }
+        if summary.merge_conflicts > 0 {
+            lines.push(format!(
+                "{} overrides merged with conflicts",
+                summary.merge_conflicts
+            ));
+        }
+        if summary.pruned > 0 {
+            lines.push(format!("{} files pruned", summary.pruned));
+        }
+        let level = if summary.merge_conflicts > 0 {
+            Level::Warning
+        } else {
+            Level::Ok
+        };
+        Notification {
+            on: NotifyOn::Sync,
+            level,
+            title: format!("Synced {}", pack),
+            lines,
+        }
+    }
+
+    /// Syncing `pack` failed with `error`, leaving `failed` files uninstalled.
+    pub fn sync_failed(pack: &str, error: &str, failed: &[PathBuf]) -> Self {
+        let mut lines = vec![error.to_string()];
+        lines.extend(listed(
+            failed
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect(),
+        ));
+        Notification {
+            on: NotifyOn::Sync,
+            level: Level::Failure,
+            title: format!("Sync of {} failed", pack),
+            lines,
+        }
+    }
+
+    /// `project` has a version newer than the one installed.
+    pub fn update_available(project: &str, from: &str, to: &str) -> Self {
+        Notification {
+            on: NotifyOn::Update,
+            level: Level::Warning,
+            title: format!("Update available for {}", project),
+            lines: vec![format!("{} -> {}", from, to)],
+        }
+    }
+
+    /// The server no longer matches `pack`.
+    pub fn drift(pack: &str, report: &VerifyReport) -> Self {
+        let mut lines = vec![format!(
+            "{} missing, {} modified, {} extra",
+            report.missing.len(),
+            report.modified.len(),
+            report.extra.len()
+        )];
+        lines.extend(listed(
+            report
+                .drift()
+                .map(|(path, kind)| format!("{} ({})", path.display(), kind))
+                .collect(),
+        ));
+        Notification {
+            on: NotifyOn::Drift,
+            level: Level::Warning,
+            title: format!("The server has drifted from {}", pack),
+            lines,
+        }
+    }
+
+    /// `path` stopped matching `pack` while being watched.
+    pub fn file_drifted(pack: &str, path: &Path, kind: DriftKind) -> Self {
+        Notification {
+            on: NotifyOn::Drift,
+            level: Level::Warning,
+            title: format!("The server has drifted from {}", pack),
+            lines: vec![format!("{} is {}", path.display(), kind)],
+        }
+    }
+}
+
+/// The first [`MAX_LISTED`] of `items` as a bulleted list, then how many
+/// more there are.
+fn listed(items: Vec<String>) -> Vec<String> {
+    let more = items.len().saturating_sub(MAX_LISTED);
+    let mut lines: Vec<String> = items
+        .into_iter()
+        .take(MAX_LISTED)
+        .map(|item| format!("• {}", item))
+        .collect();
+    if more > 0 {
+        lines.push(format!("…and {} more", more));
+    }
+    lines
+}
+
+/// Posts notifications to the Discord and Slack incoming webhooks `[notify]`
+/// in `observe.toml` names.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    client: Client,
+    discord: Option<Url>,
+    slack: Option<Url>,
+    on: Option<Vec<NotifyOn>>,
+}
+
+impl Notifier {
+    /// A notifier for `config`, or `None` if it names no webhook.
+    pub fn new(config: &Notifications) -> Result<Option<Self>, NotifyError> {
+        let parse = |kind: &str, url: &Option<String>| {
+            url.as_deref()
+                .map(|url| {
+                    Url::parse(url).map_err(|err| format!("invalid {} webhook URL: {}", kind, err))
+                })
+                .transpose()
+        };
+        let discord = parse("Discord", &config.discord)?;
+        let slack = parse("Slack", &config.slack)?;
+        if discord.is_none() && slack.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Notifier {
+            client: http::client(),
+            discord,
+            slack,
+            on: config.on.clone(),
+        }))
+    }
+
+    /// Posts `notification` to every webhook, unless it is about something
+    /// the config leaves out. Tries them all before reporting a failure.
+    pub async fn send(&self, notification: &Notification) -> Result<(), NotifyError> {
+        if let Some(on) = &self.on
+            && !on.contains(&notification.on)
+        {
+            return Ok(());
+        }
+        let mut errors = Vec::new();
+        if let Some(url) = &self.discord
+            && let Err(err) = self.post(url, &discord_payload(notification)).await
+        {
+            errors.push(format!("Discord: {}", err));
+        }
+        if let Some(url) = &self.slack
+            && let Err(err) = self.post(url, &slack_payload(notification)).await
+        {
+            errors.push(format!("Slack: {}", err));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; ").into())
+        }
+    }
+
+    async fn post(&self, url: &Url, payload: &serde_json::Value) -> Result<(), reqwest::Error> {
+        let response = self.client.post(url.clone()).json(payload).send().await;
+        // Webhook URLs carry their token, so keep them out of errors.
+        response
+            .and_then(|response| response.error_for_status())
+            .map(drop)
+            .map_err(|err| err.without_url())
+    }
+}
+
+fn discord_payload(notification: &Notification) -> serde_json::Value {
+    let color = match notification.level {
+        Level::Ok => GREEN,
+        Level::Warning => YELLOW,
+        Level::Failure => RED,
+    };
+    serde_json::json!({
+        "embeds": [{
+            "title": notification.title,
+            "description": notification.lines.join("\n"),
+            "color": color,
+        }]
+    })
+}
+
+fn slack_payload(notification: &Notification) -> serde_json::Value {
+    let icon = match notification.level {
+        Level::Ok => ":white_check_mark:",
+        Level::Warning => ":warning:",
+        Level::Failure => ":x:",
+    };
+    let mut text = format!("{} *{}*", icon, notification.title);
+    for line in &notification.lines {
+        text.push('\n');
+        text.push_str(line);
+    }
+    serde_json::json!({ "text": text })
+}
@@ -0,0 +1,43 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+/// Name of the ignore file looked up in the root of the target directory.
+pub const IGNORE_FILE_NAME: &str = ".observeignore";
+
+/// Gitignore-style patterns for local files sync must leave alone: matches
+/// are never pruned, and existing matches are never overwritten by
+/// overrides.
+#[derive(Debug, Clone)]
+pub struct ObserveIgnore {
+    matcher: Gitignore,
+}
+
+impl ObserveIgnore {
+    /// Reads `<root>/.observeignore`. A missing file ignores nothing.
+    pub fn load(root: &Path) -> Result<Self, ignore::Error> {
+        let mut builder = GitignoreBuilder::new(root);
+        let path = root.join(IGNORE_FILE_NAME);
+        if path.is_file()
+            && let Some(err) = builder.add(path)
+        {
+            return Err(err);
+        }
+        Ok(ObserveIgnore {
+            matcher: builder.build()?,
+        })
+    }
+
+    pub fn empty() -> Self {
+        ObserveIgnore {
+            matcher: Gitignore::empty(),
+        }
+    }
+
+    /// Whether `path`, relative to the root, or any directory containing it
+    /// is ignored.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.matcher
+            .matched_path_or_any_parents(path, false)
+            .is_ignore()
+    }
+}
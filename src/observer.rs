@@ -0,0 +1,255 @@
+use crate::events::SyncSummary;
+use crate::report::FileStatus;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use url::Url;
+
+/// The stages of a sync that work through a known number of files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPhase {
+    /// Checking, downloading or copying from cache the pack's index files.
+    Files,
+    /// Writing the pack's overrides.
+    Overrides,
+}
+
+/// Follows a sync as it runs, for consumers that show progress their own way;
+/// register one with [`PackSyncer::observer`](crate::PackSyncer::observer).
+/// Every method does nothing by default. Paths are relative to the target
+/// directory, and methods may be called concurrently from several download
+/// tasks.
+pub trait SyncObserver: Send + Sync {
+    /// `phase` began, covering `total` files.
+    fn on_phase_start(&self, phase: SyncPhase, total: u64) {
+        let _ = (phase, total);
+    }
+
+    /// A download of `path` from the mirror at `url` began, expecting
+    /// `size` bytes. A retried download starts again from zero, possibly
+    /// from another mirror.
+    fn on_file_start(&self, path: &Path, url: &Url, size: u64) {
+        let _ = (path, url, size);
+    }
+
+    /// Another `bytes` of `path` arrived.
+    fn on_bytes(&self, path: &Path, bytes: u64) {
+        let _ = (path, bytes);
+    }
+
+    /// `path` is dealt with, one way or another. Called for every index file
+    /// and override, including one whose failure ends the sync.
+    fn on_file_done(&self, path: &Path, status: &FileStatus) {
+        let _ = (path, status);
+    }
+
+    /// Every file of `phase` is done.
+    fn on_phase_done(&self, phase: SyncPhase) {
+        let _ = phase;
+    }
+
+    /// `path` was pruned.
+    fn on_prune(&self, path: &Path) {
+        let _ = path;
+    }
+
+    /// The sync finished. Not called when it fails.
+    fn on_complete(&self, summary: &SyncSummary) {
+        let _ = summary;
+    }
+}
+
+pub type SharedObserver = Arc<dyn SyncObserver>;
+
+/// indicatif progress bars on the terminal: one for each phase, and one for
+/// each download in flight, naming the file, its rate and the mirror it
+/// comes from.
+#[derive(Default)]
+pub struct ProgressBars {
+    bars: MultiProgress,
+    phase: Mutex<Option<ProgressBar>>,
+    downloads: Mutex<HashMap<PathBuf, ProgressBar>>,
+}
+
+impl ProgressBars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SyncObserver for ProgressBars {
+    fn on_phase_start(&self, phase: SyncPhase, total: u64) {
+        let template = match phase {
+            SyncPhase::Files => "Server files: [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
+            SyncPhase::Overrides => "Overrides: [{bar:40.green/blue}] {pos}/{len} ({eta})",
+        };
+        let bar = self.bars.add(ProgressBar::new(total));
+        bar.set_style(bar_style(template));
+        if let Some(previous) = self.phase.lock().unwrap().replace(bar) {
+            previous.finish_and_clear();
+        }
+    }
+
+    fn on_file_start(&self, path: &Path, url: &Url, size: u64) {
+        let mut downloads = self.downloads.lock().unwrap();
+        let bar = match downloads.get(path) {
+            Some(bar) => {
+                bar.set_length(size);
+                bar.reset();
+                bar
+            }
+            None => {
+                let bar = self.bars.add(ProgressBar::new(size));
+                bar.set_style(bar_style(
+                    "{prefix:.bold} [{bar:30.green/blue}] {bytes}/{total_bytes} {bytes_per_sec} ({eta}) {msg:.dim}",
+                ));
+                let name = path.file_name().unwrap_or(path.as_os_str());
+                bar.set_prefix(name.to_string_lossy().into_owned());
+                downloads.entry(path.to_path_buf()).or_insert(bar)
+            }
+        };
+        bar.set_message(format!("via {}", url.host_str().unwrap_or_default()));
+    }
+
+    fn on_bytes(&self, path: &Path, bytes: u64) {
+        if let Some(bar) = self.downloads.lock().unwrap().get(path) {
+            bar.inc(bytes);
+        }
+    }
+
+    fn on_file_done(&self, path: &Path, _status: &FileStatus) {
+        if let Some(bar) = self.downloads.lock().unwrap().remove(path) {
+            bar.finish_and_clear();
+        }
+        if let Some(bar) = &*self.phase.lock().unwrap() {
+            bar.inc(1);
+        }
+    }
+
+    fn on_phase_done(&self, _phase: SyncPhase) {
+        if let Some(bar) = self.phase.lock().unwrap().take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+/// How often [`ProgressLines`] prints while files are coming in slowly.
+const LINE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How many files [`ProgressLines`] lets go by between lines.
+const LINE_FILES: u64 = 10;
+
+/// Plain progress lines on stderr, for logs and CI where progress bars
+/// can't redraw: one every few seconds or every few files, whichever comes
+/// first.
+pub struct ProgressLines {
+    /// Put before every line, e.g. a fleet server's name.
+    prefix: String,
+    state: Mutex<LineState>,
+}
+
+struct LineState {
+    phase: SyncPhase,
+    done: u64,
+    total: u64,
+    bytes: u64,
+    printed_at: Instant,
+    printed_done: u64,
+}
+
+impl ProgressLines {
+    pub fn new() -> Self {
+        Self::with_prefix("")
+    }
+
+    /// Lines starting `<prefix>: `, to tell apart syncs running side by side.
+    pub fn with_prefix(prefix: impl Into<String>) -> Self {
+        let mut prefix = prefix.into();
+        if !prefix.is_empty() {
+            prefix.push_str(": ");
+        }
+        ProgressLines {
+            prefix,
+            state: Mutex::new(LineState {
+                phase: SyncPhase::Files,
+                done: 0,
+                total: 0,
+                bytes: 0,
+                printed_at: Instant::now(),
+                printed_done: 0,
+            }),
+        }
+    }
+}
+
+impl Default for ProgressLines {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineState {
+    /// Prints a line if enough time or files have gone by since the last.
+    fn maybe_print(&mut self, prefix: &str) {
+        if self.done - self.printed_done < LINE_FILES && self.printed_at.elapsed() < LINE_INTERVAL {
+            return;
+        }
+        self.print(prefix);
+    }
+
+    fn print(&mut self, prefix: &str) {
+        match self.phase {
+            SyncPhase::Files => eprintln!(
+                "{}Server files: {}/{} ({} downloaded)",
+                prefix,
+                self.done,
+                self.total,
+                HumanBytes(self.bytes)
+            ),
+            SyncPhase::Overrides => {
+                eprintln!("{}Overrides: {}/{}", prefix, self.done, self.total)
+            }
+        }
+        self.printed_at = Instant::now();
+        self.printed_done = self.done;
+    }
+}
+
+impl SyncObserver for ProgressLines {
+    fn on_phase_start(&self, phase: SyncPhase, total: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.phase = phase;
+        state.done = 0;
+        state.total = total;
+        state.printed_at = Instant::now();
+        state.printed_done = 0;
+    }
+
+    fn on_bytes(&self, _path: &Path, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.bytes += bytes;
+        state.maybe_print(&self.prefix);
+    }
+
+    fn on_file_done(&self, _path: &Path, _status: &FileStatus) {
+        let mut state = self.state.lock().unwrap();
+        state.done += 1;
+        state.maybe_print(&self.prefix);
+    }
+
+    /// Always ends a phase with its final count, however short it was.
+    fn on_phase_done(&self, _phase: SyncPhase) {
+        self.state.lock().unwrap().print(&self.prefix);
+    }
+}
+
+fn bar_style(template: &str) -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template(template)
+        .unwrap()
+        .progress_chars("=> ")
+}
@@ -0,0 +1,340 @@
+use crate::curseforge;
+use crate::http;
+use crate::modrinth;
+use crate::mrpack::MRIndex;
+use crate::packwiz;
+use crate::util::{is_contained_path, slash_path, zip_entry_time};
+use futures_util::StreamExt;
+use reqwest::Client;
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+use tokio::io::AsyncWriteExt;
+use url::Url;
+use zip::{CompressionMethod, ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+pub type IndexError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A parsed `.mrpack`: the Modrinth index plus the contents of its
+/// `overrides/` and `server-overrides/` trees, keyed by relative path.
+#[derive(Debug, Clone)]
+pub struct Pack {
+    pub index: MRIndex,
+    pub overrides: HashMap<PathBuf, Vec<u8>>,
+    /// Unix permission bits of the overrides whose zip entries record them,
+    /// e.g. the executable bit on start scripts, masked to `0o755`.
+    pub override_modes: HashMap<PathBuf, u32>,
+    /// Modification times the overrides' zip entries record.
+    pub override_mtimes: HashMap<PathBuf, SystemTime>,
+    /// When `modrinth.index.json` was written, per its zip entry: the time
+    /// the pack was built.
+    pub index_mtime: Option<SystemTime>,
+}
+
+impl Pack {
+    /// Opens and parses the `.mrpack` at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, IndexError> {
+        let file = File::open(path)?;
+        Self::from_reader(file)
+    }
+
+    /// Parses a `.mrpack` from any seekable reader.
+    pub fn from_reader<R: Read + Seek>(reader: R) -> Result<Self, IndexError> {
+        let mut zip = ZipArchive::new(reader)?;
+        let mut index_data = None;
+        let mut index_mtime = None;
+        let mut overrides = OverrideTree::default();
+        let mut server_overrides = OverrideTree::default();
+
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            if file.is_dir() {
+                continue;
+            }
+
+            // Zips written on Windows sometimes separate with `\\`.
+            let name = file.name().replace('\\', "/");
+            let tree = if name == "modrinth.index.json" {
+                None
+            } else if let Some(path) = name.strip_prefix("overrides/")
+                && !path.is_empty()
+            {
+                Some((&mut overrides, PathBuf::from(path)))
+            } else if let Some(path) = name.strip_prefix("server-overrides/")
+                && !path.is_empty()
+            {
+                Some((&mut server_overrides, PathBuf::from(path)))
+            } else {
+                continue;
+            };
+
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf)?;
+            let mtime = file.last_modified().and_then(zip_entry_time);
+            match tree {
+                Some((tree, path)) => {
+                    // A pack from a URL is untrusted: never take setuid, setgid,
+                    // sticky or group/world write bits from its entries. Only
+                    // the operator's own override mode rules may set those.
+                    if let Some(mode) = file.unix_mode() {
+                        tree.modes.insert(path.clone(), mode & 0o755);
+                    }
+                    if let Some(mtime) = mtime {
+                        tree.mtimes.insert(path.clone(), mtime);
+                    }
+                    tree.contents.insert(path, buf);
+                }
+                None => {
+                    index_data = Some(buf);
+                    index_mtime = mtime;
+                }
+            }
+        }
+
+        let index_data = index_data.ok_or("modrinth.index.json not found in zip file")?;
+        let index: MRIndex = serde_json::from_slice(&index_data)?;
+
+        // Server overrides win over the shared ones regardless of entry order.
+        overrides.overlay(server_overrides);
+        let mut pack = Self::new(index, overrides.contents)?;
+        pack.override_modes = overrides.modes;
+        pack.override_mtimes = overrides.mtimes;
+        pack.index_mtime = index_mtime;
+        Ok(pack)
+    }
+
+    /// Layers `other` on top of this pack: its files, overrides and
+    /// dependencies win wherever both packs have the same path or id, and
+    /// a path keeps only the winning kind, index file or override. The
+    /// result keeps this pack's name, with both versions in its version.
+    pub fn overlay(mut self, other: Pack) -> Pack {
+        let other_paths: HashSet<&PathBuf> = other
+            .index
+            .files
+            .iter()
+            .map(|file| &file.path)
+            .chain(other.overrides.keys())
+            .collect();
+        self.index
+            .files
+            .retain(|file| !other_paths.contains(&file.path));
+        self.overrides.retain(|path, _| !other_paths.contains(path));
+        self.override_modes
+            .retain(|path, _| !other_paths.contains(path));
+        self.override_mtimes
+            .retain(|path, _| !other_paths.contains(path));
+
+        self.index.files.extend(other.index.files);
+        self.overrides.extend(other.overrides);
+        self.override_modes.extend(other.override_modes);
+        self.override_mtimes.extend(other.override_mtimes);
+        self.index_mtime = self.index_mtime.max(other.index_mtime);
+        self.index.dependencies.extend(other.index.dependencies);
+        self.index.version_id = format!("{}+{}", self.index.version_id, other.index.version_id);
+        self
+    }
+
+    /// Drops the files matching any of `skip`, by path or by the Modrinth
+    /// project id in their download URL, and overrides at any of those
+    /// paths. Returns the entries of `skip` that matched nothing.
+    pub fn skip<'a>(&mut self, skip: &'a [String]) -> Vec<&'a str> {
+        let mut matched = HashSet::new();
+        let mut matches = |path: &Path, project: Option<&str>| {
+            let mut any = false;
+            for entry in skip {
+                if Path::new(entry) == path || project == Some(entry.as_str()) {
+                    matched.insert(entry.as_str());
+                    any = true;
+                }
+            }
+            any
+        };
+        self.index.files.retain(|file| {
+            let project = file
+                .downloads
+                .iter()
+                .find_map(modrinth::project_id_from_url);
+            !matches(&file.path, project)
+        });
+        self.overrides.retain(|path, _| !matches(path, None));
+
+        skip.iter()
+            .map(String::as_str)
+            .filter(|entry| !matched.contains(entry))
+            .collect()
+    }
+
+    /// Writes the pack as a `.mrpack` at `path`, overrides under
+    /// `overrides/`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), IndexError> {
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        let mut zip = ZipWriter::new(File::create(path)?);
+        zip.start_file("modrinth.index.json", options)?;
+        serde_json::to_writer_pretty(&mut zip, &self.index)?;
+
+        let mut overrides: Vec<_> = self.overrides.iter().collect();
+        overrides.sort_by_key(|(path, _)| *path);
+        for (path, content) in overrides {
+            let options = match self.override_modes.get(path) {
+                Some(mode) => options.unix_permissions(*mode),
+                None => options,
+            };
+            zip.start_file(format!("overrides/{}", slash_path(path)), options)?;
+            zip.write_all(content)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+
+    /// Assembles a pack, refusing any file or override path that could
+    /// escape the server directory.
+    pub fn new(index: MRIndex, overrides: HashMap<PathBuf, Vec<u8>>) -> Result<Self, IndexError> {
+        if let Some(path) = index
+            .files
+            .iter()
+            .map(|f| &f.path)
+            .chain(overrides.keys())
+            .find(|path| !is_contained_path(path))
+        {
+            return Err(format!(
+                "refusing pack entry `{}`: paths must be relative and stay inside the server directory",
+                path.display()
+            )
+            .into());
+        }
+
+        Ok(Pack {
+            index,
+            overrides,
+            override_modes: HashMap::new(),
+            override_mtimes: HashMap::new(),
+            index_mtime: None,
+        })
+    }
+}
+
+/// Where a pack comes from: a local `.mrpack` or an `http(s)` URL. A
+/// CurseForge export zip or a packwiz `pack.toml` works in place of the
+/// `.mrpack`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackSource {
+    Path(PathBuf),
+    Url(Url),
+}
+
+impl FromStr for PackSource {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Url::parse(s) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(PackSource::Url(url)),
+            _ => Ok(PackSource::Path(PathBuf::from(s))),
+        }
+    }
+}
+
+impl Display for PackSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackSource::Path(path) => write!(f, "{}", path.display()),
+            PackSource::Url(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+impl PackSource {
+    /// Whether this names a packwiz `pack.toml` rather than a `.mrpack`.
+    pub fn is_packwiz(&self) -> bool {
+        let name = match self {
+            PackSource::Path(path) => path.to_string_lossy().into_owned(),
+            PackSource::Url(url) => url.path().to_string(),
+        };
+        name.ends_with(".toml")
+    }
+
+    /// Opens the pack, downloading remote packs into `download_dir` first.
+    /// A packwiz `pack.toml` is read in place instead. `token` is only sent
+    /// to Modrinth hosts; `curseforge_key` is needed for CurseForge exports.
+    pub async fn load(
+        &self,
+        client: &Client,
+        download_dir: &Path,
+        token: Option<&str>,
+        curseforge_key: Option<&str>,
+    ) -> Result<Pack, IndexError> {
+        if self.is_packwiz() {
+            let url = match self {
+                PackSource::Path(path) => Url::from_file_path(std::path::absolute(path)?)
+                    .map_err(|_| format!("{} is not a valid file path", path.display()))?,
+                PackSource::Url(url) => url.clone(),
+            };
+            return packwiz::load(client, &url, token).await;
+        }
+        let path = match self {
+            PackSource::Path(path) => path.clone(),
+            PackSource::Url(url) => download_pack(client, url, download_dir, token).await?,
+        };
+        if curseforge::is_curseforge(&path)? {
+            curseforge::load(client, &path, curseforge_key).await
+        } else {
+            Pack::open(path)
+        }
+    }
+}
+
+/// Downloads `url` to `<dir>/<sha1 of url>.mrpack`, replacing any earlier
+/// copy only once the transfer completes.
+pub async fn download_pack(
+    client: &Client,
+    url: &Url,
+    dir: &Path,
+    token: Option<&str>,
+) -> Result<PathBuf, IndexError> {
+    tokio::fs::create_dir_all(dir).await?;
+
+    let name = hex::encode(Sha1::digest(url.as_str().as_bytes()));
+    let dest = dir.join(format!("{}.mrpack", name));
+    let part = dir.join(format!("{}.mrpack.part", name));
+
+    let response = http::get(client, url, token)
+        .send()
+        .await?
+        .error_for_status()?;
+    let mut file = tokio::fs::File::create(&part).await?;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        file.write_all(&chunk?).await?;
+    }
+    file.flush().await?;
+    drop(file);
+
+    tokio::fs::rename(&part, &dest).await?;
+    Ok(dest)
+}
+
+/// One of a `.mrpack`'s override trees, with the metadata its zip entries
+/// record.
+#[derive(Default)]
+struct OverrideTree {
+    contents: HashMap<PathBuf, Vec<u8>>,
+    modes: HashMap<PathBuf, u32>,
+    mtimes: HashMap<PathBuf, SystemTime>,
+}
+
+impl OverrideTree {
+    /// Replaces every path `other` has, metadata included.
+    fn overlay(&mut self, other: OverrideTree) {
+        for path in other.contents.keys() {
+            self.modes.remove(path);
+            self.mtimes.remove(path);
+        }
+        self.contents.extend(other.contents);
+        self.modes.extend(other.modes);
+        self.mtimes.extend(other.mtimes);
+    }
+}
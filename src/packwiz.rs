@@ -0,0 +1,502 @@
+use crate::errors::{FileError, IoContext};
+use crate::http;
+use crate::modrinth::{ModrinthClient, VersionFile, project_id_from_url, version_id_from_url};
+use crate::mrpack::{
+    DependencyId, Environment, FileHashes, MRFile, MRIndex, Requirement, parse_dependency_version,
+};
+use crate::pack::{IndexError, Pack};
+use crate::util::{parse_pack_path, slash_path};
+use futures_util::{StreamExt, TryStreamExt, stream};
+use hex::FromHex;
+use md5::Md5;
+use reqwest::Client;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+use url::Url;
+
+pub const PACK_FILE_NAME: &str = "pack.toml";
+pub const INDEX_FILE_NAME: &str = "index.toml";
+/// Suffix packwiz gives the metadata file standing in for a downloaded mod.
+pub const METAFILE_SUFFIX: &str = ".pw.toml";
+const PACK_FORMAT: &str = "packwiz:1.1.0";
+
+/// Index entries and mod downloads fetched at once while loading.
+const LOAD_CONCURRENCY: usize = 8;
+
+/// `pack.toml`: the pack's identity, loader versions and where its index is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PackToml {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pack_format: Option<String>,
+    pub index: IndexRef,
+    /// Keyed by `minecraft`, `fabric`, `quilt`, `forge` or `neoforge`.
+    #[serde(default)]
+    pub versions: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct IndexRef {
+    pub file: String,
+    pub hash_format: String,
+    pub hash: String,
+}
+
+/// `index.toml`: every file in the pack, metafiles included, with hashes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct IndexToml {
+    pub hash_format: String,
+    #[serde(default)]
+    pub files: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct IndexEntry {
+    /// Path relative to the index, always `/`-separated.
+    pub file: String,
+    pub hash: String,
+    /// Overrides the index's `hash-format` for this entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash_format: Option<String>,
+    /// Where the file installs, when not at `file`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub metafile: bool,
+}
+
+/// A `.pw.toml` metafile describing one downloaded file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ModToml {
+    pub name: String,
+    pub filename: String,
+    #[serde(default)]
+    pub side: PackwizSide,
+    pub download: ModDownload,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub option: Option<ModOption>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update: Option<ModUpdate>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PackwizSide {
+    #[default]
+    Both,
+    Client,
+    Server,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ModDownload {
+    /// Absent for CurseForge files, which packwiz fetches via the API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<Url>,
+    pub hash_format: String,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ModOption {
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ModUpdate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modrinth: Option<ModrinthUpdate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ModrinthUpdate {
+    pub mod_id: String,
+    pub version: String,
+}
+
+/// Writes `pack` into `dir` as a packwiz pack: overrides as plain files,
+/// each index file as a `.pw.toml` metafile beside where it installs, and
+/// `index.toml` and `pack.toml` tying them together. Client-only overrides
+/// are not part of a loaded [`Pack`], so they aren't exported.
+pub fn export(pack: &Pack, dir: &Path) -> Result<(), FileError> {
+    let mut index = IndexToml {
+        hash_format: "sha256".to_string(),
+        files: Vec::new(),
+    };
+
+    for (path, content) in &pack.overrides {
+        write_file(dir, path, content)?;
+        index.files.push(IndexEntry {
+            file: slash_path(path),
+            hash: sha256(content),
+            hash_format: None,
+            alias: None,
+            metafile: false,
+        });
+    }
+
+    for file in &pack.index.files {
+        let path = metafile_path(&file.path);
+        let content = toml_string(&mod_toml(file), &path)?;
+        write_file(dir, &path, content.as_bytes())?;
+        index.files.push(IndexEntry {
+            file: slash_path(&path),
+            hash: sha256(content.as_bytes()),
+            hash_format: None,
+            alias: None,
+            metafile: true,
+        });
+    }
+    index.files.sort_by(|a, b| a.file.cmp(&b.file));
+
+    let index_content = toml_string(&index, INDEX_FILE_NAME)?;
+    write_file(dir, Path::new(INDEX_FILE_NAME), index_content.as_bytes())?;
+
+    let pack_toml = PackToml {
+        name: pack.index.name.clone(),
+        version: Some(pack.index.version_id.clone()),
+        description: pack.index.summary.clone(),
+        pack_format: Some(PACK_FORMAT.to_string()),
+        index: IndexRef {
+            file: INDEX_FILE_NAME.to_string(),
+            hash_format: "sha256".to_string(),
+            hash: sha256(index_content.as_bytes()),
+        },
+        versions: pack
+            .index
+            .dependencies
+            .iter()
+            .map(|(id, version)| (packwiz_dependency(id).to_string(), version.to_string()))
+            .collect(),
+    };
+    let content = toml_string(&pack_toml, PACK_FILE_NAME)?;
+    write_file(dir, Path::new(PACK_FILE_NAME), content.as_bytes())
+}
+
+/// Loads the packwiz pack whose `pack.toml` is at `url`, a `file:` or
+/// `http(s):` URL, checking every hash along the way. Metafiles become
+/// [`MRFile`]s and everything else becomes an override.
+///
+/// An `MRFile` needs both SHA-1 and SHA-512 plus a size, where a metafile
+/// carries one hash. Files Modrinth knows by that hash take the rest from
+/// the API; any others are downloaded once here and hashed.
+pub(crate) async fn load(
+    client: &Client,
+    url: &Url,
+    token: Option<&str>,
+) -> Result<Pack, IndexError> {
+    let pack_toml: PackToml = parse_toml(&fetch(client, url, token).await?, url)?;
+    let index_url = url.join(&pack_toml.index.file)?;
+    let index_data = fetch(client, &index_url, token).await?;
+    check_hash(
+        &index_data,
+        &pack_toml.index.hash_format,
+        &pack_toml.index.hash,
+        &index_url,
+    )?;
+    let index: IndexToml = parse_toml(&index_data, &index_url)?;
+
+    // Entries are relative to the index and must stay beside it, so a
+    // remote index can't point at other hosts or a local one at arbitrary
+    // files.
+    let base = index_url.join("./")?;
+    let entries: Vec<(&IndexEntry, Vec<u8>)> = stream::iter(index.files.iter().map(|entry| {
+        let (base, index_url, index) = (&base, &index_url, &index);
+        async move {
+            let url = index_url.join(&entry.file)?;
+            if !url.as_str().starts_with(base.as_str()) {
+                return Err(format!(
+                    "refusing packwiz entry `{}`: it points outside the pack",
+                    entry.file
+                )
+                .into());
+            }
+            let data = fetch(client, &url, token).await?;
+            let format = entry.hash_format.as_deref().unwrap_or(&index.hash_format);
+            check_hash(&data, format, &entry.hash, &url)?;
+            Ok::<_, IndexError>((entry, data))
+        }
+    }))
+    .buffered(LOAD_CONCURRENCY)
+    .try_collect()
+    .await?;
+
+    let mut overrides = HashMap::new();
+    let mut mods = Vec::new();
+    for (entry, data) in entries {
+        if entry.metafile {
+            let meta: ModToml = parse_toml(&data, &entry.file)?;
+            let file = parse_pack_path(&entry.file);
+            let dir = file.parent().unwrap_or(Path::new(""));
+            mods.push((dir.join(&meta.filename), meta));
+        } else {
+            let dest = entry.alias.as_deref().unwrap_or(&entry.file);
+            overrides.insert(parse_pack_path(dest), data);
+        }
+    }
+
+    let mut dependencies = HashMap::new();
+    for (key, version) in &pack_toml.versions {
+        let id = match key.as_str() {
+            "fabric" => DependencyId::FabricLoader,
+            "quilt" => DependencyId::QuiltLoader,
+            "minecraft" => DependencyId::Minecraft,
+            "forge" => DependencyId::Forge,
+            "neoforge" => DependencyId::Neoforge,
+            other => DependencyId::Other(other.to_string()),
+        };
+        let version = parse_dependency_version(version)
+            .map_err(|err| format!("invalid version `{}`: {}", version, err))?;
+        dependencies.insert(id, version);
+    }
+
+    let index = MRIndex {
+        game: "minecraft".to_string(),
+        format_version: 1,
+        version_id: pack_toml.version.unwrap_or_default(),
+        name: pack_toml.name,
+        summary: pack_toml.description,
+        files: resolve_files(client, token, mods).await?,
+        dependencies,
+    };
+    Pack::new(index, overrides)
+}
+
+async fn resolve_files(
+    client: &Client,
+    token: Option<&str>,
+    mods: Vec<(PathBuf, ModToml)>,
+) -> Result<Vec<MRFile>, IndexError> {
+    let modrinth = ModrinthClient::new(client.clone()).with_token(token.map(str::to_string));
+    let mut known: HashMap<String, VersionFile> = HashMap::new();
+    for algorithm in ["sha1", "sha512"] {
+        let hashes: Vec<String> = mods
+            .iter()
+            .filter(|(_, meta)| meta.download.hash_format == algorithm)
+            .map(|(_, meta)| meta.download.hash.to_lowercase())
+            .collect();
+        // A failed lookup only means downloading those files to hash them.
+        let Ok(versions) = modrinth.versions_from_hashes(&hashes, algorithm).await else {
+            continue;
+        };
+        for (hash, version) in versions {
+            if let Some(file) = version
+                .files
+                .into_iter()
+                .find(|file| file.hashes.get(algorithm) == Some(&hash))
+            {
+                known.insert(hash, file);
+            }
+        }
+    }
+
+    stream::iter(mods.into_iter().map(|(path, meta)| {
+        let known = known.get(&meta.download.hash.to_lowercase()).cloned();
+        async move {
+            let url = meta
+                .download
+                .url
+                .clone()
+                .or_else(|| known.as_ref().map(|file| file.url.clone()))
+                .ok_or_else(|| {
+                    format!(
+                        "{}: no download URL (packwiz CurseForge metafiles aren't supported)",
+                        path.display()
+                    )
+                })?;
+            let (hashes, size) = match known.as_ref().and_then(version_file_hashes) {
+                Some(hashes) => (hashes, known.map_or(0, |file| file.size)),
+                None => {
+                    let data = fetch(client, &url, token).await?;
+                    check_hash(&data, &meta.download.hash_format, &meta.download.hash, &url)?;
+                    let hashes = FileHashes {
+                        sha1: Sha1::digest(&data).into(),
+                        sha512: Sha512::digest(&data).into(),
+                        other_hashes: HashMap::new(),
+                    };
+                    (hashes, data.len() as u64)
+                }
+            };
+            Ok::<_, IndexError>(MRFile {
+                env: environment(&meta),
+                file_size: size,
+                path,
+                hashes,
+                downloads: vec![url],
+            })
+        }
+    }))
+    .buffered(LOAD_CONCURRENCY)
+    .try_collect()
+    .await
+}
+
+fn version_file_hashes(file: &VersionFile) -> Option<FileHashes> {
+    Some(FileHashes {
+        sha1: <[u8; 20]>::from_hex(file.hashes.get("sha1")?).ok()?,
+        sha512: <[u8; 64]>::from_hex(file.hashes.get("sha512")?).ok()?,
+        other_hashes: HashMap::new(),
+    })
+}
+
+/// Maps a metafile's side and `[option]` onto an mrpack `env`.
+fn environment(meta: &ModToml) -> Option<Environment> {
+    let wanted = match &meta.option {
+        Some(option) if option.optional => Requirement::Optional,
+        _ => Requirement::Required,
+    };
+    let (client, server) = match meta.side {
+        PackwizSide::Both if wanted == Requirement::Required => return None,
+        PackwizSide::Both => (wanted, wanted),
+        PackwizSide::Client => (wanted, Requirement::Unsupported),
+        PackwizSide::Server => (Requirement::Unsupported, wanted),
+    };
+    Some(Environment { client, server })
+}
+
+/// Reads a `file:` URL from disk, or GETs anything else.
+async fn fetch(client: &Client, url: &Url, token: Option<&str>) -> Result<Vec<u8>, IndexError> {
+    if url.scheme() == "file" {
+        let path = url
+            .to_file_path()
+            .map_err(|_| format!("invalid file URL {}", url))?;
+        return tokio::fs::read(&path)
+            .await
+            .map_err(|err| format!("{}: {}", path.display(), err).into());
+    }
+    let response = http::get(client, url, token)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+fn check_hash(
+    data: &[u8],
+    format: &str,
+    expected: &str,
+    what: impl std::fmt::Display,
+) -> Result<(), IndexError> {
+    let actual = match format {
+        "sha1" => hex::encode(Sha1::digest(data)),
+        "sha256" => hex::encode(Sha256::digest(data)),
+        "sha512" => hex::encode(Sha512::digest(data)),
+        "md5" => hex::encode(Md5::digest(data)),
+        other => return Err(format!("{}: unsupported hash format `{}`", what, other).into()),
+    };
+    if actual.eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(format!("{}: content does not match its {} hash", what, format).into())
+    }
+}
+
+fn parse_toml<T: DeserializeOwned>(
+    data: &[u8],
+    what: impl std::fmt::Display,
+) -> Result<T, IndexError> {
+    let text = std::str::from_utf8(data).map_err(|err| format!("{}: {}", what, err))?;
+    toml::from_str(text).map_err(|err| format!("{}: {}", what, err).into())
+}
+
+fn mod_toml(file: &MRFile) -> ModToml {
+    let (client, server) = (file.client_requirement(), file.server_requirement());
+    let side = match (client, server) {
+        (Requirement::Unsupported, server) if server != Requirement::Unsupported => {
+            PackwizSide::Server
+        }
+        (client, Requirement::Unsupported) if client != Requirement::Unsupported => {
+            PackwizSide::Client
+        }
+        _ => PackwizSide::Both,
+    };
+    let optional = [client, server].contains(&Requirement::Optional)
+        && ![client, server].contains(&Requirement::Required);
+
+    let url = file.downloads.first();
+    let modrinth = url.and_then(|url| {
+        Some(ModrinthUpdate {
+            mod_id: project_id_from_url(url)?.to_string(),
+            version: version_id_from_url(url)?.to_string(),
+        })
+    });
+
+    ModToml {
+        name: file_stem(&file.path),
+        filename: file
+            .path
+            .file_name()
+            .unwrap_or(file.path.as_os_str())
+            .to_string_lossy()
+            .into_owned(),
+        side,
+        download: ModDownload {
+            url: url.cloned(),
+            hash_format: "sha512".to_string(),
+            hash: hex::encode(file.hashes.sha512),
+        },
+        option: optional.then_some(ModOption { optional: true }),
+        update: modrinth.map(|modrinth| ModUpdate {
+            modrinth: Some(modrinth),
+        }),
+    }
+}
+
+/// packwiz's key for a loader in `[versions]`.
+fn packwiz_dependency(id: &DependencyId) -> &str {
+    match id {
+        DependencyId::FabricLoader => "fabric",
+        DependencyId::QuiltLoader => "quilt",
+        other => other.as_str(),
+    }
+}
+
+/// `mods/sodium.jar` becomes `mods/sodium.pw.toml`.
+fn metafile_path(path: &Path) -> PathBuf {
+    path.with_file_name(format!("{}{}", file_stem(path), METAFILE_SUFFIX))
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn sha256(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+fn toml_string<T: Serialize>(value: &T, path: impl AsRef<Path>) -> Result<String, FileError> {
+    toml::to_string(value).map_err(|err| FileError::Io {
+        path: path.as_ref().to_path_buf(),
+        source: std::io::Error::other(err),
+    })
+}
+
+fn write_file(dir: &Path, path: &Path, content: &[u8]) -> Result<(), FileError> {
+    let dest = dir.join(path);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).at(parent)?;
+    }
+    fs::write(&dest, content).at(&dest)
+}
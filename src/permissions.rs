@@ -0,0 +1,51 @@
+use crate::prune::build_glob_set;
+use globset::GlobSet;
+use std::path::Path;
+
+/// Unix modes forced onto overrides by glob, e.g. `start.sh` = `755`,
+/// whatever the pack's zip entries record. Patterns match paths relative to
+/// the target directory, as prune rules do.
+#[derive(Debug, Clone, Default)]
+pub struct OverrideModes {
+    rules: Vec<(String, u32)>,
+    globs: GlobSet,
+}
+
+impl OverrideModes {
+    /// Builds the rules from `(pattern, mode)` pairs, the mode in octal.
+    /// When several patterns match a path, the longest one wins.
+    pub fn new<S: AsRef<str>, M: AsRef<str>>(
+        rules: impl IntoIterator<Item = (S, M)>,
+    ) -> Result<Self, String> {
+        let mut parsed = Vec::new();
+        for (pattern, mode) in rules {
+            let (pattern, mode) = (pattern.as_ref(), mode.as_ref());
+            let mode = u32::from_str_radix(mode, 8)
+                .ok()
+                .filter(|mode| *mode <= 0o7777)
+                .ok_or_else(|| {
+                    format!(
+                        "invalid mode `{}` for `{}` (expected octal, e.g. 755)",
+                        mode, pattern
+                    )
+                })?;
+            parsed.push((pattern.to_string(), mode));
+        }
+        let globs = build_glob_set(parsed.iter().map(|(pattern, _)| pattern))
+            .map_err(|err| err.to_string())?;
+        Ok(OverrideModes {
+            rules: parsed,
+            globs,
+        })
+    }
+
+    /// The forced mode for `path`, if a pattern matches it.
+    pub(crate) fn mode_for(&self, path: &Path) -> Option<u32> {
+        self.globs
+            .matches(path)
+            .into_iter()
+            .map(|i| &self.rules[i])
+            .max_by_key(|(pattern, _)| pattern.len())
+            .map(|(_, mode)| *mode)
+    }
+}
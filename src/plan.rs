@@ -0,0 +1,82 @@
+use serde::Serialize;
+use std::{fmt::Display, path::PathBuf};
+
+/// The changes a sync would make, as computed by a dry run. Paths are
+/// relative to the target directory.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncPlan {
+    /// Index files that are missing or fail verification.
+    pub downloads: Vec<PathBuf>,
+    /// Overrides that do not exist on disk yet.
+    pub new_overrides: Vec<PathBuf>,
+    /// Existing files whose content an override would replace.
+    pub overwrites: Vec<PathBuf>,
+    /// Locally edited overrides the pack's changes would be merged into.
+    pub merges: Vec<PathBuf>,
+    /// Locally edited overrides whose edits clash with the pack's changes;
+    /// these would be left alone.
+    pub conflicts: Vec<PathBuf>,
+    /// Files prune would delete.
+    pub prunes: Vec<PathBuf>,
+}
+
+/// A file prune is about to delete, passed to the confirmation hook.
+#[derive(Debug, Clone, Serialize)]
+pub struct PruneCandidate {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.downloads.is_empty()
+            && self.new_overrides.is_empty()
+            && self.overwrites.is_empty()
+            && self.merges.is_empty()
+            && self.conflicts.is_empty()
+            && self.prunes.is_empty()
+    }
+
+    pub(crate) fn sort(&mut self) {
+        self.downloads.sort();
+        self.new_overrides.sort();
+        self.overwrites.sort();
+        self.merges.sort();
+        self.conflicts.sort();
+        self.prunes.sort();
+    }
+}
+
+impl Display for SyncPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "Nothing to do");
+        }
+
+        let sections = [
+            ("Download", &self.downloads),
+            ("Create", &self.new_overrides),
+            ("Overwrite", &self.overwrites),
+            ("Merge", &self.merges),
+            ("Conflict", &self.conflicts),
+            ("Prune", &self.prunes),
+        ];
+
+        let mut first = true;
+        for (label, paths) in sections {
+            if paths.is_empty() {
+                continue;
+            }
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            write!(f, "{} ({}):", label, paths.len())?;
+            for path in paths {
+                write!(f, "\n  {}", path.display())?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use url::Url;
+
+/// Hosts the Modrinth pack format allows in `downloads`.
+pub const MODRINTH_DOMAINS: &[&str] = &[
+    "cdn.modrinth.com",
+    "github.com",
+    "raw.githubusercontent.com",
+    "gitlab.com",
+];
+
+/// Which download URLs in a pack may be fetched.
+#[derive(Debug, Clone)]
+pub struct DownloadPolicy {
+    allowed_domains: Vec<String>,
+    require_https: bool,
+}
+
+impl Default for DownloadPolicy {
+    fn default() -> Self {
+        DownloadPolicy {
+            allowed_domains: MODRINTH_DOMAINS.iter().map(|d| d.to_string()).collect(),
+            require_https: true,
+        }
+    }
+}
+
+impl DownloadPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also accept downloads from `domain`, e.g. a self-hosted mirror.
+    pub fn allow_domain(mut self, domain: impl Into<String>) -> Self {
+        self.allowed_domains
+            .push(domain.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Reject plain-HTTP URLs. On by default.
+    pub fn require_https(mut self, enabled: bool) -> Self {
+        self.require_https = enabled;
+        self
+    }
+
+    pub fn allows(&self, url: &Url) -> bool {
+        self.allows_scheme(url) && self.allows_host(url)
+    }
+
+    pub fn allows_scheme(&self, url: &Url) -> bool {
+        match url.scheme() {
+            "https" => true,
+            "http" => !self.require_https,
+            _ => false,
+        }
+    }
+
+    pub fn allows_host(&self, url: &Url) -> bool {
+        url.host_str()
+            .is_some_and(|host| self.allowed_domains.iter().any(|d| d == host))
+    }
+}
@@ -0,0 +1,229 @@
+use std::{collections::HashMap, path::Path};
+
+/// One logical line of a `.properties` file: a key/value pair (possibly
+/// spanning continuation lines), or a comment or blank line.
+struct Entry<'a> {
+    key: Option<&'a str>,
+    value: &'a str,
+    /// The original text, including line breaks.
+    raw: &'a str,
+}
+
+const WHITESPACE: [char; 3] = [' ', '\t', '\x0c'];
+
+/// Whether `path` is a Java `.properties` file.
+pub(crate) fn is_properties(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "properties")
+}
+
+/// The keys and values `text` sets, the last line for a key winning.
+pub(crate) fn read_properties(text: &str) -> HashMap<String, String> {
+    values(&parse(text))
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Applies the keys `upstream` sets to `local`, keeping every other line of
+/// `local` (comments, ordering and keys the pack doesn't mention) as is.
+///
+/// With the `base` content from the last sync, only keys the pack has
+/// changed since then are updated, so a key like `server-port` that was
+/// edited locally keeps its value until the pack ships a new one. Returns
+/// `None` if any input is not UTF-8.
+pub(crate) fn merge_properties(
+    base: Option<&[u8]>,
+    local: &[u8],
+    upstream: &[u8],
+) -> Option<Vec<u8>> {
+    let local = std::str::from_utf8(local).ok()?;
+    let upstream = std::str::from_utf8(upstream).ok()?;
+    let base = match base {
+        Some(base) => Some(std::str::from_utf8(base).ok()?),
+        None => None,
+    };
+
+    let base_values: Option<HashMap<&str, &str>> = base.map(|base| values(&parse(base)));
+    let upstream_entries = parse(upstream);
+    let upstream_values = values(&upstream_entries);
+    let pack_changed = |key: &str, value: &str| match &base_values {
+        Some(base) => base.get(key) != Some(&value),
+        None => true,
+    };
+
+    let mut merged = String::with_capacity(local.len());
+    let mut seen = Vec::new();
+    for entry in parse(local) {
+        match entry
+            .key
+            .and_then(|key| upstream_values.get(key).map(|v| (key, *v)))
+        {
+            Some((key, value)) => {
+                if !seen.contains(&key) {
+                    seen.push(key);
+                }
+                if pack_changed(key, value) && entry.value != value {
+                    push_line(&mut merged, upstream_line(key, &upstream_entries));
+                } else {
+                    merged.push_str(entry.raw);
+                }
+            }
+            None => merged.push_str(entry.raw),
+        }
+    }
+
+    for entry in &upstream_entries {
+        if let Some(key) = entry.key
+            && !seen.contains(&key)
+        {
+            seen.push(key);
+            push_line(&mut merged, upstream_line(key, &upstream_entries));
+        }
+    }
+
+    Some(merged.into_bytes())
+}
+
+/// The last upstream line setting `key`, which is the one that takes effect.
+fn upstream_line<'a>(key: &str, entries: &[Entry<'a>]) -> &'a str {
+    entries
+        .iter()
+        .rev()
+        .find(|e| e.key == Some(key))
+        .map_or("", |e| e.raw)
+}
+
+/// Appends `line`, making sure it starts on and ends with a new line.
+fn push_line(out: &mut String, line: &str) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str(line);
+    if !line.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+fn values<'a>(entries: &[Entry<'a>]) -> HashMap<&'a str, &'a str> {
+    entries
+        .iter()
+        .filter_map(|e| Some((e.key?, e.value)))
+        .collect()
+}
+
+fn parse(text: &str) -> Vec<Entry<'_>> {
+    let mut entries = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        // A logical line continues while a line ends in an odd number of
+        // backslashes.
+        let mut end = 0;
+        loop {
+            let line_end = rest[end..].find('\n').map_or(rest.len(), |i| end + i + 1);
+            let line = rest[end..line_end].trim_end_matches(['\n', '\r']);
+            end = line_end;
+            let continued = line.len() - line.trim_end_matches('\\').len();
+            let is_comment = matches!(line.trim_start().chars().next(), Some('#' | '!'));
+            if continued.is_multiple_of(2) || is_comment || end == rest.len() {
+                break;
+            }
+        }
+        let raw = &rest[..end];
+        rest = &rest[end..];
+
+        let line = raw.trim_start();
+        if line.is_empty() || line.starts_with(['#', '!']) {
+            entries.push(Entry {
+                key: None,
+                value: "",
+                raw,
+            });
+            continue;
+        }
+
+        let (key, value) = split_key(line);
+        entries.push(Entry {
+            key: Some(key),
+            value: value.trim_end_matches(['\n', '\r']),
+            raw,
+        });
+    }
+
+    entries
+}
+
+/// Splits a logical line at the first unescaped `=`, `:` or whitespace.
+fn split_key(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '=' || c == ':' || c.is_whitespace() {
+            let key = &line[..i];
+            let value = line[i..].trim_start_matches(WHITESPACE);
+            let value = value
+                .strip_prefix(['=', ':'])
+                .map_or(value, |value| value.trim_start_matches(WHITESPACE));
+            return (key, value);
+        }
+    }
+    (line, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(base: Option<&str>, local: &str, upstream: &str) -> String {
+        let merged = merge_properties(
+            base.map(str::as_bytes),
+            local.as_bytes(),
+            upstream.as_bytes(),
+        );
+        String::from_utf8(merged.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn local_edits_survive_a_clean_merge() {
+        let base = "server-port=25565\nmotd=Old\n";
+        let local = "# my server\nserver-port=25570\nmotd=Old\nwhite-list=true\n";
+        let upstream = "server-port=25565\nmotd=New\n";
+        assert_eq!(
+            merge(Some(base), local, upstream),
+            "# my server\nserver-port=25570\nmotd=New\nwhite-list=true\n"
+        );
+    }
+
+    #[test]
+    fn the_pack_wins_a_conflicting_key() {
+        let base = "max-players=20\n";
+        let local = "max-players=10\n";
+        let upstream = "max-players=40\n";
+        assert_eq!(merge(Some(base), local, upstream), "max-players=40\n");
+    }
+
+    #[test]
+    fn without_a_base_every_upstream_key_applies() {
+        let local = "server-port=25570\nlevel-seed=42\n";
+        let upstream = "server-port=25565\ndifficulty=hard\n";
+        assert_eq!(
+            merge(None, local, upstream),
+            "server-port=25565\nlevel-seed=42\ndifficulty=hard\n"
+        );
+    }
+
+    #[test]
+    fn non_utf8_input_is_not_merged() {
+        assert!(merge_properties(None, b"a=\xff\n", b"a=1\n").is_none());
+    }
+
+    #[test]
+    fn continuation_lines_are_one_value() {
+        let values = read_properties("motd=Hello \\\n    World\nkey : value\n");
+        assert_eq!(values["key"], "value");
+        assert!(values["motd"].starts_with("Hello"));
+    }
+}
@@ -0,0 +1,158 @@
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::{Component, Path, PathBuf};
+
+const PRUNE_DIRECTORIES_INDEX: &[&str] = &["mods", "resourcepacks"];
+const PRUNE_DIRECTORIES_OVERRIDES: &[&str] = &["config"];
+
+/// Server data that a pack may ship a few files into but never owns, so
+/// these are never pruned just because the pack writes there.
+const NEVER_DERIVED_DIRECTORIES: &[&str] = &[
+    "world",
+    "world_nether",
+    "world_the_end",
+    "logs",
+    "crash-reports",
+    "libraries",
+    "versions",
+];
+
+/// Which directories prune walks, and which files inside them it may touch.
+///
+/// Files under these directories are pruned when the index does not list
+/// them and the overrides do not ship them. Patterns are globs matched against paths relative to the target
+/// directory, where `*` stays within one path segment and `**` spans any
+/// number, e.g. `config/luckperms/**`.
+#[derive(Debug, Clone)]
+pub struct PruneRules {
+    pub(crate) index_dirs: Vec<PathBuf>,
+    pub(crate) override_dirs: Vec<PathBuf>,
+    /// Whether the pack's own directories are added to `index_dirs` and
+    /// `override_dirs`, until those are set explicitly.
+    derive_index_dirs: bool,
+    derive_override_dirs: bool,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl Default for PruneRules {
+    fn default() -> Self {
+        PruneRules {
+            index_dirs: PRUNE_DIRECTORIES_INDEX.iter().map(PathBuf::from).collect(),
+            override_dirs: PRUNE_DIRECTORIES_OVERRIDES
+                .iter()
+                .map(PathBuf::from)
+                .collect(),
+            derive_index_dirs: true,
+            derive_override_dirs: true,
+            include: None,
+            exclude: GlobSet::empty(),
+        }
+    }
+}
+
+impl PruneRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Directories checked against the index. Defaults to `mods`,
+    /// `resourcepacks` and every top-level directory the pack's files are
+    /// installed into, e.g. `shaderpacks`.
+    pub fn index_dirs<P: Into<PathBuf>>(mut self, dirs: impl IntoIterator<Item = P>) -> Self {
+        self.index_dirs = dirs.into_iter().map(Into::into).collect();
+        self.derive_index_dirs = false;
+        self
+    }
+
+    /// Directories checked against the overrides. Defaults to `config` and
+    /// every top-level directory the overrides write into, e.g. `kubejs`.
+    pub fn override_dirs<P: Into<PathBuf>>(mut self, dirs: impl IntoIterator<Item = P>) -> Self {
+        self.override_dirs = dirs.into_iter().map(Into::into).collect();
+        self.derive_override_dirs = false;
+        self
+    }
+
+    /// Adds the top-level directories of `files` and `overrides` to the
+    /// directories that weren't set explicitly. Hidden directories, world
+    /// and log directories, and any directory holding a `level.dat` under
+    /// `root` are left out.
+    pub(crate) fn with_pack_dirs<'a>(
+        mut self,
+        root: &Path,
+        files: impl IntoIterator<Item = &'a PathBuf>,
+        overrides: impl IntoIterator<Item = &'a PathBuf>,
+    ) -> Self {
+        if self.derive_index_dirs {
+            extend_dirs(&mut self.index_dirs, root, files);
+        }
+        if self.derive_override_dirs {
+            extend_dirs(&mut self.override_dirs, root, overrides);
+        }
+        self
+    }
+
+    /// When set, only files matching one of these patterns are pruned.
+    pub fn include<S: AsRef<str>>(
+        mut self,
+        patterns: impl IntoIterator<Item = S>,
+    ) -> Result<Self, globset::Error> {
+        let patterns: Vec<S> = patterns.into_iter().collect();
+        self.include = if patterns.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(patterns)?)
+        };
+        Ok(self)
+    }
+
+    /// Files matching any of these patterns are never pruned.
+    pub fn exclude<S: AsRef<str>>(
+        mut self,
+        patterns: impl IntoIterator<Item = S>,
+    ) -> Result<Self, globset::Error> {
+        self.exclude = build_glob_set(patterns)?;
+        Ok(self)
+    }
+
+    pub(crate) fn allows(&self, path: &Path) -> bool {
+        self.include.as_ref().is_none_or(|set| set.is_match(path)) && !self.exclude.is_match(path)
+    }
+}
+
+fn extend_dirs<'a>(
+    dirs: &mut Vec<PathBuf>,
+    root: &Path,
+    paths: impl IntoIterator<Item = &'a PathBuf>,
+) {
+    for path in paths {
+        let mut components = path.components();
+        let (Some(Component::Normal(first)), Some(_)) = (components.next(), components.next())
+        else {
+            continue;
+        };
+        let dir = PathBuf::from(first);
+        let name = first.to_string_lossy();
+        if dirs.contains(&dir)
+            || name.starts_with('.')
+            || NEVER_DERIVED_DIRECTORIES.contains(&name.as_ref())
+            || root.join(&dir).join("level.dat").exists()
+        {
+            continue;
+        }
+        dirs.push(dir);
+    }
+}
+
+pub(crate) fn build_glob_set<S: AsRef<str>>(
+    patterns: impl IntoIterator<Item = S>,
+) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(glob(pattern.as_ref())?);
+    }
+    builder.build()
+}
+
+fn glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
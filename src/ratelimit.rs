@@ -0,0 +1,53 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::time::sleep;
+
+/// Token bucket shared by every transfer it throttles. Up to one second of
+/// traffic may burst before callers are made to wait.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    bytes_per_sec: f64,
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(bytes_per_sec: u64) -> Self {
+        let bytes_per_sec = bytes_per_sec.max(1) as f64;
+        RateLimiter {
+            bytes_per_sec,
+            state: Mutex::new(Bucket {
+                tokens: bytes_per_sec,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Accounts for `bytes` just transferred, sleeping until the bucket is
+    /// back above zero.
+    pub(crate) async fn consume(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = self.state.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * self.bytes_per_sec).min(self.bytes_per_sec);
+            bucket.updated = now;
+            bucket.tokens -= bytes as f64;
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / self.bytes_per_sec)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::config::RconConfig;
+use crate::properties::read_properties;
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+pub type RconError = Box<dyn std::error::Error + Send + Sync>;
+
+/// RCON password used when `observe.toml` doesn't give one. Takes
+/// precedence over `rcon.password` in `server.properties`.
+pub const PASSWORD_ENV: &str = "OBSERVE_RCON_PASSWORD";
+
+/// Minecraft's default `rcon.port`.
+pub const DEFAULT_PORT: u16 = 25575;
+
+const DEFAULT_WARNING: &str = "The server is being updated; expect lag or a restart shortly";
+
+/// How long a single RCON exchange may take.
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a stopping server may take to shut down.
+const STOP_TIMEOUT: Duration = Duration::from_secs(120);
+
+const TYPE_RESPONSE: i32 = 0;
+const TYPE_COMMAND: i32 = 2;
+const TYPE_LOGIN: i32 = 3;
+
+/// An authenticated RCON connection to a Minecraft server.
+pub struct Rcon {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl Rcon {
+    pub async fn connect(address: &str, password: &str) -> Result<Self, RconError> {
+        let stream = timeout(TIMEOUT, TcpStream::connect(address))
+            .await
+            .map_err(|_| format!("{}: timed out connecting", address))?
+            .map_err(|err| format!("{}: {}", address, err))?;
+        let mut rcon = Rcon { stream, next_id: 1 };
+        let id = rcon.send(TYPE_LOGIN, password).await?;
+        // A rejected login answers with id -1 rather than the request's.
+        let (reply_id, _) = rcon.receive().await?;
+        if reply_id != id {
+            return Err(format!("{}: wrong RCON password", address).into());
+        }
+        Ok(rcon)
+    }
+
+    /// Runs `command` on the server, returning its output.
+    pub async fn command(&mut self, command: &str) -> Result<String, RconError> {
+        let id = self.send(TYPE_COMMAND, command).await?;
+        loop {
+            let (reply_id, body) = self.receive().await?;
+            if reply_id == id {
+                return Ok(body);
+            }
+        }
+    }
+
+    async fn send(&mut self, kind: i32, body: &str) -> Result<i32, RconError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let length = i32::try_from(body.len() + 10).map_err(|_| "RCON command too long")?;
+        let mut packet = Vec::with_capacity(body.len() + 14);
+        packet.extend_from_slice(&length.to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        timeout(TIMEOUT, self.stream.write_all(&packet))
+            .await
+            .map_err(|_| "timed out sending an RCON command")??;
+        Ok(id)
+    }
+
+    /// Reads one response packet: its request id and body.
+    async fn receive(&mut self) -> Result<(i32, String), RconError> {
+        timeout(TIMEOUT, async {
+            let length = self.stream.read_i32_le().await?;
+            let length = usize::try_from(length)
+                .ok()
+                .filter(|length| (10..=4096 + 10).contains(length))
+                .ok_or("malformed RCON response")?;
+            let mut packet = vec![0; length];
+            self.stream.read_exact(&mut packet).await?;
+            let id = i32::from_le_bytes(packet[..4].try_into().expect("4 bytes"));
+            let kind = i32::from_le_bytes(packet[4..8].try_into().expect("4 bytes"));
+            if kind != TYPE_RESPONSE && kind != TYPE_COMMAND {
+                return Err("unexpected RCON packet type".into());
+            }
+            let body = String::from_utf8_lossy(&packet[8..length - 2]).into_owned();
+            Ok((id, body))
+        })
+        .await
+        .map_err(|_| "timed out waiting for an RCON response")?
+    }
+}
+
+/// What [`ServerControl::before_sync`] did to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerState {
+    /// Nothing answered on the RCON address.
+    NotRunning,
+    /// Players were warned and the world saved; the server is still up.
+    Saved,
+    /// Players were warned and the server stopped.
+    Stopped,
+}
+
+/// Coordinates a sync with the running server over RCON, as `[rcon]` in
+/// `observe.toml` describes: warns players, saves the world and, if asked,
+/// stops the server beforehand and starts it again afterwards.
+#[derive(Debug, Clone)]
+pub struct ServerControl {
+    address: String,
+    password: String,
+    warning: String,
+    warning_delay: Duration,
+    /// Runs after a sync that stopped the server, if set to stop it.
+    start_command: Option<String>,
+}
+
+impl ServerControl {
+    /// Settings from `config`, taking the port and password from
+    /// `server_dir`'s `server.properties` when `config` doesn't set them.
+    pub fn new(config: &RconConfig, server_dir: &Path) -> Result<Self, RconError> {
+        let properties = std::fs::read_to_string(server_dir.join("server.properties"))
+            .map(|text| read_properties(&text))
+            .unwrap_or_default();
+        let address = match &config.address {
+            Some(address) => address.clone(),
+            None => {
+                let port = match properties.get("rcon.port") {
+                    Some(port) => port.parse::<u16>().map_err(|_| {
+                        format!("invalid rcon.port `{}` in server.properties", port)
+                    })?,
+                    None => DEFAULT_PORT,
+                };
+                format!("127.0.0.1:{}", port)
+            }
+        };
+        let password = config
+            .password
+            .clone()
+            .or_else(|| std::env::var(PASSWORD_ENV).ok())
+            .or_else(|| properties.get("rcon.password").cloned())
+            .filter(|password| !password.is_empty())
+            .ok_or_else(|| {
+                format!(
+                    "no RCON password: set password under [rcon], {} or rcon.password in server.properties",
+                    PASSWORD_ENV
+                )
+            })?;
+        let start_command = match (config.stop.unwrap_or(false), &config.start_command) {
+            (true, Some(command)) => Some(command.clone()),
+            (true, None) => {
+                return Err("stop needs a start-command to bring the server back".into());
+            }
+            (false, _) => None,
+        };
+        Ok(ServerControl {
+            address,
+            password,
+            warning: config
+                .warning
+                .clone()
+                .unwrap_or_else(|| DEFAULT_WARNING.to_string()),
+            warning_delay: Duration::from_secs(config.warning_delay.unwrap_or(0)),
+            start_command,
+        })
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Warns players, waits out the warning delay and saves the world, then
+    /// stops the server and waits for it to go down if set to.
+    pub async fn before_sync(&self) -> Result<ServerState, RconError> {
+        let mut rcon = match Rcon::connect(&self.address, &self.password).await {
+            Ok(rcon) => rcon,
+            Err(_) if !self.is_up().await => return Ok(ServerState::NotRunning),
+            Err(err) => return Err(err),
+        };
+        rcon.command(&format!("say {}", self.warning)).await?;
+        if !self.warning_delay.is_zero() {
+            tokio::time::sleep(self.warning_delay).await;
+        }
+        rcon.command("save-all flush").await?;
+        if self.start_command.is_none() {
+            return Ok(ServerState::Saved);
+        }
+
+        // The server drops the connection as it goes down, so the reply
+        // may never come.
+        let _ = rcon.command("stop").await;
+        drop(rcon);
+        let started = Instant::now();
+        while self.is_up().await {
+            if started.elapsed() > STOP_TIMEOUT {
+                return Err(format!(
+                    "the server at {} was still up {}s after being stopped",
+                    self.address,
+                    STOP_TIMEOUT.as_secs()
+                )
+                .into());
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        Ok(ServerState::Stopped)
+    }
+
+    /// Starts the server again if [`before_sync`](Self::before_sync)
+    /// stopped it.
+    pub async fn after_sync(&self, state: ServerState) -> Result<(), RconError> {
+        let Some(command) = self
+            .start_command
+            .clone()
+            .filter(|_| state == ServerState::Stopped)
+        else {
+            return Ok(());
+        };
+        let status = tokio::task::spawn_blocking(move || shell(&command).status()).await??;
+        if !status.success() {
+            return Err(format!("the start command failed ({})", status).into());
+        }
+        Ok(())
+    }
+
+    /// Whether anything accepts connections on the RCON address.
+    async fn is_up(&self) -> bool {
+        matches!(
+            timeout(TIMEOUT, TcpStream::connect(&self.address)).await,
+            Ok(Ok(_))
+        )
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> std::process::Command {
+    let mut shell = std::process::Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> std::process::Command {
+    let mut shell = std::process::Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
@@ -0,0 +1,377 @@
+use crate::lockfile::{LOCKFILE_NAME, Lockfile};
+use crate::s3::S3Target;
+use crate::sftp::SftpTarget;
+use crate::util::{STATE_DIR, slash_path};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+use url::Url;
+use walkdir::WalkDir;
+
+pub type RemoteError = Box<dyn std::error::Error + Send + Sync>;
+
+/// What the last deploy uploaded, kept on the target in [`STATE_DIR`] so
+/// any machine deploying next knows what it may replace or remove.
+const MANIFEST_FILE_NAME: &str = "deployed.json";
+
+/// URL schemes `--target` accepts.
+pub const SCHEMES: &[&str] = &["sftp", "s3"];
+
+/// A file on a remote target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoteFile {
+    pub size: u64,
+}
+
+/// Somewhere a synced server directory is uploaded to, such as an SFTP
+/// server or an S3 bucket. Paths are relative to the target's root.
+pub trait RemoteTarget: Send {
+    /// Every file under `dir`, recursively, keyed by path relative to the
+    /// root. A missing directory holds no files.
+    fn list(&mut self, dir: &Path) -> Result<BTreeMap<PathBuf, RemoteFile>, RemoteError>;
+
+    /// The file at `path`, if there is one.
+    fn stat(&mut self, path: &Path) -> Result<Option<RemoteFile>, RemoteError>;
+
+    /// The contents of `path`, if it exists.
+    fn read(&mut self, path: &Path) -> Result<Option<Vec<u8>>, RemoteError>;
+
+    /// Writes `content` to `path`, creating its parent directories.
+    fn write(&mut self, path: &Path, content: &[u8]) -> Result<(), RemoteError>;
+
+    /// Uploads the local file `local` to `path`, creating its parent
+    /// directories, and keeps its mode and modification time where the
+    /// target can.
+    fn upload(&mut self, local: &Path, path: &Path) -> Result<(), RemoteError>;
+
+    fn remove(&mut self, path: &Path) -> Result<(), RemoteError>;
+
+    /// The SHA-1 of the file at `path` computed on the target, or `None`
+    /// when the target can't hash files itself.
+    fn sha1(&mut self, path: &Path) -> Result<Option<[u8; 20]>, RemoteError>;
+}
+
+/// Parses a `--target` URL, checking its scheme is one [`connect`]
+/// understands.
+pub fn parse_target(s: &str) -> Result<Url, String> {
+    let url = Url::parse(s).map_err(|err| format!("invalid target `{}`: {}", s, err))?;
+    if !SCHEMES.contains(&url.scheme()) {
+        return Err(format!(
+            "unsupported target scheme `{}` (expected {})",
+            url.scheme(),
+            SCHEMES.join(", ")
+        ));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err(format!("invalid target `{}`: no host", s));
+    }
+    Ok(url)
+}
+
+/// Parses an SSH destination as scp writes it, `user@host:/srv/mc`, or as
+/// an `ssh://` URL, into the `sftp://` URL [`connect`] takes.
+pub fn parse_ssh_destination(s: &str) -> Result<Url, String> {
+    if s.contains("://") {
+        let mut url =
+            Url::parse(s).map_err(|err| format!("invalid destination `{}`: {}", s, err))?;
+        if !matches!(url.scheme(), "ssh" | "sftp") {
+            return Err(format!(
+                "invalid destination `{}` (expected user@host:/dir or ssh://user@host/dir)",
+                s
+            ));
+        }
+        let (user, password, host, port) = (
+            url.username().to_string(),
+            url.password().map(str::to_string),
+            url.host_str().map(str::to_string),
+            url.port(),
+        );
+        url = Url::parse(&format!(
+            "sftp://{}{}",
+            host.unwrap_or_default(),
+            url.path()
+        ))
+        .map_err(|err| format!("invalid destination `{}`: {}", s, err))?;
+        let _ = url.set_username(&user);
+        let _ = url.set_password(password.as_deref());
+        let _ = url.set_port(port);
+        return parse_target(url.as_str());
+    }
+    let (host, dir) = s
+        .split_once(':')
+        .ok_or_else(|| format!("invalid destination `{}` (expected user@host:/dir)", s))?;
+    if !dir.starts_with('/') {
+        return Err(format!(
+            "invalid destination `{}`: the directory must be an absolute path",
+            s
+        ));
+    }
+    let mut url = Url::parse("sftp://localhost/").expect("valid URL");
+    let (user, host) = match host.rsplit_once('@') {
+        Some((user, host)) => (Some(user), host),
+        None => (None, host),
+    };
+    url.set_host(Some(host))
+        .map_err(|err| format!("invalid destination `{}`: {}", s, err))?;
+    if let Some(user) = user {
+        let _ = url.set_username(user);
+    }
+    url.set_path(dir);
+    parse_target(url.as_str())
+}
+
+/// `url` without its password, for messages.
+pub fn redacted(url: &Url) -> Url {
+    let mut url = url.clone();
+    let _ = url.set_password(None);
+    url
+}
+
+/// Connects to the target `url` names, e.g. `sftp://user@host/srv/mc` or
+/// `s3://bucket/prefix`.
+pub fn connect(url: &Url) -> Result<Box<dyn RemoteTarget>, RemoteError> {
+    match url.scheme() {
+        "sftp" => Ok(Box::new(SftpTarget::connect(url)?)),
+        "s3" => Ok(Box::new(S3Target::connect(url)?)),
+        other => Err(format!(
+            "unsupported target scheme `{}` (expected {})",
+            other,
+            SCHEMES.join(", ")
+        )
+        .into()),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeployOptions {
+    /// Also remove remote files the local directory doesn't have from the
+    /// directories being deployed, not just files a past deploy uploaded.
+    pub prune: bool,
+    /// Hash remote files on the target, where it can, instead of trusting
+    /// the manifest of the last deploy.
+    pub verify_hashes: bool,
+}
+
+/// What a deploy changed on the target.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeployReport {
+    pub uploaded: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub unchanged: usize,
+    /// Bytes uploaded.
+    pub bytes: u64,
+    /// Whether remote files were checked by hash rather than by size and
+    /// the manifest.
+    pub hashes_verified: bool,
+}
+
+impl Display for DeployReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Deployed: {} uploaded ({} bytes), {} removed, {} unchanged",
+            self.uploaded.len(),
+            self.bytes,
+            self.removed.len(),
+            self.unchanged
+        )?;
+        if !self.hashes_verified {
+            write!(f, " (checked by size)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    /// Keyed by `/`-separated path.
+    files: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct ManifestEntry {
+    size: u64,
+    #[serde(with = "hex::serde")]
+    sha1: [u8; 20],
+}
+
+/// Mirrors the synced directory `root` onto `target`: uploads files that
+/// are new or changed, and removes files a past deploy uploaded that are
+/// gone locally. Only the top-level directories being deployed are listed,
+/// so worlds and logs on the target are never walked, let alone touched.
+/// Hidden top-level entries, such as observe-rs' own state and the Java
+/// runtime, stay local.
+///
+/// The delta comes from hashes already on record wherever possible: pack
+/// files are taken at the hashes `observed.lock` gives them locally, and a
+/// target synced in place, with no deploy manifest yet, is trusted to hold
+/// the files its own `observed.lock` lists, so only what differs is sent.
+pub fn deploy(
+    target: &mut dyn RemoteTarget,
+    root: &Path,
+    options: &DeployOptions,
+) -> Result<DeployReport, RemoteError> {
+    let local_lock = Lockfile::load(root)
+        .map_err(|err| format!("{}: {}", root.join(LOCKFILE_NAME).display(), err))?;
+    let local = local_files(root, local_lock.as_ref())?;
+    let manifest_path = Path::new(STATE_DIR).join(MANIFEST_FILE_NAME);
+    let old: Manifest = match target.read(&manifest_path)? {
+        Some(data) => serde_json::from_slice(&data)
+            .map_err(|err| format!("{}: {}", manifest_path.display(), err))?,
+        None => Manifest::default(),
+    };
+    let old_files: BTreeMap<PathBuf, ManifestEntry> = old
+        .files
+        .into_iter()
+        .map(|(path, entry)| (PathBuf::from(path), entry))
+        .collect();
+    // What the target holds as far as anyone recorded: its own lockfile's
+    // pack files, then whatever past deploys uploaded on top.
+    let mut known = match target.read(Path::new(LOCKFILE_NAME))? {
+        Some(data) => locked_entries(&data),
+        None => BTreeMap::new(),
+    };
+    known.extend(old_files);
+
+    // Top-level directories are listed whole; files at the root one by one.
+    let mut dirs = BTreeSet::new();
+    let mut root_files = BTreeSet::new();
+    for path in local.keys().chain(known.keys()) {
+        match top_level_dir(path) {
+            Some(dir) => dirs.insert(dir),
+            None => root_files.insert(path.clone()),
+        };
+    }
+    let mut remote = BTreeMap::new();
+    for dir in &dirs {
+        remote.extend(target.list(dir)?);
+    }
+    for path in root_files {
+        if let Some(file) = target.stat(&path)? {
+            remote.insert(path, file);
+        }
+    }
+
+    let mut report = DeployReport {
+        hashes_verified: options.verify_hashes,
+        ..DeployReport::default()
+    };
+    for (path, entry) in &local {
+        let unchanged = match remote.get(path) {
+            Some(file) if file.size == entry.size => {
+                let remote_sha1 = if options.verify_hashes {
+                    target.sha1(path)?
+                } else {
+                    None
+                };
+                if options.verify_hashes && remote_sha1.is_none() {
+                    report.hashes_verified = false;
+                }
+                match remote_sha1 {
+                    Some(sha1) => sha1 == entry.sha1,
+                    None => known.get(path) == Some(entry),
+                }
+            }
+            _ => false,
+        };
+        if unchanged {
+            report.unchanged += 1;
+        } else {
+            target.upload(&root.join(path), path)?;
+            report.uploaded.push(path.clone());
+            report.bytes += entry.size;
+        }
+    }
+
+    for path in remote.keys() {
+        if !local.contains_key(path)
+            && (known.contains_key(path) || (options.prune && top_level_dir(path).is_some()))
+        {
+            target.remove(path)?;
+            report.removed.push(path.clone());
+        }
+    }
+
+    let manifest = Manifest {
+        files: local
+            .into_iter()
+            .map(|(path, entry)| (slash_path(&path), entry))
+            .collect(),
+    };
+    target.write(
+        &manifest_path,
+        &serde_json::to_vec_pretty(&manifest).expect("Manifest serializes"),
+    )?;
+    Ok(report)
+}
+
+/// Every file under `root` to deploy, with its size and SHA-1. Files `lock`
+/// records at their current size are taken at its hash rather than re-read.
+fn local_files(
+    root: &Path,
+    lock: Option<&Lockfile>,
+) -> Result<BTreeMap<PathBuf, ManifestEntry>, RemoteError> {
+    let mut files = BTreeMap::new();
+    let entries = WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() > 1 || !entry.file_name().to_string_lossy().starts_with('.')
+        });
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path().strip_prefix(root)?.to_path_buf();
+        let size = entry.metadata()?.len();
+        let locked = lock
+            .and_then(|lock| lock.files.get(&path))
+            .filter(|locked| locked.file_size == size);
+        let (size, sha1) = match locked {
+            Some(locked) => (size, locked.sha1),
+            None => hash_file(entry.path())
+                .map_err(|err| format!("{}: {}", entry.path().display(), err))?,
+        };
+        files.insert(path, ManifestEntry { size, sha1 });
+    }
+    Ok(files)
+}
+
+/// The pack files a target's `observed.lock` lists. One that can't be read
+/// lists none, and everything is compared as if never deployed.
+fn locked_entries(data: &[u8]) -> BTreeMap<PathBuf, ManifestEntry> {
+    let Ok(lock) = serde_json::from_slice::<Lockfile>(data) else {
+        return BTreeMap::new();
+    };
+    lock.files
+        .into_iter()
+        .map(|(path, locked)| {
+            let entry = ManifestEntry {
+                size: locked.file_size,
+                sha1: locked.sha1,
+            };
+            (path, entry)
+        })
+        .collect()
+}
+
+fn hash_file(path: &Path) -> io::Result<(u64, [u8; 20])> {
+    let mut hasher = Sha1::new();
+    let size = io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok((size, hasher.finalize().into()))
+}
+
+/// The first component of `path`, when there is more than one.
+fn top_level_dir(path: &Path) -> Option<PathBuf> {
+    let mut components = path.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(first)), Some(_)) => Some(PathBuf::from(first)),
+        _ => None,
+    }
+}
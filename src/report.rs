@@ -0,0 +1,84 @@
+use crate::errors::ErrorCategory;
+use crate::events::SyncSummary;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// What a sync did to each path, alongside the totals.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    pub summary: SyncSummary,
+    pub files: Vec<FileReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    /// Relative to the target directory.
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub status: FileStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum FileStatus {
+    /// Already on disk with the right hashes.
+    Verified,
+    CacheHit,
+    Downloaded {
+        bytes: u64,
+    },
+    OverrideWritten,
+    /// A locally edited override with the pack's changes merged in.
+    OverrideMerged,
+    /// Local edits to an override clash with the pack's changes. The local
+    /// file was kept as is.
+    MergeConflict,
+    Pruned,
+    /// `eula.txt` written with `eula=true`.
+    EulaAccepted,
+    Skipped {
+        reason: SkipReason,
+    },
+    /// Could not be installed; only reported in keep-going mode.
+    Failed {
+        error: String,
+        category: ErrorCategory,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The pack marks the file unsupported on servers.
+    ServerUnsupported,
+    /// A server-optional file left out by the optional-file policy or a
+    /// choice.
+    OptionalExcluded,
+    /// An override protected by `.observeignore`.
+    Ignored,
+    /// An override whose local copy was chosen over the pack's.
+    LocalKept,
+}
+
+impl SyncReport {
+    pub(crate) fn push(&mut self, path: PathBuf, status: FileStatus) {
+        self.files.push(FileReport { path, status });
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &FileReport> {
+        self.files
+            .iter()
+            .filter(|f| matches!(f.status, FileStatus::Failed { .. }))
+    }
+
+    /// Whether every file was installed.
+    pub fn is_success(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    pub fn conflicts(&self) -> impl Iterator<Item = &FileReport> {
+        self.files
+            .iter()
+            .filter(|f| f.status == FileStatus::MergeConflict)
+    }
+}
@@ -0,0 +1,477 @@
+use crate::http;
+use crate::remote::{RemoteError, RemoteFile, RemoteTarget};
+use crate::util::{slash_path, utc_parts};
+use hmac::{Hmac, Mac};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use reqwest::{Client, Method, Response, StatusCode};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::runtime::Handle;
+use url::Url;
+
+/// Endpoint of an S3-compatible store, e.g. `https://minio.example.com`.
+/// Buckets are then addressed by path. Without it, AWS is used.
+pub const ENDPOINT_ENV: &str = "AWS_ENDPOINT_URL";
+
+/// Files larger than this are uploaded in parts of this size.
+const PART_SIZE: usize = 16 * 1024 * 1024;
+/// Object metadata holding the SHA-1 of what was uploaded.
+const SHA1_HEADER: &str = "x-amz-meta-sha1";
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// Everything but RFC 3986's unreserved characters, as SigV4 encodes them.
+const ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+/// As [`ENCODE`], leaving the slashes between key segments alone.
+const ENCODE_PATH: &AsciiSet = &ENCODE.remove(b'/');
+
+/// A prefix in an S3 bucket, e.g. `s3://servers/survival`. Credentials come
+/// from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and, for temporary
+/// ones, `AWS_SESSION_TOKEN`; the region from `AWS_REGION`. Set
+/// [`ENDPOINT_ENV`] for other S3-compatible stores.
+///
+/// Objects carry the SHA-1 of their contents as metadata, which is what
+/// deploys check instead of downloading them.
+pub struct S3Target {
+    client: Client,
+    runtime: Handle,
+    /// Bucket URL, ending in `/`, that keys are appended to.
+    base: Url,
+    prefix: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl S3Target {
+    /// Must be called from within the Tokio runtime, as requests are made on
+    /// it; the target's own methods block and belong on a blocking thread.
+    pub fn connect(url: &Url) -> Result<Self, RemoteError> {
+        let bucket = url.host_str().ok_or("S3 target has no bucket")?;
+        let prefix = percent_decode_str(url.path())
+            .decode_utf8()?
+            .trim_matches('/')
+            .to_string();
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| DEFAULT_REGION.to_string());
+        let base = match env::var(ENDPOINT_ENV) {
+            Ok(endpoint) => {
+                let endpoint =
+                    Url::parse(&endpoint).map_err(|err| format!("{}: {}", ENDPOINT_ENV, err))?;
+                endpoint.join(&format!("{}/", bucket))?
+            }
+            Err(_) => Url::parse(&format!("https://{}.s3.{}.amazonaws.com/", bucket, region))?,
+        };
+        let (Ok(access_key), Ok(secret_key)) = (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        ) else {
+            return Err(
+                "no S3 credentials: set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY".into(),
+            );
+        };
+
+        Ok(S3Target {
+            client: http::builder().build()?,
+            runtime: Handle::try_current()?,
+            base,
+            prefix,
+            region,
+            access_key,
+            secret_key,
+            session_token: env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+
+    fn key(&self, path: &Path) -> String {
+        match (self.prefix.as_str(), slash_path(path)) {
+            (prefix, path) if path.is_empty() => prefix.to_string(),
+            ("", path) => path,
+            (prefix, path) => format!("{}/{}", prefix, path),
+        }
+    }
+
+    /// Sends a request signed with AWS Signature Version 4.
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, String)],
+        body: Vec<u8>,
+    ) -> Result<Response, RemoteError> {
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(name, value)| (encode(name, ENCODE), encode(value, ENCODE)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut url = self.base.join(&encode(key, ENCODE_PATH))?;
+        url.set_query((!query.is_empty()).then_some(query.as_str()));
+
+        let (year, month, day, hour, minute, second) = utc_parts(SystemTime::now());
+        let date = format!("{:04}{:02}{:02}", year, month, day);
+        let timestamp = format!("{}T{:02}{:02}{:02}Z", date, hour, minute, second);
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let payload_hash = hex::encode(Sha256::digest(&body));
+
+        let mut signed: BTreeMap<String, String> = headers
+            .iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        signed.insert("host".into(), host);
+        signed.insert("x-amz-content-sha256".into(), payload_hash.clone());
+        signed.insert("x-amz-date".into(), timestamp.clone());
+        if let Some(token) = &self.session_token {
+            signed.insert("x-amz-security-token".into(), token.clone());
+        }
+        let signed_names = signed.keys().cloned().collect::<Vec<_>>().join(";");
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            url.path(),
+            query,
+            signed
+                .iter()
+                .map(|(name, value)| format!("{}:{}\n", name, value))
+                .collect::<String>(),
+            signed_names,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = [self.region.as_bytes(), b"s3", b"aws4_request"]
+            .iter()
+            .fold(
+                hmac_sha256(
+                    format!("AWS4{}", self.secret_key).as_bytes(),
+                    date.as_bytes(),
+                ),
+                |key, part| hmac_sha256(&key, part),
+            );
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+        let mut request = self.client.request(method, url).header(
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key, scope, signed_names, signature
+            ),
+        );
+        for (name, value) in signed.into_iter().filter(|(name, _)| name != "host") {
+            request = request.header(name, value);
+        }
+        Ok(request.body(body).send().await?)
+    }
+
+    /// Like [`send`](Self::send), but turns error responses into errors
+    /// carrying S3's own message.
+    async fn send_ok(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, String)],
+        body: Vec<u8>,
+    ) -> Result<Response, RemoteError> {
+        let response = self.send(method, key, query, headers, body).await?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        let message = match (xml_value(&body, "Code"), xml_value(&body, "Message")) {
+            (Some(code), Some(message)) => format!("{}: {}", code, message),
+            (Some(code), None) => code,
+            _ => status.to_string(),
+        };
+        Err(format!("S3 {} {}: {}", status.as_u16(), key, message).into())
+    }
+
+    /// The object's metadata, or `None` if there is no object at `key`.
+    async fn head(&self, key: &str) -> Result<Option<Response>, RemoteError> {
+        let response = self.send(Method::HEAD, key, &[], &[], Vec::new()).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(response)),
+            status => Err(format!("S3 {} {}", status.as_u16(), key).into()),
+        }
+    }
+
+    async fn upload_multipart(
+        &self,
+        key: &str,
+        mut file: fs::File,
+        headers: &[(&str, String)],
+    ) -> Result<(), RemoteError> {
+        let response = self
+            .send_ok(Method::POST, key, &[("uploads", "")], headers, Vec::new())
+            .await?
+            .text()
+            .await?;
+        let upload_id = xml_value(&response, "UploadId").ok_or("S3 returned no UploadId")?;
+
+        let mut parts = Vec::new();
+        let result: Result<(), RemoteError> = async {
+            loop {
+                let mut part = Vec::with_capacity(PART_SIZE);
+                (&mut file).take(PART_SIZE as u64).read_to_end(&mut part)?;
+                if part.is_empty() && !parts.is_empty() {
+                    return Ok(());
+                }
+                let number = (parts.len() + 1).to_string();
+                let response = self
+                    .send_ok(
+                        Method::PUT,
+                        key,
+                        &[("partNumber", &number), ("uploadId", &upload_id)],
+                        &[],
+                        part,
+                    )
+                    .await?;
+                let etag = response
+                    .headers()
+                    .get("etag")
+                    .and_then(|etag| etag.to_str().ok())
+                    .ok_or("S3 returned no ETag for a part")?;
+                parts.push(etag.to_string());
+            }
+        }
+        .await;
+        if let Err(err) = result {
+            // Otherwise the parts linger, and are billed, until a lifecycle
+            // rule cleans them up.
+            let _ = self
+                .send(
+                    Method::DELETE,
+                    key,
+                    &[("uploadId", &upload_id)],
+                    &[],
+                    Vec::new(),
+                )
+                .await;
+            return Err(err);
+        }
+
+        let body = format!(
+            "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+            parts
+                .iter()
+                .enumerate()
+                .map(|(i, etag)| format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    i + 1,
+                    xml_escape(etag)
+                ))
+                .collect::<String>()
+        );
+        let response = self
+            .send_ok(
+                Method::POST,
+                key,
+                &[("uploadId", &upload_id)],
+                &[],
+                body.into_bytes(),
+            )
+            .await?
+            .text()
+            .await?;
+        // Completion can fail after a 200, with the error in the body.
+        if response.contains("<Error>") {
+            let message = xml_value(&response, "Message").unwrap_or(response);
+            return Err(format!("S3 {}: {}", key, message).into());
+        }
+        Ok(())
+    }
+}
+
+impl RemoteTarget for S3Target {
+    fn list(&mut self, dir: &Path) -> Result<BTreeMap<PathBuf, RemoteFile>, RemoteError> {
+        let root = match self.key(Path::new("")) {
+            root if root.is_empty() => root,
+            root => format!("{}/", root),
+        };
+        let prefix = match self.key(dir) {
+            key if key.is_empty() => key,
+            key => format!("{}/", key),
+        };
+        let mut files = BTreeMap::new();
+        let mut continuation: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix.as_str())];
+            if let Some(token) = &continuation {
+                query.push(("continuation-token", token.as_str()));
+            }
+            let body = self.runtime.block_on(async {
+                self.send_ok(Method::GET, "", &query, &[], Vec::new())
+                    .await?
+                    .text()
+                    .await
+                    .map_err(RemoteError::from)
+            })?;
+            for contents in xml_elements(&body, "Contents") {
+                let (Some(key), Some(size)) =
+                    (xml_value(contents, "Key"), xml_value(contents, "Size"))
+                else {
+                    continue;
+                };
+                // Zero-byte "folder" objects some tools create.
+                if key.ends_with('/') {
+                    continue;
+                }
+                if let Some(path) = key.strip_prefix(&root) {
+                    files.insert(
+                        PathBuf::from(path),
+                        RemoteFile {
+                            size: size.parse()?,
+                        },
+                    );
+                }
+            }
+            continuation = xml_value(&body, "NextContinuationToken");
+            if continuation.is_none() || xml_value(&body, "IsTruncated").as_deref() != Some("true")
+            {
+                return Ok(files);
+            }
+        }
+    }
+
+    fn stat(&mut self, path: &Path) -> Result<Option<RemoteFile>, RemoteError> {
+        let Some(response) = self.runtime.block_on(self.head(&self.key(path)))? else {
+            return Ok(None);
+        };
+        Ok(response.content_length().map(|size| RemoteFile { size }))
+    }
+
+    fn read(&mut self, path: &Path) -> Result<Option<Vec<u8>>, RemoteError> {
+        let key = self.key(path);
+        self.runtime.block_on(async {
+            let response = self.send(Method::GET, &key, &[], &[], Vec::new()).await?;
+            match response.status() {
+                StatusCode::NOT_FOUND => Ok(None),
+                status if status.is_success() => Ok(Some(response.bytes().await?.to_vec())),
+                status => Err(format!("S3 {} {}", status.as_u16(), key).into()),
+            }
+        })
+    }
+
+    fn write(&mut self, path: &Path, content: &[u8]) -> Result<(), RemoteError> {
+        let key = self.key(path);
+        let headers = [(SHA1_HEADER, hex::encode(Sha1::digest(content)))];
+        self.runtime.block_on(async {
+            self.send_ok(Method::PUT, &key, &[], &headers, content.to_vec())
+                .await
+                .map(drop)
+        })
+    }
+
+    fn upload(&mut self, local: &Path, path: &Path) -> Result<(), RemoteError> {
+        let key = self.key(path);
+        let context = |err: io::Error| format!("{}: {}", local.display(), err);
+        let mut hasher = Sha1::new();
+        let size =
+            io::copy(&mut fs::File::open(local).map_err(context)?, &mut hasher).map_err(context)?;
+        let headers = [(SHA1_HEADER, hex::encode(hasher.finalize()))];
+
+        if size <= PART_SIZE as u64 {
+            let content = fs::read(local).map_err(context)?;
+            return self.runtime.block_on(async {
+                self.send_ok(Method::PUT, &key, &[], &headers, content)
+                    .await
+                    .map(drop)
+            });
+        }
+        let file = fs::File::open(local).map_err(context)?;
+        self.runtime
+            .block_on(self.upload_multipart(&key, file, &headers))
+    }
+
+    fn remove(&mut self, path: &Path) -> Result<(), RemoteError> {
+        let key = self.key(path);
+        self.runtime.block_on(async {
+            self.send_ok(Method::DELETE, &key, &[], &[], Vec::new())
+                .await
+                .map(drop)
+        })
+    }
+
+    fn sha1(&mut self, path: &Path) -> Result<Option<[u8; 20]>, RemoteError> {
+        let Some(response) = self.runtime.block_on(self.head(&self.key(path)))? else {
+            return Ok(None);
+        };
+        Ok(response
+            .headers()
+            .get(SHA1_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|hex| <[u8; 20]>::try_from(hex::decode(hex).ok()?).ok()))
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn encode(s: &str, set: &'static AsciiSet) -> String {
+    utf8_percent_encode(s, set).to_string()
+}
+
+/// The bodies of every `<tag>` element in `xml`, which S3's responses never
+/// nest in themselves.
+fn xml_elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+    let mut elements = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        elements.push(&rest[..end]);
+        rest = &rest[end + close.len()..];
+    }
+    elements
+}
+
+/// The unescaped text of the first `<tag>` element in `xml`.
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    xml_elements(xml, tag).first().map(|text| {
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    })
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
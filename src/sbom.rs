@@ -0,0 +1,343 @@
+use crate::modrinth::{ApiError, ModrinthClient};
+use crate::pack::Pack;
+use crate::util::rfc3339_timestamp;
+use serde::Serialize;
+use serde_json::{Value, json};
+use sha1::{Digest, Sha1};
+use sha2::Sha512;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{Display, Write},
+    path::PathBuf,
+    time::SystemTime,
+};
+use url::Url;
+
+/// Written into the documents as the license when none is known.
+const NOASSERTION: &str = "NOASSERTION";
+
+/// What a pack ships, with each file's Modrinth project license, for
+/// rendering as a CycloneDX or SPDX document or a license summary.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sbom {
+    pub name: String,
+    pub version: String,
+    pub components: Vec<Component>,
+}
+
+/// An index file, or a jar bundled in the overrides.
+#[derive(Debug, Clone, Serialize)]
+pub struct Component {
+    /// The project title, or the file name when Modrinth doesn't know the
+    /// file.
+    pub name: String,
+    pub path: PathBuf,
+    pub version: Option<String>,
+    pub project_id: Option<String>,
+    pub license: Option<ComponentLicense>,
+    #[serde(serialize_with = "hex::serialize")]
+    pub sha1: [u8; 20],
+    #[serde(serialize_with = "hex::serialize")]
+    pub sha512: [u8; 64],
+    pub download: Option<Url>,
+    pub homepage: Option<Url>,
+    /// Shipped inside the pack rather than downloaded.
+    pub bundled: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentLicense {
+    /// An SPDX expression or a `LicenseRef-`.
+    pub id: String,
+    pub name: String,
+    pub url: Option<String>,
+}
+
+impl Sbom {
+    /// Looks up every index file, and every jar among the overrides, by
+    /// hash, then the projects they belong to, in two batch requests.
+    pub async fn resolve(modrinth: &ModrinthClient, pack: &Pack) -> Result<Self, ApiError> {
+        let mut components: Vec<Component> = pack
+            .index
+            .files
+            .iter()
+            .map(|file| Component {
+                name: file_name(&file.path),
+                path: file.path.clone(),
+                version: None,
+                project_id: None,
+                license: None,
+                sha1: file.hashes.sha1,
+                sha512: file.hashes.sha512,
+                download: file.downloads.first().cloned(),
+                homepage: None,
+                bundled: false,
+            })
+            .collect();
+        components.extend(
+            pack.overrides
+                .iter()
+                .filter(|(path, _)| path.extension().is_some_and(|ext| ext == "jar"))
+                .map(|(path, content)| Component {
+                    name: file_name(path),
+                    path: path.clone(),
+                    version: None,
+                    project_id: None,
+                    license: None,
+                    sha1: Sha1::digest(content).into(),
+                    sha512: Sha512::digest(content).into(),
+                    download: None,
+                    homepage: None,
+                    bundled: true,
+                }),
+        );
+
+        let hashes: Vec<String> = components
+            .iter()
+            .map(|c| hex::encode(c.sha1))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let versions = modrinth.versions_from_hashes(&hashes, "sha1").await?;
+        let project_ids: BTreeSet<&str> =
+            versions.values().map(|v| v.project_id.as_str()).collect();
+        let projects: HashMap<String, _> = modrinth
+            .projects(&project_ids.into_iter().collect::<Vec<_>>())
+            .await?
+            .into_iter()
+            .map(|project| (project.id.clone(), project))
+            .collect();
+
+        for component in &mut components {
+            let Some(version) = versions.get(&hex::encode(component.sha1)) else {
+                continue;
+            };
+            component.version = Some(version.version_number.clone());
+            component.project_id = Some(version.project_id.clone());
+            if let Some(project) = projects.get(&version.project_id) {
+                component.name = project.title.clone();
+                component.homepage = Some(project.page_url());
+                component.license = project.license.as_ref().map(|license| ComponentLicense {
+                    id: license.id.clone(),
+                    name: license.name.clone(),
+                    url: license.url.clone(),
+                });
+            }
+        }
+        components.sort_by(|a, b| {
+            (a.name.to_lowercase(), &a.path).cmp(&(b.name.to_lowercase(), &b.path))
+        });
+
+        Ok(Sbom {
+            name: pack.index.name.clone(),
+            version: pack.index.version_id.clone(),
+            components,
+        })
+    }
+
+    /// A CycloneDX 1.5 JSON document.
+    pub fn to_cyclonedx(&self) -> String {
+        let components: Vec<Value> = self
+            .components
+            .iter()
+            .map(|c| {
+                let mut component = json!({
+                    "type": "library",
+                    "bom-ref": c.path.display().to_string(),
+                    "name": c.name,
+                    "hashes": [
+                        { "alg": "SHA-1", "content": hex::encode(c.sha1) },
+                        { "alg": "SHA-512", "content": hex::encode(c.sha512) },
+                    ],
+                    "properties": [
+                        { "name": "observe-rs:path", "value": c.path.display().to_string() },
+                    ],
+                });
+                if let Some(version) = &c.version {
+                    component["version"] = json!(version);
+                }
+                if let Some(license) = &c.license {
+                    component["licenses"] = if license.id.starts_with("LicenseRef-") {
+                        json!([{ "license": { "name": license_name(license) } }])
+                    } else if license.id.contains(' ') {
+                        json!([{ "expression": license.id }])
+                    } else {
+                        json!([{ "license": { "id": license.id } }])
+                    };
+                }
+                let mut references = Vec::new();
+                if let Some(url) = &c.download {
+                    references.push(json!({ "type": "distribution", "url": url }));
+                }
+                if let Some(url) = &c.homepage {
+                    references.push(json!({ "type": "website", "url": url }));
+                }
+                if !references.is_empty() {
+                    component["externalReferences"] = json!(references);
+                }
+                component
+            })
+            .collect();
+
+        let document = json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "version": 1,
+            "metadata": {
+                "timestamp": rfc3339_timestamp(SystemTime::now()),
+                "tools": [{ "name": "observe-rs", "version": env!("CARGO_PKG_VERSION") }],
+                "component": {
+                    "type": "application",
+                    "bom-ref": "pack",
+                    "name": self.name,
+                    "version": self.version,
+                },
+            },
+            "components": components,
+        });
+        serde_json::to_string_pretty(&document).expect("CycloneDX document serializes")
+    }
+
+    /// An SPDX 2.3 JSON document, one package per component.
+    pub fn to_spdx(&self) -> String {
+        let mut extracted: BTreeMap<&str, &ComponentLicense> = BTreeMap::new();
+        let mut packages = Vec::new();
+        let mut relationships = Vec::new();
+        for (i, c) in self.components.iter().enumerate() {
+            let id = format!("SPDXRef-Package-{}", i + 1);
+            let declared = match &c.license {
+                Some(license) => {
+                    if license.id.starts_with("LicenseRef-") {
+                        extracted.insert(&license.id, license);
+                    }
+                    license.id.as_str()
+                }
+                None => NOASSERTION,
+            };
+            let mut package = json!({
+                "name": c.name,
+                "SPDXID": id,
+                "downloadLocation": c.download.as_ref().map_or(NOASSERTION.to_string(), Url::to_string),
+                "filesAnalyzed": false,
+                "checksums": [
+                    { "algorithm": "SHA1", "checksumValue": hex::encode(c.sha1) },
+                    { "algorithm": "SHA512", "checksumValue": hex::encode(c.sha512) },
+                ],
+                "licenseConcluded": NOASSERTION,
+                "licenseDeclared": declared,
+                "copyrightText": NOASSERTION,
+                "comment": format!("Installed at {}", c.path.display()),
+            });
+            if let Some(version) = &c.version {
+                package["versionInfo"] = json!(version);
+            }
+            if let Some(url) = &c.homepage {
+                package["homepage"] = json!(url);
+            }
+            packages.push(package);
+            relationships.push(json!({
+                "spdxElementId": "SPDXRef-DOCUMENT",
+                "relationshipType": "DESCRIBES",
+                "relatedSpdxElement": id,
+            }));
+        }
+
+        let name = format!("{} {}", self.name, self.version);
+        let namespace_id = hex::encode(Sha1::digest(
+            format!("{}\n{}", name, rfc3339_timestamp(SystemTime::now())).as_bytes(),
+        ));
+        let mut document = json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": name,
+            "documentNamespace": format!("https://spdx.org/spdxdocs/observe-rs/{}", namespace_id),
+            "creationInfo": {
+                "created": rfc3339_timestamp(SystemTime::now()),
+                "creators": [format!("Tool: observe-rs-{}", env!("CARGO_PKG_VERSION"))],
+            },
+            "packages": packages,
+            "relationships": relationships,
+        });
+        // SPDX requires every LicenseRef used to be defined in the document.
+        if !extracted.is_empty() {
+            document["hasExtractedLicensingInfos"] = extracted
+                .into_values()
+                .map(|license| {
+                    json!({
+                        "licenseId": license.id,
+                        "name": license_name(license),
+                        "extractedText": license.url.as_deref().map_or_else(
+                            || license_name(license).to_string(),
+                            |url| format!("See {}", url),
+                        ),
+                    })
+                })
+                .collect();
+        }
+        serde_json::to_string_pretty(&document).expect("SPDX document serializes")
+    }
+
+    /// Components grouped by license, for a quick human review.
+    pub fn license_summary(&self) -> LicenseSummary<'_> {
+        let mut licenses: BTreeMap<String, Vec<&Component>> = BTreeMap::new();
+        for component in &self.components {
+            let key = component
+                .license
+                .as_ref()
+                .map_or_else(|| "Unknown".to_string(), |l| license_name(l).to_string());
+            licenses.entry(key).or_default().push(component);
+        }
+        LicenseSummary {
+            sbom: self,
+            licenses,
+        }
+    }
+}
+
+pub struct LicenseSummary<'a> {
+    sbom: &'a Sbom,
+    licenses: BTreeMap<String, Vec<&'a Component>>,
+}
+
+impl Display for LicenseSummary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Licenses in {} {} ({} components)",
+            self.sbom.name,
+            self.sbom.version,
+            self.sbom.components.len()
+        )?;
+        for (license, components) in &self.licenses {
+            write!(f, "\n\n{} ({})", license, components.len())?;
+            for component in components {
+                let mut line = component.name.clone();
+                if let Some(version) = &component.version {
+                    let _ = write!(line, " {}", version);
+                }
+                if component.bundled {
+                    line.push_str(" [bundled]");
+                }
+                write!(f, "\n  {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The license's name, falling back to its id.
+fn license_name(license: &ComponentLicense) -> &str {
+    if license.name.is_empty() {
+        &license.id
+    } else {
+        &license.name
+    }
+}
+
+fn file_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
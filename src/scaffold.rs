@@ -0,0 +1,101 @@
+use crate::java::RUNTIME_DIR;
+use crate::loader::ServerTarget;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const START_SCRIPT_UNIX: &str = "start.sh";
+pub const START_SCRIPT_WINDOWS: &str = "start.bat";
+
+/// Heap size used when none is given.
+pub const DEFAULT_MEMORY: u64 = 4 << 30;
+
+/// Writes `start.sh` and `start.bat` into `root`, launching `target` with
+/// `memory` bytes of heap. Returns the scripts' paths relative to `root`.
+pub(crate) fn write_start_scripts(
+    root: &Path,
+    target: &ServerTarget,
+    memory: u64,
+) -> io::Result<Vec<PathBuf>> {
+    let flags = jvm_flags(memory).join(" ");
+    let header = format!(
+        "Generated by observe-rs for {}. Uses $JAVA if set, then {}/, then $JAVA_HOME, then java on PATH.",
+        target, RUNTIME_DIR
+    );
+
+    let unix = format!(
+        "#!/usr/bin/env sh\n\
+         # {header}\n\
+         cd \"$(dirname \"$0\")\" || exit 1\n\
+         if [ -z \"$JAVA\" ]; then\n\
+         \x20   if [ -x {RUNTIME_DIR}/bin/java ]; then JAVA={RUNTIME_DIR}/bin/java\n\
+         \x20   elif [ -n \"$JAVA_HOME\" ]; then JAVA=\"$JAVA_HOME/bin/java\"\n\
+         \x20   else JAVA=java; fi\n\
+         fi\n\
+         exec \"$JAVA\" {flags} {launch} \"$@\"\n",
+        launch = target.launch_args(false),
+    );
+    let windows = format!(
+        "@echo off\r\n\
+         REM {header}\r\n\
+         cd /d \"%~dp0\"\r\n\
+         if \"%JAVA%\"==\"\" if exist \"{RUNTIME_DIR}\\bin\\java.exe\" set JAVA={RUNTIME_DIR}\\bin\\java.exe\r\n\
+         if \"%JAVA%\"==\"\" if defined JAVA_HOME set \"JAVA=%JAVA_HOME%\\bin\\java.exe\"\r\n\
+         if \"%JAVA%\"==\"\" set JAVA=java\r\n\
+         \"%JAVA%\" {flags} {launch} %*\r\n",
+        launch = target.launch_args(true),
+    );
+
+    let unix_path = root.join(START_SCRIPT_UNIX);
+    fs::write(&unix_path, unix)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&unix_path, fs::Permissions::from_mode(0o755))?;
+    }
+    fs::write(root.join(START_SCRIPT_WINDOWS), windows)?;
+
+    Ok(vec![
+        PathBuf::from(START_SCRIPT_UNIX),
+        PathBuf::from(START_SCRIPT_WINDOWS),
+    ])
+}
+
+/// Heap settings plus Aikar's G1 tuning (https://mcflags.emc.gs), with the
+/// variant recommended for heaps over 12 GiB.
+fn jvm_flags(memory: u64) -> Vec<String> {
+    let megabytes = (memory >> 20).max(512);
+    let large = memory > 12 << 30;
+    let (new_size, max_new_size, region_size, reserve, ihop) = if large {
+        (40, 50, "16M", 15, 20)
+    } else {
+        (30, 40, "8M", 20, 15)
+    };
+
+    [
+        format!("-Xms{}M", megabytes),
+        format!("-Xmx{}M", megabytes),
+        "-XX:+UseG1GC".into(),
+        "-XX:+ParallelRefProcEnabled".into(),
+        "-XX:MaxGCPauseMillis=200".into(),
+        "-XX:+UnlockExperimentalVMOptions".into(),
+        "-XX:+DisableExplicitGC".into(),
+        "-XX:+AlwaysPreTouch".into(),
+        format!("-XX:G1NewSizePercent={}", new_size),
+        format!("-XX:G1MaxNewSizePercent={}", max_new_size),
+        format!("-XX:G1HeapRegionSize={}", region_size),
+        format!("-XX:G1ReservePercent={}", reserve),
+        "-XX:G1HeapWastePercent=5".into(),
+        "-XX:G1MixedGCCountTarget=4".into(),
+        format!("-XX:InitiatingHeapOccupancyPercent={}", ihop),
+        "-XX:G1MixedGCLiveThresholdPercent=90".into(),
+        "-XX:G1RSetUpdatingPauseIntervalPercent=5".into(),
+        "-XX:SurvivorRatio=32".into(),
+        "-XX:+PerfDisableSharedMem".into(),
+        "-XX:MaxTenuringThreshold=1".into(),
+        "-Dusing.aikars.flags=https://mcflags.emc.gs".into(),
+        "-Daikars.new.flags=true".into(),
+    ]
+    .into()
+}
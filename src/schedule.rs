@@ -0,0 +1,204 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Deserializer};
+use std::{collections::BTreeSet, fmt::Display, str::FromStr, time::SystemTime};
+
+/// A cron expression, in local time: the usual five fields (minute, hour,
+/// day of month, month, day of week), six or seven with seconds first and
+/// a year last, or one of `@hourly`, `@daily`, `@weekly`, `@monthly` and
+/// `@yearly`. Days of the week are numbered as in crontab, Sunday being 0
+/// or 7, whatever the number of fields.
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    expression: String,
+    // Boxed: parsed schedules are large, and configs carry several.
+    schedule: Box<cron::Schedule>,
+}
+
+impl CronSchedule {
+    /// The first time the schedule fires after `time`.
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let time: DateTime<Local> = time.into();
+        self.schedule.after(&time).next().map(SystemTime::from)
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |err: String| format!("invalid schedule `{}`: {}", s, err);
+        let mut fields: Vec<String> = s.split_whitespace().map(str::to_string).collect();
+        // The cron crate wants seconds first and numbers Sunday 1 rather
+        // than 0 (or 7). The day of week is crontab's in every form, so
+        // `1-5` is Monday to Friday with or without seconds.
+        if fields.len() == 5 {
+            fields.insert(0, "0".to_string());
+        }
+        if let 6 | 7 = fields.len() {
+            fields[5] = crontab_weekdays(&fields[5]).map_err(invalid)?;
+        }
+        let expression = fields.join(" ");
+        let schedule: cron::Schedule = expression
+            .parse()
+            .map_err(|err: cron::error::Error| invalid(err.to_string()))?;
+        Ok(CronSchedule {
+            expression: s.trim().to_string(),
+            schedule: Box::new(schedule),
+        })
+    }
+}
+
+impl Display for CronSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl<'de> Deserialize<'de> for CronSchedule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Day names, Sunday first as crontab numbers them. Crontab takes their
+/// first three letters; the full names are accepted too.
+const WEEKDAYS: [&str; 7] = [
+    "sunday",
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+];
+
+/// Expands a crontab day-of-week field, where Sunday is 0 or 7, into an
+/// explicit list of days numbered for the cron crate, where Sunday is 1.
+/// Ranges may wrap past Saturday, e.g. `5-0` is Friday through Sunday.
+fn crontab_weekdays(field: &str) -> Result<String, String> {
+    if field == "*" || field == "?" {
+        return Ok(field.to_string());
+    }
+    let invalid = || format!("invalid day of week `{}`", field);
+    let day = |day: &str| -> Result<u8, String> {
+        match day.parse::<u8>() {
+            Ok(day @ 0..=7) => Ok(day),
+            Ok(_) => Err(invalid()),
+            Err(_) => {
+                let day = day.to_ascii_lowercase();
+                WEEKDAYS
+                    .iter()
+                    .position(|name| day == *name || day == name[..3])
+                    .map(|position| position as u8)
+                    .ok_or_else(invalid)
+            }
+        }
+    };
+
+    let mut days = BTreeSet::new();
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (item, None),
+        };
+        let step = match step {
+            Some(step) => step
+                .parse::<u8>()
+                .ok()
+                .filter(|step| *step > 0)
+                .ok_or_else(invalid)?,
+            None => 1,
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (day(start)?, day(end)?),
+            None if range == "*" => (0, 7),
+            // `N/step` runs from N to the end of the week.
+            None if item.contains('/') => (day(range)?, 7),
+            None => {
+                let day = day(range)?;
+                (day, day)
+            }
+        };
+        // A range ending before it starts wraps past Saturday.
+        let end = if end < start { end + 7 } else { end };
+        for day in (start..=end).step_by(step as usize) {
+            days.insert(day % 7 + 1);
+        }
+    }
+    Ok(days.iter().map(u8::to_string).collect::<Vec<_>>().join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, TimeZone, Weekday};
+
+    #[test]
+    fn sunday_is_zero_or_seven() {
+        assert_eq!(crontab_weekdays("0").unwrap(), "1");
+        assert_eq!(crontab_weekdays("7").unwrap(), "1");
+    }
+
+    #[test]
+    fn ranges_expand_to_days() {
+        assert_eq!(crontab_weekdays("1-7").unwrap(), "1,2,3,4,5,6,7");
+        assert_eq!(crontab_weekdays("1-5").unwrap(), "2,3,4,5,6");
+    }
+
+    #[test]
+    fn stepped_ranges_keep_sunday() {
+        assert_eq!(crontab_weekdays("1-7/2").unwrap(), "1,2,4,6");
+        assert_eq!(crontab_weekdays("*/2").unwrap(), "1,3,5,7");
+    }
+
+    #[test]
+    fn ranges_wrap_past_saturday() {
+        assert_eq!(crontab_weekdays("5-0").unwrap(), "1,6,7");
+        assert_eq!(crontab_weekdays("fri-sun").unwrap(), "1,6,7");
+    }
+
+    #[test]
+    fn names_match_numbers() {
+        assert_eq!(crontab_weekdays("mon-fri").unwrap(), "2,3,4,5,6");
+        assert_eq!(crontab_weekdays("SAT,Sunday").unwrap(), "1,7");
+    }
+
+    #[test]
+    fn invalid_days_are_rejected() {
+        assert!(crontab_weekdays("8").is_err());
+        assert!(crontab_weekdays("1-5/0").is_err());
+        assert!(crontab_weekdays("someday").is_err());
+    }
+
+    #[test]
+    fn schedules_parse_with_crontab_weekdays() {
+        for expression in ["0 4 * * 0", "0 4 * * 7", "0 4 * * 1-7/2", "0 4 * * 5-0"] {
+            let schedule: Result<CronSchedule, _> = expression.parse();
+            assert!(schedule.is_ok(), "{}: {:?}", expression, schedule.err());
+        }
+    }
+
+    #[test]
+    fn weekdays_mean_the_same_with_seconds_and_years() {
+        let monday = Local.with_ymd_and_hms(2026, 10, 12, 0, 0, 0).unwrap();
+        for expression in ["0 4 * * 1-5", "0 0 4 * * 1-5", "0 0 4 * * 1-5 *"] {
+            let schedule: CronSchedule = expression.parse().unwrap();
+            let next: DateTime<Local> = schedule.next_after(monday.into()).unwrap().into();
+            assert_eq!(next.weekday(), Weekday::Mon, "{}", expression);
+        }
+        let sunday = Local.with_ymd_and_hms(2026, 10, 11, 0, 0, 0).unwrap();
+        for expression in ["0 4 * * 0", "30 0 4 * * 7", "0 0 4 * * sun 2026-2030"] {
+            let schedule: CronSchedule = expression.parse().unwrap();
+            let next: DateTime<Local> = schedule.next_after(sunday.into()).unwrap().into();
+            assert_eq!(next.weekday(), Weekday::Sun, "{}", expression);
+        }
+    }
+
+    #[test]
+    fn errors_show_the_written_expression() {
+        let err = "0 4 * * 9".parse::<CronSchedule>().unwrap_err();
+        assert!(err.contains("`0 4 * * 9`"), "{}", err);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::prune::build_glob_set;
+use globset::GlobSet;
+use std::{collections::HashMap, fmt, path::Path};
+
+pub type SecretsError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Values substituted into `{{NAME}}` / `${NAME}` placeholders of override
+/// files at install time, so credentials don't have to ship in the pack.
+///
+/// Values never appear in `Debug` output or error messages.
+#[derive(Clone, Default)]
+pub struct Secrets {
+    values: HashMap<String, String>,
+    targets: Option<GlobSet>,
+}
+
+impl Secrets {
+    pub fn new(values: HashMap<String, String>) -> Self {
+        Secrets {
+            values,
+            targets: None,
+        }
+    }
+
+    /// Reads a secrets file: a JSON object if it ends in `.json`, otherwise
+    /// dotenv-style `NAME=VALUE` lines.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SecretsError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+
+        let values = if path.extension().is_some_and(|ext| ext == "json") {
+            parse_json(&content)
+        } else {
+            parse_dotenv(&content)
+        }
+        .map_err(|err| format!("invalid secrets file {}: {}", path.display(), err))?;
+        Ok(Secrets::new(values))
+    }
+
+    /// Only inject into overrides matching one of these patterns, e.g.
+    /// `config/discord/*.toml`. Every override is eligible by default.
+    pub fn targets<S: AsRef<str>>(
+        mut self,
+        patterns: impl IntoIterator<Item = S>,
+    ) -> Result<Self, globset::Error> {
+        let patterns: Vec<S> = patterns.into_iter().collect();
+        self.targets = if patterns.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(patterns)?)
+        };
+        Ok(self)
+    }
+
+    pub(crate) fn applies_to(&self, path: &Path) -> bool {
+        self.targets.as_ref().is_none_or(|set| set.is_match(path))
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+}
+
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.values.keys().collect();
+        names.sort();
+        f.debug_struct("Secrets").field("names", &names).finish()
+    }
+}
+
+/// A flat JSON object; numbers and booleans are taken as their text.
+fn parse_json(content: &str) -> Result<HashMap<String, String>, String> {
+    // serde_json's messages can quote the offending value, so only report
+    // where the problem is.
+    let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(content)
+        .map_err(|err| {
+            format!(
+                "not a JSON object (line {}, column {})",
+                err.line(),
+                err.column()
+            )
+        })?;
+
+    object
+        .into_iter()
+        .map(|(name, value)| match value {
+            serde_json::Value::String(value) => Ok((name, value)),
+            serde_json::Value::Number(_) | serde_json::Value::Bool(_) => {
+                Ok((name, value.to_string()))
+            }
+            _ => Err(format!("`{}` must be a string, number or boolean", name)),
+        })
+        .collect()
+}
+
+/// `NAME=VALUE` lines with optional `export` prefixes, `#` comments and
+/// single- or double-quoted values.
+fn parse_dotenv(content: &str) -> Result<HashMap<String, String>, String> {
+    let mut values = HashMap::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((name, value)) = line.split_once('=') else {
+            return Err(format!("line {}: expected NAME=VALUE", i + 1));
+        };
+
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("line {}: invalid variable name", i + 1));
+        }
+
+        let value = value.trim();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => unquote(&value[1..], quote)
+                .ok_or_else(|| format!("line {}: unterminated or malformed quoted value", i + 1))?,
+            // Unquoted values end at an inline ` #` comment.
+            _ => value
+                .split_once(" #")
+                .map_or(value, |(value, _)| value.trim_end())
+                .to_string(),
+        };
+
+        values.insert(name.to_string(), value);
+    }
+
+    Ok(values)
+}
+
+/// Reads a quoted value up to its closing `quote`, which may only be
+/// followed by a comment. Double-quoted values understand `\n`, `\"` and
+/// `\\` escapes.
+fn unquote(rest: &str, quote: char) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if quote == '"' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                c => value.push(c),
+            },
+            c if c == quote => {
+                let trailing = rest[i + 1..].trim_start();
+                return (trailing.is_empty() || trailing.starts_with('#')).then_some(value);
+            }
+            c => value.push(c),
+        }
+    }
+    None
+}
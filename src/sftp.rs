@@ -0,0 +1,335 @@
+use crate::remote::{RemoteError, RemoteFile, RemoteTarget};
+use crate::util::slash_path;
+use percent_encoding::percent_decode_str;
+use ssh2::{CheckResult, ErrorCode, FileStat, KnownHostFileKind, Session, Sftp};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+use url::Url;
+
+/// Password for SFTP targets that don't accept keys. Prefer this over a
+/// password in the URL, which ends up in shell history.
+pub const PASSWORD_ENV: &str = "OBSERVE_SFTP_PASSWORD";
+
+const DEFAULT_PORT: u16 = 22;
+/// How long a single SSH operation may block.
+const TIMEOUT_MS: u32 = 30_000;
+/// `LIBSSH2_FX_NO_SUCH_FILE`.
+const FX_NO_SUCH_FILE: i32 = 2;
+/// Keys tried, in `~/.ssh`, when the SSH agent has none that work.
+const KEY_FILES: &[&str] = &["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// A server directory reached over SFTP, e.g. `sftp://mc@host:2222/srv/mc`.
+/// The host key must already be in `~/.ssh/known_hosts`. Authenticates with
+/// [`PASSWORD_ENV`] or the URL's password when given, else the SSH agent,
+/// else the usual key files.
+pub struct SftpTarget {
+    session: Session,
+    sftp: Sftp,
+    root: String,
+    /// Whether the server lets us run `sha1sum`; unknown until tried.
+    can_hash: Option<bool>,
+}
+
+impl SftpTarget {
+    pub fn connect(url: &Url) -> Result<Self, RemoteError> {
+        let host = url.host_str().ok_or("SFTP target has no host")?;
+        let port = url.port().unwrap_or(DEFAULT_PORT);
+        let user = match decode(url.username())? {
+            user if !user.is_empty() => user,
+            _ => env::var("USER")
+                .or_else(|_| env::var("USERNAME"))
+                .map_err(|_| "SFTP target has no user: use sftp://user@host/path")?,
+        };
+        let root = match decode(url.path())?.trim_end_matches('/') {
+            "" => "/".to_string(),
+            root => root.to_string(),
+        };
+
+        let tcp = TcpStream::connect((host, port))
+            .map_err(|err| format!("{}:{}: {}", host, port, err))?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.set_timeout(TIMEOUT_MS);
+        session.handshake()?;
+        check_host_key(&session, host, port)?;
+
+        let password = env::var(PASSWORD_ENV)
+            .ok()
+            .or(url.password().map(decode).transpose()?);
+        authenticate(&session, &user, password.as_deref())
+            .map_err(|err| format!("{}@{}: {}", user, host, err))?;
+
+        let sftp = session.sftp()?;
+        Ok(SftpTarget {
+            session,
+            sftp,
+            root,
+            can_hash: None,
+        })
+    }
+
+    fn remote_path(&self, path: &Path) -> PathBuf {
+        if path.as_os_str().is_empty() {
+            return PathBuf::from(&self.root);
+        }
+        PathBuf::from(format!(
+            "{}/{}",
+            self.root.trim_end_matches('/'),
+            slash_path(path)
+        ))
+    }
+
+    /// Creates `dir` and its missing parents, like `mkdir -p`.
+    fn create_dirs(&self, dir: &Path) -> Result<(), RemoteError> {
+        let mut missing = Vec::new();
+        let mut current = Some(dir);
+        while let Some(dir) = current.filter(|dir| !dir.as_os_str().is_empty()) {
+            match self.sftp.stat(&self.remote_path(dir)) {
+                Ok(stat) if stat.is_dir() => break,
+                Ok(_) => return Err(format!("{} is not a directory", dir.display()).into()),
+                Err(err) if is_not_found(&err) => missing.push(dir),
+                Err(err) => return Err(err.into()),
+            }
+            current = dir.parent();
+        }
+        for dir in missing.into_iter().rev() {
+            self.sftp.mkdir(&self.remote_path(dir), 0o755)?;
+        }
+        Ok(())
+    }
+
+    fn list_into(
+        &self,
+        dir: &Path,
+        files: &mut BTreeMap<PathBuf, RemoteFile>,
+    ) -> Result<(), RemoteError> {
+        let entries = match self.sftp.readdir(self.remote_path(dir)) {
+            Ok(entries) => entries,
+            Err(err) if is_not_found(&err) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        for (full, stat) in entries {
+            let Some(name) = full.file_name() else {
+                continue;
+            };
+            let path = dir.join(name);
+            // Symlinks are neither; like prune, deploy never follows them.
+            if stat.is_dir() {
+                self.list_into(&path, files)?;
+            } else if stat.is_file() {
+                files.insert(
+                    path,
+                    RemoteFile {
+                        size: stat.size.unwrap_or(0),
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `command` on the server, returning its output if it exits 0.
+    fn exec(&self, command: &str) -> Result<Option<String>, RemoteError> {
+        let mut channel = self.session.channel_session()?;
+        channel.exec(command)?;
+        let mut output = String::new();
+        channel.read_to_string(&mut output)?;
+        channel.wait_close()?;
+        Ok((channel.exit_status()? == 0).then_some(output))
+    }
+}
+
+impl RemoteTarget for SftpTarget {
+    fn list(&mut self, dir: &Path) -> Result<BTreeMap<PathBuf, RemoteFile>, RemoteError> {
+        let mut files = BTreeMap::new();
+        self.list_into(dir, &mut files)?;
+        Ok(files)
+    }
+
+    fn stat(&mut self, path: &Path) -> Result<Option<RemoteFile>, RemoteError> {
+        match self.sftp.stat(&self.remote_path(path)) {
+            Ok(stat) if stat.is_file() => Ok(Some(RemoteFile {
+                size: stat.size.unwrap_or(0),
+            })),
+            Ok(_) => Ok(None),
+            Err(err) if is_not_found(&err) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn read(&mut self, path: &Path) -> Result<Option<Vec<u8>>, RemoteError> {
+        let mut file = match self.sftp.open(self.remote_path(path)) {
+            Ok(file) => file,
+            Err(err) if is_not_found(&err) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        Ok(Some(content))
+    }
+
+    fn write(&mut self, path: &Path, content: &[u8]) -> Result<(), RemoteError> {
+        if let Some(parent) = path.parent() {
+            self.create_dirs(parent)?;
+        }
+        let mut file = self.sftp.create(&self.remote_path(path))?;
+        file.write_all(content)?;
+        Ok(())
+    }
+
+    fn upload(&mut self, local: &Path, path: &Path) -> Result<(), RemoteError> {
+        if let Some(parent) = path.parent() {
+            self.create_dirs(parent)?;
+        }
+        let metadata =
+            fs::metadata(local).map_err(|err| format!("{}: {}", local.display(), err))?;
+        let dest = self.remote_path(path);
+        // Written beside the destination and moved over it, so the server
+        // never sees half a file.
+        let part = dest.with_file_name(format!(
+            "{}.part",
+            dest.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        {
+            let mut source =
+                fs::File::open(local).map_err(|err| format!("{}: {}", local.display(), err))?;
+            let mut file = self.sftp.create(&part)?;
+            io::copy(&mut source, &mut file)?;
+        }
+        self.sftp.setstat(
+            &part,
+            FileStat {
+                size: None,
+                uid: None,
+                gid: None,
+                perm: Some(mode(&metadata)),
+                atime: None,
+                mtime: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map(|time| time.as_secs()),
+            },
+        )?;
+        // SFTP v3 servers won't rename over an existing file.
+        if self.sftp.rename(&part, &dest, None).is_err() {
+            match self.sftp.unlink(&dest) {
+                Err(err) if !is_not_found(&err) => return Err(err.into()),
+                _ => {}
+            }
+            self.sftp.rename(&part, &dest, None)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> Result<(), RemoteError> {
+        match self.sftp.unlink(&self.remote_path(path)) {
+            Err(err) if !is_not_found(&err) => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn sha1(&mut self, path: &Path) -> Result<Option<[u8; 20]>, RemoteError> {
+        if self.can_hash == Some(false) {
+            return Ok(None);
+        }
+        let command = format!(
+            "sha1sum -- {}",
+            shell_quote(&self.remote_path(path).to_string_lossy())
+        );
+        // SFTP-only accounts refuse commands; fall back to sizes for good.
+        let output = self.exec(&command).ok().flatten();
+        let sha1 = output
+            .as_deref()
+            .and_then(|output| output.get(..40))
+            .and_then(|hex| <[u8; 20]>::try_from(hex::decode(hex).ok()?).ok());
+        self.can_hash = Some(sha1.is_some());
+        Ok(sha1)
+    }
+}
+
+fn check_host_key(session: &Session, host: &str, port: u16) -> Result<(), RemoteError> {
+    let (key, _) = session.host_key().ok_or("the server sent no host key")?;
+    let mut known_hosts = session.known_hosts()?;
+    if let Some(file) = env::var_os("HOME").map(|home| Path::new(&home).join(".ssh/known_hosts"))
+        && file.exists()
+    {
+        known_hosts.read_file(&file, KnownHostFileKind::OpenSSH)?;
+    }
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::NotFound => Err(format!(
+            "{} is not in ~/.ssh/known_hosts; connect once with ssh to check and accept its host key",
+            host
+        )
+        .into()),
+        CheckResult::Mismatch => Err(format!(
+            "the host key of {} does not match ~/.ssh/known_hosts; refusing to connect",
+            host
+        )
+        .into()),
+        CheckResult::Failure => Err(format!("could not check the host key of {}", host).into()),
+    }
+}
+
+fn authenticate(session: &Session, user: &str, password: Option<&str>) -> Result<(), RemoteError> {
+    if let Some(password) = password {
+        session.userauth_password(user, password)?;
+        return Ok(());
+    }
+    if session.userauth_agent(user).is_ok() {
+        return Ok(());
+    }
+    let ssh_dir = env::var_os("HOME").map(|home| Path::new(&home).join(".ssh"));
+    for key in KEY_FILES {
+        let Some(path) = ssh_dir.as_ref().map(|dir| dir.join(key)) else {
+            break;
+        };
+        if path.exists()
+            && session
+                .userauth_pubkey_file(user, None, &path, None)
+                .is_ok()
+        {
+            return Ok(());
+        }
+    }
+    Err(format!(
+        "authentication failed: no SSH agent key or key in ~/.ssh was accepted (set {} to use a password)",
+        PASSWORD_ENV
+    )
+    .into())
+}
+
+fn is_not_found(err: &ssh2::Error) -> bool {
+    err.code() == ErrorCode::SFTP(FX_NO_SUCH_FILE)
+}
+
+fn decode(s: &str) -> Result<String, RemoteError> {
+    Ok(percent_decode_str(s).decode_utf8()?.into_owned())
+}
+
+/// Quotes `s` for a POSIX shell.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[cfg(unix)]
+fn mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
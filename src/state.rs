@@ -0,0 +1,98 @@
+use crate::util::STATE_DIR;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, Metadata},
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+/// Name of the verification state file inside [`STATE_DIR`].
+pub const STATE_FILE_NAME: &str = "state.json";
+
+/// Size and modification time of a file when it last hashed correctly.
+/// A file whose metadata still matches is trusted without re-hashing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct FileState {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    #[serde(with = "hex::serde")]
+    sha1: [u8; 20],
+}
+
+impl FileState {
+    fn new(metadata: &Metadata, sha1: [u8; 20]) -> Option<Self> {
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(FileState {
+            size: metadata.len(),
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+            sha1,
+        })
+    }
+}
+
+/// Persisted map of relative path to [`FileState`], kept in
+/// `<root>/.observe/state.json`.
+#[derive(Debug, Default)]
+pub(crate) struct StateCache {
+    entries: Mutex<HashMap<PathBuf, FileState>>,
+}
+
+impl StateCache {
+    /// Loads the state of `root`. A missing or unreadable state file just
+    /// means every file gets hashed.
+    pub(crate) fn load(root: &Path) -> Self {
+        let entries = fs::read(state_path(root))
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        StateCache {
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Whether `path` was verified against `sha1` and has not been touched
+    /// since.
+    pub(crate) fn is_fresh(&self, path: &Path, metadata: &Metadata, sha1: &[u8; 20]) -> bool {
+        let Some(current) = FileState::new(metadata, *sha1) else {
+            return false;
+        };
+        self.entries.lock().unwrap().get(path) == Some(&current)
+    }
+
+    /// Records that `path` currently hashes to `sha1`.
+    pub(crate) fn record(&self, path: &Path, metadata: &Metadata, sha1: [u8; 20]) {
+        let mut entries = self.entries.lock().unwrap();
+        match FileState::new(metadata, sha1) {
+            Some(state) => entries.insert(path.to_path_buf(), state),
+            None => entries.remove(path),
+        };
+    }
+
+    /// Writes the state for the paths in `keep`, dropping everything else.
+    pub(crate) fn save<'a>(
+        &self,
+        root: &Path,
+        keep: impl IntoIterator<Item = &'a PathBuf>,
+    ) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let keep: HashSet<&PathBuf> = keep.into_iter().collect();
+        entries.retain(|path, _| keep.contains(&path));
+
+        let path = state_path(root);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(&*entries)?)?;
+        fs::rename(tmp, path)
+    }
+}
+
+fn state_path(root: &Path) -> PathBuf {
+    root.join(STATE_DIR).join(STATE_FILE_NAME)
+}
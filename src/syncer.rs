@@ -0,0 +1,569 @@
+use crate::cache::{DownloadCache, LinkMode};
+use crate::choices::{Choices, Choosable};
+use crate::errors::FileError;
+use crate::events::{ProgressCallback, SyncEvent};
+use crate::hashing::{HashPolicy, VerifyPolicy};
+use crate::http;
+use crate::mod_manager::ModManager;
+use crate::mrpack::MRFile;
+use crate::observer::{SharedObserver, SyncObserver};
+use crate::pack::Pack;
+use crate::permissions::OverrideModes;
+use crate::plan::{PruneCandidate, SyncPlan};
+use crate::policy::DownloadPolicy;
+use crate::prune::PruneRules;
+use crate::report::SyncReport;
+use crate::secrets::Secrets;
+use crate::verify::VerifyReport;
+use serde::Deserialize;
+use std::{collections::HashMap, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+/// What to do with files under managed directories that the pack no longer
+/// ships.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrunePolicy {
+    /// Leave unknown files alone.
+    #[default]
+    Disabled,
+    /// Delete unknown files.
+    Delete,
+    /// Move unknown files into `.observe-trash/<timestamp>/` so they can be
+    /// restored.
+    Quarantine,
+}
+
+impl FromStr for PrunePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disabled" => Ok(PrunePolicy::Disabled),
+            "delete" => Ok(PrunePolicy::Delete),
+            "quarantine" => Ok(PrunePolicy::Quarantine),
+            other => Err(format!(
+                "unknown prune mode `{}` (expected disabled, delete or quarantine)",
+                other
+            )),
+        }
+    }
+}
+
+/// What sync does about the Java runtime the pack's Minecraft version needs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JavaPolicy {
+    /// Don't look for Java.
+    Ignore,
+    /// Warn when the `java` the start scripts would use is the wrong
+    /// version.
+    #[default]
+    Check,
+    /// Download a matching Eclipse Temurin runtime into `.runtime/` when
+    /// the `java` the start scripts would use is the wrong version.
+    Provision,
+}
+
+impl FromStr for JavaPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(JavaPolicy::Ignore),
+            "check" => Ok(JavaPolicy::Check),
+            "provision" => Ok(JavaPolicy::Provision),
+            other => Err(format!(
+                "unknown Java policy `{}` (expected check, provision or ignore)",
+                other
+            )),
+        }
+    }
+}
+
+/// How files whose server requirement is `optional` are treated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionalPolicy {
+    /// Install every optional file.
+    #[default]
+    Include,
+    /// Skip optional files; prune treats copies on disk as unmanaged.
+    Exclude,
+    /// Let the [`PackSyncer::select_optional`] hook choose. Behaves like
+    /// `Include` when no hook is set.
+    Ask,
+}
+
+impl FromStr for OptionalPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "include" => Ok(OptionalPolicy::Include),
+            "exclude" => Ok(OptionalPolicy::Exclude),
+            "ask" => Ok(OptionalPolicy::Ask),
+            other => Err(format!(
+                "unknown optional policy `{}` (expected include, exclude or ask)",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SyncOptions {
+    pub(crate) target_dir: PathBuf,
+    pub(crate) prune: PrunePolicy,
+    pub(crate) concurrency: usize,
+    pub(crate) retries: u32,
+    pub(crate) retry_delay: Duration,
+    pub(crate) cache: Option<DownloadCache>,
+    pub(crate) link_mode: LinkMode,
+    pub(crate) dry_run: bool,
+    pub(crate) keep_going: bool,
+    pub(crate) wait_for_lock: bool,
+    pub(crate) write_lockfile: bool,
+    pub(crate) state_cache: bool,
+    pub(crate) merge_overrides: bool,
+    pub(crate) merge_properties: bool,
+    pub(crate) templates: bool,
+    pub(crate) template_vars: HashMap<String, String>,
+    pub(crate) secrets: Option<Secrets>,
+    pub(crate) override_modes: OverrideModes,
+    pub(crate) stamp_downloads: bool,
+    pub(crate) follow_symlinks: bool,
+    pub(crate) accept_eula: bool,
+    pub(crate) scaffold_memory: Option<u64>,
+    pub(crate) install_loader: bool,
+    pub(crate) java: JavaPolicy,
+    pub(crate) hash_policy: HashPolicy,
+    pub(crate) verify_policy: VerifyPolicy,
+    pub(crate) progress_bars: bool,
+    pub(crate) callbacks: Vec<ProgressCallback>,
+    pub(crate) observers: Vec<SharedObserver>,
+    pub(crate) cancel: Option<CancellationToken>,
+    pub(crate) confirm_prune: Option<PruneConfirmation>,
+    pub(crate) prune_rules: PruneRules,
+    pub(crate) download_policy: DownloadPolicy,
+    pub(crate) api_token: Option<String>,
+    pub(crate) rate_limit: Option<u64>,
+    pub(crate) connect_timeout: Duration,
+    pub(crate) read_timeout: Duration,
+    pub(crate) per_download_rate_limit: Option<u64>,
+    pub(crate) optional: OptionalPolicy,
+    pub(crate) select_optional: Option<OptionalSelection>,
+    pub(crate) choices: Choices,
+}
+
+pub type OptionalSelection = Arc<dyn Fn(&[MRFile]) -> Vec<PathBuf> + Send + Sync>;
+
+pub type PruneConfirmation = Arc<dyn Fn(&[PruneCandidate]) -> bool + Send + Sync>;
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        SyncOptions {
+            target_dir: PathBuf::from("."),
+            prune: PrunePolicy::Disabled,
+            concurrency: 8,
+            retries: 3,
+            retry_delay: Duration::from_millis(500),
+            cache: None,
+            link_mode: LinkMode::Copy,
+            dry_run: false,
+            keep_going: false,
+            wait_for_lock: false,
+            write_lockfile: true,
+            state_cache: true,
+            merge_overrides: true,
+            merge_properties: false,
+            templates: false,
+            template_vars: HashMap::new(),
+            secrets: None,
+            override_modes: OverrideModes::default(),
+            stamp_downloads: false,
+            follow_symlinks: false,
+            accept_eula: false,
+            scaffold_memory: None,
+            install_loader: true,
+            java: JavaPolicy::Check,
+            hash_policy: HashPolicy::Standard,
+            verify_policy: VerifyPolicy::Strict,
+            progress_bars: false,
+            callbacks: Vec::new(),
+            observers: Vec::new(),
+            cancel: None,
+            confirm_prune: None,
+            prune_rules: PruneRules::default(),
+            download_policy: DownloadPolicy::default(),
+            api_token: None,
+            rate_limit: None,
+            connect_timeout: http::DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: http::DEFAULT_READ_TIMEOUT,
+            per_download_rate_limit: None,
+            optional: OptionalPolicy::Include,
+            select_optional: None,
+            choices: Choices::default(),
+        }
+    }
+}
+
+/// Builder for syncing a [`Pack`] into a server directory.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// use observe_rs::{Pack, PackSyncer, PrunePolicy};
+///
+/// let pack = Pack::open("server.mrpack")?;
+/// PackSyncer::new()
+///     .target_dir("/srv/minecraft")
+///     .prune(PrunePolicy::Delete)
+///     .on_progress(|event| println!("{event:?}"))
+///     .sync(pack)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct PackSyncer {
+    options: SyncOptions,
+}
+
+impl PackSyncer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Directory all pack paths are resolved against. Defaults to `.`.
+    pub fn target_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.target_dir = dir.into();
+        self
+    }
+
+    pub fn prune(mut self, policy: PrunePolicy) -> Self {
+        self.options.prune = policy;
+        self
+    }
+
+    /// Directories and patterns that limit what prune may delete.
+    pub fn prune_rules(mut self, rules: PruneRules) -> Self {
+        self.options.prune_rules = rules;
+        self
+    }
+
+    /// Which download URLs may be fetched. Defaults to the hosts allowed by
+    /// the Modrinth pack format.
+    pub fn download_policy(mut self, policy: DownloadPolicy) -> Self {
+        self.options.download_policy = policy;
+        self
+    }
+
+    /// Modrinth personal access token, sent with downloads from Modrinth's
+    /// CDN for better rate limits.
+    pub fn api_token(mut self, token: Option<String>) -> Self {
+        self.options.api_token = token;
+        self
+    }
+
+    /// Cap combined download throughput across all concurrent transfers, in
+    /// bytes per second.
+    pub fn rate_limit(mut self, bytes_per_sec: Option<u64>) -> Self {
+        self.options.rate_limit = bytes_per_sec;
+        self
+    }
+
+    /// How long to wait for a connection to a download host.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.options.connect_timeout = timeout;
+        self
+    }
+
+    /// How long a transfer may go without receiving data before it is
+    /// aborted and retried.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.options.read_timeout = timeout;
+        self
+    }
+
+    /// Cap the throughput of each individual transfer, in bytes per second.
+    pub fn per_download_rate_limit(mut self, bytes_per_sec: Option<u64>) -> Self {
+        self.options.per_download_rate_limit = bytes_per_sec;
+        self
+    }
+
+    pub fn optional(mut self, policy: OptionalPolicy) -> Self {
+        self.options.optional = policy;
+        self
+    }
+
+    /// Called with the pack's server-optional files under
+    /// [`OptionalPolicy::Ask`], leaving out those with a
+    /// [choice](Self::choices); returns the paths to install.
+    pub fn select_optional(
+        mut self,
+        select: impl Fn(&[MRFile]) -> Vec<PathBuf> + Send + Sync + 'static,
+    ) -> Self {
+        self.options.select_optional = Some(Arc::new(select));
+        self
+    }
+
+    /// Per-file decisions that take precedence over the optional-file
+    /// policy and over merging edited overrides. See
+    /// [`choosable`](Self::choosable) for what there is to decide.
+    pub fn choices(mut self, choices: Choices) -> Self {
+        self.options.choices = choices;
+        self
+    }
+
+    /// Maximum number of files verified or downloaded at once.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.options.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// How many times a file's download is retried after a round with a
+    /// transient failure. Each round races all the mirrors not yet dropped
+    /// for good, and counts once however many of them failed.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.options.retries = retries;
+        self
+    }
+
+    /// Base delay for exponential backoff between retries. Each attempt
+    /// doubles it, with random jitter of up to half the delay.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.options.retry_delay = delay;
+        self
+    }
+
+    /// Share verified downloads through a content-addressed cache directory,
+    /// see [`DownloadCache::default_dir`]. Disabled by default.
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.cache = Some(DownloadCache::new(dir).with_link_mode(self.options.link_mode));
+        self
+    }
+
+    /// How cached files are installed into the target directory.
+    pub fn link_mode(mut self, link_mode: LinkMode) -> Self {
+        self.options.link_mode = link_mode;
+        self.options.cache = self.options.cache.map(|c| c.with_link_mode(link_mode));
+        self
+    }
+
+    /// Hash-check everything and report the [`SyncPlan`] instead of writing
+    /// or deleting files.
+    pub fn dry_run(mut self, enabled: bool) -> Self {
+        self.options.dry_run = enabled;
+        self
+    }
+
+    /// Carry on past files that fail to install, reporting each as
+    /// [`SyncEvent::FileFailed`] and in the returned [`SyncReport`] instead
+    /// of aborting.
+    pub fn keep_going(mut self, enabled: bool) -> Self {
+        self.options.keep_going = enabled;
+        self
+    }
+
+    /// Wait for another process syncing the same directory to finish instead
+    /// of failing with [`FileError::Locked`].
+    pub fn wait_for_lock(mut self, enabled: bool) -> Self {
+        self.options.wait_for_lock = enabled;
+        self
+    }
+
+    /// Record the synced state in `observed.lock` after a successful sync.
+    /// On by default.
+    pub fn write_lockfile(mut self, enabled: bool) -> Self {
+        self.options.write_lockfile = enabled;
+        self
+    }
+
+    /// Skip re-hashing files whose size and mtime match the last verified
+    /// sync. Disable to force full verification. On by default.
+    pub fn state_cache(mut self, enabled: bool) -> Self {
+        self.options.state_cache = enabled;
+        self
+    }
+
+    /// Keep local edits to override files by three-way merging the pack's
+    /// changes into them, using the version recorded at the last sync as
+    /// the base. Overlapping edits are reported as conflicts and the local
+    /// file is left alone. On by default.
+    pub fn merge_overrides(mut self, enabled: bool) -> Self {
+        self.options.merge_overrides = enabled;
+        self
+    }
+
+    /// Merge `.properties` overrides key by key: keys the pack sets are
+    /// added or updated and every other line of the local file is kept.
+    /// Keys the pack hasn't changed since the last sync keep their local
+    /// value. Off by default.
+    pub fn merge_properties(mut self, enabled: bool) -> Self {
+        self.options.merge_properties = enabled;
+        self
+    }
+
+    /// Substitute `{{NAME}}` and `${NAME}` placeholders in override files
+    /// from [`template_var`](Self::template_var) values, falling back to
+    /// environment variables. Unknown placeholders are left as they are.
+    /// Off by default.
+    pub fn templates(mut self, enabled: bool) -> Self {
+        self.options.templates = enabled;
+        self
+    }
+
+    /// Sets a template variable, taking precedence over an environment
+    /// variable of the same name.
+    pub fn template_var(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.template_vars.insert(name.into(), value.into());
+        self
+    }
+
+    /// Substitute placeholders in override files with these secret values,
+    /// whether or not [`templates`](Self::templates) is on. Secrets take
+    /// precedence over template variables, and overrides they are injected
+    /// into are not stored as merge bases.
+    pub fn secrets(mut self, secrets: Secrets) -> Self {
+        self.options.secrets = Some(secrets);
+        self
+    }
+
+    /// Write `eula=true` to `eula.txt`, accepting the Minecraft EULA
+    /// (https://aka.ms/MinecraftEULA) on the operator's behalf. Off by
+    /// default.
+    pub fn accept_eula(mut self, enabled: bool) -> Self {
+        self.options.accept_eula = enabled;
+        self
+    }
+
+    /// After syncing, write `start.sh` and `start.bat` that launch the
+    /// pack's loader with Aikar's JVM flags and `memory` bytes of heap.
+    pub fn scaffold(mut self, memory: u64) -> Self {
+        self.options.scaffold_memory = Some(memory);
+        self
+    }
+
+    /// Install the server side of the loader the pack declares into the
+    /// target directory: Mojang's server jar plus Fabric's launcher, or the
+    /// result of running the Quilt, Forge or NeoForge installer. On by
+    /// default.
+    pub fn install_loader(mut self, enabled: bool) -> Self {
+        self.options.install_loader = enabled;
+        self
+    }
+
+    /// Whether to check, or provision, the Java runtime the pack's
+    /// Minecraft version needs. Defaults to [`JavaPolicy::Check`].
+    pub fn java(mut self, policy: JavaPolicy) -> Self {
+        self.options.java = policy;
+        self
+    }
+
+    /// Which of each file's hashes are checked, on download and on disk.
+    /// Defaults to [`HashPolicy::Standard`].
+    pub fn hash_policy(mut self, policy: HashPolicy) -> Self {
+        self.options.hash_policy = policy;
+        self
+    }
+
+    /// Unix modes to force onto matching overrides, over the ones the
+    /// pack's zip entries record.
+    pub fn override_modes(mut self, modes: OverrideModes) -> Self {
+        self.options.override_modes = modes;
+        self
+    }
+
+    /// Set downloaded files' modification times to when the pack was built,
+    /// instead of when they were downloaded. Off by default. Overrides
+    /// always get the times their zip entries record.
+    pub fn stamp_downloads(mut self, enabled: bool) -> Self {
+        self.options.stamp_downloads = enabled;
+        self
+    }
+
+    /// Walk symlinked directories inside the managed directories when
+    /// looking for files to prune, as long as they lead somewhere inside
+    /// the target directory. Off by default. Symlinks out of the target
+    /// directory are never followed, and pack files are never written
+    /// through them.
+    pub fn follow_symlinks(mut self, enabled: bool) -> Self {
+        self.options.follow_symlinks = enabled;
+        self
+    }
+
+    /// How thoroughly files already on disk are checked before they're
+    /// kept. Defaults to [`VerifyPolicy::Strict`].
+    pub fn verify_policy(mut self, policy: VerifyPolicy) -> Self {
+        self.options.verify_policy = policy;
+        self
+    }
+
+    /// Draw indicatif progress bars on the terminal, with a
+    /// [`ProgressBars`](crate::observer::ProgressBars) observer, or print
+    /// [`ProgressLines`](crate::observer::ProgressLines) when stderr isn't a
+    /// terminal. Off by default.
+    pub fn progress_bars(mut self, enabled: bool) -> Self {
+        self.options.progress_bars = enabled;
+        self
+    }
+
+    /// Registers a callback invoked for every [`SyncEvent`]. Callbacks may be
+    /// called concurrently from several download tasks.
+    pub fn on_progress(mut self, callback: impl Fn(&SyncEvent) + Send + Sync + 'static) -> Self {
+        self.options.callbacks.push(Arc::new(callback));
+        self
+    }
+
+    /// Registers an observer told about each file and phase as the sync
+    /// goes, and the bytes of each download.
+    pub fn observer(mut self, observer: impl SyncObserver + 'static) -> Self {
+        self.options.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Lets another task or thread abort the sync by cancelling `token`. It
+    /// is checked before each file and between download chunks; a cancelled
+    /// sync rolls back what it staged and fails with
+    /// [`FileError::Cancelled`]. Once files start moving into place the sync
+    /// runs to the end.
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.options.cancel = Some(token);
+        self
+    }
+
+    /// Called with the full list of files prune is about to delete; nothing
+    /// is pruned unless it returns `true`. Without a hook, pruning proceeds
+    /// unconditionally.
+    pub fn confirm_prune(
+        mut self,
+        confirm: impl Fn(&[PruneCandidate]) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.options.confirm_prune = Some(Arc::new(confirm));
+        self
+    }
+
+    pub fn build(self, pack: Pack) -> ModManager {
+        ModManager::new(pack, self.options)
+    }
+
+    pub async fn sync(self, pack: Pack) -> Result<SyncReport, FileError> {
+        self.build(pack).sync().await
+    }
+
+    pub async fn plan(self, pack: Pack) -> Result<SyncPlan, FileError> {
+        self.build(pack).plan().await
+    }
+
+    pub async fn choosable(self, pack: Pack) -> Result<Choosable, FileError> {
+        self.build(pack).choosable().await
+    }
+
+    pub async fn verify(self, pack: Pack) -> Result<VerifyReport, FileError> {
+        self.build(pack).verify().await
+    }
+
+    pub async fn watch(self, pack: Pack) -> Result<(), FileError> {
+        self.build(pack).watch().await
+    }
+}
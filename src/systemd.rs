@@ -0,0 +1,110 @@
+use std::{io, net::TcpListener, time::Duration};
+
+/// Tells systemd the service has started, for `Type=notify` units.
+/// Like the other notifications, does nothing when not run by systemd.
+pub fn notify_ready() {
+    #[cfg(unix)]
+    notify(&[sd_notify::NotifyState::Ready]);
+}
+
+/// Tells systemd the service is shutting down.
+pub fn notify_stopping() {
+    #[cfg(unix)]
+    notify(&[sd_notify::NotifyState::Stopping]);
+}
+
+/// Sets the status line `systemctl status` shows.
+pub fn notify_status(status: &str) {
+    #[cfg(unix)]
+    notify(&[sd_notify::NotifyState::Status(status)]);
+    #[cfg(not(unix))]
+    let _ = status;
+}
+
+/// Tells the watchdog the service is still alive.
+pub fn notify_watchdog() {
+    #[cfg(unix)]
+    notify(&[sd_notify::NotifyState::Watchdog]);
+}
+
+#[cfg(unix)]
+fn notify(state: &[sd_notify::NotifyState]) {
+    // Best effort: a lost notification is systemd's to time out on.
+    let _ = sd_notify::notify(false, state);
+}
+
+/// How often to ping the watchdog when the unit sets `WatchdogSec=`: half
+/// its timeout, as systemd recommends.
+pub fn watchdog_interval() -> Option<Duration> {
+    #[cfg(unix)]
+    {
+        let mut usec = 0;
+        sd_notify::watchdog_enabled(false, &mut usec).then(|| Duration::from_micros(usec / 2))
+    }
+    #[cfg(not(unix))]
+    None
+}
+
+/// The listening socket systemd passed in, with socket activation (a
+/// `.socket` unit with `ListenStream=`). Only the first is used.
+pub fn activated_listener() -> io::Result<Option<TcpListener>> {
+    #[cfg(unix)]
+    {
+        use std::os::fd::{FromRawFd, OwnedFd};
+
+        /// `SD_LISTEN_FDS_START`.
+        const FIRST_FD: i32 = 3;
+
+        let for_us = std::env::var("LISTEN_PID")
+            .ok()
+            .and_then(|pid| pid.parse::<u32>().ok())
+            == Some(std::process::id());
+        let count = std::env::var("LISTEN_FDS")
+            .ok()
+            .and_then(|count| count.parse::<u32>().ok())
+            .unwrap_or(0);
+        if !for_us || count == 0 {
+            return Ok(None);
+        }
+        // SAFETY: systemd hands the process these descriptors to own, and
+        // nothing else in it takes them.
+        let listener = TcpListener::from(unsafe { OwnedFd::from_raw_fd(FIRST_FD) });
+        // Fails unless the socket is a TCP one.
+        listener.local_addr().map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("the socket systemd passed is not a TCP socket: {}", err),
+            )
+        })?;
+        listener.set_nonblocking(true)?;
+        Ok(Some(listener))
+    }
+    #[cfg(not(unix))]
+    Ok(None)
+}
+
+/// Whether stderr goes to the journal, which reads a `<N>` syslog
+/// priority prefix on each line.
+pub fn stderr_is_journal() -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::{fd::AsFd, unix::fs::MetadataExt};
+
+        let Some((device, inode)) = std::env::var("JOURNAL_STREAM").ok().and_then(|stream| {
+            let (device, inode) = stream.split_once(':')?;
+            Some((device.parse::<u64>().ok()?, inode.parse::<u64>().ok()?))
+        }) else {
+            return false;
+        };
+        // systemd sets JOURNAL_STREAM for the streams it connects; a child
+        // whose stderr was redirected inherits the variable but not those.
+        std::io::stderr()
+            .as_fd()
+            .try_clone_to_owned()
+            .map(std::fs::File::from)
+            .and_then(|file| file.metadata())
+            .is_ok_and(|metadata| metadata.dev() == device && metadata.ino() == inode)
+    }
+    #[cfg(not(unix))]
+    false
+}
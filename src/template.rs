@@ -0,0 +1,54 @@
+/// Replaces `{{NAME}}` and `${NAME}` placeholders in `content` with the
+/// value `lookup` returns for `NAME`. Placeholders `lookup` doesn't know are
+/// left as they are, since `${...}` is common syntax in configs.
+///
+/// Returns `None` if `content` isn't UTF-8 or has nothing to substitute.
+pub(crate) fn render(content: &[u8], lookup: impl Fn(&str) -> Option<String>) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(content).ok()?;
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut changed = false;
+
+    while let Some(start) = rest.find(['{', '$']) {
+        let (open, close) = if rest[start..].starts_with("{{") {
+            ("{{", "}}")
+        } else if rest[start..].starts_with("${") {
+            ("${", "}")
+        } else {
+            rendered.push_str(&rest[..=start]);
+            rest = &rest[start + 1..];
+            continue;
+        };
+
+        let body = &rest[start + open.len()..];
+        let value = body.find(close).and_then(|end| {
+            let name = body[..end].trim();
+            let value = is_var_name(name).then(|| lookup(name)).flatten()?;
+            Some((value, start + open.len() + end + close.len()))
+        });
+
+        match value {
+            Some((value, end)) => {
+                rendered.push_str(&rest[..start]);
+                rendered.push_str(&value);
+                rest = &rest[end..];
+                changed = true;
+            }
+            None => {
+                rendered.push_str(&rest[..start + open.len()]);
+                rest = &rest[start + open.len()..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+
+    changed.then(|| rendered.into_bytes())
+}
+
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,337 @@
+use crate::errors::{FileError, IoContext};
+use crate::trash::TRASH_DIR;
+use crate::util::STATE_DIR;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+/// Working directory of an in-progress sync inside [`STATE_DIR`]. New files
+/// are staged under `staged/`, displaced originals are kept under `backup/`
+/// and every change to the server directory is logged to `journal` first.
+pub const TXN_DIR: &str = "txn";
+
+const STAGED_DIR: &str = "staged";
+const BACKUP_DIR: &str = "backup";
+const JOURNAL_FILE: &str = "journal";
+
+/// One change to the server directory, written to the journal before it is
+/// made. Paths are relative to the server root.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum JournalEntry {
+    /// A staged file moved to `path`, with any previous file moved to
+    /// `backup` first.
+    Install {
+        path: PathBuf,
+        backup: Option<PathBuf>,
+    },
+    /// The file at `path` moved to `moved_to` (the backup area or trash).
+    Remove { path: PathBuf, moved_to: PathBuf },
+}
+
+/// Applies a sync's staged files and prunes to the server directory as a
+/// unit: either every change lands, or the directory is put back the way
+/// it was.
+#[derive(Debug)]
+pub(crate) struct Transaction {
+    root: PathBuf,
+    journal: File,
+    entries: Vec<JournalEntry>,
+}
+
+impl Transaction {
+    /// Starts a transaction on `root`, first rolling back any transaction a
+    /// crashed run left behind. Returns whether a rollback was needed.
+    pub(crate) fn begin(root: &Path) -> Result<(Self, bool), FileError> {
+        let recovered = recover(root)?;
+
+        let dir = txn_dir(root);
+        if dir.exists() {
+            fs::remove_dir_all(&dir).at(&dir)?;
+        }
+        let staged = dir.join(STAGED_DIR);
+        fs::create_dir_all(&staged).at(&staged)?;
+        let journal_path = dir.join(JOURNAL_FILE);
+        let journal = File::create(&journal_path).at(&journal_path)?;
+
+        let txn = Transaction {
+            root: root.to_path_buf(),
+            journal,
+            entries: Vec::new(),
+        };
+        Ok((txn, recovered))
+    }
+
+    /// Where the new version of `path` is written before commit.
+    pub(crate) fn staged_path(root: &Path, path: &Path) -> PathBuf {
+        txn_dir(root).join(STAGED_DIR).join(path)
+    }
+
+    /// Moves the staged copy of `path` into place, keeping any existing file
+    /// in the backup area.
+    pub(crate) fn install(&mut self, path: &Path) -> Result<(), FileError> {
+        let target = self.root.join(path);
+        let backup = target.symlink_metadata().is_ok().then(|| backup_path(path));
+
+        self.log(JournalEntry::Install {
+            path: path.to_path_buf(),
+            backup: backup.clone(),
+        })?;
+
+        if let Some(backup) = &backup {
+            move_path(&target, &self.root.join(backup))?;
+        } else if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).at(parent)?;
+        }
+        let staged = Self::staged_path(&self.root, path);
+        fs::rename(&staged, &target).at(&target)
+    }
+
+    /// Removes `path` from the server directory. With a quarantine `batch`
+    /// it goes to the trash; otherwise it is dropped along with the
+    /// transaction once committed.
+    pub(crate) fn remove(&mut self, path: &Path, batch: Option<&str>) -> Result<(), FileError> {
+        let moved_to = match batch {
+            Some(batch) => Path::new(TRASH_DIR).join(batch).join(path),
+            None => backup_path(path),
+        };
+        self.log(JournalEntry::Remove {
+            path: path.to_path_buf(),
+            moved_to: moved_to.clone(),
+        })?;
+
+        let target = self.root.join(path);
+        move_path(&target, &self.root.join(&moved_to)).map_err(|err| match err {
+            FileError::Io { source, .. } => FileError::DeleteFailed {
+                path: target,
+                source,
+            },
+            err => err,
+        })
+    }
+
+    /// Makes the changes permanent. Removing the journal is the commit
+    /// point: once it is gone a crash can no longer roll anything back, and
+    /// the backups and anything left in staging are discarded after.
+    pub(crate) fn commit(self) -> Result<(), FileError> {
+        drop(self.journal);
+        discard(&self.root)
+    }
+
+    /// Undoes every change made so far and discards the transaction.
+    pub(crate) fn rollback(self) -> Result<(), FileError> {
+        drop(self.journal);
+        undo(&self.root, self.entries)?;
+        discard(&self.root)
+    }
+
+    fn log(&mut self, entry: JournalEntry) -> Result<(), FileError> {
+        let path = txn_dir(&self.root).join(JOURNAL_FILE);
+        let mut line = serde_json::to_vec(&entry)
+            .map_err(io::Error::from)
+            .at(&path)?;
+        line.push(b'\n');
+        self.journal.write_all(&line).at(&path)?;
+        self.journal.sync_data().at(&path)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+}
+
+/// Rolls back a transaction left behind by an interrupted sync, if any.
+fn recover(root: &Path) -> Result<bool, FileError> {
+    let path = txn_dir(root).join(JOURNAL_FILE);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err).at(&path),
+    };
+
+    // A torn final line means that change was never started.
+    let entries: Vec<JournalEntry> = BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .map_while(|line| serde_json::from_str(&line).ok())
+        .collect();
+    let recovered = !entries.is_empty();
+    undo(root, entries)?;
+    Ok(recovered)
+}
+
+/// Reverts journal entries newest first. Each step checks what actually
+/// happened, since the journal is written before the change it records.
+fn undo(root: &Path, entries: Vec<JournalEntry>) -> Result<(), FileError> {
+    for entry in entries.into_iter().rev() {
+        match entry {
+            JournalEntry::Install { path, backup } => {
+                let target = root.join(&path);
+                match backup.map(|backup| root.join(backup)) {
+                    Some(backup) if backup.symlink_metadata().is_ok() => {
+                        remove_path(&target)?;
+                        move_path(&backup, &target)?;
+                    }
+                    // The original was never moved aside, so it is still in place.
+                    Some(_) => {}
+                    None => remove_path(&target)?,
+                }
+            }
+            JournalEntry::Remove { path, moved_to } => {
+                let moved_to = root.join(moved_to);
+                if moved_to.symlink_metadata().is_ok() {
+                    move_path(&moved_to, &root.join(path))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes the journal, then the rest of the transaction directory. A
+/// crash in between leaves only files the next [`Transaction::begin`]
+/// clears without replaying anything.
+fn discard(root: &Path) -> Result<(), FileError> {
+    let dir = txn_dir(root);
+    let journal = dir.join(JOURNAL_FILE);
+    fs::remove_file(&journal).at(&journal)?;
+    // Make the removal durable before anything it protected goes away.
+    if let Ok(dir) = File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    fs::remove_dir_all(&dir).at(&dir)
+}
+
+fn txn_dir(root: &Path) -> PathBuf {
+    root.join(STATE_DIR).join(TXN_DIR)
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    Path::new(STATE_DIR)
+        .join(TXN_DIR)
+        .join(BACKUP_DIR)
+        .join(path)
+}
+
+fn move_path(from: &Path, to: &Path) -> Result<(), FileError> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).at(parent)?;
+    }
+    fs::rename(from, to).at(from)
+}
+
+fn remove_path(path: &Path) -> Result<(), FileError> {
+    let result = match path.symlink_metadata() {
+        Ok(md) if md.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => Ok(()),
+    };
+    result.at(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// An empty server root under the system temp directory.
+    fn temp_root() -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "observe-txn-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn stage(root: &Path, path: &str, content: &str) {
+        let staged = Transaction::staged_path(root, Path::new(path));
+        fs::create_dir_all(staged.parent().unwrap()).unwrap();
+        fs::write(staged, content).unwrap();
+    }
+
+    fn read(root: &Path, path: &str) -> Option<String> {
+        fs::read_to_string(root.join(path)).ok()
+    }
+
+    #[test]
+    fn commit_keeps_installed_files() {
+        let root = temp_root();
+        fs::write(root.join("old.txt"), "old").unwrap();
+        let (mut txn, recovered) = Transaction::begin(&root).unwrap();
+        assert!(!recovered);
+        stage(&root, "old.txt", "new");
+        stage(&root, "mods/a.jar", "a");
+        txn.install(Path::new("old.txt")).unwrap();
+        txn.install(Path::new("mods/a.jar")).unwrap();
+        txn.commit().unwrap();
+
+        assert_eq!(read(&root, "old.txt").as_deref(), Some("new"));
+        assert_eq!(read(&root, "mods/a.jar").as_deref(), Some("a"));
+        assert!(!txn_dir(&root).exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rollback_after_partial_install_restores_originals() {
+        let root = temp_root();
+        fs::write(root.join("old.txt"), "old").unwrap();
+        fs::write(root.join("gone.txt"), "gone").unwrap();
+        let (mut txn, _) = Transaction::begin(&root).unwrap();
+        stage(&root, "old.txt", "new");
+        stage(&root, "mods/a.jar", "a");
+        txn.install(Path::new("old.txt")).unwrap();
+        txn.install(Path::new("mods/a.jar")).unwrap();
+        txn.remove(Path::new("gone.txt"), None).unwrap();
+        // The last file was never staged, so its install fails midway.
+        assert!(txn.install(Path::new("mods/b.jar")).is_err());
+        txn.rollback().unwrap();
+
+        assert_eq!(read(&root, "old.txt").as_deref(), Some("old"));
+        assert_eq!(read(&root, "gone.txt").as_deref(), Some("gone"));
+        assert_eq!(read(&root, "mods/a.jar"), None);
+        assert!(!txn_dir(&root).exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn begin_rolls_back_a_journal_left_behind() {
+        let root = temp_root();
+        fs::write(root.join("old.txt"), "old").unwrap();
+        let (mut txn, _) = Transaction::begin(&root).unwrap();
+        stage(&root, "old.txt", "new");
+        stage(&root, "new.txt", "added");
+        txn.install(Path::new("old.txt")).unwrap();
+        txn.install(Path::new("new.txt")).unwrap();
+        // The process dies here, before commit or rollback.
+        drop(txn);
+
+        let (txn, recovered) = Transaction::begin(&root).unwrap();
+        assert!(recovered);
+        assert_eq!(read(&root, "old.txt").as_deref(), Some("old"));
+        assert_eq!(read(&root, "new.txt"), None);
+        txn.commit().unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn leftovers_of_a_committed_transaction_are_not_replayed() {
+        let root = temp_root();
+        let (mut txn, _) = Transaction::begin(&root).unwrap();
+        stage(&root, "new.txt", "added");
+        txn.install(Path::new("new.txt")).unwrap();
+        // A crash after the commit point leaves the directory, not the journal.
+        drop(txn);
+        fs::remove_file(txn_dir(&root).join(JOURNAL_FILE)).unwrap();
+
+        let (txn, recovered) = Transaction::begin(&root).unwrap();
+        assert!(!recovered);
+        assert_eq!(read(&root, "new.txt").as_deref(), Some("added"));
+        txn.commit().unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
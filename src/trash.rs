@@ -0,0 +1,46 @@
+use std::{
+    fs, io,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+/// Directory under the server root that pruned files are moved into.
+pub const TRASH_DIR: &str = ".observe-trash";
+
+/// Permanently deletes quarantined batches, optionally only those last
+/// modified more than `older_than` ago. Returns the number of batches
+/// removed.
+pub fn purge_trash(root: &Path, older_than: Option<Duration>) -> io::Result<usize> {
+    let trash = root.join(TRASH_DIR);
+    let entries = match fs::read_dir(&trash) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+
+    let now = SystemTime::now();
+    let mut purged = 0;
+
+    for entry in entries {
+        let entry = entry?;
+        if let Some(min_age) = older_than {
+            let modified = entry.metadata()?.modified()?;
+            if now.duration_since(modified).unwrap_or_default() < min_age {
+                continue;
+            }
+        }
+
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
+        purged += 1;
+    }
+
+    if fs::read_dir(&trash)?.next().is_none() {
+        fs::remove_dir(&trash)?;
+    }
+
+    Ok(purged)
+}
@@ -0,0 +1,482 @@
+use crate::events::SyncSummary;
+use crate::info::PackInfo;
+use crate::mrpack::Requirement;
+use crate::observer::{SyncObserver, SyncPhase};
+use crate::pack::Pack;
+use crate::report::FileStatus;
+use crate::verify::VerifyReport;
+use indicatif::HumanBytes;
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Cell, Gauge, Paragraph, Row, Table},
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use url::Url;
+
+/// What the dashboard is busy with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activity {
+    Idle,
+    Verifying,
+    Syncing,
+}
+
+/// The state `observe tui` draws: the pack, each index file's progress, the
+/// last verify and the files prune would remove. Register it with
+/// [`PackSyncer::observer`](crate::PackSyncer::observer) to follow a sync;
+/// clones share the same state.
+#[derive(Clone)]
+pub struct Dashboard {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    info: PackInfo,
+    target_dir: PathBuf,
+    files: Vec<FileRow>,
+    rows: HashMap<PathBuf, usize>,
+    overrides: Progress,
+    activity: Activity,
+    verify: Option<VerifyReport>,
+    prune_approved: bool,
+    pruned: usize,
+    summary: Option<SyncSummary>,
+    message: Option<(bool, String)>,
+    scroll: usize,
+}
+
+struct Progress {
+    done: u64,
+    total: u64,
+}
+
+struct FileRow {
+    path: PathBuf,
+    size: u64,
+    state: FileState,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum FileState {
+    Pending,
+    Downloading { bytes: u64, total: u64 },
+    Done(FileStatus),
+}
+
+impl Dashboard {
+    /// A dashboard for syncing `pack` into `target_dir`.
+    pub fn new(pack: &Pack, target_dir: &Path) -> Self {
+        let files: Vec<FileRow> = pack
+            .index
+            .files
+            .iter()
+            .filter(|file| file.server_requirement() != Requirement::Unsupported)
+            .map(|file| FileRow {
+                path: file.path.clone(),
+                size: file.file_size,
+                state: FileState::Pending,
+            })
+            .collect();
+        let rows = files
+            .iter()
+            .enumerate()
+            .map(|(row, file)| (file.path.clone(), row))
+            .collect();
+        Dashboard {
+            state: Arc::new(Mutex::new(State {
+                info: PackInfo::new(pack),
+                target_dir: target_dir.to_path_buf(),
+                files,
+                rows,
+                overrides: Progress {
+                    done: 0,
+                    total: pack.overrides.len() as u64,
+                },
+                activity: Activity::Idle,
+                verify: None,
+                prune_approved: false,
+                pruned: 0,
+                summary: None,
+                message: None,
+                scroll: 0,
+            })),
+        }
+    }
+
+    /// Marks a verify as started.
+    pub fn start_verify(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.activity = Activity::Verifying;
+    }
+
+    /// Shows the outcome of a verify; its extra files become the prune
+    /// candidates.
+    pub fn finish_verify(&self, result: Result<VerifyReport, String>) {
+        let mut state = self.state.lock().unwrap();
+        state.activity = Activity::Idle;
+        match result {
+            Ok(report) => state.verify = Some(report),
+            Err(err) => state.message = Some((true, format!("Verify failed: {}", err))),
+        }
+    }
+
+    /// Marks a sync as started, resetting every file to pending.
+    pub fn start_sync(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.activity = Activity::Syncing;
+        for file in &mut state.files {
+            file.state = FileState::Pending;
+        }
+        state.overrides.done = 0;
+        state.pruned = 0;
+        state.summary = None;
+        state.message = None;
+    }
+
+    /// Shows how a sync ended. Files that failed stay listed as such.
+    /// An approval to prune only ever covers one sync.
+    pub fn finish_sync(&self, result: Result<(), String>) {
+        let mut state = self.state.lock().unwrap();
+        state.activity = Activity::Idle;
+        state.prune_approved = false;
+        state.message = Some(match result {
+            Ok(()) if state.failed() > 0 => (
+                true,
+                format!("{} files failed; press r to retry them", state.failed()),
+            ),
+            Ok(()) => (false, "Sync completed".to_string()),
+            Err(err) => (true, format!("Sync failed: {}", err)),
+        });
+    }
+
+    /// Shows a note in the status line.
+    pub fn set_message(&self, message: impl Into<String>) {
+        self.state.lock().unwrap().message = Some((false, message.into()));
+    }
+
+    /// Files the last sync couldn't install.
+    pub fn failed(&self) -> usize {
+        self.state.lock().unwrap().failed()
+    }
+
+    /// Prune candidates from the last verify.
+    pub fn prune_candidates(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.verify.as_ref().map_or(0, |report| report.extra.len())
+    }
+
+    /// Approves pruning for the next sync, or withdraws the approval.
+    /// Returns whether it is now approved.
+    pub fn toggle_prune(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.prune_approved = !state.prune_approved;
+        state.prune_approved
+    }
+
+    pub fn prune_approved(&self) -> bool {
+        self.state.lock().unwrap().prune_approved
+    }
+
+    /// Scrolls the file list by `rows`.
+    pub fn scroll(&self, rows: isize) {
+        let mut state = self.state.lock().unwrap();
+        let last = state.files.len().saturating_sub(1);
+        state.scroll = state.scroll.saturating_add_signed(rows).min(last);
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
+        let state = self.state.lock().unwrap();
+        let [top, files, prune, status] = Layout::vertical([
+            Constraint::Length(8),
+            Constraint::Min(6),
+            Constraint::Length(7),
+            Constraint::Length(2),
+        ])
+        .areas(frame.area());
+        let [info, verify] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(top);
+        state.draw_info(frame, info);
+        state.draw_verify(frame, verify);
+        state.draw_files(frame, files);
+        state.draw_prune(frame, prune);
+        state.draw_status(frame, status);
+    }
+}
+
+impl State {
+    fn failed(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.state, FileState::Done(FileStatus::Failed { .. })))
+            .count()
+    }
+
+    fn draw_info(&self, frame: &mut Frame, area: Rect) {
+        let info = &self.info;
+        let mut lines = vec![
+            Line::from(vec![
+                Span::styled(&info.name, Style::new().add_modifier(Modifier::BOLD)),
+                Span::raw(format!(" {}", info.version_id)),
+            ]),
+            Line::from(
+                info.dependencies
+                    .iter()
+                    .map(|dep| format!("{} {}", dep.id, dep.version))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            Line::from(format!(
+                "{} server files ({}), {} overrides",
+                self.files.len(),
+                HumanBytes(info.server_download_size),
+                info.overrides
+            )),
+            Line::from(format!("Into {}", self.target_dir.display())),
+        ];
+        if let Some(summary) = &info.summary {
+            lines.insert(
+                1,
+                Line::from(summary.as_str()).style(Style::new().fg(Color::Gray)),
+            );
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Pack ")),
+            area,
+        );
+    }
+
+    fn draw_verify(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(" Verification ");
+        let lines = match (&self.verify, self.activity) {
+            (_, Activity::Verifying) => vec![Line::from("Checking the server against the pack…")],
+            (None, _) => vec![Line::from("Not verified yet; press v")],
+            (Some(report), _) if report.is_clean() => {
+                vec![Line::from("The server matches the pack").style(Style::new().fg(Color::Green))]
+            }
+            (Some(report), _) => {
+                let mut lines = vec![
+                    Line::from(format!(
+                        "{} missing, {} modified, {} extra",
+                        report.missing.len(),
+                        report.modified.len(),
+                        report.extra.len()
+                    ))
+                    .style(Style::new().fg(Color::Yellow)),
+                ];
+                let height = area.height.saturating_sub(3) as usize;
+                lines.extend(
+                    report
+                        .drift()
+                        .take(height)
+                        .map(|(path, kind)| Line::from(format!("{} ({})", path.display(), kind))),
+                );
+                lines
+            }
+        };
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn draw_files(&self, frame: &mut Frame, area: Rect) {
+        let done = self
+            .files
+            .iter()
+            .filter(|file| matches!(file.state, FileState::Done(_)))
+            .count();
+        let title = format!(
+            " Files {}/{}, overrides {}/{} ",
+            done,
+            self.files.len(),
+            self.overrides.done,
+            self.overrides.total
+        );
+        let block = Block::bordered().title(title);
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let [gauge, table] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(inner);
+        let total = self.files.len() as u64 + self.overrides.total;
+        let ratio = if total == 0 {
+            0.0
+        } else {
+            (done as u64 + self.overrides.done) as f64 / total as f64
+        };
+        frame.render_widget(
+            Gauge::default()
+                .gauge_style(Style::new().fg(Color::Cyan))
+                .ratio(ratio.min(1.0)),
+            gauge,
+        );
+
+        let rows = self
+            .files
+            .iter()
+            .skip(self.scroll)
+            .take(table.height as usize)
+            .map(|file| {
+                let (label, color, detail) = file.describe();
+                Row::new(vec![
+                    Cell::from(label).style(Style::new().fg(color)),
+                    Cell::from(file.path.display().to_string()),
+                    Cell::from(detail),
+                ])
+            });
+        let widths = [
+            Constraint::Length(11),
+            Constraint::Min(20),
+            Constraint::Length(40),
+        ];
+        frame.render_widget(Table::new(rows, widths), table);
+    }
+
+    fn draw_prune(&self, frame: &mut Frame, area: Rect) {
+        let candidates = self
+            .verify
+            .as_ref()
+            .map_or(&[][..], |report| &report.extra[..]);
+        let approval = if self.prune_approved {
+            Span::styled("approved", Style::new().fg(Color::Red))
+        } else {
+            Span::raw("not approved, press p")
+        };
+        let title = Line::from(vec![
+            Span::raw(format!(" Prune candidates ({}): ", candidates.len())),
+            approval,
+            Span::raw(" "),
+        ]);
+        let mut lines: Vec<Line> = candidates
+            .iter()
+            .take(area.height.saturating_sub(2) as usize)
+            .map(|path| Line::from(path.display().to_string()))
+            .collect();
+        if self.pruned > 0 {
+            lines.insert(0, Line::from(format!("Pruned {} files", self.pruned)));
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(title)),
+            area,
+        );
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let [message, keys] =
+            Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).areas(area);
+        let activity = match self.activity {
+            Activity::Idle => None,
+            Activity::Verifying => Some("Verifying…".to_string()),
+            Activity::Syncing => Some("Syncing…".to_string()),
+        };
+        let line = match (activity, &self.message, &self.summary) {
+            (Some(activity), _, _) => Line::from(activity),
+            (None, Some((true, message)), _) => {
+                Line::from(message.as_str()).style(Style::new().fg(Color::Red))
+            }
+            (None, Some((false, message)), Some(summary)) => Line::from(format!(
+                "{}: {} downloaded ({}), {} from cache, {} verified, {} overrides",
+                message,
+                summary.downloaded,
+                HumanBytes(summary.bytes_downloaded),
+                summary.cache_hits,
+                summary.verified,
+                summary.overrides_written
+            )),
+            (None, Some((false, message)), None) => Line::from(message.as_str()),
+            (None, None, _) => Line::default(),
+        };
+        frame.render_widget(Paragraph::new(line), message);
+        frame.render_widget(
+            Paragraph::new(
+                "s sync · r retry failed · p approve pruning · v verify · ↑↓ scroll · q quit",
+            )
+            .style(Style::new().fg(Color::DarkGray)),
+            keys,
+        );
+    }
+}
+
+impl FileRow {
+    /// A short status, its colour, and any detail such as progress or an
+    /// error.
+    fn describe(&self) -> (&'static str, Color, String) {
+        match &self.state {
+            FileState::Pending => (
+                "pending",
+                Color::DarkGray,
+                HumanBytes(self.size).to_string(),
+            ),
+            FileState::Downloading { bytes, total } => (
+                "downloading",
+                Color::Cyan,
+                format!(
+                    "{}/{} ({}%)",
+                    HumanBytes(*bytes),
+                    HumanBytes(*total),
+                    (bytes * 100).checked_div(*total).unwrap_or(0)
+                ),
+            ),
+            FileState::Done(status) => match status {
+                FileStatus::Verified => ("ok", Color::Green, String::new()),
+                FileStatus::CacheHit => ("cached", Color::Green, String::new()),
+                FileStatus::Downloaded { bytes } => {
+                    ("downloaded", Color::Green, HumanBytes(*bytes).to_string())
+                }
+                FileStatus::Failed { error, .. } => ("failed", Color::Red, error.clone()),
+                _ => ("done", Color::Green, String::new()),
+            },
+        }
+    }
+}
+
+impl SyncObserver for Dashboard {
+    fn on_phase_start(&self, phase: SyncPhase, total: u64) {
+        if phase == SyncPhase::Overrides {
+            self.state.lock().unwrap().overrides = Progress { done: 0, total };
+        }
+    }
+
+    fn on_file_start(&self, path: &Path, _url: &Url, size: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(&row) = state.rows.get(path) {
+            state.files[row].state = FileState::Downloading {
+                bytes: 0,
+                total: size,
+            };
+        }
+    }
+
+    fn on_bytes(&self, path: &Path, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(&row) = state.rows.get(path)
+            && let FileState::Downloading { bytes: done, .. } = &mut state.files[row].state
+        {
+            *done += bytes;
+        }
+    }
+
+    fn on_file_done(&self, path: &Path, status: &FileStatus) {
+        let mut state = self.state.lock().unwrap();
+        match state.rows.get(path) {
+            Some(&row) => state.files[row].state = FileState::Done(status.clone()),
+            None => state.overrides.done += 1,
+        }
+    }
+
+    fn on_prune(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        state.pruned += 1;
+        if let Some(report) = &mut state.verify {
+            report.extra.retain(|extra| extra != path);
+        }
+    }
+
+    fn on_complete(&self, summary: &SyncSummary) {
+        self.state.lock().unwrap().summary = Some(summary.clone());
+    }
+}
@@ -0,0 +1,182 @@
+use crate::modrinth::{ApiError, ModrinthClient, Version};
+use crate::mrpack::{DependencyId, FileHashes, MRFile, Requirement};
+use crate::pack::Pack;
+use hex::FromHex;
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+/// A newer version of a file's Modrinth project for the pack's game and
+/// loader versions.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModUpgrade {
+    /// The project title.
+    pub name: String,
+    pub old_path: PathBuf,
+    pub old_version: String,
+    pub new_version: String,
+    /// The replacement, in the same directory as the old file.
+    pub file: MRFile,
+}
+
+impl Display for ModUpgrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} → {} ({})",
+            self.name,
+            self.old_version,
+            self.new_version,
+            self.file.path.display()
+        )
+    }
+}
+
+/// Looks up newer versions of the pack's server files on Modrinth,
+/// matching the loader and Minecraft version the pack declares. With
+/// `installed`, only those paths are considered. Files Modrinth doesn't
+/// know, and files already on the newest version, are left alone.
+pub async fn find_upgrades(
+    modrinth: &ModrinthClient,
+    pack: &Pack,
+    installed: Option<&BTreeSet<PathBuf>>,
+) -> Result<Vec<ModUpgrade>, ApiError> {
+    let minecraft = pack
+        .index
+        .dependencies
+        .get(&DependencyId::Minecraft)
+        .ok_or("the pack doesn't declare a Minecraft version")?;
+    // Modrinth names release versions without a zero patch, e.g. `1.21`.
+    let game_versions = vec![if minecraft.patch == 0 && minecraft.pre.is_empty() {
+        format!("{}.{}", minecraft.major, minecraft.minor)
+    } else {
+        minecraft.to_string()
+    }];
+    let loaders = modrinth_loaders(pack);
+    if loaders.is_empty() {
+        return Err("the pack doesn't declare a mod loader".into());
+    }
+
+    let files: Vec<&MRFile> = pack
+        .index
+        .files
+        .iter()
+        .filter(|file| file.server_requirement() != Requirement::Unsupported)
+        .filter(|file| installed.is_none_or(|paths| paths.contains(&file.path)))
+        .collect();
+    let hashes: Vec<String> = files
+        .iter()
+        .map(|file| hex::encode(file.hashes.sha1))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let current = modrinth.versions_from_hashes(&hashes, "sha1").await?;
+    let latest = modrinth
+        .latest_versions_from_hashes(&hashes, "sha1", &loaders, &game_versions)
+        .await?;
+
+    let mut candidates = Vec::new();
+    for file in files {
+        let sha1 = hex::encode(file.hashes.sha1);
+        let (Some(current), Some(latest)) = (current.get(&sha1), latest.get(&sha1)) else {
+            continue;
+        };
+        // The newest compatible version can be older than the one installed,
+        // e.g. when the pack ships a beta.
+        if latest.id != current.id && latest.date_published > current.date_published {
+            candidates.push((file, current, latest));
+        }
+    }
+
+    let project_ids: BTreeSet<&str> = candidates
+        .iter()
+        .map(|(_, _, latest)| latest.project_id.as_str())
+        .collect();
+    let titles: HashMap<String, String> = modrinth
+        .projects(&project_ids.into_iter().collect::<Vec<_>>())
+        .await?
+        .into_iter()
+        .map(|project| (project.id, project.title))
+        .collect();
+
+    let mut upgrades = Vec::new();
+    for (file, current, latest) in candidates {
+        upgrades.push(ModUpgrade {
+            name: titles
+                .get(&latest.project_id)
+                .cloned()
+                .unwrap_or_else(|| latest.project_id.clone()),
+            old_path: file.path.clone(),
+            old_version: current.version_number.clone(),
+            new_version: latest.version_number.clone(),
+            file: replacement(file, latest)?,
+        });
+    }
+    upgrades.sort_by_key(|upgrade| upgrade.name.to_lowercase());
+    Ok(upgrades)
+}
+
+/// Swaps each upgraded file into the pack.
+pub fn apply(pack: &mut Pack, upgrades: &[ModUpgrade]) {
+    let old: BTreeSet<&Path> = upgrades.iter().map(|u| u.old_path.as_path()).collect();
+    pack.index
+        .files
+        .retain(|file| !old.contains(file.path.as_path()));
+    pack.index
+        .files
+        .extend(upgrades.iter().map(|upgrade| upgrade.file.clone()));
+}
+
+/// Modrinth's names for the loaders the pack declares. Quilt also loads
+/// Fabric mods.
+fn modrinth_loaders(pack: &Pack) -> Vec<&'static str> {
+    let deps = &pack.index.dependencies;
+    let mut loaders = Vec::new();
+    if deps.contains_key(&DependencyId::Forge) {
+        loaders.push("forge");
+    }
+    if deps.contains_key(&DependencyId::Neoforge) {
+        loaders.push("neoforge");
+    }
+    if deps.contains_key(&DependencyId::QuiltLoader) {
+        loaders.push("quilt");
+        loaders.push("fabric");
+    } else if deps.contains_key(&DependencyId::FabricLoader) {
+        loaders.push("fabric");
+    }
+    loaders
+}
+
+/// The primary file of `version` as an index entry next to `old`.
+fn replacement(old: &MRFile, version: &Version) -> Result<MRFile, ApiError> {
+    let file = version
+        .files
+        .iter()
+        .find(|file| file.primary)
+        .or(version.files.first())
+        .ok_or_else(|| format!("{} has no files", version.id))?;
+    let hash = |algorithm: &str| {
+        file.hashes
+            .get(algorithm)
+            .ok_or_else(|| format!("{}: Modrinth gave no {} hash", file.filename, algorithm))
+    };
+    let path = old
+        .path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(&file.filename);
+    Ok(MRFile {
+        hashes: FileHashes {
+            sha1: <[u8; 20]>::from_hex(hash("sha1")?)?,
+            sha512: <[u8; 64]>::from_hex(hash("sha512")?)?,
+            other_hashes: HashMap::new(),
+        },
+        env: old.env.clone(),
+        downloads: vec![file.url.clone()],
+        file_size: file.size,
+        path,
+    })
+}
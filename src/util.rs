@@ -0,0 +1,210 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Directory under the server root holding observe-rs' own state.
+pub const STATE_DIR: &str = ".observe";
+
+/// Whether `path` is relative and can't climb out of the directory it is
+/// joined onto: no root, drive prefix or `..` components. Drives such as
+/// `C:` are refused on every platform, not just where they mean one.
+pub fn is_contained_path(path: &Path) -> bool {
+    let drive = path
+        .components()
+        .next()
+        .and_then(|c| c.as_os_str().to_str())
+        .is_some_and(
+            |first| matches!(first.as_bytes(), [letter, b':', ..] if letter.is_ascii_alphabetic()),
+        );
+    !drive
+        && path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        && path.components().any(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Parses a path as a pack spells it. Packs use `/`, but ones built on
+/// Windows sometimes use `\\`, which Unix would read as part of a file name.
+pub fn parse_pack_path(path: &str) -> PathBuf {
+    PathBuf::from(path.replace('\\', "/"))
+}
+
+/// Spells `path` the way packs do, with `/` whatever the platform.
+pub fn slash_path(path: &Path) -> String {
+    path.iter()
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Serde adapter for pack paths, through [`parse_pack_path`] and
+/// [`slash_path`].
+pub(crate) mod pack_path {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::path::{Path, PathBuf};
+
+    pub(crate) fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::slash_path(path))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PathBuf, D::Error> {
+        String::deserialize(deserializer).map(|path| super::parse_pack_path(&path))
+    }
+}
+
+/// Parses a byte count such as `512K`, `10M` or `1.5G` (binary multiples,
+/// as curl's `--limit-rate` does). A bare number is bytes.
+pub fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, multiplier) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1u64 << 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    match number.parse::<f64>() {
+        Ok(n) if n.is_finite() && n >= 0.0 => Ok((n * multiplier as f64) as u64),
+        _ => Err(format!(
+            "invalid size `{}` (expected e.g. 500K, 10M or 1G)",
+            s
+        )),
+    }
+}
+
+/// UTC timestamp safe for use in file names, e.g. `20261016T093000Z`.
+pub fn file_timestamp(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc_parts(time);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// UTC date and time for display, e.g. `2026-10-16 09:30:00`.
+pub fn display_timestamp(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc_parts(time);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// RFC 3339 UTC timestamp, e.g. `2026-10-16T09:30:00Z`.
+pub fn rfc3339_timestamp(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc_parts(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+pub(crate) fn utc_parts(time: SystemTime) -> (i64, u32, u32, i64, i64, i64) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    (year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+/// The modification time a zip entry records. Zip stores a local time
+/// without a zone; it is read as UTC so every machine agrees on it.
+pub(crate) fn zip_entry_time(time: zip::DateTime) -> Option<SystemTime> {
+    let days = days_from_civil(
+        i64::from(time.year()),
+        u32::from(time.month()),
+        u32::from(time.day()),
+    );
+    let secs = days * 86_400
+        + i64::from(time.hour()) * 3600
+        + i64::from(time.minute()) * 60
+        + i64::from(time.second());
+    Some(UNIX_EPOCH + std::time::Duration::from_secs(u64::try_from(secs).ok()?))
+}
+
+/// Converts a proleptic Gregorian date to days since the Unix epoch, the
+/// inverse of [`civil_from_days`].
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Converts days since the Unix epoch to a proleptic Gregorian date
+/// (Howard Hinnant's `civil_from_days`).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contained(path: &str) -> bool {
+        is_contained_path(&parse_pack_path(path))
+    }
+
+    #[test]
+    fn relative_paths_are_contained() {
+        assert!(contained("mods/a.jar"));
+        assert!(contained("./config/b.toml"));
+    }
+
+    #[test]
+    fn traversal_is_refused() {
+        assert!(!contained("../server.properties"));
+        assert!(!contained("mods/../../etc/passwd"));
+        assert!(!contained(".."));
+        // Refused even when it would land back inside.
+        assert!(!contained("mods/../mods-extra"));
+    }
+
+    #[test]
+    fn empty_paths_are_refused() {
+        assert!(!contained(""));
+        assert!(!contained("."));
+    }
+
+    #[test]
+    fn absolute_paths_are_refused() {
+        assert!(!contained("/etc/passwd"));
+        assert!(!contained("//server/share/file"));
+    }
+
+    #[test]
+    fn windows_drives_are_refused() {
+        assert!(!contained("C:/Windows/system32"));
+        assert!(!contained("c:relative"));
+        assert!(!contained("C:\\Windows\\system32"));
+    }
+
+    #[test]
+    fn backslashes_separate_components() {
+        assert_eq!(parse_pack_path("mods\\a.jar"), PathBuf::from("mods/a.jar"));
+        assert!(contained("config\\b.toml"));
+        assert!(!contained("..\\..\\server.properties"));
+        assert!(!contained("mods\\..\\..\\x"));
+        assert!(!contained("\\etc\\passwd"));
+    }
+
+    #[test]
+    fn slash_path_round_trips() {
+        assert_eq!(slash_path(&parse_pack_path("a\\b/c.txt")), "a/b/c.txt");
+    }
+}
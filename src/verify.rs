@@ -0,0 +1,86 @@
+use serde::Serialize;
+use std::{fmt::Display, path::PathBuf};
+
+/// How an installed server differs from a pack, as found by a read-only
+/// audit. Paths are relative to the target directory.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    /// Index files and overrides that are not on disk.
+    pub missing: Vec<PathBuf>,
+    /// Index files whose hashes don't match, and overrides whose content
+    /// differs from the pack's.
+    pub modified: Vec<PathBuf>,
+    /// Files under the managed directories the pack doesn't account for.
+    pub extra: Vec<PathBuf>,
+}
+
+/// How a single file differs from the pack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DriftKind {
+    Missing,
+    Modified,
+    Extra,
+}
+
+impl Display for DriftKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DriftKind::Missing => write!(f, "missing"),
+            DriftKind::Modified => write!(f, "modified"),
+            DriftKind::Extra => write!(f, "extra"),
+        }
+    }
+}
+
+impl VerifyReport {
+    /// Every drifted path with how it differs.
+    pub fn drift(&self) -> impl Iterator<Item = (&PathBuf, DriftKind)> {
+        let missing = self.missing.iter().map(|p| (p, DriftKind::Missing));
+        let modified = self.modified.iter().map(|p| (p, DriftKind::Modified));
+        let extra = self.extra.iter().map(|p| (p, DriftKind::Extra));
+        missing.chain(modified).chain(extra)
+    }
+
+    /// Whether the server matches the pack exactly.
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.modified.is_empty() && self.extra.is_empty()
+    }
+
+    pub(crate) fn sort(&mut self) {
+        self.missing.sort();
+        self.modified.sort();
+        self.extra.sort();
+    }
+}
+
+impl Display for VerifyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_clean() {
+            return write!(f, "No drift: the server matches the pack");
+        }
+
+        let sections = [
+            ("Missing", &self.missing),
+            ("Modified", &self.modified),
+            ("Extra", &self.extra),
+        ];
+
+        let mut first = true;
+        for (label, paths) in sections {
+            if paths.is_empty() {
+                continue;
+            }
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            write!(f, "{} ({}):", label, paths.len())?;
+            for path in paths {
+                write!(f, "\n  {}", path.display())?;
+            }
+        }
+
+        Ok(())
+    }
+}